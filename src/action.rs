@@ -0,0 +1,172 @@
+use serde::Deserialize;
+use tracing::{debug, warn};
+
+use crate::{direction::Direction, layout, snap::Snap, TwmState};
+
+/// Compositor action triggered by a keybinding
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Spawns the given command line
+    Spawn(String),
+    /// Asks the focused window to close
+    CloseWindow,
+    /// Disconnects the client of the focused window, for clients not reacting to `CloseWindow`
+    KillClient,
+    /// Moves keyboard focus to the next window
+    FocusNext,
+    /// Moves keyboard focus to the previous window
+    FocusPrev,
+    /// Focuses the window to the left of the focused one
+    FocusLeft,
+    /// Focuses the window to the right of the focused one
+    FocusRight,
+    /// Focuses the window above the focused one
+    FocusUp,
+    /// Focuses the window below the focused one
+    FocusDown,
+    /// Swaps the focused window with the master window
+    SwapWithMaster,
+    /// Swaps the focused window with the next window
+    SwapNext,
+    /// Swaps the focused window with the previous window
+    SwapPrev,
+    /// Swaps the focused window with the tiled window to its left, moves floating windows
+    /// left by `floating_move_step`
+    MoveLeft,
+    /// Like `MoveLeft` to the right
+    MoveRight,
+    /// Like `MoveLeft` upwards
+    MoveUp,
+    /// Like `MoveLeft` downwards
+    MoveDown,
+    /// Widens the master area of the current workspace
+    IncreaseMasterRatio,
+    /// Narrows the master area of the current workspace
+    DecreaseMasterRatio,
+    /// Takes the focused window out of its workspace until it is restored
+    Minimize,
+    /// Restores the window minimized last and focuses it
+    RestoreMinimized,
+    /// Floats the focused window or puts it back into tiling
+    ToggleFloating,
+    /// Shows the focused window on every workspace of its output or only on its current one
+    ToggleSticky,
+    /// Snaps the focused floating window to the left part of its output, again to cycle
+    /// through a half, a third and two thirds of it
+    SnapLeft,
+    /// Snaps the focused floating window to the right part of its output
+    SnapRight,
+    /// Snaps the focused floating window to the upper part of its output
+    SnapUp,
+    /// Snaps the focused floating window to the lower part of its output
+    SnapDown,
+    /// Snaps the focused floating window to the top left corner of its output
+    SnapTopLeft,
+    /// Snaps the focused floating window to the top right corner of its output
+    SnapTopRight,
+    /// Snaps the focused floating window to the bottom left corner of its output
+    SnapBottomLeft,
+    /// Snaps the focused floating window to the bottom right corner of its output
+    SnapBottomRight,
+    /// Switches the current workspace to the next layout
+    CycleLayout,
+    /// Puts the focused window and the next tiled window into one tabbed container
+    JoinTabs,
+    /// Takes the focused window out of its tabbed container
+    SplitTabs,
+    /// Focuses the next tab of the focused tabbed container
+    FocusNextTab,
+    /// Focuses the previous tab of the focused tabbed container
+    FocusPrevTab,
+    /// Shows or hides the scratchpad window, starting it first if needed
+    ToggleScratchpad,
+    /// Shows the workspace with the given number on the output under the pointer,
+    /// counted from 1
+    SwitchWorkspace(usize),
+    /// Shows the workspace after the current one on the output under the pointer, wrapping
+    /// around after the last
+    NextWorkspace,
+    /// Shows the workspace before the current one on the output under the pointer, wrapping
+    /// around before the first
+    PrevWorkspace,
+    /// Moves the focused window to the workspace with the given number of its output,
+    /// counted from 1
+    MoveWindowToWorkspace(usize),
+    /// Exchanges the workspace shown on the output under the pointer with the one on the
+    /// next output
+    MoveWorkspaceToNextOutput,
+    /// Switches to another virtual terminal, only supported on the udev backend
+    SwitchVt(i32),
+    /// Shows or hides frame rate, damage and tiling details on every output
+    ToggleDebugOverlay,
+    /// Reads the config file again and applies it
+    ReloadConfig,
+    /// Takes all keys back from the focused client if it inhibits keyboard shortcuts, the
+    /// only binding working while it does
+    ReleaseShortcutsInhibitor,
+    /// Stops the compositor
+    Quit,
+}
+
+impl TwmState {
+    pub fn handle_action(&mut self, action: Action) {
+        debug!("Handling action: {:?}", action);
+        match action {
+            Action::Spawn(command) => self.spawn(&command),
+            Action::CloseWindow => self.close_focused_window(),
+            Action::KillClient => self.kill_focused_client(),
+            Action::FocusNext => self.focus_next(),
+            Action::FocusPrev => self.focus_prev(),
+            Action::FocusLeft => self.focus_in_direction(Direction::Left),
+            Action::FocusRight => self.focus_in_direction(Direction::Right),
+            Action::FocusUp => self.focus_in_direction(Direction::Up),
+            Action::FocusDown => self.focus_in_direction(Direction::Down),
+            Action::SwapWithMaster => self.swap_with_master(),
+            Action::SwapNext => self.swap_next(),
+            Action::SwapPrev => self.swap_prev(),
+            Action::MoveLeft => self.move_in_direction(Direction::Left),
+            Action::MoveRight => self.move_in_direction(Direction::Right),
+            Action::MoveUp => self.move_in_direction(Direction::Up),
+            Action::MoveDown => self.move_in_direction(Direction::Down),
+            Action::IncreaseMasterRatio => self.adjust_master_ratio(layout::MASTER_RATIO_STEP),
+            Action::DecreaseMasterRatio => self.adjust_master_ratio(-layout::MASTER_RATIO_STEP),
+            Action::Minimize => self.minimize_focused_window(),
+            Action::RestoreMinimized => self.restore_last_minimized(),
+            Action::ToggleFloating => self.toggle_floating(),
+            Action::ToggleSticky => self.toggle_sticky(),
+            Action::SnapLeft => self.snap_focused_window(Snap::Left),
+            Action::SnapRight => self.snap_focused_window(Snap::Right),
+            Action::SnapUp => self.snap_focused_window(Snap::Up),
+            Action::SnapDown => self.snap_focused_window(Snap::Down),
+            Action::SnapTopLeft => self.snap_focused_window(Snap::TopLeft),
+            Action::SnapTopRight => self.snap_focused_window(Snap::TopRight),
+            Action::SnapBottomLeft => self.snap_focused_window(Snap::BottomLeft),
+            Action::SnapBottomRight => self.snap_focused_window(Snap::BottomRight),
+            Action::CycleLayout => self.cycle_layout(),
+            Action::JoinTabs => self.join_tabs(),
+            Action::SplitTabs => self.split_tabs(),
+            Action::FocusNextTab => self.focus_next_tab(),
+            Action::FocusPrevTab => self.focus_prev_tab(),
+            Action::ToggleScratchpad => self.toggle_scratchpad(),
+            Action::SwitchWorkspace(number) => {
+                self.switch_workspace(number.saturating_sub(1));
+            },
+            Action::NextWorkspace => self.cycle_workspace(1),
+            Action::PrevWorkspace => self.cycle_workspace(-1),
+            Action::MoveWindowToWorkspace(number) => {
+                self.move_window_to_workspace(number.saturating_sub(1));
+            },
+            Action::MoveWorkspaceToNextOutput => self.move_workspace_to_next_output(),
+            Action::SwitchVt(vt) => self.requested_vt = Some(vt),
+            Action::ReleaseShortcutsInhibitor => self.release_shortcuts_inhibitor(),
+            Action::ToggleDebugOverlay => self.toggle_debug_overlay(),
+            Action::ReloadConfig => {
+                if let Err(err) = self.reload_config() {
+                    warn!("Failed to reload the config: {:#}", err);
+                }
+            },
+            Action::Quit => self.ev_signal.stop(),
+        }
+    }
+}
@@ -0,0 +1,203 @@
+//! The xdg-activation protocol used by clients to focus their own windows, or windows of
+//! other clients like a browser opened from a link in a chat client.
+//!
+//! Tokens are only honored for a few seconds after they were created. Depending on
+//! `focus_on_window_activation` in the config the target window is then focused, switching
+//! to its workspace if needed, or only marked urgent. The smart policy only focuses it if
+//! the token carries the serial of a key or button event since the keyboard focus last
+//! changed, so only the client the user is interacting with can pass the focus on.
+//! Urgent windows get a border in the urgent color until they are focused, and are flagged
+//! as urgent in IPC window queries.
+//! The foreign-toplevel protocol has no urgency state, taskbars learn about urgency changes
+//! from IPC window events instead.
+//!
+//! The same setting keeps new windows from stealing the focus. They only take it right
+//! away if nothing has the focus, and are otherwise focused or marked urgent like activated
+//! windows, unless the `focus` window rule decides.
+
+use std::cell::Cell;
+use std::time::Duration;
+
+use smithay::{
+    desktop::Window,
+    reexports::wayland_server::{protocol::wl_surface::WlSurface, Resource},
+    utils::Serial,
+    wayland::xdg_activation::{XdgActivationHandler, XdgActivationState, XdgActivationToken, XdgActivationTokenData},
+};
+use tracing::debug;
+
+use crate::{config::Activation, ipc, window_info::WindowInfo, TwmState};
+
+/// Time after which unused tokens can't activate windows anymore
+const TOKEN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Urgency of a window kept in its user data
+#[derive(Debug, Default)]
+struct Urgent(Cell<bool>);
+
+/// Serials of the user's key and button events, to tell tokens created from input to the
+/// focused client from the others
+#[derive(Debug, Default)]
+pub struct InputSerials {
+    /// Latest event
+    latest: Option<Serial>,
+    /// Keyboard focus change
+    focus: Option<Serial>,
+}
+
+impl InputSerials {
+    /// Records a key or button press or release
+    pub fn input(&mut self, serial: Serial) {
+        self.latest = Some(serial);
+    }
+
+    /// Input before the keyboard focus change with `serial` went to another client
+    pub fn focus_changed(&mut self, serial: Serial) {
+        self.focus = Some(serial);
+    }
+
+    /// Whether `serial` belongs to input since the focus last changed
+    pub fn is_recent(&self, serial: Serial) -> bool {
+        self.latest.map_or(false, |latest| latest.is_no_older_than(&serial))
+            && self.focus.map_or(true, |focus| serial.is_no_older_than(&focus))
+    }
+}
+
+impl XdgActivationHandler for TwmState {
+    fn activation_state(&mut self) -> &mut XdgActivationState {
+        &mut self.xdg_activation_state
+    }
+
+    fn request_activation(&mut self,
+                          token: XdgActivationToken,
+                          token_data: XdgActivationTokenData,
+                          surface: WlSurface) {
+        // Tokens are single use
+        self.xdg_activation_state.remove_request(&token);
+        if token_data.timestamp.elapsed() >= TOKEN_TIMEOUT {
+            debug!("Ignoring activation with an expired token");
+            return;
+        }
+        let Some(window) = self.all_windows().find(|w| w.toplevel().wl_surface() == &surface).cloned() else {
+            debug!("Ignoring activation of a surface without window");
+            return;
+        };
+
+        let focus = match self.config.focus_on_window_activation {
+            Activation::Focus => true,
+            // Windows on hidden workspaces don't switch workspaces
+            Activation::Smart => {
+                let from_input = token_data.serial.as_ref().map_or(false, |(serial, _)| self.input_serials.is_recent(*serial));
+                if !from_input {
+                    debug!("Activation token without recent input");
+                }
+                from_input && self.space.element_location(&window).is_some()
+            },
+            Activation::Urgent => false,
+            Activation::None => {
+                debug!("Ignoring activation of {:?}", self.window_info(&window));
+                return;
+            },
+        };
+        if focus {
+            debug!("Activating window {:?}", self.window_info(&window));
+            self.activate_window(&window);
+        } else {
+            self.set_urgent(&window, true);
+        }
+    }
+
+    fn destroy_activation(&mut self,
+                          _token: XdgActivationToken,
+                          _token_data: XdgActivationTokenData,
+                          _surface: WlSurface) {}
+}
+
+impl TwmState {
+    /// Focuses the newly mapped `window` or marks it urgent, keeping it from stealing the
+    /// focus from another window unless the config allows it
+    pub fn focus_new_window(&mut self, window: &Window) {
+        let WindowInfo { app_id, title } = self.window_info(window);
+        let rule = self.config.window_rules
+            .iter()
+            .filter(|rule| rule.matches(app_id.as_deref(), title.as_deref()))
+            .filter_map(|rule| rule.focus)
+            .last();
+        let Some(focused) = self.focused_window().filter(|focused| focused != window) else {
+            // Nothing to steal the focus from
+            if rule != Some(false) && self.space.element_location(window).is_some() {
+                self.focus_window(Some(window));
+            }
+            return;
+        };
+
+        let focus = rule.unwrap_or_else(|| match self.config.focus_on_window_activation {
+            Activation::Focus => true,
+            // Dialogs and further windows of the app in use
+            Activation::Smart => focused.toplevel().wl_surface().client() == window.toplevel().wl_surface().client(),
+            Activation::Urgent | Activation::None => false,
+        });
+        if focus {
+            debug!("Focusing new window {:?}", app_id);
+            self.activate_window(window);
+        } else if self.config.focus_on_window_activation != Activation::None {
+            self.set_urgent(window, true);
+        }
+    }
+
+    /// Window asks for attention
+    pub fn is_urgent(&self, window: &Window) -> bool {
+        window.user_data().get::<Urgent>().map_or(false, |urgent| urgent.0.get())
+    }
+
+    /// Marks `window` as asking for attention or clears the mark
+    pub fn set_urgent(&mut self, window: &Window, urgent: bool) {
+        window.user_data().insert_if_missing(Urgent::default);
+        let flag = &window.user_data().get::<Urgent>().expect("Urgency available").0;
+        if flag.get() != urgent {
+            debug!("Window {:?} urgent: {}", self.window_info(window), urgent);
+            flag.set(urgent);
+            self.needs_redraw = true;
+            let change = if urgent { ipc::WindowChange::Urgent } else { ipc::WindowChange::UrgentCleared };
+            let window = self.window_info(window);
+            self.emit_ipc_event(ipc::Event::Window { change, window });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_serial_is_recent_without_input() {
+        let serials = InputSerials::default();
+        assert!(!serials.is_recent(Serial::from(1)));
+    }
+
+    #[test]
+    fn input_since_the_focus_change_is_recent() {
+        let mut serials = InputSerials::default();
+        serials.input(Serial::from(3));
+        // Press of the click focusing a window, then its release
+        serials.input(Serial::from(5));
+        serials.focus_changed(Serial::from(6));
+        serials.input(Serial::from(7));
+        assert!(!serials.is_recent(Serial::from(3)));
+        assert!(!serials.is_recent(Serial::from(5)));
+        assert!(serials.is_recent(Serial::from(7)));
+        // Not handed out yet
+        assert!(!serials.is_recent(Serial::from(8)));
+    }
+
+    #[test]
+    fn focus_change_without_input_ends_recent_input() {
+        let mut serials = InputSerials::default();
+        serials.input(Serial::from(2));
+        serials.focus_changed(Serial::from(4));
+        assert!(!serials.is_recent(Serial::from(2)));
+        assert!(!serials.is_recent(Serial::from(4)));
+        serials.input(Serial::from(5));
+        assert!(serials.is_recent(Serial::from(5)));
+    }
+}
@@ -0,0 +1,190 @@
+//! Optional animations of windows: they fade in when they are first drawn, fade out
+//! before they are asked to close and glide to the geometries the layout gives them.
+//!
+//! The real geometry of a window changes right away, only what is drawn is interpolated.
+//! Moving windows are drawn scaled from the size they had to the size they get, until the
+//! client caught up with the new size. Windows closing on their own vanish at once, only
+//! closes through twm can fade out first.
+
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use smithay::{
+    desktop::Window,
+    reexports::calloop::{
+        timer::{TimeoutAction, Timer},
+        LoopHandle,
+    },
+    utils::{Logical, Rectangle},
+};
+use tracing::debug;
+
+use crate::{content_type, TwmLoopData, TwmState};
+
+/// Time between two animation frames
+const FRAME_INTERVAL: Duration = Duration::from_millis(16);
+/// Time after its close request until a window that is still open is shown again
+const CLOSE_GRACE: Duration = Duration::from_secs(1);
+
+/// Move of a window from one geometry to another
+#[derive(Debug, Clone, Copy)]
+struct Movement {
+    from: Rectangle<i32, Logical>,
+    to: Rectangle<i32, Logical>,
+    start: Instant,
+}
+
+/// Animation state of a window, kept in its user data
+#[derive(Debug, Default)]
+struct WindowAnimation {
+    /// When the window was first drawn
+    opened: Cell<Option<Instant>>,
+    /// Last move the layout started, also once it finished
+    movement: Cell<Option<Movement>>,
+    /// When the window started fading out and whether the close request was sent since
+    closing: Cell<Option<(Instant, bool)>>,
+}
+
+fn window_animation(window: &Window) -> &WindowAnimation {
+    window.user_data().insert_if_missing(WindowAnimation::default);
+    window.user_data().get::<WindowAnimation>().expect("Window animation available")
+}
+
+/// Share of `duration` passed since `start`, 1 once it is over
+fn progress(start: Instant, duration: Duration) -> f64 {
+    if duration.is_zero() {
+        return 1.0;
+    }
+    (start.elapsed().as_secs_f64() / duration.as_secs_f64()).min(1.0)
+}
+
+fn lerp(from: i32, to: i32, t: f64) -> i32 {
+    from + ((to - from) as f64 * t).round() as i32
+}
+
+impl TwmState {
+    /// Starts moving `window` to `geometry` if that is a new target, called whenever the
+    /// layout places the window
+    pub fn animate_move(&self, window: &Window, geometry: Rectangle<i32, Logical>) {
+        let config = &self.config.animations;
+        if !config.enabled {
+            return;
+        }
+        let animation = window_animation(window);
+        if animation.movement.get().map_or(false, |movement| movement.to == geometry) {
+            return;
+        }
+        // Windows shown for the first time appear in place
+        let from = self.animated_geometry(window)
+            .or_else(|| self.space.element_geometry(window))
+            .unwrap_or(geometry);
+        animation.movement.set(Some(Movement { from, to: geometry, start: Instant::now() }));
+    }
+
+    /// Geometry `window` is drawn at while it moves, `None` when it is drawn where it is
+    pub fn animated_geometry(&self, window: &Window) -> Option<Rectangle<i32, Logical>> {
+        let config = &self.config.animations;
+        if !config.enabled || content_type::shows_media(window) {
+            return None;
+        }
+        let movement = window.user_data().get::<WindowAnimation>()?.movement.get()?;
+        let t = progress(movement.start, Duration::from_millis(config.move_duration));
+        if t >= 1.0 {
+            return None;
+        }
+        let t = config.easing.apply(t);
+        let (from, to) = (movement.from, movement.to);
+        Some(Rectangle::from_loc_and_size(
+            (lerp(from.loc.x, to.loc.x, t), lerp(from.loc.y, to.loc.y, t)),
+            (lerp(from.size.w, to.size.w, t).max(1), lerp(from.size.h, to.size.h, t).max(1))))
+    }
+
+    /// Alpha of `window` fading in or out, 1 without animation. The fade in starts with the
+    /// first call.
+    pub fn animation_alpha(&self, window: &Window) -> f32 {
+        let config = &self.config.animations;
+        if !config.enabled || content_type::shows_media(window) {
+            return 1.0;
+        }
+        let animation = window_animation(window);
+        let opened = animation.opened.get().unwrap_or_else(|| {
+            let now = Instant::now();
+            animation.opened.set(Some(now));
+            now
+        });
+        let fade_in = config.easing.apply(progress(opened, Duration::from_millis(config.open_duration)));
+        let fade_out = animation.closing.get().map_or(0.0, |(start, _)| {
+            config.easing.apply(progress(start, Duration::from_millis(config.close_duration)))
+        });
+        (fade_in * (1.0 - fade_out)) as f32
+    }
+
+    /// Asks `window` to close, after fading it out if animations are enabled
+    pub fn close_window(&mut self, window: &Window) {
+        let animations = &self.config.animations;
+        if !animations.enabled || animations.close_duration == 0 || content_type::shows_media(window) {
+            window.toplevel().send_close();
+            return;
+        }
+        debug!("Fading out {:?}", self.window_info(window));
+        window_animation(window).closing.set(Some((Instant::now(), false)));
+        self.needs_redraw = true;
+    }
+
+    /// Keeps frames coming while animations run and sends the close requests of windows
+    /// that faded out. Returns the time until the next check.
+    fn tick_animations(&mut self) -> Duration {
+        let config = &self.config.animations;
+        if !config.enabled {
+            return FRAME_INTERVAL;
+        }
+        let close_duration = Duration::from_millis(config.close_duration);
+        let open_duration = Duration::from_millis(config.open_duration);
+        let move_duration = Duration::from_millis(config.move_duration);
+
+        let mut moving = false;
+        let mut fading = false;
+        for window in self.space.elements() {
+            let Some(animation) = window.user_data().get::<WindowAnimation>() else {
+                continue;
+            };
+            moving |= animation.movement.get().map_or(false, |movement| movement.start.elapsed() < move_duration);
+            fading |= animation.opened.get().map_or(false, |opened| opened.elapsed() < open_duration);
+
+            let Some((start, close_sent)) = animation.closing.get() else {
+                continue;
+            };
+            let elapsed = start.elapsed();
+            fading |= elapsed < close_duration;
+            if elapsed >= close_duration + CLOSE_GRACE {
+                debug!("Window still open after its close request");
+                animation.closing.set(None);
+                fading = true;
+            } else if elapsed >= close_duration && !close_sent {
+                window.toplevel().send_close();
+                animation.closing.set(Some((start, true)));
+            }
+        }
+
+        if moving || fading {
+            self.needs_redraw = true;
+        }
+        // The damage of surfaces doesn't cover their changing alpha
+        if fading {
+            self.full_redraw = self.space.outputs().cloned().collect();
+        }
+        FRAME_INTERVAL
+    }
+}
+
+/// Inserts the timer driving animations into the event loop
+pub fn init(handle: &LoopHandle<'static, TwmLoopData>) -> Result<()> {
+    handle
+        .insert_source(Timer::from_duration(FRAME_INTERVAL), |_, _, data| {
+            TimeoutAction::ToDuration(data.state.tick_animations())
+        })
+        .map_err(|err| err.error)
+        .context("Failed to insert the animation timer into the event loop")?;
+    Ok(())
+}
@@ -0,0 +1,131 @@
+//! Backend without any window, display or gpu, for running twm in automated tests.
+//!
+//! Frames of a single virtual output are rendered in software into a buffer in memory,
+//! which screencopy clients can read back. There are no input devices, a test harness
+//! connects its clients through `WAYLAND_DISPLAY` and drives the seat with the synthetic
+//! input requests of the IPC socket, whose queries also expose the window and focus state.
+
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use smithay::{
+    backend::{
+        allocator::Fourcc,
+        renderer::{damage::OutputDamageTracker, pixman::PixmanRenderer, Bind, Offscreen},
+    },
+    output::{Mode, Output, PhysicalProperties, Subpixel},
+    reexports::{
+        calloop::{
+            timer::{TimeoutAction, Timer},
+            EventLoop,
+        },
+        pixman::Image,
+        wayland_protocols::wp::presentation_time::server::wp_presentation_feedback,
+        wayland_server::Display,
+    },
+    utils::{Physical, Size},
+};
+use tracing::{info, warn};
+
+use crate::{backend::Backend, output::reconfigure_output, render, TwmLoopData, TwmState};
+
+/// Size of the virtual output unless another one is given on the command line
+pub const DEFAULT_SIZE: (i32, i32) = (1920, 1080);
+/// Refresh rate of the virtual output in mHz
+const REFRESH: i32 = 60_000;
+/// Shortest time between two frames, so constantly redrawing clients don't spin
+const FRAME_INTERVAL: Duration = Duration::from_millis(16);
+
+/// Backend rendering a virtual output into memory
+pub struct HeadlessData {
+    renderer: PixmanRenderer,
+    /// Buffer the frames are rendered into, reused for every frame
+    buffer: Image<'static, 'static>,
+    damage_tracker: OutputDamageTracker,
+    output: Output,
+    last_frame: Option<Instant>,
+}
+
+impl HeadlessData {
+    pub fn render(&mut self, state: &mut TwmState) {
+        // The frame timer renders once the interval passed
+        if self.last_frame.map_or(false, |last_frame| last_frame.elapsed() < FRAME_INTERVAL) {
+            return;
+        }
+        state.needs_redraw = false;
+        self.last_frame = Some(Instant::now());
+
+        if let Err(err) = self.renderer.bind(self.buffer.clone()) {
+            warn!("Failed to bind the output buffer: {:?}", err);
+            return;
+        }
+        // The single buffer always holds the previous frame
+        let age = if state.take_full_redraw(&self.output) || state.has_pending_screencopy(&self.output) { 0 } else { 1 };
+        let (damage, states) = match state.render_output(&mut self.renderer, &self.output, age, &mut self.damage_tracker) {
+            Ok(result) => result,
+            Err(err) => {
+                warn!("Failed to render output: {:?}", err);
+                return;
+            }
+        };
+        state.finish_screencopy(&mut self.renderer, &self.output, false);
+
+        if damage.is_some() {
+            state
+                .take_presentation_feedback(&self.output, &states)
+                .presented(state.clock.now(), render::refresh_interval(&self.output), 0, wp_presentation_feedback::Kind::empty());
+        }
+
+        state.send_frames(&self.output);
+    }
+}
+
+/// Starts the backend with a virtual output of `size` physical pixels
+pub fn init(event_loop: &mut EventLoop<TwmLoopData>,
+            display: &mut Display<TwmState>,
+            state: &mut TwmState,
+            size: (i32, i32)) -> Result<Backend> {
+    let mut renderer = PixmanRenderer::new().context("Failed to create the software renderer")?;
+    let buffer_size = Size::from(size);
+    let buffer: Image<'static, 'static> = renderer
+        .create_buffer(Fourcc::Argb8888, buffer_size)
+        .map_err(|err| anyhow!("Failed to create the output buffer: {:?}", err))?;
+
+    let output = Output::new(
+        "HEADLESS-1".to_string(),
+        PhysicalProperties {
+            size: (0, 0).into(),
+            subpixel: Subpixel::Unknown,
+            make: "Twm".into(),
+            model: "Headless".into(),
+        }
+    );
+    let mode = Mode {
+        size: Size::<i32, Physical>::from(size),
+        refresh: REFRESH,
+    };
+    info!("Using headless output with mode {:?}", mode);
+
+    let _global = output.create_global::<TwmState>(&display.handle());
+    let transform = state.output_transform(&output);
+    reconfigure_output(&output, Some(mode), Some(transform), None, None);
+    state.add_output(&output);
+
+    // Frames postponed by the frame interval still get rendered without any other event
+    event_loop
+        .handle()
+        .insert_source(Timer::from_duration(FRAME_INTERVAL), |_, _, data| {
+            data.backend.render_if_needed(&mut data.state);
+            TimeoutAction::ToDuration(FRAME_INTERVAL)
+        })
+        .map_err(|err| err.error)
+        .context("Failed to insert the frame timer into the event loop")?;
+
+    Ok(Backend::Headless(HeadlessData {
+        renderer,
+        buffer,
+        damage_tracker: OutputDamageTracker::from_output(&output),
+        output,
+        last_frame: None,
+    }))
+}
@@ -0,0 +1,70 @@
+//! Graphics and input backends driving the compositor.
+//!
+//! A backend creates the outputs it renders to, feeds its input events into
+//! [`TwmState::process_input_event`](crate::TwmState::process_input_event) and renders
+//! through [`TwmState::render_output`](crate::TwmState::render_output), so all window
+//! management stays backend independent.
+
+use crate::TwmState;
+
+pub mod headless;
+pub mod udev;
+pub mod winit;
+pub mod x11;
+
+/// Backend selected at startup
+pub enum Backend {
+    /// Nested inside another Wayland or X11 session in a winit window
+    Winit(winit::WinitData),
+    /// Nested inside an X11 session in an X11 window
+    X11(x11::X11Data),
+    /// Directly on a TTY through DRM and libinput
+    Udev(udev::UdevData),
+    /// Without window or display, rendering into memory
+    Headless(headless::HeadlessData),
+}
+
+impl Backend {
+    /// Renders a new frame if anything changed since the last one, called once per event
+    /// loop iteration so an idle compositor does not draw at all
+    pub fn render_if_needed(&mut self, state: &mut TwmState) {
+        // Nested windows are simply not redrawn while blanked
+        if let Backend::Udev(udev) = self {
+            udev.set_blanked(state.idle.blanked);
+        }
+        if !state.needs_redraw || state.idle.blanked {
+            return;
+        }
+        match self {
+            Backend::Winit(winit) => winit.render(state),
+            Backend::X11(x11) => x11.render(state),
+            Backend::Udev(udev) => udev.render(state),
+            Backend::Headless(headless) => headless.render(state),
+        }
+    }
+}
+
+/// Kind of backend to start
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendKind {
+    Winit,
+    X11,
+    /// Falls back to software rendering when the gpu can't be used for OpenGL ES
+    Udev,
+    /// Like `Udev`, always rendering in software with pixman
+    Pixman,
+    Headless,
+}
+
+impl BackendKind {
+    /// Backend requested via `TWM_BACKEND`, `winit` when unset
+    pub fn from_env() -> Self {
+        match std::env::var("TWM_BACKEND").as_deref() {
+            Ok("x11") => Self::X11,
+            Ok("udev") | Ok("drm") | Ok("tty") => Self::Udev,
+            Ok("pixman") => Self::Pixman,
+            Ok("headless") => Self::Headless,
+            _ => Self::Winit,
+        }
+    }
+}
@@ -0,0 +1,555 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, Context, Result};
+use smithay::{
+    backend::{
+        allocator::{
+            dmabuf::Dmabuf,
+            gbm::{GbmAllocator, GbmBufferFlags, GbmDevice},
+            Format,
+            Fourcc,
+        },
+        drm::{DrmDevice, DrmDeviceFd, DrmEvent, GbmBufferedSurface},
+        egl::{EGLContext, EGLDisplay},
+        libinput::{LibinputInputBackend, LibinputSessionInterface},
+        renderer::{
+            damage::OutputDamageTracker,
+            gles::GlesRenderer,
+            pixman::PixmanRenderer,
+            Bind,
+            ExportMem,
+            ImportAll,
+            ImportDma,
+            ImportMem,
+            Renderer,
+        },
+        session::{libseat::LibSeatSession, Event as SessionEvent, Session},
+        udev::{all_gpus, primary_gpu, UdevBackend, UdevEvent},
+    },
+    desktop::utils::OutputPresentationFeedback,
+    output::{Mode, Output, PhysicalProperties, Subpixel},
+    reexports::{
+        calloop::EventLoop,
+        drm::control::{connector, crtc, Device as ControlDevice, Mode as DrmMode, ModeTypeFlags},
+        input::Libinput,
+        nix::fcntl::OFlag,
+        wayland_protocols::wp::presentation_time::server::wp_presentation_feedback,
+        wayland_server::{backend::GlobalId, Display, DisplayHandle},
+    },
+    utils::DeviceFd,
+};
+use smithay_drm_extras::edid::EdidInfo;
+use tracing::{debug, info, warn};
+
+use crate::{backend::Backend, config::OutputMode, output::reconfigure_output, render, TwmLoopData, TwmState};
+
+/// Color formats tried for the scanout buffers, in order of preference
+const SUPPORTED_FORMATS: &[Fourcc] = &[Fourcc::Argb8888, Fourcc::Xrgb8888];
+/// Times in a row the renderer is recreated after failing frames before giving up
+const MAX_RENDERER_RESETS: u32 = 3;
+
+/// Backend driving the outputs of a single GPU through DRM, with input from libinput
+pub struct UdevData {
+    session: LibSeatSession,
+    libinput: Libinput,
+    drm: DrmDevice,
+    gbm: GbmDevice<DrmDeviceFd>,
+    renderer: UdevRenderer,
+    renderer_formats: HashSet<Format>,
+    display_handle: DisplayHandle,
+    surfaces: HashMap<crtc::Handle, OutputSurface>,
+    /// Displays are turned off through DPMS
+    blanked: bool,
+    /// Renderer recreations since the last frame that rendered
+    renderer_resets: u32,
+}
+
+/// Renderer drawing the frames of all outputs
+enum UdevRenderer {
+    Gles(GlesRenderer),
+    /// Software rendering into scanout buffers mapped into memory, for gpus without
+    /// OpenGL ES support
+    Pixman(PixmanRenderer),
+}
+
+/// A connected display driven by one crtc
+struct OutputSurface {
+    connector: connector::Handle,
+    /// Queued frames carry the presentation feedback of their surfaces
+    surface: GbmBufferedSurface<GbmAllocator<DrmDeviceFd>, OutputPresentationFeedback>,
+    output: Output,
+    global: GlobalId,
+    damage_tracker: OutputDamageTracker,
+    /// Something changed on the output since its last frame
+    dirty: bool,
+    /// A frame was queued and its vblank did not arrive yet
+    frame_pending: bool,
+}
+
+impl UdevData {
+    /// Renders all outputs that changed and are ready for a new frame
+    pub fn render(&mut self, state: &mut TwmState) {
+        if self.blanked {
+            return;
+        }
+        if state.needs_redraw {
+            state.needs_redraw = false;
+            self.surfaces.values_mut().for_each(|surface| surface.dirty = true);
+        }
+
+        let mut failed = false;
+        for surface in self.surfaces.values_mut() {
+            // The next frame is rendered once the pending one was presented
+            if surface.dirty && !surface.frame_pending {
+                let rendered = match &mut self.renderer {
+                    UdevRenderer::Gles(renderer) => surface.render(renderer, state, true),
+                    UdevRenderer::Pixman(renderer) => surface.render(renderer, state, false),
+                };
+                failed |= !rendered;
+            }
+        }
+
+        if !failed {
+            self.renderer_resets = 0;
+        } else if matches!(self.renderer, UdevRenderer::Gles(_)) {
+            self.recreate_renderer(state);
+        }
+    }
+
+    /// Replaces the OpenGL ES renderer after it failed to render, as its context may have been
+    /// lost, e.g. by the gpu resetting across a suspend. Client buffers are imported into the
+    /// new renderer again when they are drawn next, their textures belong to the old one.
+    fn recreate_renderer(&mut self, state: &mut TwmState) {
+        if self.renderer_resets >= MAX_RENDERER_RESETS {
+            warn!("Renderer still failing after {} recreations, not recreating it again", MAX_RENDERER_RESETS);
+            return;
+        }
+        self.renderer_resets += 1;
+        warn!("Rendering failed, recreating the renderer in case its context was lost");
+
+        match gles_renderer(&self.gbm) {
+            Ok((renderer, renderer_formats)) => {
+                self.renderer = renderer;
+                self.renderer_formats = renderer_formats;
+                for surface in self.surfaces.values_mut() {
+                    // Whatever the lost context drew into the buffers is undefined
+                    surface.surface.reset_buffers();
+                    surface.damage_tracker = OutputDamageTracker::from_output(&surface.output);
+                    surface.frame_pending = false;
+                    surface.dirty = true;
+                }
+                state.needs_redraw = true;
+            },
+            Err(err) => warn!("Failed to recreate the renderer, retrying with the next frame: {:#}", err),
+        }
+    }
+
+    /// Handles the vblank of `crtc`, the frame queued on it reached the screen
+    fn on_vblank(&mut self, crtc: crtc::Handle, state: &mut TwmState) {
+        let Some(surface) = self.surfaces.get_mut(&crtc) else {
+            return;
+        };
+        surface.frame_pending = false;
+        match surface.surface.frame_submitted() {
+            Ok(Some(feedback)) => feedback.presented(
+                state.clock.now(),
+                render::refresh_interval(&surface.output),
+                0,
+                wp_presentation_feedback::Kind::Vsync),
+            Ok(None) => {},
+            Err(err) => warn!("Failed to mark the frame as submitted: {}", err),
+        }
+        // Only keep flipping while something changes
+        self.render(state);
+    }
+
+    /// Turns all displays off or back on
+    pub fn set_blanked(&mut self, blanked: bool) {
+        if self.blanked == blanked {
+            return;
+        }
+        self.blanked = blanked;
+
+        for surface in self.surfaces.values_mut() {
+            if let Err(err) = set_dpms(&self.drm, surface.connector, !blanked) {
+                warn!("Failed to set dpms of {}: {:#}", surface.output.name(), err);
+            }
+            if !blanked {
+                surface.surface.reset_buffers();
+                surface.frame_pending = false;
+                surface.dirty = true;
+            }
+        }
+    }
+
+    /// Switches to another virtual terminal if one was requested
+    fn apply_vt_switch(&mut self, state: &mut TwmState) {
+        if let Some(vt) = state.requested_vt.take() {
+            info!("Switching to vt {}", vt);
+            if let Err(err) = self.session.change_vt(vt) {
+                warn!("Failed to switch to vt {}: {}", vt, err);
+            }
+        }
+    }
+
+    /// Creates outputs for newly connected displays and removes those of unplugged ones
+    fn scan_connectors(&mut self, state: &mut TwmState) {
+        let resources = match self.drm.resource_handles() {
+            Ok(resources) => resources,
+            Err(err) => {
+                warn!("Failed to query drm resources: {}", err);
+                return;
+            }
+        };
+        let connectors: Vec<connector::Info> = resources
+            .connectors()
+            .iter()
+            .filter_map(|handle| self.drm.get_connector(*handle, true).ok())
+            .collect();
+
+        let unplugged: Vec<crtc::Handle> = self.surfaces
+            .iter()
+            .filter(|(_, surface)| !connectors
+                .iter()
+                .any(|info| info.handle() == surface.connector && info.state() == connector::State::Connected))
+            .map(|(crtc, _)| *crtc)
+            .collect();
+        for crtc in unplugged {
+            let surface = self.surfaces.remove(&crtc).expect("Surface of unplugged connector");
+            state.remove_output(&surface.output);
+            self.display_handle.remove_global::<TwmState>(surface.global);
+        }
+
+        for info in connectors.iter().filter(|info| info.state() == connector::State::Connected) {
+            if self.surfaces.values().any(|surface| surface.connector == info.handle()) {
+                continue;
+            }
+            let free_crtc = info
+                .encoders()
+                .iter()
+                .filter_map(|handle| self.drm.get_encoder(*handle).ok())
+                .flat_map(|encoder| resources.filter_crtcs(encoder.possible_crtcs()))
+                .find(|crtc| !self.surfaces.contains_key(crtc));
+            let Some(crtc) = free_crtc else {
+                warn!("No free crtc for connector {:?}", info.interface());
+                continue;
+            };
+            match self.create_surface(info, crtc, state) {
+                Ok(surface) => {
+                    self.surfaces.insert(crtc, surface);
+                },
+                Err(err) => warn!("Failed to set up connector {:?}: {:#}", info.interface(), err),
+            }
+        }
+
+        state.needs_redraw = true;
+    }
+
+    fn create_surface(&mut self,
+                      info: &connector::Info,
+                      crtc: crtc::Handle,
+                      state: &mut TwmState) -> Result<OutputSurface> {
+        let output_name = format!("{}-{}", info.interface().as_str(), info.interface_id());
+        // Outputs are also configured by make and model, which only the EDID tells
+        let (make, model) = EdidInfo::for_connector(&self.drm, info.handle())
+            .map(|edid| (edid.manufacturer, edid.model))
+            .unwrap_or_else(|| ("Unknown".into(), "Unknown".into()));
+        let configured = state.config
+            .output_config(&output_name, &make, &model)
+            .and_then(|config| config.mode)
+            .and_then(|configured| {
+                let mode = configured_mode(info, configured);
+                if mode.is_none() {
+                    warn!("Output {} doesn't support mode {:?}", output_name, configured);
+                }
+                mode
+            });
+        let drm_mode = configured
+            .or_else(|| info.modes().iter().find(|mode| mode.mode_type().contains(ModeTypeFlags::PREFERRED)).copied())
+            .or_else(|| info.modes().first().copied())
+            .context("Connected display has no modes")?;
+
+        let drm_surface = self.drm
+            .create_surface(crtc, drm_mode, &[info.handle()])
+            .context("Failed to create a drm surface")?;
+        let mut flags = GbmBufferFlags::RENDERING | GbmBufferFlags::SCANOUT;
+        if let UdevRenderer::Pixman(_) = self.renderer {
+            // Pixman can only draw into buffers it can map without a tiled layout
+            flags |= GbmBufferFlags::LINEAR;
+        }
+        let allocator = GbmAllocator::new(self.gbm.clone(), flags);
+        let surface = GbmBufferedSurface::new(drm_surface, allocator, SUPPORTED_FORMATS, self.renderer_formats.clone())
+            .context("Failed to create the scanout surface")?;
+
+        let (physical_width, physical_height) = info.size().unwrap_or((0, 0));
+        let output = Output::new(
+            output_name.clone(),
+            PhysicalProperties {
+                size: (physical_width as i32, physical_height as i32).into(),
+                subpixel: Subpixel::Unknown,
+                make,
+                model,
+            }
+        );
+        let mode = Mode::from(drm_mode);
+        info!("Using output {} with mode {:?}", output_name, mode);
+
+        let global = output.create_global::<TwmState>(&self.display_handle);
+        let transform = state.output_transform(&output);
+        reconfigure_output(&output, Some(mode), Some(transform), None, None);
+        state.add_output(&output);
+
+        Ok(OutputSurface {
+            connector: info.handle(),
+            surface,
+            damage_tracker: OutputDamageTracker::from_output(&output),
+            output,
+            global,
+            dirty: true,
+            frame_pending: false,
+        })
+    }
+}
+
+/// Mode of the connector with the configured resolution and the refresh rate closest to
+/// the configured one, or the highest refresh rate if none is configured
+fn configured_mode(info: &connector::Info, configured: OutputMode) -> Option<DrmMode> {
+    info.modes()
+        .iter()
+        .filter(|mode| {
+            let (width, height) = mode.size();
+            (width as i32, height as i32) == (configured.width, configured.height)
+        })
+        .min_by_key(|mode| match configured.refresh {
+            Some(refresh) => ((mode.vrefresh() as f64 - refresh).abs() * 1000.0) as i64,
+            None => -(mode.vrefresh() as i64),
+        })
+        .copied()
+}
+
+/// Sets the DPMS property of `connector`, the kernel translates it for atomic drivers
+fn set_dpms(drm: &DrmDevice, connector: connector::Handle, on: bool) -> Result<()> {
+    const DPMS_ON: u64 = 0;
+    const DPMS_OFF: u64 = 3;
+
+    let properties = drm.get_properties(connector).context("Failed to query connector properties")?;
+    let (handles, _) = properties.as_props_and_values();
+    let dpms = handles
+        .iter()
+        .copied()
+        .find(|&handle| drm
+            .get_property(handle)
+            .map(|info| info.name().to_bytes() == b"DPMS")
+            .unwrap_or(false))
+        .context("Connector has no DPMS property")?;
+
+    drm.set_property(connector, dpms, if on { DPMS_ON } else { DPMS_OFF })
+        .context("Failed to set the DPMS property")?;
+    Ok(())
+}
+
+/// OpenGL ES renderer of the gpu and the formats it can render into
+fn gles_renderer(gbm: &GbmDevice<DrmDeviceFd>) -> Result<(UdevRenderer, HashSet<Format>)> {
+    let egl_display = EGLDisplay::new(gbm.clone()).context("Failed to create an egl display")?;
+    let egl_context = EGLContext::new(&egl_display).context("Failed to create an egl context")?;
+    let renderer_formats = egl_context.dmabuf_render_formats().clone();
+    let renderer = unsafe { GlesRenderer::new(egl_context) }.context("Failed to create the renderer")?;
+    Ok((UdevRenderer::Gles(renderer), renderer_formats))
+}
+
+/// Software renderer and the formats it can render into
+fn pixman_renderer() -> Result<(UdevRenderer, HashSet<Format>)> {
+    let renderer = PixmanRenderer::new().context("Failed to create the software renderer")?;
+    let renderer_formats = renderer.dmabuf_formats().cloned().collect();
+    Ok((UdevRenderer::Pixman(renderer), renderer_formats))
+}
+
+impl OutputSurface {
+    /// Renders a frame with `renderer`, `bottom_up` if its framebuffer origin is at the bottom.
+    /// Returns false if the renderer failed.
+    fn render<R>(&mut self, renderer: &mut R, state: &mut TwmState, bottom_up: bool) -> bool
+    where
+        R: Renderer + ImportAll + ImportMem + ExportMem + Bind<Dmabuf>,
+        <R as Renderer>::TextureId: Clone + 'static,
+    {
+        self.dirty = false;
+
+        let (dmabuf, age) = match self.surface.next_buffer() {
+            Ok(buffer) => buffer,
+            Err(err) => {
+                warn!("Failed to get the next scanout buffer: {}", err);
+                return true;
+            }
+        };
+
+        if let Err(err) = renderer.bind(dmabuf) {
+            warn!("Failed to bind scanout buffer: {:?}", err);
+            return false;
+        }
+
+        // Captures need the whole frame in the buffer, not only the damaged parts
+        let age = if state.take_full_redraw(&self.output) || state.has_pending_screencopy(&self.output) { 0 } else { age as usize };
+        let (damage, states) = match state.render_output(renderer, &self.output, age, &mut self.damage_tracker) {
+            Ok(result) => result,
+            Err(err) => {
+                warn!("Failed to render output: {:?}", err);
+                return false;
+            }
+        };
+        state.finish_screencopy(renderer, &self.output, bottom_up);
+
+        // Nothing changed on screen, the buffer is reused for the next frame instead of
+        // flipping to an identical one
+        if damage.is_some() {
+            let feedback = state.take_presentation_feedback(&self.output, &states);
+            match self.surface.queue_buffer(None, damage, feedback) {
+                Ok(()) => self.frame_pending = true,
+                Err(err) => warn!("Failed to queue scanout buffer: {}", err),
+            }
+        }
+
+        state.send_frames(&self.output);
+        true
+    }
+}
+
+/// Starts the backend, rendering in software right away if `software` is set and
+/// otherwise only if OpenGL ES fails
+pub fn init(event_loop: &mut EventLoop<TwmLoopData>,
+            display: &mut Display<TwmState>,
+            state: &mut TwmState,
+            software: bool) -> Result<Backend> {
+    let (mut session, notifier) = LibSeatSession::new().context("Failed to create a libseat session")?;
+
+    let gpu_path = primary_gpu(&session.seat())
+        .context("Failed to query the primary gpu")?
+        .or_else(|| all_gpus(session.seat()).ok()?.into_iter().next())
+        .context("No gpu found")?;
+    info!("Using gpu {:?}", gpu_path);
+
+    let fd = session
+        .open(&gpu_path, OFlag::O_RDWR | OFlag::O_CLOEXEC | OFlag::O_NOCTTY | OFlag::O_NONBLOCK)
+        .context("Failed to open the gpu")?;
+    let fd = DrmDeviceFd::new(DeviceFd::from(fd));
+
+    let (drm, drm_notifier) = DrmDevice::new(fd.clone(), true).context("Failed to initialize the drm device")?;
+    let gbm = GbmDevice::new(fd).context("Failed to initialize the gbm device")?;
+    let (renderer, renderer_formats) = if software {
+        info!("Rendering in software");
+        pixman_renderer()?
+    } else {
+        match gles_renderer(&gbm) {
+            Ok(renderer) => renderer,
+            Err(err) => {
+                warn!("OpenGL ES unavailable, falling back to software rendering: {:#}", err);
+                pixman_renderer()?
+            }
+        }
+    };
+    // Clients stick to shared memory buffers when rendering happens in software anyway
+    if let UdevRenderer::Gles(renderer) = &renderer {
+        state.create_dmabuf_global(renderer.dmabuf_formats().cloned().collect());
+    }
+
+    let mut libinput = Libinput::new_with_udev::<LibinputSessionInterface<LibSeatSession>>(session.clone().into());
+    libinput
+        .udev_assign_seat(&session.seat())
+        .map_err(|_| anyhow!("Failed to assign the libinput seat"))?;
+
+    event_loop
+        .handle()
+        .insert_source(LibinputInputBackend::new(libinput.clone()), |event, _, data| {
+            data.state.process_input_event(event);
+            if let Backend::Udev(udev) = &mut data.backend {
+                udev.apply_vt_switch(&mut data.state);
+            }
+        })
+        .map_err(|err| err.error)
+        .context("Failed to insert the libinput source into the event loop")?;
+
+    event_loop
+        .handle()
+        .insert_source(drm_notifier, |event, _, data| {
+            let Backend::Udev(udev) = &mut data.backend else {
+                return;
+            };
+            match event {
+                DrmEvent::VBlank(crtc) => udev.on_vblank(crtc, &mut data.state),
+                DrmEvent::Error(err) => warn!("Drm error: {}", err),
+            }
+        })
+        .map_err(|err| err.error)
+        .context("Failed to insert the drm source into the event loop")?;
+
+    event_loop
+        .handle()
+        .insert_source(notifier, |event, _, data| {
+            let Backend::Udev(udev) = &mut data.backend else {
+                return;
+            };
+            match event {
+                SessionEvent::PauseSession => {
+                    debug!("Session paused");
+                    udev.libinput.suspend();
+                    udev.drm.pause();
+                },
+                SessionEvent::ActivateSession => {
+                    debug!("Session activated");
+                    if udev.libinput.resume().is_err() {
+                        warn!("Failed to resume libinput");
+                    }
+                    if let Err(err) = udev.drm.activate() {
+                        warn!("Failed to activate the drm device: {}", err);
+                    }
+                    for surface in udev.surfaces.values_mut() {
+                        // The buffers may have been modified while another session owned the gpu
+                        surface.surface.reset_buffers();
+                        surface.frame_pending = false;
+                        surface.dirty = true;
+                    }
+                    // Displays may have been plugged in or out in the meantime
+                    udev.scan_connectors(&mut data.state);
+                    udev.render(&mut data.state);
+                },
+            }
+        })
+        .map_err(|err| err.error)
+        .context("Failed to insert the session source into the event loop")?;
+
+    // Connectors are rescanned whenever udev reports a change of the gpu
+    let udev_backend = UdevBackend::new(session.seat()).context("Failed to create the udev backend")?;
+    event_loop
+        .handle()
+        .insert_source(udev_backend, |event, _, data| {
+            let Backend::Udev(udev) = &mut data.backend else {
+                return;
+            };
+            if let UdevEvent::Changed { .. } = event {
+                debug!("Gpu changed, rescanning connectors");
+                udev.scan_connectors(&mut data.state);
+            }
+        })
+        .map_err(|err| err.error)
+        .context("Failed to insert the udev source into the event loop")?;
+
+    let mut udev = UdevData {
+        session,
+        libinput,
+        drm,
+        gbm,
+        renderer,
+        renderer_formats,
+        display_handle: display.handle(),
+        surfaces: HashMap::new(),
+        blanked: false,
+        renderer_resets: 0,
+    };
+    udev.scan_connectors(state);
+    if udev.surfaces.is_empty() {
+        warn!("No connected display found");
+    }
+    // Afterwards frames are rendered on demand, throttled by vblanks
+    udev.render(state);
+
+    Ok(Backend::Udev(udev))
+}
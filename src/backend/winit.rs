@@ -0,0 +1,166 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use smithay::{
+    backend::{
+        input::InputEvent,
+        renderer::{damage::OutputDamageTracker, gles::GlesRenderer, ImportDma},
+        winit::{self, WinitError, WinitEvent, WinitGraphicsBackend},
+    },
+    output::{Mode, Output, PhysicalProperties, Subpixel},
+    reexports::{
+        calloop::{
+            timer::{TimeoutAction, Timer},
+            EventLoop,
+        },
+        wayland_protocols::wp::presentation_time::server::wp_presentation_feedback,
+        wayland_server::Display,
+    },
+    utils::{Physical, Size, Transform},
+};
+use tracing::{info, warn};
+
+use crate::{backend::Backend, output::reconfigure_output, render, TwmLoopData, TwmState};
+
+/// How often winit is polled for new events, rendering happens independently on demand
+const EVENT_POLL_INTERVAL: Duration = Duration::from_millis(16);
+
+/// Nested backend rendering into a winit window
+pub struct WinitData {
+    backend: WinitGraphicsBackend<GlesRenderer>,
+    damage_tracker: OutputDamageTracker,
+    output: Output,
+}
+
+impl WinitData {
+    pub fn render(&mut self, state: &mut TwmState) {
+        state.needs_redraw = false;
+        if let Err(err) = self.backend.bind() {
+            // The window's surface may be gone for now, e.g. while it is recreated. The next
+            // frame is drawn in full, as nothing of the old one can be relied on.
+            warn!("Failed to bind the window surface: {}", err);
+            self.damage_tracker = damage_tracker(&self.output);
+            return;
+        }
+
+        // Captures need the whole frame in the buffer, not only the damaged parts
+        let age = if state.take_full_redraw(&self.output) || state.has_pending_screencopy(&self.output) { 0 } else { self.backend.buffer_age().unwrap_or(0) };
+        let (damage, states) = match state.render_output(self.backend.renderer(), &self.output, age, &mut self.damage_tracker) {
+            Ok(result) => result,
+            Err(err) => {
+                warn!("Failed to render output: {:?}", err);
+                self.damage_tracker = damage_tracker(&self.output);
+                return;
+            }
+        };
+        state.finish_screencopy(self.backend.renderer(), &self.output, true);
+
+        // Nothing changed on screen, the previous frame stays
+        if let Some(damage) = damage {
+            if let Err(err) = self.backend.submit(Some(&damage)) {
+                warn!("Failed to submit the frame: {}", err);
+                self.damage_tracker = damage_tracker(&self.output);
+                state.send_frames(&self.output);
+                return;
+            }
+            state
+                .take_presentation_feedback(&self.output, &states)
+                .presented(state.clock.now(), render::refresh_interval(&self.output), 0, wp_presentation_feedback::Kind::Vsync);
+        }
+
+        state.send_frames(&self.output);
+    }
+
+    /// Changes the output mode to the new size of the window
+    fn resize(&mut self, size: Size<i32, Physical>, state: &mut TwmState) {
+        info!("Window resized to {:?}", size);
+        let mode = Mode { size, refresh: 60_000 };
+        reconfigure_output(&self.output, Some(mode), None, None, None);
+        // Damage tracked for the old size doesn't apply to buffers of the new one
+        self.damage_tracker = damage_tracker(&self.output);
+        state.output_resized(&self.output);
+    }
+}
+
+/// Damage tracker rendering upside down, the way frames of the GL renderer end up upright
+/// in the window. The output itself stays untransformed, so clients and absolute pointer
+/// positions of the window see it as it is shown.
+fn damage_tracker(output: &Output) -> OutputDamageTracker {
+    let size = output.current_mode().map(|mode| mode.size).unwrap_or_default();
+    OutputDamageTracker::new(size, output.current_scale().fractional_scale(), Transform::Flipped180)
+}
+
+pub fn init(event_loop: &mut EventLoop<TwmLoopData>,
+            display: &mut Display<TwmState>,
+            state: &mut TwmState) -> Result<Backend> {
+    let (mut backend, mut winit_el) = winit::init::<GlesRenderer>()
+        // The window can only be presented through EGL, there is no software fallback when nested
+        .context("Failed to Initialize a graphics and input backend, the pixman backend works without OpenGL ES on a TTY")?;
+
+    let output = Output::new(
+        "winit".to_string(),
+        PhysicalProperties { 
+            size: (0, 0).into(), // initial size
+            subpixel: Subpixel::Unknown, // sub-pixel setting
+            make: "Twm".into(), // monitor manufacturer 
+            model: "Winit".into() // monitor model
+        }
+    );
+    
+    let mode = Mode {
+        size: backend.window_size().physical_size,
+        refresh: 60_000,
+    };
+
+    info!("window size {:?}", backend.window_size());
+
+    let _global = output.create_global::<TwmState>(&display.handle());
+    reconfigure_output(&output, Some(mode), Some(Transform::Normal), None, None);
+
+    state.add_output(&output);
+    state.create_dmabuf_global(backend.renderer().dmabuf_formats().cloned().collect());
+
+    let damage_tracker = damage_tracker(&output);
+
+    let winit_output = output.clone();
+    let timert = Timer::immediate();
+    event_loop.handle().insert_source(timert, move |_, _, data| {
+        let res = winit_el.dispatch_new_events(|event| match event {
+            WinitEvent::Input(input_event) => {
+                // All pointer positions of the window belong to its output
+                if let InputEvent::DeviceAdded { device } = &input_event {
+                    data.state.map_device_to_output(device, &winit_output);
+                }
+                data.state.process_input_event(input_event)
+            },
+            WinitEvent::Resized { size, .. } => {
+                if let Backend::Winit(winit) = &mut data.backend {
+                    winit.resize(size, &mut data.state);
+                }
+            },
+            // Also sent when the window was recreated, its surface has no content then
+            WinitEvent::Refresh => {
+                if let Backend::Winit(winit) = &mut data.backend {
+                    winit.damage_tracker = damage_tracker(&winit.output);
+                }
+                data.state.needs_redraw = true;
+            },
+            _ => {}
+        });
+
+        if let Err(WinitError::WindowClosed) = res { // if this happens our compositor got closed
+            data.state.ev_signal.stop(); // Since the compositor stopped we stop the whole event loop
+            return TimeoutAction::Drop;
+        } else if let Err(err) = res {
+            warn!("Failed to dispatch winit events: {}", err);
+        }
+
+        TimeoutAction::ToDuration(EVENT_POLL_INTERVAL)
+    }).map_err(|err| err.error).context("Failed to insert winit source into event loop")?;
+
+    Ok(Backend::Winit(WinitData {
+        backend,
+        damage_tracker,
+        output,
+    }))
+}
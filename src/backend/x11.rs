@@ -0,0 +1,186 @@
+//! Nested backend rendering into a window of an X11 session.
+//!
+//! Frames are rendered with OpenGL ES into dmabufs of the window's gpu and presented
+//! through the X11 Present extension. A new frame is only drawn after the previous one was
+//! presented, which paces rendering to the refresh of the X server.
+
+use anyhow::{Context, Result};
+use smithay::{
+    backend::{
+        allocator::{
+            dmabuf::DmabufAllocator,
+            gbm::{GbmAllocator, GbmBufferFlags, GbmDevice},
+        },
+        egl::{EGLContext, EGLDisplay},
+        input::InputEvent,
+        renderer::{damage::OutputDamageTracker, gles::GlesRenderer, Bind, ImportDma},
+        x11::{Window, WindowBuilder, X11Backend, X11Event, X11Surface},
+    },
+    output::{Mode, Output, PhysicalProperties, Subpixel},
+    reexports::{
+        calloop::EventLoop,
+        wayland_protocols::wp::presentation_time::server::wp_presentation_feedback,
+        wayland_server::Display,
+    },
+    utils::{DeviceFd, Physical, Size, Transform},
+};
+use tracing::{debug, info, warn};
+
+use crate::{backend::Backend, output::reconfigure_output, render, TwmLoopData, TwmState};
+
+/// Refresh rate of the window's output in mHz, X11 doesn't tell the rate of the screen
+const REFRESH: i32 = 60_000;
+
+/// Nested backend rendering into an X11 window
+pub struct X11Data {
+    surface: X11Surface,
+    /// The surface only holds on weakly to the window, which closes once dropped
+    _window: Window,
+    renderer: GlesRenderer,
+    damage_tracker: OutputDamageTracker,
+    output: Output,
+    /// A frame was submitted and not presented yet
+    frame_pending: bool,
+}
+
+impl X11Data {
+    pub fn render(&mut self, state: &mut TwmState) {
+        // The frame is drawn once the pending one was presented
+        if self.frame_pending {
+            return;
+        }
+        state.needs_redraw = false;
+
+        let (dmabuf, age) = match self.surface.buffer() {
+            Ok(buffer) => buffer,
+            Err(err) => {
+                warn!("Failed to get the next window buffer: {}", err);
+                return;
+            }
+        };
+        if let Err(err) = self.renderer.bind(dmabuf) {
+            warn!("Failed to bind the window buffer: {:?}", err);
+            return;
+        }
+
+        // Captures need the whole frame in the buffer, not only the damaged parts
+        let age = if state.take_full_redraw(&self.output) || state.has_pending_screencopy(&self.output) { 0 } else { age as usize };
+        let (damage, states) = match state.render_output(&mut self.renderer, &self.output, age, &mut self.damage_tracker) {
+            Ok(result) => result,
+            Err(err) => {
+                warn!("Failed to render output: {:?}", err);
+                return;
+            }
+        };
+        state.finish_screencopy(&mut self.renderer, &self.output, true);
+
+        // Nothing changed on screen, the buffer is reused for the next frame
+        if damage.is_some() {
+            match self.surface.submit() {
+                Ok(()) => {
+                    self.frame_pending = true;
+                    state
+                        .take_presentation_feedback(&self.output, &states)
+                        .presented(state.clock.now(), render::refresh_interval(&self.output), 0, wp_presentation_feedback::Kind::Vsync);
+                },
+                Err(err) => warn!("Failed to present the frame: {}", err),
+            }
+        }
+
+        state.send_frames(&self.output);
+    }
+
+    /// Changes the output mode to the new size of the window
+    fn resize(&mut self, size: Size<i32, Physical>, state: &mut TwmState) {
+        info!("Window resized to {:?}", size);
+        reconfigure_output(&self.output, Some(Mode { size, refresh: REFRESH }), None, None, None);
+        // Damage tracked for the old size doesn't apply to buffers of the new one
+        self.damage_tracker = OutputDamageTracker::from_output(&self.output);
+        state.output_resized(&self.output);
+    }
+}
+
+pub fn init(event_loop: &mut EventLoop<TwmLoopData>,
+            display: &mut Display<TwmState>,
+            state: &mut TwmState) -> Result<Backend> {
+    let backend = X11Backend::new().context("Failed to connect to the X server")?;
+    let handle = backend.handle();
+
+    // Buffers are allocated on the gpu the X server renders with
+    let (_node, fd) = handle.drm_node().context("Failed to get the gpu of the X server")?;
+    let gbm = GbmDevice::new(DeviceFd::from(fd)).context("Failed to initialize the gbm device")?;
+    let egl_display = EGLDisplay::new(gbm.clone()).context("Failed to create an egl display")?;
+    let egl_context = EGLContext::new(&egl_display).context("Failed to create an egl context")?;
+    let modifiers: Vec<_> = egl_context.dmabuf_render_formats().iter().map(|format| format.modifier).collect();
+
+    let window = WindowBuilder::new()
+        .title("twm")
+        .build(&handle)
+        .context("Failed to create the X11 window")?;
+    let allocator = DmabufAllocator(GbmAllocator::new(gbm, GbmBufferFlags::RENDERING));
+    let surface = handle
+        .create_surface(&window, allocator, modifiers.into_iter())
+        .context("Failed to create the X11 surface")?;
+    let renderer = unsafe { GlesRenderer::new(egl_context) }.context("Failed to create the renderer")?;
+
+    let output = Output::new(
+        "X11-1".to_string(),
+        PhysicalProperties {
+            size: (0, 0).into(),
+            subpixel: Subpixel::Unknown,
+            make: "Twm".into(),
+            model: "X11".into(),
+        }
+    );
+    let window_size = window.size();
+    let mode = Mode {
+        size: (window_size.w as i32, window_size.h as i32).into(),
+        refresh: REFRESH,
+    };
+    info!("Using X11 window with mode {:?}", mode);
+
+    let _global = output.create_global::<TwmState>(&display.handle());
+    reconfigure_output(&output, Some(mode), Some(Transform::Normal), None, None);
+    state.add_output(&output);
+    state.create_dmabuf_global(renderer.dmabuf_formats().cloned().collect());
+
+    let x11_output = output.clone();
+    event_loop
+        .handle()
+        .insert_source(backend, move |event, _, data| match event {
+            X11Event::Input(input_event) => {
+                // All pointer positions of the window belong to its output
+                if let InputEvent::DeviceAdded { device } = &input_event {
+                    data.state.map_device_to_output(device, &x11_output);
+                }
+                data.state.process_input_event(input_event);
+            },
+            X11Event::Resized { new_size, .. } => {
+                if let Backend::X11(x11) = &mut data.backend {
+                    x11.resize((new_size.w as i32, new_size.h as i32).into(), &mut data.state);
+                }
+            },
+            X11Event::PresentCompleted { .. } => {
+                if let Backend::X11(x11) = &mut data.backend {
+                    x11.frame_pending = false;
+                }
+            },
+            X11Event::Refresh { .. } => data.state.needs_redraw = true,
+            X11Event::CloseRequested { .. } => {
+                debug!("X11 window closed");
+                data.state.ev_signal.stop();
+            },
+            _ => {},
+        })
+        .map_err(|err| err.error)
+        .context("Failed to insert the X11 source into the event loop")?;
+
+    Ok(Backend::X11(X11Data {
+        surface,
+        _window: window,
+        renderer,
+        damage_tracker: OutputDamageTracker::from_output(&output),
+        output,
+        frame_pending: false,
+    }))
+}
@@ -0,0 +1,88 @@
+//! Command line arguments, so twm can be started from a display manager session file.
+
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+
+use crate::backend::BackendKind;
+
+const USAGE: &str = "\
+Usage: twm [OPTIONS]
+
+Options:
+  -b, --backend <BACKEND>  Backend to start: winit (nested), x11 (nested in an X11
+                           session), udev (on a TTY), pixman (on a TTY without gpu
+                           acceleration) or headless (for tests), defaults to TWM_BACKEND
+                           or winit
+      --size <WxH>         Size of the headless output, defaults to 1920x1080
+      --config <PATH>      Config file to load instead of $XDG_CONFIG_HOME/twm/config.toml
+  -c, --command <COMMAND>  Command line to run once the compositor is up, may be repeated
+  -h, --help               Print this help
+  -V, --version            Print the version";
+
+/// Options given on the command line
+#[derive(Debug, Default)]
+pub struct Args {
+    pub backend: Option<BackendKind>,
+    pub config: Option<PathBuf>,
+    /// Size of the output of the headless backend
+    pub size: Option<(i32, i32)>,
+    pub commands: Vec<String>,
+}
+
+impl Args {
+    /// Parses the arguments of the process, `None` when only help or the version was asked
+    /// for, which is already printed then
+    pub fn parse() -> Result<Option<Self>> {
+        let mut args = Self::default();
+        let mut iter = std::env::args().skip(1);
+
+        while let Some(arg) = iter.next() {
+            // Both `--option value` and `--option=value`
+            let (name, inline_value) = match arg.split_once('=') {
+                Some((name, value)) if name.starts_with("--") => (name.to_string(), Some(value.to_string())),
+                _ => (arg, None),
+            };
+            let mut value = || {
+                inline_value.clone()
+                    .or_else(|| iter.next())
+                    .with_context(|| format!("Missing value for {}\n\n{}", name, USAGE))
+            };
+
+            match name.as_str() {
+                "-b" | "--backend" => {
+                    let backend = value()?;
+                    args.backend = Some(match backend.as_str() {
+                        "winit" => BackendKind::Winit,
+                        "x11" => BackendKind::X11,
+                        "udev" | "drm" | "tty" => BackendKind::Udev,
+                        "pixman" => BackendKind::Pixman,
+                        "headless" => BackendKind::Headless,
+                        _ => bail!("Unknown backend {:?}, expected winit, x11, udev, pixman or headless", backend),
+                    });
+                },
+                "--config" => args.config = Some(PathBuf::from(value()?)),
+                "--size" => {
+                    let size = value()?;
+                    let parsed = size
+                        .split_once('x')
+                        .and_then(|(width, height)| Some((width.parse::<i32>().ok()?, height.parse::<i32>().ok()?)))
+                        .filter(|&(width, height)| width > 0 && height > 0);
+                    args.size = Some(parsed.with_context(|| format!("Invalid size {:?}, expected WIDTHxHEIGHT", size))?);
+                },
+                "-c" | "--command" => args.commands.push(value()?),
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    return Ok(None);
+                },
+                "-V" | "--version" => {
+                    println!("twm {}", env!("CARGO_PKG_VERSION"));
+                    return Ok(None);
+                },
+                _ => bail!("Unknown argument {:?}\n\n{}", name, USAGE),
+            }
+        }
+
+        Ok(Some(args))
+    }
+}
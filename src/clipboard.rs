@@ -0,0 +1,159 @@
+//! Clipboard persistence, like wl-clip-persist: the contents of new client selections are
+//! read into memory and the compositor takes the selection over, so it outlives the
+//! client it was copied from.
+//!
+//! Only the configured mime types are read and offered afterwards. Selections with none
+//! of them, or more data than the configured maximum, stay with their client.
+
+use std::io::{ErrorKind, Read, Write};
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::os::unix::net::UnixStream;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use smithay::{
+    reexports::{
+        calloop::{generic::Generic, Interest, Mode, PostAction},
+        wayland_server::protocol::wl_data_source::WlDataSource,
+    },
+    wayland::data_device::{set_data_device_selection, with_source_metadata},
+};
+use tracing::{debug, warn};
+
+use crate::TwmState;
+
+/// Selection read from clients and served by the compositor
+#[derive(Debug, Default)]
+pub struct Clipboard {
+    /// Counts client selections, reads of replaced selections are dropped
+    generation: u64,
+    /// Contents of the current client selection read so far
+    read: Vec<(String, Arc<Vec<u8>>)>,
+    /// Mime types still being read
+    pending: usize,
+    /// Contents of the selection the compositor owns
+    served: Vec<(String, Arc<Vec<u8>>)>,
+}
+
+impl TwmState {
+    /// Starts reading a new client selection if clipboard persistence is enabled
+    pub fn persist_selection(&mut self, source: Option<WlDataSource>) {
+        self.clipboard.generation += 1;
+        self.clipboard.read.clear();
+        self.clipboard.pending = 0;
+        let (Some(source), true) = (source, self.config.clipboard.persist) else {
+            return;
+        };
+        let Ok(offered) = with_source_metadata(&source, |metadata| metadata.mime_types.clone()) else {
+            return;
+        };
+        let mime_types: Vec<String> = offered
+            .into_iter()
+            .filter(|mime_type| self.config.clipboard.mime_types.contains(mime_type))
+            .collect();
+        if mime_types.is_empty() {
+            debug!("No persisted mime type in the new selection");
+            return;
+        }
+
+        debug!("Reading selection as {:?}", mime_types);
+        let generation = self.clipboard.generation;
+        for mime_type in mime_types {
+            match self.read_selection(&source, mime_type, generation) {
+                Ok(()) => self.clipboard.pending += 1,
+                Err(err) => {
+                    warn!("Failed to read the selection: {:?}", err);
+                    self.clipboard.generation += 1;
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Asks the client of `source` to send its data as `mime_type` and reads it from the
+    /// event loop
+    fn read_selection(&mut self, source: &WlDataSource, mut mime_type: String, generation: u64) -> Result<()> {
+        let (stream, client_stream) = UnixStream::pair().context("Failed to create the selection socket")?;
+        stream.set_nonblocking(true).context("Failed to make the selection socket non blocking")?;
+        source.send(mime_type.clone(), client_stream.as_raw_fd());
+        // The client got its own copy with the request
+        drop(client_stream);
+
+        let max_size = self.config.clipboard.max_size;
+        let mut buffer = Vec::new();
+        self.loop_handle
+            .insert_source(Generic::new(stream, Interest::READ, Mode::Level), move |_, stream, data| {
+                let mut chunk = [0u8; 4096];
+                loop {
+                    match stream.read(&mut chunk) {
+                        Ok(0) => {
+                            let content = std::mem::take(&mut buffer);
+                            data.state.selection_read(generation, std::mem::take(&mut mime_type), content);
+                            return Ok(PostAction::Remove);
+                        },
+                        Ok(read) if buffer.len() + read > max_size => {
+                            debug!("Selection larger than {} bytes, not persisting it", max_size);
+                            data.state.selection_read_failed(generation);
+                            return Ok(PostAction::Remove);
+                        },
+                        Ok(read) => buffer.extend_from_slice(&chunk[..read]),
+                        Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                        Err(err) => {
+                            debug!("Failed to read the selection: {}", err);
+                            data.state.selection_read_failed(generation);
+                            return Ok(PostAction::Remove);
+                        }
+                    }
+                }
+                Ok(PostAction::Continue)
+            })
+            .map_err(|err| err.error)
+            .context("Failed to insert the selection socket into the event loop")?;
+        Ok(())
+    }
+
+    /// Stores the selection read as `mime_type`, the compositor takes the selection over once
+    /// all mime types were read
+    fn selection_read(&mut self, generation: u64, mime_type: String, content: Vec<u8>) {
+        if generation != self.clipboard.generation {
+            return;
+        }
+        self.clipboard.read.push((mime_type, Arc::new(content)));
+        self.clipboard.pending -= 1;
+        if self.clipboard.pending > 0 {
+            return;
+        }
+
+        let size: usize = self.clipboard.read.iter().map(|(_, content)| content.len()).sum();
+        if size > self.config.clipboard.max_size {
+            debug!("Selection larger than {} bytes, not persisting it", self.config.clipboard.max_size);
+            return;
+        }
+        debug!("Taking over the selection, {} bytes", size);
+        self.clipboard.served = std::mem::take(&mut self.clipboard.read);
+        let mime_types = self.clipboard.served.iter().map(|(mime_type, _)| mime_type.clone()).collect();
+        set_data_device_selection(&self.display_handle, &self.seat, mime_types, ());
+    }
+
+    fn selection_read_failed(&mut self, generation: u64) {
+        if generation == self.clipboard.generation {
+            // Leaves the selection with its client
+            self.clipboard.generation += 1;
+        }
+    }
+
+    /// Writes the persisted selection as `mime_type` to a pasting client. The write happens
+    /// on its own thread, so clients reading slowly don't hold up the compositor.
+    pub fn send_persisted_selection(&self, mime_type: &str, fd: OwnedFd) {
+        let Some((_, content)) = self.clipboard.served.iter().find(|(served, _)| served == mime_type) else {
+            debug!("Persisted selection not available as {}", mime_type);
+            return;
+        };
+        let content = content.clone();
+        std::thread::spawn(move || {
+            if let Err(err) = std::fs::File::from(fd).write_all(&content) {
+                debug!("Failed to send the selection: {}", err);
+            }
+        });
+    }
+}
@@ -0,0 +1,1010 @@
+//! Compositor configuration loaded from `$XDG_CONFIG_HOME/twm/config.toml`
+//! (`~/.config/twm/config.toml` when `XDG_CONFIG_HOME` is not set).
+//!
+//! Schema:
+//!
+//! ```toml
+//! # Space in pixels between tiled windows and between windows and the output edges
+//! inner_gap = 0
+//! outer_gap = 0
+//!
+//! # Keyboard focus moves to the window under the pointer, without raising it. Otherwise
+//! # windows are focused by clicking them.
+//! focus_follows_mouse = false
+//!
+//! # The `focus_left`, `focus_right`, `focus_up` and `focus_down` actions wrap around to
+//! # the other side of the output at its edges instead of keeping the focus
+//! focus_wrap = false
+//!
+//! # Layout workspaces start with: "master_stack", "monocle" or "spiral"
+//! default_layout = "master_stack"
+//!
+//! # Width in pixels of the border drawn around windows and its RGBA colors, the urgent
+//! # color marks windows asking for attention until they are focused
+//! border_width = 2
+//! focused_border_color = [0.32, 0.53, 0.84, 1.0]
+//! unfocused_border_color = [0.25, 0.25, 0.25, 1.0]
+//! urgent_border_color = [0.84, 0.27, 0.22, 1.0]
+//!
+//! # Second border between the window and the one above, like the child border of i3.
+//! # Both borders are reserved when tiling, the inner one is left out when its width is 0.
+//! inner_border_width = 0
+//! focused_inner_border_color = [0.2, 0.36, 0.6, 1.0]
+//! unfocused_inner_border_color = [0.16, 0.16, 0.16, 1.0]
+//! urgent_inner_border_color = [0.6, 0.18, 0.15, 1.0]
+//!
+//! # Height in pixels of the tab bar of tabbed containers and the TrueType font of its
+//! # titles, a few common system fonts are tried when left out
+//! tab_bar_height = 20
+//! tab_font = "/usr/share/fonts/TTF/DejaVuSans.ttf"
+//!
+//! # What happens when a client activates one of its windows, e.g. a link opened in a
+//! # browser, and when a new window opens while another one has the focus. "smart" focuses
+//! # windows on a shown workspace activated from input to the focused client and new
+//! # windows of the client with the focus, and marks all others urgent. "focus" always
+//! # switches to the window, "urgent" only marks it urgent and "none" leaves it alone. The
+//! # `focus` window rule overrides this for new windows.
+//! focus_on_window_activation = "smart"
+//!
+//! # Where new floating windows appear on their output: "center", "under_cursor", or
+//! # "smart" for the spot overlapping the other floating windows the least
+//! floating_placement = "center"
+//!
+//! # Distance in pixels the `move_*` actions move floating windows, tiled windows swap
+//! # places with their neighbour instead
+//! floating_move_step = 20
+//!
+//! # Opacity windows without focus are drawn with, e.g. 0.9 to dim them a little. Multiplies
+//! # the `opacity` window rules give windows.
+//! inactive_opacity = 1.0
+//!
+//! # Saves where windows are on shutdown and puts windows with the same app id back there
+//! # after the next start
+//! restore_session = false
+//!
+//! # Starts Xwayland for X11 clients, whose windows float above the tiled ones
+//! xwayland = true
+//!
+//! # Seconds without input until the outputs are turned off, never when left out
+//! idle_timeout = 600
+//!
+//! # Commands started once twm is up, as command lines or with separate arguments.
+//! # `delay` is in milliseconds after startup.
+//! autostart = [
+//!     "waybar",
+//!     { command = "swaybg", args = ["-i", "/usr/share/backgrounds/default.png"] },
+//!     { command = "nm-applet --indicator", delay = 2000 },
+//! ]
+//!
+//! # Settings of single outputs, keyed by output name or by make and model separated by
+//! # a space, a name taking precedence
+//! [outputs.eDP-1]
+//! # Integer or fractional scale factor
+//! scale = 1.5
+//! # Rotation of the output counterclockwise, for monitors mounted sideways or upside
+//! # down: "normal", "90", "180", "270", "flipped", "flipped_90", "flipped_180" or
+//! # "flipped_270". The flipped variants mirror the output horizontally first. Outputs of
+//! # the winit backend are always drawn upright.
+//! transform = "normal"
+//! # Resolution with an optional refresh rate in Hz, the closest supported one is picked.
+//! # The monitor's preferred mode is used when left out or not supported. Nested outputs
+//! # take the size of their window instead.
+//! mode = "2560x1440@144"
+//! # Location of the top left corner in the global space, in logical pixels. Outputs
+//! # without one are placed left to right after the rightmost configured output.
+//! position = [0, 0]
+//!
+//! [outputs."Dell Inc. DELL U2720Q"]
+//! position = [2560, 0]
+//!
+//! # Rules applied to new windows, and again whenever a window changes its app_id.
+//! # `app_id` has to match exactly and `title` has to be part of the title, rules without
+//! # either match all windows. All matching rules apply in order.
+//! [[window_rules]]
+//! app_id = "firefox"
+//! workspace = 2
+//!
+//! [[window_rules]]
+//! title = "Open File"
+//! floating = true
+//! placement = "under_cursor"
+//!
+//! [[window_rules]]
+//! app_id = "mpv"
+//! fullscreen = true
+//!
+//! [[window_rules]]
+//! app_id = "Alacritty"
+//! opacity = 0.95
+//!
+//! [[window_rules]]
+//! app_id = "org.keepassxc.KeePassXC"
+//! focus = true
+//!
+//! # Window toggled over the current workspace with `toggle_scratchpad`, the first window
+//! # with `app_id` becomes the scratchpad. `command` is started by `toggle_scratchpad` when
+//! # there is no scratchpad window yet.
+//! [scratchpad]
+//! app_id = "scratchpad"
+//! command = "alacritty --class scratchpad"
+//!
+//! # Terminals with one of the `terminals` app ids hide while a tiled window started
+//! # from them is open, the window takes their tile. Windows are matched to terminals by
+//! # their processes, which fails for programs that hand their window to another process.
+//! [swallow]
+//! enabled = false
+//! terminals = ["foot", "Alacritty", "kitty"]
+//!
+//! # Dragging floating windows from anywhere inside them while holding `modifier`, which
+//! # is written like the modifiers of keybindings. Buttons: "left", "right" or "middle".
+//! [mouse]
+//! modifier = "Super"
+//! move_button = "left"
+//! resize_button = "right"
+//!
+//! # Scrolling of mice and touchpads. `factor` scales all scroll distances, `wheel_step`
+//! # is the distance of one wheel click for wheels only reporting clicks and `natural`
+//! # inverts the direction, so content follows the fingers.
+//! [scroll]
+//! factor = 1.0
+//! wheel_step = 3.0
+//! natural = false
+//!
+//! # Windows fading in and out and gliding to their place in the layout. Durations are in
+//! # milliseconds, `easing` is "linear", "ease_out_cubic" or "ease_in_out_cubic".
+//! [animations]
+//! enabled = false
+//! open_duration = 150
+//! close_duration = 150
+//! move_duration = 200
+//! easing = "ease_out_cubic"
+//!
+//! # Keeps the clipboard when the client it was copied from exits. The copied data of the
+//! # listed mime types is read and the compositor offers it from then on, other mime types
+//! # of the selection are lost. Selections larger than `max_size` bytes in total stay with
+//! # their client.
+//! [clipboard]
+//! persist = false
+//! mime_types = ["text/plain;charset=utf-8", "text/plain", "UTF8_STRING", "STRING", "TEXT", "image/png"]
+//! max_size = 10485760
+//!
+//! # Touchpad gestures triggering actions, they don't reach clients then. `swipe` is
+//! # "left", "right", "up" or "down", `pinch` is "in" or "out". When left out three finger
+//! # horizontal swipes switch workspaces.
+//! [[gestures]]
+//! fingers = 3
+//! swipe = "left"
+//! action = "next_workspace"
+//!
+//! [[gestures]]
+//! fingers = 4
+//! pinch = "in"
+//! action = "toggle_scratchpad"
+//!
+//! [keyboard]
+//! # XKB keymap names, empty values use the xkbcommon defaults
+//! rules = ""
+//! model = ""
+//! layout = "us"
+//! variant = ""
+//! options = "ctrl:nocaps"
+//! # Milliseconds before a held key repeats and repeats per second
+//! repeat_delay = 200
+//! repeat_rate = 25
+//!
+//! [keybindings]
+//! # "<Modifier>+...+<keysym>" = <action>
+//! # Modifiers: Super (Logo, Mod4), Ctrl (Control), Alt (Mod1), Shift
+//! # Keysyms are xkb keysym names and are matched case insensitively
+//! "Super+Return" = { spawn = "alacritty" }
+//! "Super+Shift+q" = "close_window"
+//! "Super+Ctrl+Shift+q" = "kill_client"
+//! "Super+j" = "focus_next"
+//! "Super+k" = "focus_prev"
+//! "Super+Left" = "focus_left"
+//! "Super+Right" = "focus_right"
+//! "Super+Up" = "focus_up"
+//! "Super+Down" = "focus_down"
+//! "Super+Shift+Return" = "swap_with_master"
+//! "Super+Shift+j" = "swap_next"
+//! "Super+Shift+k" = "swap_prev"
+//! "Super+Shift+Left" = "move_left"
+//! "Super+Shift+Right" = "move_right"
+//! "Super+Shift+Up" = "move_up"
+//! "Super+Shift+Down" = "move_down"
+//! "Super+l" = "increase_master_ratio"
+//! "Super+h" = "decrease_master_ratio"
+//! "Super+m" = "minimize"
+//! "Super+Shift+m" = "restore_minimized"
+//! "Super+Shift+space" = "toggle_floating"
+//! "Super+s" = "toggle_sticky"
+//! # Snapping again cycles through a half, a third and two thirds of the output
+//! "Super+Ctrl+Left" = "snap_left"
+//! "Super+Ctrl+Right" = "snap_right"
+//! "Super+Ctrl+Up" = "snap_up"
+//! "Super+Ctrl+Down" = "snap_down"
+//! # Corners are unbound by default: "snap_top_left", "snap_top_right", "snap_bottom_left"
+//! # and "snap_bottom_right"
+//! "Super+space" = "cycle_layout"
+//! "Super+grave" = "toggle_scratchpad"
+//! "Super+1" = { switch_workspace = 1 }
+//! "Super+Shift+1" = { move_window_to_workspace = 1 }
+//! "Super+o" = "move_workspace_to_next_output"
+//! "Super+t" = "join_tabs"
+//! "Super+Shift+t" = "split_tabs"
+//! "Super+Tab" = "focus_next_tab"
+//! "Super+Shift+Tab" = "focus_prev_tab"
+//! "Ctrl+Alt+F2" = { switch_vt = 2 }
+//! "Super+Shift+c" = "reload_config"
+//! # Unbound by default: "toggle_debug_overlay"
+//! # Still works while a client like a virtual machine inhibits all other bindings
+//! "Super+Escape" = "release_shortcuts_inhibitor"
+//! "Super+Shift+e" = "quit"
+//! ```
+//!
+//! A missing or invalid config file falls back to [`Config::default`]. The `reload_config`
+//! action reads the file again, an invalid file keeps the config in use.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use smithay::{
+    input::keyboard::{keysyms, xkb, Keysym, ModifiersState, XkbConfig},
+    utils::Transform,
+};
+use tracing::warn;
+
+use crate::action::Action;
+use crate::layout::Layout;
+use crate::workspace::WORKSPACE_COUNT;
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub inner_gap: i32,
+    pub outer_gap: i32,
+    pub default_layout: Layout,
+    pub focus_follows_mouse: bool,
+    pub focus_wrap: bool,
+    pub border_width: i32,
+    pub focused_border_color: [f32; 4],
+    pub unfocused_border_color: [f32; 4],
+    pub urgent_border_color: [f32; 4],
+    pub inner_border_width: i32,
+    pub focused_inner_border_color: [f32; 4],
+    pub unfocused_inner_border_color: [f32; 4],
+    pub urgent_inner_border_color: [f32; 4],
+    pub tab_bar_height: i32,
+    pub tab_font: Option<PathBuf>,
+    pub focus_on_window_activation: Activation,
+    pub floating_placement: Placement,
+    pub floating_move_step: i32,
+    pub inactive_opacity: f32,
+    pub restore_session: bool,
+    pub xwayland: bool,
+    pub idle_timeout: Option<u64>,
+    pub autostart: Vec<AutostartCommand>,
+    pub keyboard: KeyboardConfig,
+    pub outputs: HashMap<String, OutputConfig>,
+    pub window_rules: Vec<WindowRule>,
+    pub scratchpad: ScratchpadConfig,
+    pub swallow: SwallowConfig,
+    pub mouse: MouseConfig,
+    pub scroll: ScrollConfig,
+    pub animations: AnimationConfig,
+    pub clipboard: ClipboardConfig,
+    pub gestures: Vec<GestureBinding>,
+    pub keybindings: Keybindings,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            inner_gap: 0,
+            outer_gap: 0,
+            default_layout: Layout::default(),
+            focus_follows_mouse: false,
+            focus_wrap: false,
+            border_width: 2,
+            focused_border_color: [0.32, 0.53, 0.84, 1.0],
+            unfocused_border_color: [0.25, 0.25, 0.25, 1.0],
+            urgent_border_color: [0.84, 0.27, 0.22, 1.0],
+            inner_border_width: 0,
+            focused_inner_border_color: [0.2, 0.36, 0.6, 1.0],
+            unfocused_inner_border_color: [0.16, 0.16, 0.16, 1.0],
+            urgent_inner_border_color: [0.6, 0.18, 0.15, 1.0],
+            tab_bar_height: 20,
+            tab_font: None,
+            focus_on_window_activation: Activation::default(),
+            floating_placement: Placement::default(),
+            floating_move_step: 20,
+            inactive_opacity: 1.0,
+            restore_session: false,
+            xwayland: true,
+            idle_timeout: None,
+            autostart: Vec::new(),
+            keyboard: KeyboardConfig::default(),
+            outputs: HashMap::new(),
+            window_rules: Vec::new(),
+            scratchpad: ScratchpadConfig::default(),
+            swallow: SwallowConfig::default(),
+            mouse: MouseConfig::default(),
+            scroll: ScrollConfig::default(),
+            animations: AnimationConfig::default(),
+            clipboard: ClipboardConfig::default(),
+            gestures: vec![
+                GestureBinding { fingers: 3, gesture: Gesture::Swipe(SwipeDirection::Left), action: Action::NextWorkspace },
+                GestureBinding { fingers: 3, gesture: Gesture::Swipe(SwipeDirection::Right), action: Action::PrevWorkspace },
+            ],
+            keybindings: Keybindings::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Settings of the output called `name`, made by `make` as `model`
+    pub fn output_config(&self, name: &str, make: &str, model: &str) -> Option<&OutputConfig> {
+        self.outputs
+            .get(name)
+            .or_else(|| self.outputs.get(&format!("{} {}", make, model)))
+    }
+
+    /// Space taken by both borders on each side of a window
+    pub fn total_border_width(&self) -> i32 {
+        self.border_width.max(0) + self.inner_border_width.max(0)
+    }
+
+    /// Default location of the config file
+    pub fn path() -> Option<PathBuf> {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .map(|config_dir| config_dir.join("twm").join("config.toml"))
+    }
+
+    /// Loads the config from `path` or the default location, falling back to the default
+    /// config when the file is missing or invalid
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let Some(path) = path.or_else(Self::path) else {
+            warn!("Couldn't determine config path, using default config");
+            return Self::default();
+        };
+
+        match Self::read(&path) {
+            Ok(config) => config,
+            Err(err) => {
+                warn!("{:#}, using default config", err);
+                Self::default()
+            }
+        }
+    }
+
+    /// Reads and parses the config file at `path`
+    pub fn read(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path).with_context(|| format!("Couldn't read config {:?}", path))?;
+        toml::from_str(&content).with_context(|| format!("Invalid config {:?}", path))
+    }
+}
+
+/// Handling of activation requests of clients and of new windows opening while another
+/// window has the focus
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Activation {
+    /// Focuses windows on a shown workspace activated from a key or button event of the user
+    /// since the last focus change and new windows of the client with the focus, marks all
+    /// others urgent
+    #[default]
+    Smart,
+    /// Focuses the window, switching to its workspace
+    Focus,
+    /// Leaves the focus where it is and marks the window urgent
+    Urgent,
+    /// Leaves the focus where it is
+    None,
+}
+
+/// Where new floating windows are put on their output
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Placement {
+    #[default]
+    Center,
+    /// Centered on the pointer
+    UnderCursor,
+    /// Where the window overlaps the other floating windows the least
+    Smart,
+}
+
+/// Keymap and key repeat of the keyboard
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct KeyboardConfig {
+    pub rules: String,
+    pub model: String,
+    pub layout: String,
+    pub variant: String,
+    pub options: Option<String>,
+    pub repeat_delay: i32,
+    pub repeat_rate: i32,
+}
+
+impl Default for KeyboardConfig {
+    fn default() -> Self {
+        Self {
+            rules: String::new(),
+            model: String::new(),
+            layout: String::new(),
+            variant: String::new(),
+            options: None,
+            repeat_delay: 200,
+            repeat_rate: 25,
+        }
+    }
+}
+
+impl KeyboardConfig {
+    pub fn xkb_config(&self) -> XkbConfig<'_> {
+        XkbConfig {
+            rules: &self.rules,
+            model: &self.model,
+            layout: &self.layout,
+            variant: &self.variant,
+            options: self.options.clone(),
+        }
+    }
+}
+
+/// Settings of a single output
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct OutputConfig {
+    pub scale: f64,
+    pub transform: OutputTransform,
+    pub mode: Option<OutputMode>,
+    /// Global location of the top left corner in logical pixels
+    pub position: Option<[i32; 2]>,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            transform: OutputTransform::default(),
+            mode: None,
+            position: None,
+        }
+    }
+}
+
+/// Resolution and optional refresh rate of an output, e.g. `2560x1440@144`
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct OutputMode {
+    pub width: i32,
+    pub height: i32,
+    /// Refresh rate in Hz
+    pub refresh: Option<f64>,
+}
+
+impl TryFrom<String> for OutputMode {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("Invalid output mode {:?}, expected e.g. 1920x1080 or 1920x1080@60", text);
+        let (size, refresh) = match text.split_once('@') {
+            Some((size, refresh)) => (size, Some(refresh.trim().parse().map_err(|_| invalid())?)),
+            None => (text.as_str(), None),
+        };
+        let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+        Ok(Self {
+            width: width.trim().parse().map_err(|_| invalid())?,
+            height: height.trim().parse().map_err(|_| invalid())?,
+            refresh,
+        })
+    }
+}
+
+/// Rotation and mirroring of an output
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputTransform {
+    #[default]
+    Normal,
+    #[serde(rename = "90")]
+    Rotate90,
+    #[serde(rename = "180")]
+    Rotate180,
+    #[serde(rename = "270")]
+    Rotate270,
+    Flipped,
+    #[serde(rename = "flipped_90")]
+    Flipped90,
+    #[serde(rename = "flipped_180")]
+    Flipped180,
+    #[serde(rename = "flipped_270")]
+    Flipped270,
+}
+
+impl From<OutputTransform> for Transform {
+    fn from(transform: OutputTransform) -> Self {
+        match transform {
+            OutputTransform::Normal => Transform::Normal,
+            OutputTransform::Rotate90 => Transform::_90,
+            OutputTransform::Rotate180 => Transform::_180,
+            OutputTransform::Rotate270 => Transform::_270,
+            OutputTransform::Flipped => Transform::Flipped,
+            OutputTransform::Flipped90 => Transform::Flipped90,
+            OutputTransform::Flipped180 => Transform::Flipped180,
+            OutputTransform::Flipped270 => Transform::Flipped270,
+        }
+    }
+}
+
+/// Rule for windows with a matching app_id and title
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct WindowRule {
+    pub app_id: Option<String>,
+    pub title: Option<String>,
+    /// Workspace number on its output the window is moved to, counted from 1
+    pub workspace: Option<usize>,
+    pub floating: Option<bool>,
+    /// Placement of the window when it floats from the start, instead of
+    /// `floating_placement`
+    pub placement: Option<Placement>,
+    /// Between 0 (invisible) and 1 (opaque)
+    pub opacity: Option<f32>,
+    pub fullscreen: bool,
+    /// New windows take the focus or never do, regardless of
+    /// `focus_on_window_activation`
+    pub focus: Option<bool>,
+}
+
+impl WindowRule {
+    pub fn matches(&self, app_id: Option<&str>, title: Option<&str>) -> bool {
+        let app_id_matches = self.app_id.as_deref().map_or(true, |rule| Some(rule) == app_id);
+        let title_matches = self.title.as_deref().map_or(true, |rule| title.map_or(false, |title| title.contains(rule)));
+        app_id_matches && title_matches
+    }
+}
+
+/// Command started at startup
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum AutostartCommand {
+    /// Shell-style command line
+    Line(String),
+    Full {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+        /// Milliseconds to wait after startup
+        #[serde(default)]
+        delay: u64,
+    },
+}
+
+impl AutostartCommand {
+    /// Shell-style command line with the arguments appended
+    pub fn command_line(&self) -> String {
+        match self {
+            AutostartCommand::Line(command) => command.clone(),
+            AutostartCommand::Full { command, args, .. } if args.is_empty() => command.clone(),
+            AutostartCommand::Full { command, args, .. } => format!("{} {}", command, shell_words::join(args)),
+        }
+    }
+
+    pub fn delay(&self) -> Duration {
+        match self {
+            AutostartCommand::Line(_) => Duration::ZERO,
+            AutostartCommand::Full { delay, .. } => Duration::from_millis(*delay),
+        }
+    }
+}
+
+/// Window toggled with the `toggle_scratchpad` action
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ScratchpadConfig {
+    pub app_id: Option<String>,
+    pub command: Option<String>,
+}
+
+/// Terminals hidden by the windows started from them
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SwallowConfig {
+    pub enabled: bool,
+    /// App ids of the terminals that swallow
+    pub terminals: Vec<String>,
+}
+
+impl Default for SwallowConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            terminals: vec!["foot".to_string(), "Alacritty".to_string(), "kitty".to_string()],
+        }
+    }
+}
+
+/// Modifier and buttons moving and resizing floating windows
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MouseConfig {
+    pub modifier: Modifiers,
+    pub move_button: MouseButton,
+    pub resize_button: MouseButton,
+}
+
+impl Default for MouseConfig {
+    fn default() -> Self {
+        Self {
+            modifier: Modifiers { logo: true, ..Default::default() },
+            move_button: MouseButton::Left,
+            resize_button: MouseButton::Right,
+        }
+    }
+}
+
+/// Speed and direction of scrolling
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ScrollConfig {
+    pub factor: f64,
+    pub wheel_step: f64,
+    pub natural: bool,
+}
+
+impl Default for ScrollConfig {
+    fn default() -> Self {
+        Self {
+            factor: 1.0,
+            wheel_step: 3.0,
+            natural: false,
+        }
+    }
+}
+
+/// Durations in milliseconds and easing of window animations
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AnimationConfig {
+    pub enabled: bool,
+    pub open_duration: u64,
+    pub close_duration: u64,
+    pub move_duration: u64,
+    pub easing: Easing,
+}
+
+impl Default for AnimationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            open_duration: 150,
+            close_duration: 150,
+            move_duration: 200,
+            easing: Easing::default(),
+        }
+    }
+}
+
+/// Clipboard kept after its client exits
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ClipboardConfig {
+    pub persist: bool,
+    /// Mime types read from selections and offered once the compositor owns them
+    pub mime_types: Vec<String>,
+    /// Largest total size in bytes of a persisted selection
+    pub max_size: usize,
+}
+
+impl Default for ClipboardConfig {
+    fn default() -> Self {
+        Self {
+            persist: false,
+            mime_types: ["text/plain;charset=utf-8", "text/plain", "UTF8_STRING", "STRING", "TEXT", "image/png"]
+                .iter()
+                .map(|mime_type| mime_type.to_string())
+                .collect(),
+            max_size: 10 * 1024 * 1024,
+        }
+    }
+}
+
+/// Course of animations over their duration
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Easing {
+    Linear,
+    /// Fast at first, slowing down towards the end
+    #[default]
+    EaseOutCubic,
+    /// Slow at the start and the end
+    EaseInOutCubic,
+}
+
+impl Easing {
+    /// Eased progress for the linear progress `t` between 0 and 1
+    pub fn apply(self, t: f64) -> f64 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseOutCubic => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOutCubic if t < 0.5 => 4.0 * t.powi(3),
+            Easing::EaseInOutCubic => 1.0 - (-2.0 * t + 2.0).powi(3) / 2.0,
+        }
+    }
+}
+
+/// Touchpad gesture bound to an action
+#[derive(Debug, Clone, Deserialize)]
+pub struct GestureBinding {
+    pub fingers: u32,
+    #[serde(flatten)]
+    pub gesture: Gesture,
+    pub action: Action,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Gesture {
+    Swipe(SwipeDirection),
+    Pinch(PinchDirection),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SwipeDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PinchDirection {
+    /// Fingers moving together
+    In,
+    /// Fingers moving apart
+    Out,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+}
+
+impl MouseButton {
+    /// Linux input event code of the button
+    pub fn code(self) -> u32 {
+        match self {
+            MouseButton::Left => 0x110,
+            MouseButton::Right => 0x111,
+            MouseButton::Middle => 0x112,
+        }
+    }
+}
+
+/// Modifier keys that have to be held for a keybinding
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub logo: bool,
+}
+
+impl Modifiers {
+    /// Adds the modifier called `name`, returns whether the name is known
+    fn add(&mut self, name: &str) -> bool {
+        match name.to_lowercase().as_str() {
+            "super" | "logo" | "mod4" => self.logo = true,
+            "ctrl" | "control" => self.ctrl = true,
+            "alt" | "mod1" => self.alt = true,
+            "shift" => self.shift = true,
+            _ => return false,
+        }
+        true
+    }
+}
+
+impl TryFrom<String> for Modifiers {
+    type Error = String;
+
+    /// Parses modifiers joined by `+`, e.g. `Super+Shift`
+    fn try_from(names: String) -> Result<Self, Self::Error> {
+        let mut modifiers = Modifiers::default();
+        for name in names.split('+').map(str::trim) {
+            if !modifiers.add(name) {
+                return Err(format!("Unknown modifier {:?} in {:?}", name, names));
+            }
+        }
+        Ok(modifiers)
+    }
+}
+
+impl From<&ModifiersState> for Modifiers {
+    fn from(state: &ModifiersState) -> Self {
+        Self {
+            ctrl: state.ctrl,
+            alt: state.alt,
+            shift: state.shift,
+            logo: state.logo,
+        }
+    }
+}
+
+/// Modifiers and keysym combination, e.g. `Super+Return`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyCombo {
+    pub modifiers: Modifiers,
+    pub keysym: Keysym,
+}
+
+impl std::str::FromStr for KeyCombo {
+    type Err = String;
+
+    fn from_str(combo: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = Modifiers::default();
+        let mut parts: Vec<&str> = combo.split('+').map(str::trim).collect();
+        let key = parts.pop().filter(|key| !key.is_empty())
+            .ok_or_else(|| format!("Missing key in {:?}", combo))?;
+
+        for modifier in parts {
+            if !modifiers.add(modifier) {
+                return Err(format!("Unknown modifier {:?} in {:?}", modifier, combo));
+            }
+        }
+
+        let keysym = xkb::keysym_from_name(key, xkb::KEYSYM_CASE_INSENSITIVE);
+        if keysym == keysyms::KEY_NoSymbol {
+            return Err(format!("Unknown key {:?} in {:?}", key, combo));
+        }
+
+        Ok(Self { modifiers, keysym })
+    }
+}
+
+/// Keybindings mapping key combinations to compositor actions
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "HashMap<String, Action>")]
+pub struct Keybindings {
+    bindings: HashMap<KeyCombo, Action>,
+}
+
+impl Keybindings {
+    /// Action bound to the given modifiers and any of the keysyms
+    pub fn action(&self, modifiers: Modifiers, keysyms: &[Keysym]) -> Option<&Action> {
+        keysyms
+            .iter()
+            .find_map(|&keysym| self.bindings.get(&KeyCombo { modifiers, keysym }))
+    }
+}
+
+impl TryFrom<HashMap<String, Action>> for Keybindings {
+    type Error = String;
+
+    fn try_from(raw: HashMap<String, Action>) -> Result<Self, Self::Error> {
+        let bindings = raw
+            .into_iter()
+            .map(|(combo, action)| combo.parse().map(|combo| (combo, action)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { bindings })
+    }
+}
+
+impl Default for Keybindings {
+    fn default() -> Self {
+        let workspace_bindings = (1..=WORKSPACE_COUNT).flat_map(|number| [
+            (format!("Super+{}", number), Action::SwitchWorkspace(number)),
+            (format!("Super+Shift+{}", number), Action::MoveWindowToWorkspace(number)),
+        ]);
+
+        let vt_bindings = (1..=12).map(|vt| (format!("Ctrl+Alt+F{}", vt), Action::SwitchVt(vt)));
+
+        let bindings = [
+            ("Super+Return", Action::Spawn("alacritty".into())),
+            ("Super+Shift+q", Action::CloseWindow),
+            ("Super+Ctrl+Shift+q", Action::KillClient),
+            ("Super+j", Action::FocusNext),
+            ("Super+k", Action::FocusPrev),
+            ("Super+Left", Action::FocusLeft),
+            ("Super+Right", Action::FocusRight),
+            ("Super+Up", Action::FocusUp),
+            ("Super+Down", Action::FocusDown),
+            ("Super+Shift+Return", Action::SwapWithMaster),
+            ("Super+Shift+j", Action::SwapNext),
+            ("Super+Shift+k", Action::SwapPrev),
+            ("Super+Shift+Left", Action::MoveLeft),
+            ("Super+Shift+Right", Action::MoveRight),
+            ("Super+Shift+Up", Action::MoveUp),
+            ("Super+Shift+Down", Action::MoveDown),
+            ("Super+l", Action::IncreaseMasterRatio),
+            ("Super+h", Action::DecreaseMasterRatio),
+            ("Super+m", Action::Minimize),
+            ("Super+Shift+m", Action::RestoreMinimized),
+            ("Super+Shift+space", Action::ToggleFloating),
+            ("Super+s", Action::ToggleSticky),
+            ("Super+Ctrl+Left", Action::SnapLeft),
+            ("Super+Ctrl+Right", Action::SnapRight),
+            ("Super+Ctrl+Up", Action::SnapUp),
+            ("Super+Ctrl+Down", Action::SnapDown),
+            ("Super+space", Action::CycleLayout),
+            ("Super+grave", Action::ToggleScratchpad),
+            ("Super+o", Action::MoveWorkspaceToNextOutput),
+            ("Super+t", Action::JoinTabs),
+            ("Super+Shift+t", Action::SplitTabs),
+            ("Super+Tab", Action::FocusNextTab),
+            ("Super+Shift+Tab", Action::FocusPrevTab),
+            ("Super+Shift+c", Action::ReloadConfig),
+            ("Super+Escape", Action::ReleaseShortcutsInhibitor),
+            ("Super+Shift+e", Action::Quit),
+        ]
+        .into_iter()
+        .map(|(combo, action)| (combo.to_string(), action))
+        .chain(workspace_bindings)
+        .chain(vt_bindings)
+        .map(|(combo, action)| (combo.parse().expect("Valid default keybinding"), action))
+        .collect();
+
+        Self { bindings }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_config_uses_defaults() {
+        let config: Config = toml::from_str("").expect("Empty config parses");
+        assert_eq!(config.floating_placement, Placement::Center);
+        assert!(config.window_rules.is_empty());
+    }
+
+    #[test]
+    fn parses_floating_placement() {
+        for (value, placement) in [
+            ("center", Placement::Center),
+            ("under_cursor", Placement::UnderCursor),
+            ("smart", Placement::Smart),
+        ] {
+            let config: Config = toml::from_str(&format!("floating_placement = \"{}\"", value))
+                .expect("Placement parses");
+            assert_eq!(config.floating_placement, placement);
+        }
+    }
+
+    #[test]
+    fn parses_window_rule_placement() {
+        let config: Config = toml::from_str(r#"
+            [[window_rules]]
+            app_id = "pavucontrol"
+            floating = true
+            placement = "under_cursor"
+
+            [[window_rules]]
+            app_id = "mpv"
+        "#).expect("Window rules parse");
+        assert_eq!(config.window_rules.len(), 2);
+        assert_eq!(config.window_rules[0].floating, Some(true));
+        assert_eq!(config.window_rules[0].placement, Some(Placement::UnderCursor));
+        assert_eq!(config.window_rules[1].placement, None);
+    }
+
+    #[test]
+    fn rejects_unknown_placement() {
+        assert!(toml::from_str::<Config>("floating_placement = \"random\"").is_err());
+        assert!(toml::from_str::<Config>("[[window_rules]]\nplacement = \"top\"").is_err());
+    }
+}
@@ -0,0 +1,76 @@
+//! Moves of resized windows wait for the client to catch up with the configure.
+//!
+//! A window getting a new size and location from the layout keeps drawing its old buffer
+//! until the client acked the configure and committed a buffer of the new size. Moving
+//! it right away would show the old buffer at the new location for a frame or more, so the
+//! move is applied with that commit instead. Moves without a size change apply at once.
+
+use std::cell::Cell;
+
+use smithay::{
+    desktop::Window,
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::{Logical, Point, Serial},
+};
+use tracing::trace;
+
+use crate::TwmState;
+
+/// Location a window moves to once it committed the configure with `serial`
+#[derive(Debug, Clone, Copy)]
+struct PendingMove {
+    serial: Serial,
+    location: Point<i32, Logical>,
+    acked: bool,
+}
+
+/// Pending move of a window, kept in its user data
+#[derive(Debug, Default)]
+struct PendingConfigure(Cell<Option<PendingMove>>);
+
+fn pending_configure(window: &Window) -> &PendingConfigure {
+    window.user_data().insert_if_missing(PendingConfigure::default);
+    window.user_data().get::<PendingConfigure>().expect("Pending configure available")
+}
+
+impl TwmState {
+    /// Moves `window` to `location` with the first commit after the client acked the
+    /// configure with `serial`, replacing any earlier pending move
+    pub fn move_on_commit(&self, window: &Window, serial: Serial, location: Point<i32, Logical>) {
+        trace!("Moving window once configure {:?} is committed", serial);
+        pending_configure(window).0.set(Some(PendingMove { serial, location, acked: false }));
+    }
+
+    /// Drops the pending move of `window`, for windows placed right away
+    pub fn cancel_pending_move(&self, window: &Window) {
+        if let Some(pending) = window.user_data().get::<PendingConfigure>() {
+            pending.0.set(None);
+        }
+    }
+
+    /// Notes that the client of the toplevel `surface` acked the configure with `serial`
+    pub fn configure_acked(&self, surface: &WlSurface, serial: Serial) {
+        let Some(window) = self.space.elements().find(|window| window.toplevel().wl_surface() == surface) else {
+            return;
+        };
+        let Some(pending) = window.user_data().get::<PendingConfigure>() else {
+            return;
+        };
+        // Acks of older configures don't carry the new size yet
+        if let Some(pending_move) = pending.0.get().filter(|pending_move| serial >= pending_move.serial) {
+            pending.0.set(Some(PendingMove { acked: true, ..pending_move }));
+        }
+    }
+
+    /// Applies the pending move of `window` if this commit follows the ack of its configure
+    pub fn apply_pending_move(&mut self, window: &Window) {
+        let Some(pending) = window.user_data().get::<PendingConfigure>() else {
+            return;
+        };
+        let Some(pending_move) = pending.0.get().filter(|pending_move| pending_move.acked) else {
+            return;
+        };
+        pending.0.set(None);
+        self.space.map_element(window.clone(), pending_move.location, false);
+    }
+}
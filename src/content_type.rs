@@ -0,0 +1,170 @@
+//! The content-type protocol through which clients tell what their surfaces show.
+//!
+//! Windows whose toplevel surface shows video or a game are drawn unaltered: they aren't
+//! dimmed without focus and skip the fade and move animations, so their frames reach the
+//! screen as the client drew them and right away. The type applies with the next commit of
+//! the surface like other surface state. None of the backends scan out client buffers
+//! directly, so there is nothing else the hint changes.
+
+use std::sync::Mutex;
+
+use smithay::{
+    desktop::Window,
+    reexports::{
+        wayland_protocols::wp::content_type::v1::server::{
+            wp_content_type_manager_v1::{self, WpContentTypeManagerV1},
+            wp_content_type_v1::{self, Type, WpContentTypeV1},
+        },
+        wayland_server::{
+            backend::{ClientId, GlobalId, ObjectId},
+            protocol::wl_surface::WlSurface,
+            Client,
+            DataInit,
+            Dispatch,
+            DisplayHandle,
+            GlobalDispatch,
+            New,
+            Resource,
+        },
+    },
+    wayland::compositor::with_states,
+};
+use tracing::debug;
+
+use crate::TwmState;
+
+#[derive(Debug)]
+pub struct ContentTypeState {
+    _global: GlobalId,
+}
+
+impl ContentTypeState {
+    pub fn new(display_handle: &DisplayHandle) -> Self {
+        Self {
+            _global: display_handle.create_global::<TwmState, WpContentTypeManagerV1, _>(1, ()),
+        }
+    }
+}
+
+/// Content type of a surface, kept in its data map
+#[derive(Debug)]
+struct SurfaceContentType {
+    /// A content type object exists for the surface, there may only be one
+    constructed: bool,
+    pending: Type,
+    current: Type,
+}
+
+fn with_content_type<T>(surface: &WlSurface, f: impl FnOnce(&mut SurfaceContentType) -> T) -> T {
+    with_states(surface, |states| {
+        states.data_map.insert_if_missing_threadsafe(|| Mutex::new(SurfaceContentType {
+            constructed: false,
+            pending: Type::None,
+            current: Type::None,
+        }));
+        let content_type = states.data_map.get::<Mutex<SurfaceContentType>>().expect("Content type available");
+        let mut content_type = content_type.lock().unwrap();
+        f(&mut content_type)
+    })
+}
+
+/// Content type `surface` committed last
+pub fn content_type(surface: &WlSurface) -> Type {
+    with_states(surface, |states| {
+        states.data_map
+            .get::<Mutex<SurfaceContentType>>()
+            .map_or(Type::None, |content_type| content_type.lock().unwrap().current)
+    })
+}
+
+/// Whether `window` shows video or a game, which are drawn unaltered
+pub fn shows_media(window: &Window) -> bool {
+    matches!(content_type(window.toplevel().wl_surface()), Type::Video | Type::Game)
+}
+
+impl TwmState {
+    /// Applies the pending content type of `surface`
+    pub fn content_type_commit(&mut self, surface: &WlSurface) {
+        let changed = with_states(surface, |states| {
+            let mut content_type = states.data_map.get::<Mutex<SurfaceContentType>>()?.lock().unwrap();
+            let changed = content_type.current != content_type.pending;
+            content_type.current = content_type.pending;
+            changed.then_some(content_type.current)
+        });
+        if let Some(content_type) = changed {
+            debug!("Surface content type: {:?}", content_type);
+            // Dimming may change without any surface damage
+            self.damage_dimmed_windows();
+        }
+    }
+}
+
+impl GlobalDispatch<WpContentTypeManagerV1, ()> for TwmState {
+    fn bind(_state: &mut Self,
+            _handle: &DisplayHandle,
+            _client: &Client,
+            resource: New<WpContentTypeManagerV1>,
+            _global_data: &(),
+            data_init: &mut DataInit<'_, Self>) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<WpContentTypeManagerV1, ()> for TwmState {
+    fn request(_state: &mut Self,
+               _client: &Client,
+               resource: &WpContentTypeManagerV1,
+               request: wp_content_type_manager_v1::Request,
+               _data: &(),
+               _handle: &DisplayHandle,
+               data_init: &mut DataInit<'_, Self>) {
+        match request {
+            wp_content_type_manager_v1::Request::GetSurfaceContentType { id, surface } => {
+                let constructed = with_content_type(&surface, |content_type| {
+                    std::mem::replace(&mut content_type.constructed, true)
+                });
+                if constructed {
+                    resource.post_error(
+                        wp_content_type_manager_v1::Error::AlreadyConstructed,
+                        "Surface already has a content type object");
+                    return;
+                }
+                data_init.init(id, surface);
+            },
+            wp_content_type_manager_v1::Request::Destroy => {},
+            _ => {},
+        }
+    }
+}
+
+impl Dispatch<WpContentTypeV1, WlSurface> for TwmState {
+    fn request(_state: &mut Self,
+               _client: &Client,
+               _resource: &WpContentTypeV1,
+               request: wp_content_type_v1::Request,
+               surface: &WlSurface,
+               _handle: &DisplayHandle,
+               _data_init: &mut DataInit<'_, Self>) {
+        match request {
+            wp_content_type_v1::Request::SetContentType { content_type } => {
+                // Unknown types of newer protocol versions count as no type
+                let content_type = content_type.into_result().unwrap_or(Type::None);
+                if surface.alive() {
+                    with_content_type(surface, |state| state.pending = content_type);
+                }
+            },
+            wp_content_type_v1::Request::Destroy => {},
+            _ => {},
+        }
+    }
+
+    fn destroyed(_state: &mut Self, _client: ClientId, _resource: ObjectId, surface: &WlSurface) {
+        // The surface goes back to no type with its next commit
+        if surface.alive() {
+            with_content_type(surface, |content_type| {
+                content_type.constructed = false;
+                content_type.pending = Type::None;
+            });
+        }
+    }
+}
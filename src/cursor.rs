@@ -0,0 +1,209 @@
+//! Cursor images drawn by the compositor, used while no client sets a cursor image.
+//!
+//! Images come from the XCursor theme named by `XCURSOR_THEME` at the size given by
+//! `XCURSOR_SIZE`, the same variables clients read. A built-in arrow is drawn when the theme
+//! has no default cursor. Animated cursors show their first frame.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use smithay::{
+    backend::{allocator::Fourcc, renderer::element::memory::MemoryRenderBuffer},
+    input::pointer::CursorImageStatus,
+    reexports::wayland_protocols::xdg::shell::server::xdg_toplevel::ResizeEdge,
+    utils::{Logical, Point, Transform},
+};
+use tracing::{debug, warn};
+use xcursor::parser::{parse_xcursor, Image};
+
+use crate::TwmState;
+
+/// Theme and nominal size used when the environment doesn't set them
+const DEFAULT_THEME: &str = "default";
+const DEFAULT_SIZE: u32 = 24;
+
+/// Cursor the compositor shows for what is under the pointer or for a running grab
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CursorIcon {
+    #[default]
+    Default,
+    /// A window is moved
+    Move,
+    /// A window is resized from the given edges
+    Resize(ResizeEdge),
+}
+
+impl CursorIcon {
+    /// XCursor names of the icon, tried in order as themes name some cursors differently
+    fn names(self) -> &'static [&'static str] {
+        match self {
+            CursorIcon::Default => &["left_ptr", "default", "arrow"],
+            CursorIcon::Move => &["fleur", "move", "grabbing"],
+            CursorIcon::Resize(ResizeEdge::Top) => &["top_side", "n-resize"],
+            CursorIcon::Resize(ResizeEdge::Bottom) => &["bottom_side", "s-resize"],
+            CursorIcon::Resize(ResizeEdge::Left) => &["left_side", "w-resize"],
+            CursorIcon::Resize(ResizeEdge::Right) => &["right_side", "e-resize"],
+            CursorIcon::Resize(ResizeEdge::TopLeft) => &["top_left_corner", "nw-resize"],
+            CursorIcon::Resize(ResizeEdge::TopRight) => &["top_right_corner", "ne-resize"],
+            CursorIcon::Resize(ResizeEdge::BottomLeft) => &["bottom_left_corner", "sw-resize"],
+            CursorIcon::Resize(ResizeEdge::BottomRight) => &["bottom_right_corner", "se-resize"],
+            CursorIcon::Resize(_) => &["left_ptr", "default", "arrow"],
+        }
+    }
+}
+
+/// Cursor image ready to be drawn
+#[derive(Debug, Clone)]
+pub struct CursorBuffer {
+    pub buffer: MemoryRenderBuffer,
+    /// Point of the image at the pointer location
+    pub hotspot: Point<i32, Logical>,
+}
+
+/// Loaded XCursor theme with the images of the cursors shown so far
+#[derive(Debug)]
+pub struct CursorTheme {
+    theme: xcursor::CursorTheme,
+    size: u32,
+    /// Images of every cursor name loaded so far, `None` if the theme doesn't have it
+    images: RefCell<HashMap<String, Option<Vec<Image>>>>,
+    /// Buffers keyed by cursor icon and output scale, reused so the cursor only causes
+    /// damage when it moves or changes
+    buffers: RefCell<HashMap<(CursorIcon, i32), CursorBuffer>>,
+}
+
+impl CursorTheme {
+    /// Loads the theme named by `XCURSOR_THEME`
+    pub fn load() -> Self {
+        let name = std::env::var("XCURSOR_THEME").unwrap_or_else(|_| DEFAULT_THEME.to_string());
+        let size = std::env::var("XCURSOR_SIZE")
+            .ok()
+            .and_then(|size| size.parse().ok())
+            .filter(|&size| size > 0)
+            .unwrap_or(DEFAULT_SIZE);
+        debug!("Cursor theme {:?} at size {}", name, size);
+
+        Self {
+            theme: xcursor::CursorTheme::load(&name),
+            size,
+            images: RefCell::new(HashMap::new()),
+            buffers: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Image of `icon` for outputs with the integer `scale`, falling back to the default
+    /// cursor and then to the built-in arrow
+    pub fn buffer(&self, icon: CursorIcon, scale: i32) -> CursorBuffer {
+        if let Some(buffer) = self.buffers.borrow().get(&(icon, scale)) {
+            return buffer.clone();
+        }
+
+        let size = self.size * scale.max(1) as u32;
+        let image = icon.names()
+            .iter()
+            .chain(CursorIcon::Default.names())
+            .find_map(|name| self.image(name, size))
+            .unwrap_or_else(|| builtin_cursor(size));
+
+        let buffer = CursorBuffer {
+            buffer: MemoryRenderBuffer::from_memory(
+                &image.pixels_rgba,
+                Fourcc::Abgr8888,
+                (image.width as i32, image.height as i32),
+                scale,
+                Transform::Normal,
+                None),
+            hotspot: Point::from((image.xhot as i32, image.yhot as i32)).downscale(scale.max(1)),
+        };
+        self.buffers.borrow_mut().insert((icon, scale), buffer.clone());
+        buffer
+    }
+
+    /// First frame of the cursor `name` closest to `size`
+    fn image(&self, name: &str, size: u32) -> Option<Image> {
+        let mut images = self.images.borrow_mut();
+        let frames = images.entry(name.to_string()).or_insert_with(|| self.load_images(name));
+        let frames = frames.as_ref()?;
+
+        let nearest = frames.iter().map(|image| image.size).min_by_key(|&nominal| nominal.abs_diff(size))?;
+        frames.iter().find(|image| image.size == nearest).cloned()
+    }
+
+    fn load_images(&self, name: &str) -> Option<Vec<Image>> {
+        let path = self.theme.load_icon(name)?;
+        let content = match std::fs::read(&path) {
+            Ok(content) => content,
+            Err(err) => {
+                warn!("Failed to read cursor {:?}: {}", path, err);
+                return None;
+            }
+        };
+        let images = parse_xcursor(&content);
+        if images.is_none() {
+            warn!("Invalid cursor file {:?}", path);
+        }
+        images
+    }
+}
+
+/// White arrow with a black outline, `size` pixels high
+fn builtin_cursor(size: u32) -> Image {
+    let height = size.max(8) as i32;
+    let width = height * 3 / 4;
+    // Tip at the top left, the long edge going straight down
+    let (bottom, side) = ((0, height * 5 / 6), (height * 3 / 5, height * 3 / 5));
+    let inside = |x: i32, y: i32| {
+        let cross = |(ax, ay): (i32, i32), (bx, by): (i32, i32)| (bx - ax) * (y - ay) - (by - ay) * (x - ax);
+        x >= 0 && cross(bottom, side) <= 0 && cross(side, (0, 0)) <= 0
+    };
+
+    let mut pixels_rgba = Vec::with_capacity((width * height * 4) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = if !inside(x, y) {
+                [0, 0, 0, 0]
+            } else if [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)].into_iter().all(|(x, y)| inside(x, y)) {
+                [255, 255, 255, 255]
+            } else {
+                [0, 0, 0, 255]
+            };
+            pixels_rgba.extend_from_slice(&pixel);
+        }
+    }
+
+    Image {
+        size,
+        width: width as u32,
+        height: height as u32,
+        xhot: 0,
+        yhot: 0,
+        delay: 0,
+        pixels_argb: Vec::new(),
+        pixels_rgba,
+    }
+}
+
+impl TwmState {
+    /// Shows the compositor cursor `icon` in place of any client cursor
+    pub fn show_cursor_icon(&mut self, icon: CursorIcon) {
+        if matches!(self.cursor_status, CursorImageStatus::Default) && self.cursor_icon == icon {
+            return;
+        }
+        self.cursor_status = CursorImageStatus::Default;
+        self.cursor_icon = icon;
+        self.needs_redraw = true;
+    }
+
+    /// Picks the cursor for the pointer at `position` outside of grabs. Client surfaces show
+    /// the cursor their client sets, borders of floating windows show resize cursors.
+    pub fn update_cursor_icon(&mut self, position: Point<f64, Logical>, over_surface: bool) {
+        if over_surface {
+            // Clients not setting a cursor get the default one
+            self.cursor_icon = CursorIcon::Default;
+            return;
+        }
+        let icon = self.floating_border_under(position)
+            .map_or(CursorIcon::Default, |(_, edges)| CursorIcon::Resize(edges));
+        self.show_cursor_icon(icon);
+    }
+}
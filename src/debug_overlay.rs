@@ -0,0 +1,156 @@
+//! On-screen overlay for debugging rendering and tiling, toggled with the
+//! `toggle_debug_overlay` action and never shown otherwise.
+//!
+//! Each output shows its frame rate over the last second, the number of mapped windows, the
+//! layout of its workspace and the app id of the focused window in its top left corner. The
+//! regions damaged by the previous frame of the output are outlined. Both change with every
+//! frame, so outputs showing the overlay are redrawn more than needed.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use smithay::{
+    backend::{
+        allocator::Fourcc,
+        renderer::{
+            element::{
+                memory::{MemoryRenderBuffer, MemoryRenderBufferRenderElement},
+                solid::SolidColorRenderElement,
+                Id,
+            },
+            utils::CommitCounter,
+            ImportAll, ImportMem, Renderer,
+        },
+    },
+    output::Output,
+    utils::{Logical, Physical, Rectangle, Size, Transform},
+};
+use tracing::{debug, warn};
+
+use crate::{render::CustomRenderElements, tabs, TwmState};
+
+/// Size in logical pixels of the text line
+const TEXT_SIZE: (i32, i32) = (480, 20);
+const BACKGROUND_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.7];
+const DAMAGE_COLOR: [f32; 4] = [0.9, 0.2, 0.2, 1.0];
+/// Damaged rectangles outlined at most, frames damaged in many small places show the first
+const MAX_OUTLINES: usize = 64;
+
+/// Frame statistics of one output
+#[derive(Debug, Default)]
+struct OutputStats {
+    /// Times frames were presented within the last second
+    frames: VecDeque<Instant>,
+    /// Damage of the last presented frame
+    damage: Vec<Rectangle<i32, Physical>>,
+    /// Text and scale the text buffer was drawn for
+    text: Option<(String, i32, MemoryRenderBuffer)>,
+}
+
+/// Debug overlay, kept while it is shown. Statistics are updated while rendering.
+#[derive(Debug, Default)]
+pub struct DebugOverlay {
+    /// Statistics by output name
+    outputs: RefCell<HashMap<String, OutputStats>>,
+}
+
+impl DebugOverlay {
+    /// Notes that a frame of `output` with `damage` was rendered
+    pub fn frame_rendered(&self, output: &Output, damage: &[Rectangle<i32, Physical>]) {
+        let mut outputs = self.outputs.borrow_mut();
+        let stats = outputs.entry(output.name()).or_default();
+        let now = Instant::now();
+        stats.frames.push_back(now);
+        while stats.frames.front().map_or(false, |&time| now - time > Duration::from_secs(1)) {
+            stats.frames.pop_front();
+        }
+        stats.damage = damage.to_vec();
+    }
+}
+
+impl TwmState {
+    /// Shows or hides the debug overlay
+    pub fn toggle_debug_overlay(&mut self) {
+        self.debug_overlay = match self.debug_overlay.take() {
+            Some(_) => None,
+            None => Some(DebugOverlay::default()),
+        };
+        debug!("Debug overlay shown: {}", self.debug_overlay.is_some());
+        self.full_redraw = self.space.outputs().cloned().collect();
+        self.needs_redraw = true;
+    }
+
+    /// Render elements of the debug overlay of `output`, nothing while it is hidden
+    pub fn debug_overlay_elements<R>(&self, renderer: &mut R, output: &Output) -> Vec<CustomRenderElements<R>>
+    where
+        R: Renderer + ImportAll + ImportMem,
+        <R as Renderer>::TextureId: Clone + 'static,
+    {
+        let Some(overlay) = self.debug_overlay.as_ref() else {
+            return Vec::new();
+        };
+        let mut outputs = overlay.outputs.borrow_mut();
+        let stats = outputs.entry(output.name()).or_default();
+        let scale = output.current_scale().fractional_scale();
+        // Text is drawn at whole scales like tab titles
+        let buffer_scale = output.current_scale().integer_scale().max(1);
+        let mut elements = Vec::new();
+
+        let layout = self.output_workspace(output).map(|workspace| workspace.layout).unwrap_or_default();
+        let focused = self.focused_window().and_then(|window| self.window_info(&window).app_id);
+        let text = format!(
+            "{} fps | {} windows | {:?} | {}",
+            stats.frames.len(),
+            self.space.elements().count(),
+            layout,
+            focused.as_deref().unwrap_or("no focus"));
+        if let Some(font) = self.tab_font.as_ref() {
+            let outdated = stats.text
+                .as_ref()
+                .map_or(true, |(cached, cached_scale, _)| cached != &text || *cached_scale != buffer_scale);
+            if outdated {
+                let size = Size::<i32, Physical>::from((TEXT_SIZE.0 * buffer_scale, TEXT_SIZE.1 * buffer_scale));
+                let pixels = tabs::rasterize_title(font, &text, size);
+                let buffer = MemoryRenderBuffer::from_memory(
+                    &pixels,
+                    Fourcc::Abgr8888,
+                    (size.w, size.h),
+                    buffer_scale,
+                    Transform::Normal,
+                    None);
+                stats.text = Some((text, buffer_scale, buffer));
+            }
+            let (_, _, buffer) = stats.text.as_ref().expect("Text buffer drawn");
+            match MemoryRenderBufferRenderElement::from_buffer(renderer, (0.0, 0.0), buffer, None, None, None) {
+                Ok(element) => elements.push(CustomRenderElements::Memory(element)),
+                Err(err) => warn!("Failed to draw the debug overlay: {:?}", err),
+            }
+        }
+        let background = Rectangle::from_loc_and_size(
+            (0, 0),
+            Size::<i32, Logical>::from(TEXT_SIZE).to_f64().to_physical(scale).to_i32_round());
+        elements.push(solid_element(background, BACKGROUND_COLOR));
+
+        for damage in stats.damage.iter().take(MAX_OUTLINES) {
+            let (x, y, w, h) = (damage.loc.x, damage.loc.y, damage.size.w, damage.size.h);
+            let outline = [
+                Rectangle::from_loc_and_size((x, y), (w, 1)),
+                Rectangle::from_loc_and_size((x, y + h - 1), (w, 1)),
+                Rectangle::from_loc_and_size((x, y), (1, h)),
+                Rectangle::from_loc_and_size((x + w - 1, y), (1, h)),
+            ];
+            elements.extend(outline.into_iter().map(|side| solid_element(side, DAMAGE_COLOR)));
+        }
+        elements
+    }
+}
+
+/// Element filling `geometry` of the output with `color`, new for every frame
+fn solid_element<R>(geometry: Rectangle<i32, Physical>, color: [f32; 4]) -> CustomRenderElements<R>
+where
+    R: Renderer + ImportAll + ImportMem,
+    <R as Renderer>::TextureId: Clone + 'static,
+{
+    CustomRenderElements::SolidColor(SolidColorRenderElement::new(Id::new(), geometry, CommitCounter::default(), color))
+}
@@ -0,0 +1,169 @@
+//! Finding the window next to another one in a direction, by the geometries the windows
+//! are shown at rather than their tiling order.
+
+use smithay::utils::{Logical, Point, Rectangle};
+
+/// Direction on the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+fn center(rect: Rectangle<i32, Logical>) -> Point<i32, Logical> {
+    (rect.loc.x + rect.size.w / 2, rect.loc.y + rect.size.h / 2).into()
+}
+
+/// Whether `a` and `b` share a stretch of the axis across `direction`
+fn overlaps_across(a: Rectangle<i32, Logical>, b: Rectangle<i32, Logical>, direction: Direction) -> bool {
+    match direction {
+        Direction::Left | Direction::Right => a.loc.y < b.loc.y + b.size.h && b.loc.y < a.loc.y + a.size.h,
+        Direction::Up | Direction::Down => a.loc.x < b.loc.x + b.size.w && b.loc.x < a.loc.x + a.size.w,
+    }
+}
+
+/// Distances of the center of `candidate` from the center of `from`, along `direction`
+/// and across it
+fn offsets(from: Rectangle<i32, Logical>, candidate: Rectangle<i32, Logical>, direction: Direction) -> (i32, i32) {
+    let offset = center(candidate) - center(from);
+    match direction {
+        Direction::Left => (-offset.x, offset.y.abs()),
+        Direction::Right => (offset.x, offset.y.abs()),
+        Direction::Up => (-offset.y, offset.x.abs()),
+        Direction::Down => (offset.y, offset.x.abs()),
+    }
+}
+
+/// Index of the geometry in `candidates` closest to `from` in `direction`, whose center lies
+/// beyond the center of `from`. Candidates sharing a row or column with `from` come first,
+/// then the nearest along the direction, then the nearest across it.
+pub fn neighbour(from: Rectangle<i32, Logical>,
+                 candidates: &[Rectangle<i32, Logical>],
+                 direction: Direction) -> Option<usize> {
+    candidates
+        .iter()
+        .enumerate()
+        .filter_map(|(index, &candidate)| {
+            let (along, across) = offsets(from, candidate, direction);
+            (along > 0).then_some((index, (!overlaps_across(from, candidate, direction), along, across)))
+        })
+        .min_by_key(|&(_, key)| key)
+        .map(|(index, _)| index)
+}
+
+/// Index of the geometry in `candidates` that going in `direction` from `from` wraps
+/// around to: the farthest one the opposite way, preferring the row or column of `from`
+pub fn wrap_around(from: Rectangle<i32, Logical>,
+                   candidates: &[Rectangle<i32, Logical>],
+                   direction: Direction) -> Option<usize> {
+    candidates
+        .iter()
+        .enumerate()
+        .filter_map(|(index, &candidate)| {
+            let (along, across) = offsets(from, candidate, direction);
+            (along < 0).then_some((index, (!overlaps_across(from, candidate, direction), along, across)))
+        })
+        .min_by_key(|&(_, key)| key)
+        .map(|(index, _)| index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: i32, y: i32, w: i32, h: i32) -> Rectangle<i32, Logical> {
+        Rectangle::from_loc_and_size((x, y), (w, h))
+    }
+
+    /// Three rows of three 100x100 cells, numbered row by row
+    fn grid() -> Vec<Rectangle<i32, Logical>> {
+        (0..9).map(|index| rect(index % 3 * 100, index / 3 * 100, 100, 100)).collect()
+    }
+
+    /// Master on the left and three stacked windows on the right
+    fn master_stack() -> Vec<Rectangle<i32, Logical>> {
+        vec![rect(0, 0, 500, 600), rect(500, 0, 500, 200), rect(500, 200, 500, 200), rect(500, 400, 500, 200)]
+    }
+
+    #[test]
+    fn neighbours_in_grid() {
+        let grid = grid();
+        assert_eq!(neighbour(grid[4], &grid, Direction::Left), Some(3));
+        assert_eq!(neighbour(grid[4], &grid, Direction::Right), Some(5));
+        assert_eq!(neighbour(grid[4], &grid, Direction::Up), Some(1));
+        assert_eq!(neighbour(grid[4], &grid, Direction::Down), Some(7));
+        assert_eq!(neighbour(grid[0], &grid, Direction::Right), Some(1));
+        assert_eq!(neighbour(grid[0], &grid, Direction::Down), Some(3));
+        assert_eq!(neighbour(grid[8], &grid, Direction::Left), Some(7));
+        assert_eq!(neighbour(grid[8], &grid, Direction::Up), Some(5));
+    }
+
+    #[test]
+    fn no_neighbour_at_grid_edges() {
+        let grid = grid();
+        assert_eq!(neighbour(grid[0], &grid, Direction::Left), None);
+        assert_eq!(neighbour(grid[0], &grid, Direction::Up), None);
+        assert_eq!(neighbour(grid[8], &grid, Direction::Right), None);
+        assert_eq!(neighbour(grid[8], &grid, Direction::Down), None);
+    }
+
+    #[test]
+    fn no_neighbour_without_candidates() {
+        assert_eq!(neighbour(rect(0, 0, 100, 100), &[], Direction::Right), None);
+    }
+
+    #[test]
+    fn neighbours_in_master_stack() {
+        let tiles = master_stack();
+        // The stack window closest to the center of the master across the direction
+        assert_eq!(neighbour(tiles[0], &tiles, Direction::Right), Some(2));
+        assert_eq!(neighbour(tiles[3], &tiles, Direction::Left), Some(0));
+        assert_eq!(neighbour(tiles[1], &tiles, Direction::Down), Some(2));
+        assert_eq!(neighbour(tiles[3], &tiles, Direction::Up), Some(2));
+        assert_eq!(neighbour(tiles[0], &tiles, Direction::Left), None);
+        assert_eq!(neighbour(tiles[1], &tiles, Direction::Up), None);
+    }
+
+    #[test]
+    fn neighbour_in_same_row_wins_over_closer_one() {
+        let from = rect(0, 0, 100, 100);
+        let candidates = [rect(120, 300, 100, 100), rect(150, 80, 100, 100)];
+        assert_eq!(neighbour(from, &candidates, Direction::Right), Some(1));
+    }
+
+    #[test]
+    fn neighbour_needs_center_beyond_center_of_window() {
+        let from = rect(0, 0, 200, 100);
+        // Below `from`, centered on the same vertical line
+        let candidates = [rect(80, 100, 40, 100)];
+        assert_eq!(neighbour(from, &candidates, Direction::Right), None);
+        assert_eq!(neighbour(from, &candidates, Direction::Left), None);
+        assert_eq!(neighbour(from, &candidates, Direction::Down), Some(0));
+    }
+
+    #[test]
+    fn wrap_around_grid() {
+        let grid = grid();
+        // The farthest cell the other way in the same row or column
+        assert_eq!(wrap_around(grid[2], &grid, Direction::Right), Some(0));
+        assert_eq!(wrap_around(grid[3], &grid, Direction::Left), Some(5));
+        assert_eq!(wrap_around(grid[8], &grid, Direction::Down), Some(2));
+        assert_eq!(wrap_around(grid[1], &grid, Direction::Up), Some(7));
+    }
+
+    #[test]
+    fn wrap_around_master_stack() {
+        let tiles = master_stack();
+        assert_eq!(wrap_around(tiles[2], &tiles, Direction::Right), Some(0));
+        assert_eq!(wrap_around(tiles[0], &tiles, Direction::Left), Some(2));
+        assert_eq!(wrap_around(tiles[3], &tiles, Direction::Down), Some(1));
+    }
+
+    #[test]
+    fn no_wrap_around_alone() {
+        let from = rect(0, 0, 100, 100);
+        assert_eq!(wrap_around(from, &[from], Direction::Left), None);
+    }
+}
@@ -0,0 +1,55 @@
+//! The linux-dmabuf protocol, through which clients rendering on the gpu or decoding video
+//! in hardware hand over their buffers without copying them through shared memory.
+//!
+//! Backends rendering with OpenGL ES advertise the formats and modifiers their renderer
+//! can sample from. Buffers of other formats are refused when the client creates them.
+//! The renderer imports a buffer when its surface is drawn, a surface whose buffer fails
+//! to import is left out of the frame.
+
+use std::collections::HashSet;
+
+use smithay::{
+    backend::allocator::{dmabuf::Dmabuf, Buffer, Format},
+    wayland::dmabuf::{DmabufGlobal, DmabufHandler, DmabufState, ImportError},
+};
+use tracing::{debug, info};
+
+use crate::TwmState;
+
+/// Dmabuf global of the backend and the formats it advertises
+#[derive(Debug, Default)]
+pub struct Dmabufs {
+    global: Option<DmabufGlobal>,
+    formats: HashSet<Format>,
+}
+
+impl TwmState {
+    /// Advertises linux-dmabuf with `formats` the renderer can import, called by backends
+    /// rendering on the gpu
+    pub fn create_dmabuf_global(&mut self, formats: HashSet<Format>) {
+        if formats.is_empty() {
+            info!("Renderer imports no dmabuf formats, not advertising linux-dmabuf");
+            return;
+        }
+        if let Some(global) = self.dmabufs.global.take() {
+            self.dmabuf_state.destroy_global::<TwmState>(&self.display_handle, global);
+        }
+        debug!("Advertising {} dmabuf formats", formats.len());
+        let global = self.dmabuf_state.create_global::<TwmState>(&self.display_handle, formats.iter().copied().collect());
+        self.dmabufs = Dmabufs { global: Some(global), formats };
+    }
+}
+
+impl DmabufHandler for TwmState {
+    fn dmabuf_state(&mut self) -> &mut DmabufState {
+        &mut self.dmabuf_state
+    }
+
+    fn dmabuf_imported(&mut self, _global: &DmabufGlobal, dmabuf: Dmabuf) -> Result<(), ImportError> {
+        if !self.dmabufs.formats.contains(&dmabuf.format()) {
+            debug!("Refusing dmabuf of unsupported format {:?}", dmabuf.format());
+            return Err(ImportError::InvalidFormat);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+//! Icon of drag-and-drop operations started by clients, drawn below the cursor while the
+//! drag lasts.
+
+use smithay::{
+    backend::renderer::{element::surface::render_elements_from_surface_tree, ImportAll, ImportMem, Renderer},
+    output::Output,
+    reexports::wayland_server::{protocol::wl_surface::WlSurface, Resource},
+    utils::{Logical, Point, Scale},
+    wayland::compositor::{with_states, SurfaceAttributes},
+};
+
+use crate::{render::CustomRenderElements, TwmState};
+
+/// Surface dragged along with the pointer
+#[derive(Debug)]
+pub struct DndIcon {
+    pub surface: WlSurface,
+    /// Location of the surface relative to the pointer, moved by the offsets of attached
+    /// buffers
+    offset: Point<i32, Logical>,
+}
+
+impl DndIcon {
+    pub fn new(surface: WlSurface) -> Self {
+        Self { surface, offset: Point::default() }
+    }
+}
+
+impl TwmState {
+    /// Picks up the buffer offset of the drag icon if `surface` is the icon
+    pub fn dnd_icon_commit(&mut self, surface: &WlSurface) {
+        let Some(icon) = self.dnd_icon.as_mut().filter(|icon| &icon.surface == surface) else {
+            return;
+        };
+        let delta = with_states(surface, |states| {
+            states.cached_state.current::<SurfaceAttributes>().buffer_delta.take()
+        });
+        if let Some(delta) = delta {
+            icon.offset += delta;
+        }
+    }
+
+    /// Render elements of the drag icon relative to `output`
+    pub fn dnd_icon_elements<R>(&self, renderer: &mut R, output: &Output) -> Vec<CustomRenderElements<R>>
+    where
+        R: Renderer + ImportAll + ImportMem,
+        <R as Renderer>::TextureId: Clone + 'static,
+    {
+        let (Some(icon), Some(pointer)) = (self.dnd_icon.as_ref(), self.seat.get_pointer()) else {
+            return Vec::new();
+        };
+        if !icon.surface.alive() {
+            return Vec::new();
+        }
+        let output_location = self.space
+            .output_geometry(output)
+            .map(|geometry| geometry.loc)
+            .unwrap_or_default();
+        let scale = Scale::from(output.current_scale().fractional_scale());
+        let location = pointer.current_location() - output_location.to_f64() + icon.offset.to_f64();
+        render_elements_from_surface_tree(renderer, &icon.surface, location.to_physical_precise_round(scale), scale, 1.0)
+            .into_iter()
+            .map(CustomRenderElements::Surface)
+            .collect()
+    }
+}
@@ -0,0 +1,405 @@
+use std::cell::Cell;
+
+use smithay::{
+    desktop::{layer_map_for_output, Window},
+    input::pointer::{Focus, GrabStartData as PointerGrabStartData},
+    reexports::wayland_protocols::xdg::shell::server::xdg_toplevel::ResizeEdge,
+    utils::{Logical, Point, Rectangle, Serial, Size},
+};
+use tracing::debug;
+
+use crate::{config::{Modifiers, Placement}, cursor::CursorIcon, grabs, initial_configure_sent, layout, tabs, TwmState};
+
+/// Floating state of a window, kept in the window's user data
+#[derive(Debug, Default)]
+struct FloatingState {
+    floating: Cell<bool>,
+    /// The window gets placed on its output once its size is known
+    needs_placement: Cell<bool>,
+    /// Placement set by a window rule
+    placement: Cell<Option<Placement>>,
+    /// Location of the window while its workspace is hidden, where it is shown again
+    hidden_location: Cell<Option<Point<i32, Logical>>>,
+}
+
+fn floating_state(window: &Window) -> &FloatingState {
+    window.user_data().insert_if_missing(FloatingState::default);
+    window.user_data().get::<FloatingState>().expect("Floating state available")
+}
+
+/// Location of a window of `size` placed in `area`, clamped so the window stays inside
+/// it as far as it fits. `others` are the geometries of the floating windows to avoid.
+fn place(placement: Placement,
+         size: Size<i32, Logical>,
+         area: Rectangle<i32, Logical>,
+         cursor: Point<i32, Logical>,
+         others: &[Rectangle<i32, Logical>]) -> Point<i32, Logical> {
+    let centered = Point::from((
+        area.loc.x + (area.size.w - size.w) / 2,
+        area.loc.y + (area.size.h - size.h) / 2,
+    ));
+    let location = match placement {
+        Placement::Center => centered,
+        Placement::UnderCursor => Point::from((cursor.x - size.w / 2, cursor.y - size.h / 2)),
+        Placement::Smart => {
+            // Next to the other windows or in the corner, wherever the overlap is smallest
+            let mut candidates = vec![centered, area.loc];
+            for other in others {
+                candidates.push((other.loc.x + other.size.w, other.loc.y).into());
+                candidates.push((other.loc.x, other.loc.y + other.size.h).into());
+            }
+            candidates
+                .into_iter()
+                .map(|location| clamp(location, size, area))
+                .min_by_key(|&location| {
+                    let rect = Rectangle::from_loc_and_size(location, size);
+                    let overlap: i64 = others
+                        .iter()
+                        .filter_map(|other| other.intersection(rect))
+                        .map(|overlap| overlap.size.w as i64 * overlap.size.h as i64)
+                        .sum();
+                    let distance = (location.x - centered.x).abs() + (location.y - centered.y).abs();
+                    (overlap, distance)
+                })
+                .unwrap_or(centered)
+        },
+    };
+    clamp(location, size, area)
+}
+
+/// Corner of `geometry` closest to `position`, which follows the pointer in a resize
+fn closest_corner(geometry: Rectangle<i32, Logical>, position: Point<f64, Logical>) -> ResizeEdge {
+    let center_x = geometry.loc.x as f64 + geometry.size.w as f64 / 2.0;
+    let center_y = geometry.loc.y as f64 + geometry.size.h as f64 / 2.0;
+    match (position.y < center_y, position.x < center_x) {
+        (true, true) => ResizeEdge::TopLeft,
+        (true, false) => ResizeEdge::TopRight,
+        (false, true) => ResizeEdge::BottomLeft,
+        (false, false) => ResizeEdge::BottomRight,
+    }
+}
+
+/// Moves `location` so a window of `size` lies in `area`, windows larger than `area`
+/// keep their top left corner inside it
+fn clamp(location: Point<i32, Logical>, size: Size<i32, Logical>, area: Rectangle<i32, Logical>) -> Point<i32, Logical> {
+    let max_x = (area.loc.x + area.size.w - size.w).max(area.loc.x);
+    let max_y = (area.loc.y + area.size.h - size.h).max(area.loc.y);
+    (location.x.clamp(area.loc.x, max_x), location.y.clamp(area.loc.y, max_y)).into()
+}
+
+impl TwmState {
+    /// Whether the window is excluded from tiling
+    pub fn is_floating(&self, window: &Window) -> bool {
+        floating_state(window).floating.get()
+    }
+
+    /// Floats or tiles the focused window
+    pub fn toggle_floating(&mut self) {
+        let Some(window) = self.focused_window() else {
+            debug!("No focused window to toggle floating");
+            return;
+        };
+        let floating = !self.is_floating(&window);
+        self.set_floating(&window, floating);
+    }
+
+    /// Floating windows keep their size and location, windows going back to tiling are
+    /// appended to the tiling order
+    pub fn set_floating(&mut self, window: &Window, floating: bool) {
+        debug!("Setting window floating: {}", floating);
+        floating_state(window).floating.set(floating);
+        if floating {
+            // Floating windows leave their tabbed container
+            if let Some(workspace) = self.workspace_of_mut(window) {
+                tabs::remove_from_containers(&mut workspace.containers, window);
+            }
+            self.space.raise_element(window, true);
+        } else {
+            if let Some(workspace) = self.workspace_of_mut(window) {
+                workspace.remove_window(window);
+                workspace.windows.push(window.clone());
+            }
+        }
+        self.retile();
+    }
+
+    /// Unmaps `window` along with its hidden workspace, floating windows are shown at the
+    /// same location again
+    pub fn hide_window(&mut self, window: &Window) {
+        if let Some(location) = self.space.element_location(window).filter(|_| self.is_floating(window)) {
+            floating_state(window).hidden_location.set(Some(location));
+        }
+        self.space.unmap_elem(window);
+    }
+
+    /// Maps `window` as its workspace is shown, at its former location if it floats. Tiled
+    /// windows get their place from the next retile.
+    pub fn unhide_window(&mut self, window: &Window) {
+        let location = floating_state(window).hidden_location.take().filter(|_| self.is_floating(window));
+        self.space.map_element(window.clone(), location.unwrap_or_default(), false);
+    }
+
+    /// Moves the location a hidden floating window is shown at again by `offset`
+    pub fn shift_hidden_location(&self, window: &Window, offset: Point<i32, Logical>) {
+        let state = floating_state(window);
+        if let Some(location) = state.hidden_location.get() {
+            state.hidden_location.set(Some(location + offset));
+        }
+    }
+
+    /// Places `window` with `placement` instead of the configured floating placement when
+    /// it floats from the start
+    pub fn set_placement(&mut self, window: &Window, placement: Placement) {
+        floating_state(window).placement.set(Some(placement));
+    }
+
+    /// Marks a new window as floating, it is placed on its output after its first commit
+    pub fn float_new_window(&mut self, window: &Window) {
+        let state = floating_state(window);
+        state.floating.set(true);
+        state.needs_placement.set(true);
+    }
+
+    /// Floats the tiled `window` whose minimum size doesn't fit its tile, at least at its
+    /// minimum size. It is placed like a new floating window once the client took the size.
+    pub fn float_oversized_window(&mut self, window: &Window) {
+        debug!("Floating window that doesn't fit its tile");
+        let state = floating_state(window);
+        state.floating.set(true);
+        state.needs_placement.set(true);
+        if let Some(workspace) = self.workspace_of_mut(window) {
+            tabs::remove_from_containers(&mut workspace.containers, window);
+        }
+        self.cancel_pending_move(window);
+
+        let min_size = layout::window_size_hints(window).0.unwrap_or_default();
+        let current = window.geometry().size;
+        let size = Size::from((current.w.max(min_size.w), current.h.max(min_size.h)));
+        window.toplevel().with_pending_state(|state| state.size = Some(size));
+        if initial_configure_sent(window.toplevel()) {
+            window.toplevel().send_pending_configure();
+        }
+        if size == current {
+            self.place_floating_window(window);
+        }
+    }
+
+    /// Places a new floating window in the area of its output not taken by panels once
+    /// the client chose its size
+    pub fn place_floating_window(&mut self, window: &Window) {
+        let state = floating_state(window);
+        let size = window.geometry().size;
+        if !state.needs_placement.get() || size.w <= 0 || size.h <= 0 {
+            return;
+        }
+        let Some(output) = self.output_for_window(window).or_else(|| self.active_output()) else {
+            return;
+        };
+        let Some(output_geometry) = self.space.output_geometry(&output) else {
+            return;
+        };
+        state.needs_placement.set(false);
+
+        let mut area = layer_map_for_output(&output).non_exclusive_zone();
+        area.loc += output_geometry.loc;
+        let placement = state.placement.get().unwrap_or(self.config.floating_placement);
+        let cursor = self.seat
+            .get_pointer()
+            .map(|pointer| pointer.current_location().to_i32_round())
+            .unwrap_or_default();
+        let others: Vec<_> = self.space
+            .elements()
+            .filter(|&other| other != window && self.is_floating(other))
+            .filter_map(|other| self.space.element_geometry(other))
+            .filter(|geometry| geometry.overlaps(output_geometry))
+            .collect();
+
+        let location = place(placement, size, area, cursor, &others);
+        debug!("Placing floating window at {:?} ({:?})", location, placement);
+        self.space.map_element(window.clone(), location, true);
+    }
+
+    /// Floating window whose border is under `position` and the edges of the window that
+    /// border is on
+    pub fn floating_border_under(&self, position: Point<f64, Logical>) -> Option<(Window, ResizeEdge)> {
+        let width = self.config.total_border_width();
+        if width <= 0 {
+            return None;
+        }
+        // Topmost window first
+        self.space
+            .elements()
+            .rev()
+            .filter(|window| self.is_floating(window) && !self.is_maximized(window))
+            .find_map(|window| {
+                let geometry = self.space.element_geometry(window)?;
+                let outer = Rectangle::from_loc_and_size(
+                    geometry.loc - Point::from((width, width)),
+                    (geometry.size.w + 2 * width, geometry.size.h + 2 * width));
+                if !outer.to_f64().contains(position) || geometry.to_f64().contains(position) {
+                    return None;
+                }
+
+                let top = position.y < geometry.loc.y as f64;
+                let bottom = position.y >= (geometry.loc.y + geometry.size.h) as f64;
+                let left = position.x < geometry.loc.x as f64;
+                let right = position.x >= (geometry.loc.x + geometry.size.w) as f64;
+                let edges = match (top, bottom, left, right) {
+                    (true, _, true, _) => ResizeEdge::TopLeft,
+                    (true, _, _, true) => ResizeEdge::TopRight,
+                    (_, true, true, _) => ResizeEdge::BottomLeft,
+                    (_, true, _, true) => ResizeEdge::BottomRight,
+                    (true, ..) => ResizeEdge::Top,
+                    (_, true, ..) => ResizeEdge::Bottom,
+                    (_, _, true, _) => ResizeEdge::Left,
+                    _ => ResizeEdge::Right,
+                };
+                Some((window.clone(), edges))
+            })
+    }
+
+    /// Moves or resizes the floating window under the pointer while `button` stays pressed,
+    /// if the configured mouse modifier is held and `button` is one of the drag buttons.
+    /// Returns whether a drag started, the press doesn't reach the client then.
+    pub fn start_modifier_drag(&mut self, button: u32, serial: Serial) -> bool {
+        let mouse = &self.config.mouse;
+        if button != mouse.move_button.code() && button != mouse.resize_button.code() {
+            return false;
+        }
+        let Some(modifiers) = self.seat.get_keyboard().map(|keyboard| keyboard.modifier_state()) else {
+            return false;
+        };
+        if Modifiers::from(&modifiers) != mouse.modifier {
+            return false;
+        }
+        let pointer = self.seat.get_pointer().expect("Pointer available");
+        let position = pointer.current_location();
+        // X11 windows float above the others
+        if let Some(window) = self.x11_window_under(position).filter(|window| !window.is_override_redirect()) {
+            self.focus_x11_window(&window);
+            let start_data = PointerGrabStartData { focus: None, button, location: position };
+            if button == self.config.mouse.move_button.code() {
+                debug!("Moving X11 window with the mouse modifier");
+                self.start_x11_move(window, start_data, serial);
+            } else {
+                let edges = closest_corner(window.geometry(), position);
+                self.start_x11_resize(window, start_data, edges, serial);
+            }
+            return true;
+        }
+        let Some((window, _)) = self.space
+            .element_under(position)
+            .filter(|(window, _)| self.is_floating(window) && !self.is_maximized(window))
+            .map(|(window, location)| (window.clone(), location)) else {
+            return false;
+        };
+        let Some(geometry) = self.space.element_geometry(&window) else {
+            return false;
+        };
+        self.focus_window(Some(&window));
+
+        if button == self.config.mouse.move_button.code() {
+            debug!("Moving floating window with the mouse modifier");
+            let grab = grabs::MoveSurfaceGrab {
+                start_data: PointerGrabStartData { focus: None, button, location: position },
+                window,
+                initial_window_location: geometry.loc,
+            };
+            self.show_cursor_icon(CursorIcon::Move);
+            pointer.set_grab(self, grab, serial, Focus::Clear);
+        } else {
+            let edges = closest_corner(geometry, position);
+            self.start_border_resize(window, edges, button, serial);
+        }
+        true
+    }
+
+    /// Resizes a floating window from its border while `button` stays pressed
+    pub fn start_border_resize(&mut self, window: Window, edges: ResizeEdge, button: u32, serial: Serial) {
+        let Some(geometry) = self.space.element_geometry(&window) else {
+            return;
+        };
+        let pointer = self.seat.get_pointer().expect("Pointer available");
+        let start_data = PointerGrabStartData {
+            focus: None,
+            button,
+            location: pointer.current_location(),
+        };
+        debug!("Resizing floating window from its border");
+        let grab = grabs::ResizeSurfaceGrab::new(start_data, window, edges, geometry);
+        self.show_cursor_icon(CursorIcon::Resize(edges));
+        pointer.set_grab(self, grab, serial, Focus::Clear);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: i32, y: i32, w: i32, h: i32) -> Rectangle<i32, Logical> {
+        Rectangle::from_loc_and_size((x, y), (w, h))
+    }
+
+    fn area() -> Rectangle<i32, Logical> {
+        rect(0, 0, 1000, 600)
+    }
+
+    fn size() -> Size<i32, Logical> {
+        (200, 100).into()
+    }
+
+    #[test]
+    fn center_places_in_the_middle_of_the_area() {
+        assert_eq!(place(Placement::Center, size(), area(), (0, 0).into(), &[]), (400, 250).into());
+        let second_output = rect(1000, 0, 1000, 600);
+        assert_eq!(place(Placement::Center, size(), second_output, (0, 0).into(), &[]), (1400, 250).into());
+    }
+
+    #[test]
+    fn under_cursor_centers_on_the_cursor() {
+        assert_eq!(place(Placement::UnderCursor, size(), area(), (300, 200).into(), &[]), (200, 150).into());
+    }
+
+    #[test]
+    fn under_cursor_stays_inside_the_area() {
+        assert_eq!(place(Placement::UnderCursor, size(), area(), (10, 10).into(), &[]), (0, 0).into());
+        assert_eq!(place(Placement::UnderCursor, size(), area(), (990, 590).into(), &[]), (800, 500).into());
+    }
+
+    #[test]
+    fn smart_centers_without_other_windows() {
+        assert_eq!(place(Placement::Smart, size(), area(), (0, 0).into(), &[]), (400, 250).into());
+    }
+
+    #[test]
+    fn smart_avoids_other_windows() {
+        // Below the centered window is closest to the center without overlapping
+        let others = [rect(400, 250, 200, 100)];
+        assert_eq!(place(Placement::Smart, size(), area(), (0, 0).into(), &others), (400, 350).into());
+
+        // With that taken too, right of the centered window comes first of the closest
+        let others = [rect(400, 250, 200, 100), rect(400, 350, 200, 100)];
+        assert_eq!(place(Placement::Smart, size(), area(), (0, 0).into(), &others), (600, 250).into());
+    }
+
+    #[test]
+    fn smart_takes_the_least_overlap_when_nothing_is_free() {
+        let others = [area()];
+        let location = place(Placement::Smart, size(), area(), (0, 0).into(), &others);
+        assert!(area().contains_rect(Rectangle::from_loc_and_size(location, size())));
+    }
+
+    #[test]
+    fn clamp_keeps_windows_inside() {
+        assert_eq!(clamp((100, 100).into(), size(), area()), (100, 100).into());
+        assert_eq!(clamp((-50, -20).into(), size(), area()), (0, 0).into());
+        assert_eq!(clamp((900, 550).into(), size(), area()), (800, 500).into());
+    }
+
+    #[test]
+    fn clamp_keeps_the_top_left_corner_of_large_windows_inside() {
+        let large = (1200, 700).into();
+        assert_eq!(clamp((100, 100).into(), large, area()), (0, 0).into());
+        assert_eq!(clamp((-100, -100).into(), large, rect(1000, 0, 1000, 600)), (1000, 0).into());
+    }
+}
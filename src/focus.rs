@@ -0,0 +1,107 @@
+use smithay::{desktop::Window, xwayland::X11Surface};
+use tracing::debug;
+
+use crate::{direction::{self, Direction}, TwmState};
+
+/// Window the focus can move to, X11 windows live apart from the others
+#[derive(PartialEq)]
+enum FocusTarget {
+    Window(Window),
+    X11(X11Surface),
+}
+
+impl TwmState {
+    /// Focuses the window after the focused one in tiling order, wrapping around
+    pub fn focus_next(&mut self) {
+        self.cycle_focus(1);
+    }
+
+    /// Focuses the window before the focused one in tiling order, wrapping around
+    pub fn focus_prev(&mut self) {
+        self.cycle_focus(-1);
+    }
+
+    fn cycle_focus(&mut self, step: isize) {
+        // X11 windows come after the others
+        let workspace = self.current_workspace();
+        let targets: Vec<FocusTarget> = workspace.windows
+            .iter()
+            .cloned()
+            .map(FocusTarget::Window)
+            .chain(workspace.x11_windows.iter().cloned().map(FocusTarget::X11))
+            .collect();
+        if targets.is_empty() {
+            return;
+        }
+
+        let next = match self.focused_target_index(&targets) {
+            Some(index) => (index as isize + step).rem_euclid(targets.len() as isize) as usize,
+            // Without a focused window the first one gets focus
+            None => 0,
+        };
+        self.focus_target(&targets[next]);
+    }
+
+    /// Focuses the window next to the focused one in `direction` on its output, by where
+    /// the windows are shown. At the edge of the output the focus wraps around to the other
+    /// side if `focus_wrap` is set and stays otherwise.
+    pub fn focus_in_direction(&mut self, direction: Direction) {
+        let focused = match (self.focused_window(), self.focused_x11_window()) {
+            (Some(window), _) => FocusTarget::Window(window),
+            (None, Some(window)) => FocusTarget::X11(window),
+            (None, None) => {
+                // Without a focused window the last one of the workspace gets focus
+                let last = self.current_workspace().windows.last().cloned();
+                self.focus_window(last.as_ref());
+                return;
+            },
+        };
+        let (output, geometry) = match &focused {
+            FocusTarget::Window(window) => (self.output_for_window(window), self.space.element_geometry(window)),
+            FocusTarget::X11(window) => (self.x11_output(window), Some(window.geometry())),
+        };
+        let (Some(output), Some(geometry)) = (output, geometry) else {
+            return;
+        };
+        let x11_windows = self.output_workspace(&output)
+            .map(|workspace| workspace.x11_windows.clone())
+            .unwrap_or_default();
+        let (targets, geometries): (Vec<FocusTarget>, Vec<_>) = self.windows_on_output(&output)
+            .into_iter()
+            .filter_map(|window| {
+                let geometry = self.space.element_geometry(&window)?;
+                Some((FocusTarget::Window(window), geometry))
+            })
+            .chain(x11_windows.into_iter().map(|window| {
+                let geometry = window.geometry();
+                (FocusTarget::X11(window), geometry)
+            }))
+            .filter(|(target, _)| target != &focused)
+            .unzip();
+
+        let target = direction::neighbour(geometry, &geometries, direction).or_else(|| {
+            self.config.focus_wrap.then(|| direction::wrap_around(geometry, &geometries, direction)).flatten()
+        });
+        match target {
+            Some(index) => self.focus_target(&targets[index]),
+            None => debug!("No window {:?} of the focused one", direction),
+        }
+    }
+
+    /// Index of the focused window among `targets`
+    fn focused_target_index(&self, targets: &[FocusTarget]) -> Option<usize> {
+        let focused = self.focused_window();
+        let focused_x11 = self.focused_x11_window();
+        targets.iter().position(|target| match target {
+            FocusTarget::Window(window) => Some(window) == focused.as_ref(),
+            FocusTarget::X11(window) => Some(window) == focused_x11.as_ref(),
+        })
+    }
+
+    fn focus_target(&mut self, target: &FocusTarget) {
+        match target {
+            FocusTarget::Window(window) => self.focus_window(Some(window)),
+            FocusTarget::X11(window) => self.focus_x11_window(window),
+        }
+    }
+}
@@ -0,0 +1,265 @@
+//! The wlr-foreign-toplevel-management protocol for taskbars and window switchers.
+//!
+//! Every window of every workspace gets a handle per bound manager. Handles are brought up
+//! to date once per event loop iteration by comparing against what was sent last, so title,
+//! app id, state and output changes need no notification from the code causing them.
+
+use smithay::{
+    desktop::Window,
+    output::Output,
+    reexports::{
+        wayland_protocols::xdg::shell::server::xdg_toplevel,
+        wayland_protocols_wlr::foreign_toplevel::v1::server::{
+            zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
+            zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1},
+        },
+        wayland_server::{
+            backend::{ClientId, GlobalId, ObjectId},
+            Client,
+            DataInit,
+            Dispatch,
+            DisplayHandle,
+            GlobalDispatch,
+            New,
+            Resource,
+        },
+    },
+};
+use tracing::{debug, warn};
+
+use crate::{window_info::WindowInfo, TwmState};
+
+/// Everything a handle tells its client about a window
+#[derive(Debug, Default, Clone, PartialEq)]
+struct ToplevelSnapshot {
+    info: WindowInfo,
+    states: Vec<zwlr_foreign_toplevel_handle_v1::State>,
+    output: Option<Output>,
+}
+
+/// Handles of one window and the snapshot they were last updated with
+#[derive(Debug)]
+struct ForeignToplevel {
+    window: Window,
+    handles: Vec<ZwlrForeignToplevelHandleV1>,
+    sent: ToplevelSnapshot,
+}
+
+#[derive(Debug)]
+pub struct ForeignToplevelState {
+    _global: GlobalId,
+    managers: Vec<ZwlrForeignToplevelManagerV1>,
+    toplevels: Vec<ForeignToplevel>,
+}
+
+impl ForeignToplevelState {
+    pub fn new(display_handle: &DisplayHandle) -> Self {
+        Self {
+            _global: display_handle.create_global::<TwmState, ZwlrForeignToplevelManagerV1, _>(3, ()),
+            managers: Vec::new(),
+            toplevels: Vec::new(),
+        }
+    }
+}
+
+impl TwmState {
+    /// Creates handles for new windows, updates the handles of changed ones and closes the
+    /// handles of windows that are gone
+    pub fn refresh_foreign_toplevels(&mut self) {
+        let windows: Vec<Window> = self.all_windows().cloned().collect();
+
+        self.foreign_toplevels.toplevels.retain(|toplevel| {
+            let alive = windows.contains(&toplevel.window);
+            if !alive {
+                toplevel.handles.iter().for_each(|handle| handle.closed());
+            }
+            alive
+        });
+
+        for window in windows {
+            let snapshot = self.toplevel_snapshot(&window);
+            let state = &mut self.foreign_toplevels;
+            let index = match state.toplevels.iter().position(|toplevel| toplevel.window == window) {
+                Some(index) => index,
+                None => {
+                    let handles = state.managers
+                        .iter()
+                        .filter_map(|manager| create_handle(&self.display_handle, manager))
+                        .collect();
+                    state.toplevels.push(ForeignToplevel {
+                        window,
+                        handles,
+                        sent: ToplevelSnapshot::default(),
+                    });
+                    state.toplevels.len() - 1
+                }
+            };
+
+            let toplevel = &mut state.toplevels[index];
+            if toplevel.sent != snapshot {
+                toplevel.handles.iter().for_each(|handle| send_changes(handle, &toplevel.sent, &snapshot));
+                toplevel.sent = snapshot;
+            }
+        }
+    }
+
+    fn toplevel_snapshot(&self, window: &Window) -> ToplevelSnapshot {
+        let (maximized, fullscreen) = window.toplevel().with_pending_state(|state| (
+            state.states.contains(xdg_toplevel::State::Maximized),
+            state.states.contains(xdg_toplevel::State::Fullscreen),
+        ));
+        let activated = self.focused_window().as_ref() == Some(window);
+        let minimized = self.is_minimized(window);
+
+        let states = [
+            (maximized, zwlr_foreign_toplevel_handle_v1::State::Maximized),
+            (fullscreen, zwlr_foreign_toplevel_handle_v1::State::Fullscreen),
+            (activated, zwlr_foreign_toplevel_handle_v1::State::Activated),
+            (minimized, zwlr_foreign_toplevel_handle_v1::State::Minimized),
+        ]
+        .into_iter()
+        .filter_map(|(set, state)| set.then_some(state))
+        .collect();
+
+        ToplevelSnapshot {
+            info: self.window_info(window),
+            states,
+            output: self.output_for_window(window),
+        }
+    }
+
+    fn foreign_toplevel_window(&self, handle: &ZwlrForeignToplevelHandleV1) -> Option<Window> {
+        self.foreign_toplevels.toplevels
+            .iter()
+            .find(|toplevel| toplevel.handles.contains(handle))
+            .map(|toplevel| toplevel.window.clone())
+    }
+
+    /// Shows the workspace of `window` on its output and focuses it, restoring it first if
+    /// it is minimized
+    pub fn activate_window(&mut self, window: &Window) {
+        self.restore_window(window);
+        let Some((output_index, index)) = self.workspace_index(window) else {
+            return;
+        };
+        self.show_workspace(output_index, index);
+        self.focus_window(Some(window));
+    }
+}
+
+fn create_handle(display_handle: &DisplayHandle,
+                 manager: &ZwlrForeignToplevelManagerV1) -> Option<ZwlrForeignToplevelHandleV1> {
+    let client = manager.client()?;
+    match client.create_resource::<ZwlrForeignToplevelHandleV1, _, TwmState>(display_handle, manager.version(), ()) {
+        Ok(handle) => {
+            manager.toplevel(&handle);
+            Some(handle)
+        },
+        Err(err) => {
+            warn!("Failed to create a foreign toplevel handle: {}", err);
+            None
+        }
+    }
+}
+
+/// Sends everything that differs between `sent` and `snapshot`, followed by `done`
+fn send_changes(handle: &ZwlrForeignToplevelHandleV1, sent: &ToplevelSnapshot, snapshot: &ToplevelSnapshot) {
+    if sent.info.title != snapshot.info.title {
+        handle.title(snapshot.info.title.clone().unwrap_or_default());
+    }
+    if sent.info.app_id != snapshot.info.app_id {
+        handle.app_id(snapshot.info.app_id.clone().unwrap_or_default());
+    }
+    if sent.states != snapshot.states {
+        let states = snapshot.states
+            .iter()
+            .flat_map(|&state| (state as u32).to_ne_bytes())
+            .collect();
+        handle.state(states);
+    }
+    if sent.output != snapshot.output {
+        if let Some(client) = handle.client() {
+            for output in sent.output.iter().flat_map(|output| output.client_outputs(&client)) {
+                handle.output_leave(&output);
+            }
+            for output in snapshot.output.iter().flat_map(|output| output.client_outputs(&client)) {
+                handle.output_enter(&output);
+            }
+        }
+    }
+    handle.done();
+}
+
+impl GlobalDispatch<ZwlrForeignToplevelManagerV1, ()> for TwmState {
+    fn bind(state: &mut Self,
+            handle: &DisplayHandle,
+            _client: &Client,
+            resource: New<ZwlrForeignToplevelManagerV1>,
+            _global_data: &(),
+            data_init: &mut DataInit<'_, Self>) {
+        let manager = data_init.init(resource, ());
+        debug!("New foreign toplevel manager");
+
+        // Existing windows are announced right away with their full state
+        for toplevel in state.foreign_toplevels.toplevels.iter_mut() {
+            if let Some(toplevel_handle) = create_handle(handle, &manager) {
+                send_changes(&toplevel_handle, &ToplevelSnapshot::default(), &toplevel.sent);
+                toplevel.handles.push(toplevel_handle);
+            }
+        }
+        state.foreign_toplevels.managers.push(manager);
+    }
+}
+
+impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for TwmState {
+    fn request(state: &mut Self,
+               _client: &Client,
+               resource: &ZwlrForeignToplevelManagerV1,
+               request: zwlr_foreign_toplevel_manager_v1::Request,
+               _data: &(),
+               _handle: &DisplayHandle,
+               _data_init: &mut DataInit<'_, Self>) {
+        if let zwlr_foreign_toplevel_manager_v1::Request::Stop = request {
+            state.foreign_toplevels.managers.retain(|manager| manager != resource);
+            resource.finished();
+        }
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, resource: ObjectId, _data: &()) {
+        state.foreign_toplevels.managers.retain(|manager| manager.id() != resource);
+    }
+}
+
+impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for TwmState {
+    fn request(state: &mut Self,
+               _client: &Client,
+               resource: &ZwlrForeignToplevelHandleV1,
+               request: zwlr_foreign_toplevel_handle_v1::Request,
+               _data: &(),
+               _handle: &DisplayHandle,
+               _data_init: &mut DataInit<'_, Self>) {
+        let Some(window) = state.foreign_toplevel_window(resource) else {
+            return;
+        };
+        match request {
+            zwlr_foreign_toplevel_handle_v1::Request::Activate { .. } => state.activate_window(&window),
+            zwlr_foreign_toplevel_handle_v1::Request::Close => state.close_window(&window),
+            zwlr_foreign_toplevel_handle_v1::Request::SetMaximized => state.maximize_window(&window),
+            zwlr_foreign_toplevel_handle_v1::Request::UnsetMaximized => state.unmaximize_window(&window),
+            zwlr_foreign_toplevel_handle_v1::Request::SetFullscreen { .. } => state.fullscreen_window(&window),
+            zwlr_foreign_toplevel_handle_v1::Request::UnsetFullscreen => state.unfullscreen_window(&window),
+            zwlr_foreign_toplevel_handle_v1::Request::SetMinimized => state.minimize_window(&window),
+            zwlr_foreign_toplevel_handle_v1::Request::UnsetMinimized => state.restore_window(&window),
+            // There is no minimize animation to aim at the taskbar entry
+            zwlr_foreign_toplevel_handle_v1::Request::SetRectangle { .. } => {},
+            zwlr_foreign_toplevel_handle_v1::Request::Destroy => {},
+            _ => {},
+        }
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, resource: ObjectId, _data: &()) {
+        for toplevel in state.foreign_toplevels.toplevels.iter_mut() {
+            toplevel.handles.retain(|handle| handle.id() != resource);
+        }
+    }
+}
@@ -0,0 +1,199 @@
+//! Touchpad gestures, forwarded to clients through the pointer-gestures protocol.
+//!
+//! Swipes and pinches with a finger count some configured gesture uses are taken by the
+//! compositor instead: the whole gesture is withheld from clients and the action of the
+//! matching binding runs once the fingers are lifted. Holds always reach clients.
+
+use smithay::{
+    backend::input::{
+        Event,
+        GestureBeginEvent,
+        GestureEndEvent,
+        GesturePinchUpdateEvent as _,
+        GestureSwipeUpdateEvent as _,
+        InputBackend,
+    },
+    input::pointer::{
+        GestureHoldBeginEvent,
+        GestureHoldEndEvent,
+        GesturePinchBeginEvent,
+        GesturePinchEndEvent,
+        GesturePinchUpdateEvent,
+        GestureSwipeBeginEvent,
+        GestureSwipeEndEvent,
+        GestureSwipeUpdateEvent,
+    },
+    utils::{Logical, Point, SERIAL_COUNTER},
+};
+use tracing::debug;
+
+use crate::{
+    config::{Gesture, PinchDirection, SwipeDirection},
+    TwmState,
+};
+
+/// Distance in pointer units a swipe has to cover to trigger its action
+const SWIPE_THRESHOLD: f64 = 100.0;
+/// Factor the finger distance has to change by for a pinch to trigger its action
+const PINCH_THRESHOLD: f64 = 0.25;
+
+/// Swipe or pinch taken by the compositor
+#[derive(Debug)]
+pub enum CompositorGesture {
+    Swipe { fingers: u32, delta: Point<f64, Logical> },
+    Pinch { fingers: u32, scale: f64 },
+}
+
+impl CompositorGesture {
+    /// Gesture the fingers made so far, `None` while they didn't move far enough
+    fn recognize(&self) -> Option<(u32, Gesture)> {
+        match *self {
+            CompositorGesture::Swipe { fingers, delta } => {
+                if delta.x.abs().max(delta.y.abs()) < SWIPE_THRESHOLD {
+                    return None;
+                }
+                let direction = if delta.x.abs() >= delta.y.abs() {
+                    if delta.x < 0.0 { SwipeDirection::Left } else { SwipeDirection::Right }
+                } else if delta.y < 0.0 {
+                    SwipeDirection::Up
+                } else {
+                    SwipeDirection::Down
+                };
+                Some((fingers, Gesture::Swipe(direction)))
+            },
+            CompositorGesture::Pinch { fingers, scale } => {
+                if (scale - 1.0).abs() < PINCH_THRESHOLD {
+                    return None;
+                }
+                let direction = if scale < 1.0 { PinchDirection::In } else { PinchDirection::Out };
+                Some((fingers, Gesture::Pinch(direction)))
+            },
+        }
+    }
+}
+
+impl TwmState {
+    /// Whether some configured gesture of the kind of `gesture` uses `fingers`
+    fn gesture_bound(&self, fingers: u32, swipe: bool) -> bool {
+        self.config.gestures.iter().any(|binding| {
+            binding.fingers == fingers && matches!(binding.gesture, Gesture::Swipe(_)) == swipe
+        })
+    }
+
+    /// Runs the action bound to the gesture taken by the compositor, if it was completed
+    fn finish_compositor_gesture(&mut self, cancelled: bool) {
+        let Some(gesture) = self.compositor_gesture.take() else {
+            return;
+        };
+        let Some((fingers, recognized)) = gesture.recognize().filter(|_| !cancelled) else {
+            return;
+        };
+        let action = self.config.gestures
+            .iter()
+            .find(|binding| binding.fingers == fingers && binding.gesture == recognized)
+            .map(|binding| binding.action.clone());
+        if let Some(action) = action {
+            debug!("Gesture {:?} with {} fingers", recognized, fingers);
+            self.handle_action(action);
+        }
+    }
+
+    pub fn on_gesture_swipe_begin<I: InputBackend>(&mut self, event: I::GestureSwipeBeginEvent) {
+        let fingers = event.fingers();
+        if self.gesture_bound(fingers, true) {
+            self.compositor_gesture = Some(CompositorGesture::Swipe { fingers, delta: Point::default() });
+            return;
+        }
+        let pointer = self.seat.get_pointer().expect("Pointer available");
+        pointer.gesture_swipe_begin(self, &GestureSwipeBeginEvent {
+            serial: SERIAL_COUNTER.next_serial(),
+            time: event.time_msec(),
+            fingers,
+        });
+    }
+
+    pub fn on_gesture_swipe_update<I: InputBackend>(&mut self, event: I::GestureSwipeUpdateEvent) {
+        if let Some(CompositorGesture::Swipe { delta, .. }) = &mut self.compositor_gesture {
+            *delta += event.delta();
+            return;
+        }
+        let pointer = self.seat.get_pointer().expect("Pointer available");
+        pointer.gesture_swipe_update(self, &GestureSwipeUpdateEvent {
+            time: event.time_msec(),
+            delta: event.delta(),
+        });
+    }
+
+    pub fn on_gesture_swipe_end<I: InputBackend>(&mut self, event: I::GestureSwipeEndEvent) {
+        if let Some(CompositorGesture::Swipe { .. }) = self.compositor_gesture {
+            self.finish_compositor_gesture(event.cancelled());
+            return;
+        }
+        let pointer = self.seat.get_pointer().expect("Pointer available");
+        pointer.gesture_swipe_end(self, &GestureSwipeEndEvent {
+            serial: SERIAL_COUNTER.next_serial(),
+            time: event.time_msec(),
+            cancelled: event.cancelled(),
+        });
+    }
+
+    pub fn on_gesture_pinch_begin<I: InputBackend>(&mut self, event: I::GesturePinchBeginEvent) {
+        let fingers = event.fingers();
+        if self.gesture_bound(fingers, false) {
+            self.compositor_gesture = Some(CompositorGesture::Pinch { fingers, scale: 1.0 });
+            return;
+        }
+        let pointer = self.seat.get_pointer().expect("Pointer available");
+        pointer.gesture_pinch_begin(self, &GesturePinchBeginEvent {
+            serial: SERIAL_COUNTER.next_serial(),
+            time: event.time_msec(),
+            fingers,
+        });
+    }
+
+    pub fn on_gesture_pinch_update<I: InputBackend>(&mut self, event: I::GesturePinchUpdateEvent) {
+        if let Some(CompositorGesture::Pinch { scale, .. }) = &mut self.compositor_gesture {
+            // Scales are relative to the start of the gesture
+            *scale = event.scale();
+            return;
+        }
+        let pointer = self.seat.get_pointer().expect("Pointer available");
+        pointer.gesture_pinch_update(self, &GesturePinchUpdateEvent {
+            time: event.time_msec(),
+            delta: event.delta(),
+            scale: event.scale(),
+            rotation: event.rotation(),
+        });
+    }
+
+    pub fn on_gesture_pinch_end<I: InputBackend>(&mut self, event: I::GesturePinchEndEvent) {
+        if let Some(CompositorGesture::Pinch { .. }) = self.compositor_gesture {
+            self.finish_compositor_gesture(event.cancelled());
+            return;
+        }
+        let pointer = self.seat.get_pointer().expect("Pointer available");
+        pointer.gesture_pinch_end(self, &GesturePinchEndEvent {
+            serial: SERIAL_COUNTER.next_serial(),
+            time: event.time_msec(),
+            cancelled: event.cancelled(),
+        });
+    }
+
+    pub fn on_gesture_hold_begin<I: InputBackend>(&mut self, event: I::GestureHoldBeginEvent) {
+        let pointer = self.seat.get_pointer().expect("Pointer available");
+        pointer.gesture_hold_begin(self, &GestureHoldBeginEvent {
+            serial: SERIAL_COUNTER.next_serial(),
+            time: event.time_msec(),
+            fingers: event.fingers(),
+        });
+    }
+
+    pub fn on_gesture_hold_end<I: InputBackend>(&mut self, event: I::GestureHoldEndEvent) {
+        let pointer = self.seat.get_pointer().expect("Pointer available");
+        pointer.gesture_hold_end(self, &GestureHoldEndEvent {
+            serial: SERIAL_COUNTER.next_serial(),
+            time: event.time_msec(),
+            cancelled: event.cancelled(),
+        });
+    }
+}
@@ -0,0 +1,373 @@
+use smithay::{
+    desktop::Window,
+    input::{
+        pointer::{
+            AxisFrame,
+            ButtonEvent,
+            GrabStartData as PointerGrabStartData,
+            MotionEvent,
+            PointerGrab,
+            PointerInnerHandle,
+            RelativeMotionEvent,
+        },
+        Seat,
+    },
+    reexports::{
+        wayland_protocols::xdg::shell::server::xdg_toplevel::{self, ResizeEdge},
+        wayland_server::{protocol::wl_surface::WlSurface, Resource},
+    },
+    utils::{Logical, Point, Rectangle, Serial, Size},
+    xwayland::X11Surface,
+};
+
+use tracing::warn;
+
+use crate::{cursor::CursorIcon, layout, TwmState};
+
+/// Returns the start data of the pointer grab if the grab with `serial` was started by a
+/// press on the client owning `surface`
+pub fn check_grab(seat: &Seat<TwmState>,
+                  surface: &WlSurface,
+                  serial: Serial) -> Option<PointerGrabStartData<TwmState>> {
+    let pointer = seat.get_pointer()?;
+
+    // The grab has to be started by a recent button press
+    if !pointer.has_grab(serial) {
+        return None;
+    }
+
+    let start_data = pointer.grab_start_data()?;
+    let (focus, _) = start_data.focus.as_ref()?;
+
+    // The focused surface has to belong to the client requesting the grab
+    if !focus.id().same_client_as(&surface.id()) {
+        return None;
+    }
+
+    Some(start_data)
+}
+
+/// Pointer grab moving a window along with the cursor
+pub struct MoveSurfaceGrab {
+    pub start_data: PointerGrabStartData<TwmState>,
+    pub window: Window,
+    pub initial_window_location: Point<i32, Logical>,
+}
+
+impl PointerGrab<TwmState> for MoveSurfaceGrab {
+    fn motion(&mut self,
+              data: &mut TwmState,
+              handle: &mut PointerInnerHandle<'_, TwmState>,
+              _focus: Option<(WlSurface, Point<i32, Logical>)>,
+              event: &MotionEvent) {
+        // No client has pointer focus while the window is moved
+        handle.motion(data, None, event);
+
+        let delta = event.location - self.start_data.location;
+        let new_location = self.initial_window_location.to_f64() + delta;
+        data.space.map_element(self.window.clone(), new_location.to_i32_round(), true);
+    }
+
+    fn relative_motion(&mut self,
+                       data: &mut TwmState,
+                       handle: &mut PointerInnerHandle<'_, TwmState>,
+                       focus: Option<(WlSurface, Point<i32, Logical>)>,
+                       event: &RelativeMotionEvent) {
+        handle.relative_motion(data, focus, event);
+    }
+
+    fn button(&mut self,
+              data: &mut TwmState,
+              handle: &mut PointerInnerHandle<'_, TwmState>,
+              event: &ButtonEvent) {
+        handle.button(data, event);
+
+        if handle.current_pressed().is_empty() {
+            handle.unset_grab(data, event.serial, event.time);
+            data.show_cursor_icon(CursorIcon::Default);
+        }
+    }
+
+    fn axis(&mut self,
+            data: &mut TwmState,
+            handle: &mut PointerInnerHandle<'_, TwmState>,
+            details: AxisFrame) {
+        handle.axis(data, details);
+    }
+
+    fn start_data(&self) -> &PointerGrabStartData<TwmState> {
+        &self.start_data
+    }
+}
+
+/// Pointer grab interactively resizing a window from the grabbed edges
+pub struct ResizeSurfaceGrab {
+    pub start_data: PointerGrabStartData<TwmState>,
+    pub window: Window,
+    pub edges: ResizeEdge,
+    pub initial_window_geometry: Rectangle<i32, Logical>,
+    pub last_window_geometry: Rectangle<i32, Logical>,
+}
+
+impl ResizeSurfaceGrab {
+    pub fn new(start_data: PointerGrabStartData<TwmState>,
+               window: Window,
+               edges: ResizeEdge,
+               initial_window_geometry: Rectangle<i32, Logical>) -> Self {
+        Self {
+            start_data,
+            window,
+            edges,
+            initial_window_geometry,
+            last_window_geometry: initial_window_geometry,
+        }
+    }
+
+    /// Window geometry after moving the grabbed edges by `delta`
+    fn resized_geometry(&self, delta: Point<i32, Logical>) -> Rectangle<i32, Logical> {
+        let (min_size, max_size) = layout::window_size_hints(&self.window);
+        resized_geometry(self.initial_window_geometry, self.edges, delta, min_size, max_size)
+    }
+}
+
+/// Geometry of a window at `initial` after moving its `edges` by `delta`, within the size
+/// hints of the window
+fn resized_geometry(initial: Rectangle<i32, Logical>,
+                    edges: ResizeEdge,
+                    delta: Point<i32, Logical>,
+                    min_size: Option<Size<i32, Logical>>,
+                    max_size: Option<Size<i32, Logical>>) -> Rectangle<i32, Logical> {
+    let (top, bottom, left, right) = edge_flags(edges);
+
+    let mut width = initial.size.w;
+    let mut height = initial.size.h;
+    if left {
+        width -= delta.x;
+    } else if right {
+        width += delta.x;
+    }
+    if top {
+        height -= delta.y;
+    } else if bottom {
+        height += delta.y;
+    }
+
+    let (min_size, max_size) = (min_size.unwrap_or_default(), max_size.unwrap_or_default());
+    // A zero hint means the client doesn't care about that dimension
+    let min_width = min_size.w.max(1);
+    let min_height = min_size.h.max(1);
+    let max_width = if max_size.w == 0 { i32::MAX } else { max_size.w.max(min_width) };
+    let max_height = if max_size.h == 0 { i32::MAX } else { max_size.h.max(min_height) };
+    let size = Size::from((width.clamp(min_width, max_width), height.clamp(min_height, max_height)));
+
+    // Top and left edges keep the opposite edge in place by moving the origin
+    let mut location = initial.loc;
+    if left {
+        location.x = initial.loc.x + initial.size.w - size.w;
+    }
+    if top {
+        location.y = initial.loc.y + initial.size.h - size.h;
+    }
+
+    Rectangle::from_loc_and_size(location, size)
+}
+
+/// Splits resize edges into (top, bottom, left, right) flags
+fn edge_flags(edges: ResizeEdge) -> (bool, bool, bool, bool) {
+    (
+        matches!(edges, ResizeEdge::Top | ResizeEdge::TopLeft | ResizeEdge::TopRight),
+        matches!(edges, ResizeEdge::Bottom | ResizeEdge::BottomLeft | ResizeEdge::BottomRight),
+        matches!(edges, ResizeEdge::Left | ResizeEdge::TopLeft | ResizeEdge::BottomLeft),
+        matches!(edges, ResizeEdge::Right | ResizeEdge::TopRight | ResizeEdge::BottomRight),
+    )
+}
+
+impl PointerGrab<TwmState> for ResizeSurfaceGrab {
+    fn motion(&mut self,
+              data: &mut TwmState,
+              handle: &mut PointerInnerHandle<'_, TwmState>,
+              _focus: Option<(WlSurface, Point<i32, Logical>)>,
+              event: &MotionEvent) {
+        // No client has pointer focus while the window is resized
+        handle.motion(data, None, event);
+
+        let delta = (event.location - self.start_data.location).to_i32_round();
+        let geometry = self.resized_geometry(delta);
+        if geometry == self.last_window_geometry {
+            return;
+        }
+        self.last_window_geometry = geometry;
+
+        let toplevel = self.window.toplevel();
+        toplevel.with_pending_state(|state| {
+            state.states.set(xdg_toplevel::State::Resizing);
+            state.size = Some(geometry.size);
+        });
+        toplevel.send_pending_configure();
+        data.space.map_element(self.window.clone(), geometry.loc, true);
+    }
+
+    fn relative_motion(&mut self,
+                       data: &mut TwmState,
+                       handle: &mut PointerInnerHandle<'_, TwmState>,
+                       focus: Option<(WlSurface, Point<i32, Logical>)>,
+                       event: &RelativeMotionEvent) {
+        handle.relative_motion(data, focus, event);
+    }
+
+    fn button(&mut self,
+              data: &mut TwmState,
+              handle: &mut PointerInnerHandle<'_, TwmState>,
+              event: &ButtonEvent) {
+        handle.button(data, event);
+
+        if handle.current_pressed().is_empty() {
+            handle.unset_grab(data, event.serial, event.time);
+            data.show_cursor_icon(CursorIcon::Default);
+
+            // Final configure committing the geometry the grab ended with
+            let geometry = self.last_window_geometry;
+            let toplevel = self.window.toplevel();
+            toplevel.with_pending_state(|state| {
+                state.states.unset(xdg_toplevel::State::Resizing);
+                state.size = Some(geometry.size);
+            });
+            toplevel.send_pending_configure();
+            data.space.map_element(self.window.clone(), geometry.loc, false);
+        }
+    }
+
+    fn axis(&mut self,
+            data: &mut TwmState,
+            handle: &mut PointerInnerHandle<'_, TwmState>,
+            details: AxisFrame) {
+        handle.axis(data, details);
+    }
+
+    fn start_data(&self) -> &PointerGrabStartData<TwmState> {
+        &self.start_data
+    }
+}
+
+/// Pointer grab moving an X11 window along with the cursor
+pub struct X11MoveGrab {
+    pub start_data: PointerGrabStartData<TwmState>,
+    pub window: X11Surface,
+    pub initial_window_location: Point<i32, Logical>,
+}
+
+impl PointerGrab<TwmState> for X11MoveGrab {
+    fn motion(&mut self,
+              data: &mut TwmState,
+              handle: &mut PointerInnerHandle<'_, TwmState>,
+              _focus: Option<(WlSurface, Point<i32, Logical>)>,
+              event: &MotionEvent) {
+        // No client has pointer focus while the window is moved
+        handle.motion(data, None, event);
+
+        let delta = event.location - self.start_data.location;
+        let location = (self.initial_window_location.to_f64() + delta).to_i32_round();
+        let geometry = Rectangle::from_loc_and_size(location, self.window.geometry().size);
+        if let Err(err) = self.window.configure(geometry) {
+            warn!("Failed to move X11 window: {}", err);
+        }
+        data.needs_redraw = true;
+    }
+
+    fn relative_motion(&mut self,
+                       data: &mut TwmState,
+                       handle: &mut PointerInnerHandle<'_, TwmState>,
+                       focus: Option<(WlSurface, Point<i32, Logical>)>,
+                       event: &RelativeMotionEvent) {
+        handle.relative_motion(data, focus, event);
+    }
+
+    fn button(&mut self,
+              data: &mut TwmState,
+              handle: &mut PointerInnerHandle<'_, TwmState>,
+              event: &ButtonEvent) {
+        handle.button(data, event);
+
+        if handle.current_pressed().is_empty() {
+            handle.unset_grab(data, event.serial, event.time);
+            data.show_cursor_icon(CursorIcon::Default);
+        }
+    }
+
+    fn axis(&mut self,
+            data: &mut TwmState,
+            handle: &mut PointerInnerHandle<'_, TwmState>,
+            details: AxisFrame) {
+        handle.axis(data, details);
+    }
+
+    fn start_data(&self) -> &PointerGrabStartData<TwmState> {
+        &self.start_data
+    }
+}
+
+/// Pointer grab interactively resizing an X11 window from the grabbed edges, within its
+/// WM_NORMAL_HINTS size limits
+pub struct X11ResizeGrab {
+    pub start_data: PointerGrabStartData<TwmState>,
+    pub window: X11Surface,
+    pub edges: ResizeEdge,
+    pub initial_window_geometry: Rectangle<i32, Logical>,
+}
+
+impl PointerGrab<TwmState> for X11ResizeGrab {
+    fn motion(&mut self,
+              data: &mut TwmState,
+              handle: &mut PointerInnerHandle<'_, TwmState>,
+              _focus: Option<(WlSurface, Point<i32, Logical>)>,
+              event: &MotionEvent) {
+        // No client has pointer focus while the window is resized
+        handle.motion(data, None, event);
+
+        let delta = (event.location - self.start_data.location).to_i32_round();
+        let geometry = resized_geometry(
+            self.initial_window_geometry,
+            self.edges,
+            delta,
+            self.window.min_size(),
+            self.window.max_size());
+        if geometry == self.window.geometry() {
+            return;
+        }
+        if let Err(err) = self.window.configure(geometry) {
+            warn!("Failed to resize X11 window: {}", err);
+        }
+        data.needs_redraw = true;
+    }
+
+    fn relative_motion(&mut self,
+                       data: &mut TwmState,
+                       handle: &mut PointerInnerHandle<'_, TwmState>,
+                       focus: Option<(WlSurface, Point<i32, Logical>)>,
+                       event: &RelativeMotionEvent) {
+        handle.relative_motion(data, focus, event);
+    }
+
+    fn button(&mut self,
+              data: &mut TwmState,
+              handle: &mut PointerInnerHandle<'_, TwmState>,
+              event: &ButtonEvent) {
+        handle.button(data, event);
+
+        if handle.current_pressed().is_empty() {
+            handle.unset_grab(data, event.serial, event.time);
+            data.show_cursor_icon(CursorIcon::Default);
+        }
+    }
+
+    fn axis(&mut self,
+            data: &mut TwmState,
+            handle: &mut PointerInnerHandle<'_, TwmState>,
+            details: AxisFrame) {
+        handle.axis(data, details);
+    }
+
+    fn start_data(&self) -> &PointerGrabStartData<TwmState> {
+        &self.start_data
+    }
+}
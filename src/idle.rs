@@ -0,0 +1,169 @@
+//! Idle tracking and the ext-idle-notify-v1 protocol.
+//!
+//! Any input resets the idle time. Clients get notified once the seat was idle for the
+//! timeout they asked for, and the outputs are blanked after the configured `idle_timeout`.
+
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use smithay::reexports::{
+    calloop::{
+        timer::{TimeoutAction, Timer},
+        LoopHandle,
+    },
+    wayland_protocols::ext::idle_notify::v1::server::{
+        ext_idle_notification_v1::{self, ExtIdleNotificationV1},
+        ext_idle_notifier_v1::{self, ExtIdleNotifierV1},
+    },
+    wayland_server::{
+        backend::{ClientId, GlobalId, ObjectId},
+        Client,
+        DataInit,
+        Dispatch,
+        DisplayHandle,
+        GlobalDispatch,
+        New,
+        Resource,
+    },
+};
+use tracing::{debug, info};
+
+use crate::{TwmLoopData, TwmState};
+
+/// Longest time between two idle checks, new notifications are picked up at the latest then
+const MAX_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Idle notification requested by a client
+#[derive(Debug)]
+struct IdleNotification {
+    notification: ExtIdleNotificationV1,
+    timeout: Duration,
+    idled: bool,
+}
+
+#[derive(Debug)]
+pub struct IdleState {
+    _global: GlobalId,
+    last_activity: Instant,
+    notifications: Vec<IdleNotification>,
+    /// Outputs are blanked because of inactivity
+    pub blanked: bool,
+}
+
+impl IdleState {
+    pub fn new(display_handle: &DisplayHandle) -> Self {
+        Self {
+            _global: display_handle.create_global::<TwmState, ExtIdleNotifierV1, _>(1, ()),
+            last_activity: Instant::now(),
+            notifications: Vec::new(),
+            blanked: false,
+        }
+    }
+}
+
+impl TwmState {
+    /// Resets the idle time, resuming idle clients and unblanking the outputs
+    pub fn notify_activity(&mut self) {
+        self.idle.last_activity = Instant::now();
+        for notification in self.idle.notifications.iter_mut().filter(|n| n.idled) {
+            notification.idled = false;
+            notification.notification.resumed();
+        }
+        if self.idle.blanked {
+            info!("Activity, unblanking outputs");
+            self.idle.blanked = false;
+            self.needs_redraw = true;
+        }
+    }
+
+    /// Notifies clients and blanks the outputs whose timeouts passed, returns the time until
+    /// the next check is due
+    pub fn check_idle(&mut self) -> Duration {
+        let idle_time = self.idle.last_activity.elapsed();
+        let mut next_check = MAX_CHECK_INTERVAL;
+
+        for notification in self.idle.notifications.iter_mut().filter(|n| !n.idled) {
+            if idle_time >= notification.timeout {
+                notification.idled = true;
+                notification.notification.idled();
+            } else {
+                next_check = next_check.min(notification.timeout - idle_time);
+            }
+        }
+
+        if let Some(timeout) = self.config.idle_timeout.map(Duration::from_secs) {
+            if self.idle.blanked {
+                // Nothing to do until the next activity
+            } else if idle_time >= timeout {
+                info!("Idle for {:?}, blanking outputs", idle_time);
+                self.idle.blanked = true;
+            } else {
+                next_check = next_check.min(timeout - idle_time);
+            }
+        }
+
+        next_check
+    }
+}
+
+/// Inserts the timer checking idle timeouts into the event loop
+pub fn init(handle: &LoopHandle<'static, TwmLoopData>) -> Result<()> {
+    handle
+        .insert_source(Timer::from_duration(MAX_CHECK_INTERVAL), |_, _, data| {
+            TimeoutAction::ToDuration(data.state.check_idle())
+        })
+        .map_err(|err| err.error)
+        .context("Failed to insert the idle timer into the event loop")?;
+    Ok(())
+}
+
+impl GlobalDispatch<ExtIdleNotifierV1, ()> for TwmState {
+    fn bind(_state: &mut Self,
+            _handle: &DisplayHandle,
+            _client: &Client,
+            resource: New<ExtIdleNotifierV1>,
+            _global_data: &(),
+            data_init: &mut DataInit<'_, Self>) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<ExtIdleNotifierV1, ()> for TwmState {
+    fn request(state: &mut Self,
+               _client: &Client,
+               _resource: &ExtIdleNotifierV1,
+               request: ext_idle_notifier_v1::Request,
+               _data: &(),
+               _handle: &DisplayHandle,
+               data_init: &mut DataInit<'_, Self>) {
+        match request {
+            ext_idle_notifier_v1::Request::GetIdleNotification { id, timeout, seat: _ } => {
+                debug!("New idle notification with timeout {}ms", timeout);
+                let notification = data_init.init(id, ());
+                state.idle.notifications.push(IdleNotification {
+                    notification,
+                    timeout: Duration::from_millis(timeout as u64),
+                    idled: false,
+                });
+            },
+            ext_idle_notifier_v1::Request::Destroy => {},
+            _ => {},
+        }
+    }
+}
+
+impl Dispatch<ExtIdleNotificationV1, ()> for TwmState {
+    fn request(_state: &mut Self,
+               _client: &Client,
+               _resource: &ExtIdleNotificationV1,
+               _request: ext_idle_notification_v1::Request,
+               _data: &(),
+               _handle: &DisplayHandle,
+               _data_init: &mut DataInit<'_, Self>) {
+        // Destroy is the only request, cleanup happens in `destroyed`
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, resource: ObjectId, _data: &()) {
+        state.idle.notifications.retain(|n| n.notification.id() != resource);
+    }
+}
@@ -0,0 +1,377 @@
+use anyhow::{Context, Result};
+use smithay::{
+    backend::input::{
+        AbsolutePositionEvent,
+        Axis,
+        AxisSource,
+        ButtonState,
+        Device,
+        Event,
+        InputBackend,
+        InputEvent,
+        KeyState,
+        KeyboardKeyEvent,
+        PointerAxisEvent,
+        PointerButtonEvent,
+        PointerMotionEvent,
+    },
+    input::{
+        keyboard::{FilterResult, KeysymHandle, ModifiersState, XkbConfig},
+        pointer::{AxisFrame, ButtonEvent, MotionEvent, RelativeMotionEvent},
+    },
+    output::Output,
+    utils::{Logical, Point, Rectangle, Transform, SERIAL_COUNTER},
+    wayland::shell::wlr_layer::Layer,
+};
+use tracing::{debug, trace, warn};
+
+use crate::{action::Action, TwmState};
+
+/// Global position of `position` on the untransformed output, for an output with
+/// `transform` at `geometry`
+fn device_to_global(position: Point<f64, Logical>, transform: Transform, geometry: Rectangle<i32, Logical>) -> Point<f64, Logical> {
+    let device_size = transform.transform_size(geometry.size);
+    transform.invert().transform_point_in(position, &device_size.to_f64()) + geometry.loc.to_f64()
+}
+
+impl TwmState {
+    /// Adds the keyboard with the configured keymap and repeat info to the seat, falling back
+    /// to the `us` layout when the configured keymap doesn't compile
+    pub fn add_keyboard(&mut self) -> Result<()> {
+        let keyboard = &self.config.keyboard;
+        let (repeat_delay, repeat_rate) = (keyboard.repeat_delay, keyboard.repeat_rate);
+        if let Err(err) = self.seat.add_keyboard(keyboard.xkb_config(), repeat_delay, repeat_rate) {
+            warn!("Invalid keymap {:?}: {}, falling back to the us layout", keyboard, err);
+            let fallback = XkbConfig { layout: "us", ..Default::default() };
+            self.seat
+                .add_keyboard(fallback, repeat_delay, repeat_rate)
+                .context("Failed to compile the fallback keymap")?;
+        }
+        Ok(())
+    }
+
+    /// Handles an input event of any backend
+    pub fn process_input_event<I: InputBackend>(&mut self, event: InputEvent<I>) {
+        // At least the cursor may have moved
+        self.needs_redraw = true;
+        self.notify_activity();
+        match event {
+            InputEvent::Keyboard { event } => self.on_keyboard_key::<I>(event),
+            InputEvent::PointerMotion { event } => self.on_pointer_motion::<I>(event),
+            InputEvent::PointerMotionAbsolute { event } => self.on_pointer_motion_absolute::<I>(event),
+            InputEvent::PointerButton { event } => self.on_pointer_button::<I>(event),
+            InputEvent::PointerAxis { event } => self.on_pointer_axis::<I>(event),
+            InputEvent::TouchDown { event } => self.on_touch_down::<I>(event),
+            InputEvent::TouchMotion { event } => self.on_touch_motion::<I>(event),
+            InputEvent::TouchUp { event } => self.on_touch_up::<I>(event),
+            InputEvent::TouchFrame { .. } => self.on_touch_frame(),
+            InputEvent::TouchCancel { .. } => self.on_touch_cancel(),
+            InputEvent::GestureSwipeBegin { event } => self.on_gesture_swipe_begin::<I>(event),
+            InputEvent::GestureSwipeUpdate { event } => self.on_gesture_swipe_update::<I>(event),
+            InputEvent::GestureSwipeEnd { event } => self.on_gesture_swipe_end::<I>(event),
+            InputEvent::GesturePinchBegin { event } => self.on_gesture_pinch_begin::<I>(event),
+            InputEvent::GesturePinchUpdate { event } => self.on_gesture_pinch_update::<I>(event),
+            InputEvent::GesturePinchEnd { event } => self.on_gesture_pinch_end::<I>(event),
+            InputEvent::GestureHoldBegin { event } => self.on_gesture_hold_begin::<I>(event),
+            InputEvent::GestureHoldEnd { event } => self.on_gesture_hold_end::<I>(event),
+            InputEvent::DeviceRemoved { device } => {
+                self.device_outputs.remove(&device.id());
+            },
+            _ => {}
+        }
+    }
+
+    /// Maps absolute positions of `device` onto `output`
+    pub fn map_device_to_output(&mut self, device: &impl Device, output: &Output) {
+        debug!("Mapping input device {:?} to output {}", device.name(), output.name());
+        self.device_outputs.insert(device.id(), output.clone());
+    }
+
+    /// Output absolute positions of `device` are mapped to, the active output for devices
+    /// without a mapping or whose output is gone
+    fn output_for_device(&self, device: &impl Device) -> Option<Output> {
+        self.device_outputs
+            .get(&device.id())
+            .filter(|output| self.space.outputs().any(|o| o == *output))
+            .cloned()
+            .or_else(|| self.active_output())
+    }
+
+    fn on_keyboard_key<I: InputBackend>(&mut self, event: I::KeyboardKeyEvent) {
+        self.handle_key(event.key_code(), event.state(), Event::time_msec(&event));
+    }
+
+    /// Passes a key of a physical or virtual keyboard through the keybindings to the focused
+    /// client
+    pub fn handle_key(&mut self, keycode: u32, key_state: KeyState, time: u32) {
+        let serial = SERIAL_COUNTER.next_serial();
+        let keyboard = self.seat.get_keyboard().expect("Keyboard available");
+        self.input_serials.input(serial);
+
+        let action = keyboard.input::<Option<Action>, _>(
+            self, // compositor state
+            keycode, // keyboard key code
+            key_state, // keoboard event state
+            serial,
+            time,
+            |state, modifiers, handle| { // Event filter block
+                trace!("Key {}: {:?}", keycode, key_state);
+                state.filter_keybinding(keycode, key_state, modifiers, handle)
+            }
+        ).flatten();
+
+        if let Some(action) = action {
+            self.handle_action(action);
+        }
+    }
+
+    /// Keyboard filter intercepting keys bound to a compositor action
+    fn filter_keybinding(&mut self,
+                         keycode: u32,
+                         key_state: KeyState,
+                         modifiers: &ModifiersState,
+                         handle: KeysymHandle<'_>) -> FilterResult<Option<Action>> {
+        if key_state == KeyState::Released {
+            if let Some(index) = self.suppressed_keys.iter().position(|&k| k == keycode) {
+                self.suppressed_keys.remove(index);
+                return FilterResult::Intercept(None);
+            }
+            return FilterResult::Forward;
+        }
+
+        let mut keysyms = vec![handle.modified_sym()];
+        keysyms.extend_from_slice(handle.raw_syms());
+
+        // Clients inhibiting shortcuts get all keys but the one releasing the inhibitor
+        let inhibited = self.shortcuts_inhibited();
+        match self.config.keybindings.action(modifiers.into(), &keysyms) {
+            // Only virtual terminals can be switched while the session is locked
+            Some(action) if matches!(action, Action::SwitchVt(_))
+                || (inhibited && matches!(action, Action::ReleaseShortcutsInhibitor))
+                || (!inhibited && !self.is_locked()) => {
+                self.suppressed_keys.push(keycode);
+                FilterResult::Intercept(Some(action.clone()))
+            },
+            _ => FilterResult::Forward,
+        }
+    }
+
+    fn on_pointer_motion<I: InputBackend>(&mut self, event: I::PointerMotionEvent) {
+        let pointer = self.seat.get_pointer().expect("Pointer available");
+        // Positions stay fractional so sub-pixel deltas add up
+        let current = pointer.current_location();
+        let position = self.constrain_pointer_motion(current, self.clamp_to_outputs(current + event.delta()));
+        self.pointer_motion_to(position, event.time_msec());
+
+        // Clients using relative-pointer get the raw deltas, even at the edge of the outputs
+        let surface_under_pointer = self.surface_under(position);
+        pointer.relative_motion(self, surface_under_pointer, &RelativeMotionEvent {
+            delta: event.delta(),
+            delta_unaccel: event.delta_unaccel(),
+            utime: Event::time(&event),
+        });
+    }
+
+    /// Moves the pointer to `position` as if it was moved there, used for synthetic input
+    pub fn warp_pointer(&mut self, position: Point<f64, Logical>, time: u32) {
+        let current = self.seat.get_pointer().expect("Pointer available").current_location();
+        let position = self.constrain_pointer_motion(current, self.clamp_to_outputs(position));
+        self.pointer_motion_to(position, time);
+    }
+
+    /// Global position of an absolute event of a tablet or touchscreen, mapped onto the
+    /// output of its device. Devices built into a rotated or flipped output turn with it,
+    /// so positions hit what is drawn where they were touched.
+    pub fn absolute_position<I: InputBackend>(&self, event: &impl AbsolutePositionEvent<I>) -> Option<Point<f64, Logical>> {
+        let output = self.output_for_device(&event.device())?;
+        let geometry_output = self.space.output_geometry(&output)?;
+        let transform = output.current_transform();
+        // Devices report positions on the untransformed output
+        let position = event.position_transformed(transform.transform_size(geometry_output.size));
+        Some(device_to_global(position, transform, geometry_output))
+    }
+
+    fn on_pointer_motion_absolute<I: InputBackend>(&mut self, event: I::PointerMotionAbsoluteEvent) {
+        let Some(position) = self.absolute_position(&event) else {
+            return;
+        };
+        let current = self.seat.get_pointer().expect("Pointer available").current_location();
+        let position = self.constrain_pointer_motion(current, position);
+        self.pointer_motion_to(position, event.time_msec());
+    }
+
+    fn pointer_motion_to(&mut self, position: Point<f64, Logical>, time: u32) {
+        let serial = SERIAL_COUNTER.next_serial();
+        let pointer = self.seat.get_pointer().expect("Pointer available");
+        if !pointer.is_grabbed() {
+            self.focus_output_under(pointer.current_location(), position);
+            if self.config.focus_follows_mouse {
+                self.focus_window_under(position);
+            }
+        }
+        let surface_under_pointer = self.surface_under(position);
+        trace!("Pointer motion to {:?}", position);
+        if !pointer.is_grabbed() {
+            self.update_cursor_icon(position, surface_under_pointer.is_some());
+        }
+
+        pointer.motion(self, surface_under_pointer, &MotionEvent {
+            location: position,
+            serial,
+            time,
+        });
+        self.update_pointer_constraint(position);
+    }
+
+    /// Gives keyboard focus to the window under `position` without raising it. Popups count
+    /// as part of their window, so moving over them keeps the focus where it is.
+    fn focus_window_under(&mut self, position: Point<f64, Logical>) {
+        // Panels and other layers above the windows don't take the focus away
+        if self.layer_surface_under(position, &[Layer::Overlay, Layer::Top]).is_some() {
+            return;
+        }
+        let Some(window) = self.space.element_under(position).map(|(window, _)| window.clone()) else {
+            return;
+        };
+        if self.focused_window().as_ref() != Some(&window) {
+            trace!("Focus follows mouse");
+            self.set_keyboard_focus(Some(window.toplevel().wl_surface().clone()));
+        }
+    }
+
+    /// Keeps `position` inside the output the pointer is currently on if it left all outputs
+    fn clamp_to_outputs(&self, position: Point<f64, Logical>) -> Point<f64, Logical> {
+        if self.space.output_under(position).next().is_some() {
+            return position;
+        }
+
+        let Some(geometry) = self.active_output()
+            .and_then(|output| self.space.output_geometry(&output)) else {
+            return position;
+        };
+        let max_x = (geometry.loc.x + geometry.size.w - 1) as f64;
+        let max_y = (geometry.loc.y + geometry.size.h - 1) as f64;
+        (
+            position.x.clamp(geometry.loc.x as f64, max_x),
+            position.y.clamp(geometry.loc.y as f64, max_y),
+        ).into()
+    }
+
+    fn on_pointer_button<I: InputBackend>(&mut self, event: I::PointerButtonEvent) {
+        self.handle_pointer_button(event.button_code(), event.state(), event.time_msec());
+    }
+
+    /// Handles a press or release of a physical or synthetic pointer button
+    pub fn handle_pointer_button(&mut self, button: u32, button_state: ButtonState, time: u32) {
+        let pointer = self.seat.get_pointer().expect("Pointer available");
+        let serial = SERIAL_COUNTER.next_serial();
+        self.input_serials.input(serial);
+
+        if ButtonState::Pressed == button_state && !pointer.is_grabbed() && !self.is_locked() {
+            if self.start_modifier_drag(button, serial) {
+                trace!("Dragging window with the mouse modifier");
+            } else if let Some(window) = self.x11_window_under(pointer.current_location()) {
+                debug!("Clicked on X11 window");
+                self.focus_x11_window(&window);
+            } else if let Some((window, _location)) = self
+                    .space
+                    .element_under(pointer.current_location())
+                    .map(|(w, l)| (w.clone(), l)) {
+                debug!("Clicked on window");
+                self.focus_window(Some(&window));
+                trace!("Update focus");
+            } else if let Some((window, edges)) = self.floating_border_under(pointer.current_location()) {
+                self.focus_window(Some(&window));
+                self.start_border_resize(window, edges, button, serial);
+            } else {
+                self.set_keyboard_focus(None);
+                trace!("Reset focus");
+            }
+        }
+
+        // Releases have to reach the pointer as well so grabs can end
+        pointer.button(
+            self,
+            &ButtonEvent {
+                button,
+                state: button_state,
+                serial,
+                time,
+            }
+        );
+    }
+
+    fn on_pointer_axis<I: InputBackend>(&mut self, event: I::PointerAxisEvent) {
+        let source = event.source();
+        let scroll = &self.config.scroll;
+        let direction = if scroll.natural { -1.0 } else { 1.0 };
+
+        let mut frame = AxisFrame::new(event.time_msec()).source(source);
+        for axis in [Axis::Horizontal, Axis::Vertical] {
+            let discrete = event.amount_discrete(axis);
+            let amount = event.amount(axis).or_else(|| discrete.map(|steps| steps * scroll.wheel_step));
+            match amount {
+                // Lifting the fingers off a touchpad reports a zero amount, clients may
+                // start kinetic scrolling then
+                Some(amount) if amount == 0.0 && source == AxisSource::Finger => frame = frame.stop(axis),
+                Some(amount) if amount != 0.0 => {
+                    frame = frame.value(axis, amount * scroll.factor * direction);
+                    // Only wheels scroll in clicks
+                    if let Some(steps) = discrete.filter(|_| matches!(source, AxisSource::Wheel | AxisSource::WheelTilt)) {
+                        frame = frame.discrete(axis, (steps * direction) as i32);
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        self.seat.get_pointer().expect("Pointer available").axis(self, frame);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn device_positions_follow_the_output_transform() {
+        // Output of 400x200 at (100, 50), touched 10 right and 20 down from the top left
+        // corner of the device
+        let geometry = Rectangle::from_loc_and_size((100, 50), (400, 200));
+        let position = Point::from((10.0, 20.0));
+        for (transform, expected) in [
+            (Transform::Normal, (110.0, 70.0)),
+            (Transform::_90, (120.0, 240.0)),
+            (Transform::_180, (490.0, 230.0)),
+            (Transform::_270, (480.0, 60.0)),
+            (Transform::Flipped, (490.0, 70.0)),
+            (Transform::Flipped90, (120.0, 60.0)),
+            (Transform::Flipped180, (110.0, 230.0)),
+            (Transform::Flipped270, (480.0, 240.0)),
+        ] {
+            assert_eq!(device_to_global(position, transform, geometry), expected.into(), "{:?}", transform);
+        }
+    }
+
+    #[test]
+    fn device_corners_stay_on_the_output() {
+        let geometry = Rectangle::from_loc_and_size((100, 50), (400, 200));
+        for transform in [
+            Transform::Normal,
+            Transform::_90,
+            Transform::_180,
+            Transform::_270,
+            Transform::Flipped,
+            Transform::Flipped90,
+            Transform::Flipped180,
+            Transform::Flipped270,
+        ] {
+            let device_size = transform.transform_size(geometry.size).to_f64();
+            for corner in [(0.0, 0.0), (device_size.w, 0.0), (0.0, device_size.h), (device_size.w, device_size.h)] {
+                let position = device_to_global(corner.into(), transform, geometry);
+                assert!((100.0..=500.0).contains(&position.x) && (50.0..=250.0).contains(&position.y),
+                        "{:?} maps {:?} to {:?}", transform, corner, position);
+            }
+        }
+    }
+}
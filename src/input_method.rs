@@ -0,0 +1,76 @@
+//! The text-input-v3 and input-method-v2 protocols used by input methods like fcitx5 and ibus.
+//!
+//! Smithay relays preedit and commit strings between the input method and the text input
+//! of the surface with keyboard focus, which also makes text inputs follow focus changes.
+//! Key events reach the input method through the keyboard grab it takes, after keybindings
+//! were handled. What's left here is placing and drawing the candidate popups.
+
+use smithay::{
+    backend::renderer::{element::surface::render_elements_from_surface_tree, ImportAll, ImportMem, Renderer},
+    output::Output,
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::{Logical, Point, Rectangle, Scale},
+    wayland::input_method::{InputMethodHandler, PopupSurface},
+};
+use tracing::debug;
+
+use crate::{render::CustomRenderElements, TwmState};
+
+impl InputMethodHandler for TwmState {
+    fn new_popup(&mut self, surface: PopupSurface) {
+        debug!("New input method popup");
+        self.input_method_popups.retain(PopupSurface::alive);
+        self.input_method_popups.push(surface);
+    }
+
+    fn parent_geometry(&self, parent: &WlSurface) -> Rectangle<i32, Logical> {
+        self.space
+            .elements()
+            .find(|window| window.toplevel().wl_surface() == parent)
+            .map(|window| window.geometry())
+            .unwrap_or_default()
+    }
+}
+
+impl TwmState {
+    /// Global location of an input method popup, below the text cursor of its parent
+    fn input_method_popup_location(&self, popup: &PopupSurface) -> Option<Point<i32, Logical>> {
+        let parent = popup.get_parent()?;
+        let window = self.space.elements().find(|window| window.toplevel().wl_surface() == &parent.surface)?;
+        let window_location = self.space.element_location(window)? - window.geometry().loc;
+        let cursor = parent.location;
+        Some(window_location + cursor.loc + Point::from((0, cursor.size.h)))
+    }
+
+    /// Render elements of the input method popups shown on `output`
+    pub fn input_method_popup_elements<R>(&self, renderer: &mut R, output: &Output) -> Vec<CustomRenderElements<R>>
+    where
+        R: Renderer + ImportAll + ImportMem,
+        <R as Renderer>::TextureId: Clone + 'static,
+    {
+        let Some(output_geometry) = self.space.output_geometry(output) else {
+            return Vec::new();
+        };
+        let scale = Scale::from(output.current_scale().fractional_scale());
+
+        self.input_method_popups
+            .iter()
+            .filter(|popup| popup.alive())
+            .filter_map(|popup| Some((popup, self.input_method_popup_location(popup)?)))
+            .filter(|(_, location)| output_geometry.contains(*location))
+            .flat_map(|(popup, location)| {
+                let position = (location - output_geometry.loc).to_physical_precise_round(scale);
+                render_elements_from_surface_tree(renderer, popup.wl_surface(), position, scale, 1.0)
+            })
+            .map(CustomRenderElements::Surface)
+            .collect()
+    }
+
+    /// Surfaces of the input method popups that are still alive
+    pub fn input_method_popup_surfaces(&self) -> impl Iterator<Item = &WlSurface> {
+        self.input_method_popups
+            .iter()
+            .filter(|popup| popup.alive())
+            .map(PopupSurface::wl_surface)
+    }
+}
@@ -0,0 +1,656 @@
+//! Control socket for scripting the compositor.
+//!
+//! The socket is created at `$XDG_RUNTIME_DIR/twm-<pid>.sock` and its path is exported to
+//! children as `TWM_SOCK`. Clients send one JSON request per line and get one JSON
+//! response per line back:
+//!
+//! ```text
+//! -> {"cmd":"focus_next"}
+//! <- {"success":true}
+//! -> {"cmd":"spawn","arg":"firefox"}
+//! <- {"success":true}
+//! -> {"cmd":"focused_window"}
+//! <- {"success":true,"window":{"app_id":"firefox","title":"Mozilla Firefox"}}
+//! -> {"cmd":"bogus"}
+//! <- {"success":false,"error":"unknown variant `bogus`, ..."}
+//! ```
+//!
+//! Requests: `spawn` (arg: command line), `close`, `kill_client`, `focus_next`, `focus_prev`,
+//! `focus_left`, `focus_right`, `focus_up`, `focus_down`,
+//! `swap_with_master`, `swap_next`, `swap_prev`, `move_left`, `move_right`, `move_up`,
+//! `move_down`, `increase_master_ratio`, `decrease_master_ratio`,
+//! `minimize`, `restore_minimized`, `toggle_floating`, `toggle_sticky`, `snap_left`,
+//! `snap_right`, `snap_up`, `snap_down`, `snap_top_left`, `snap_top_right`, `snap_bottom_left`,
+//! `snap_bottom_right`, `cycle_layout`, `join_tabs`, `split_tabs`, `focus_next_tab`,
+//! `focus_prev_tab`, `toggle_scratchpad`,
+//! `switch_workspace` (arg: workspace number), `next_workspace`, `prev_workspace`,
+//! `move_window_to_workspace`
+//! (arg: workspace number), `move_workspace_to_next_output`, `toggle_debug_overlay`, `quit`.
+//!
+//! `reload_config` reads the config file again and applies it. It fails with the error of
+//! the file in `error` if the file is invalid, the config in use stays then.
+//!
+//! Queries: `focused_window`, answered with the app id and title of the focused window
+//! (`window` is left out without a focused window), and `windows`, answered with all
+//! windows shown on the outputs with their geometry and focused, floating, sticky and
+//! urgent flags in `windows`.
+//!
+//! `get_tree` answers with the whole state in `tree`, like `swaymsg -t get_tree`: the
+//! outputs with their geometry, scale and workspaces, the windows of every workspace in
+//! tiling order with their flags, and the focused output and window. Windows of hidden
+//! workspaces have no geometry.
+//!
+//! X11 windows are listed after the others as floating windows, with their WM_CLASS class
+//! as app id.
+//!
+//! `subscribe` (events: list of "window", "workspace" and "focus") turns the connection
+//! into an event stream. After the response the client gets one JSON event per line for
+//! the kinds it subscribed to, requests on the connection keep working:
+//!
+//! ```text
+//! -> {"cmd":"subscribe","events":["window","focus"]}
+//! <- {"success":true}
+//! <- {"event":"window","change":"new","window":{"app_id":"foot","title":null}}
+//! <- {"event":"focus","window":{"app_id":"foot","title":"~"}}
+//! <- {"event":"window","change":"close","window":{"app_id":"foot","title":"~"}}
+//! ```
+//!
+//! `window` events have the changes `new`, `title` (app id or title changed), `urgent`
+//! (the window asks for attention), `urgent_cleared` and `close`, `workspace` events carry
+//! the output and the number of the workspace it now shows, and `focus` events the newly
+//! focused window, `null` when the focus left all windows.
+//! Subscribers that don't keep up with reading their events are disconnected.
+//!
+//! Synthetic input, mostly for tests on the headless backend: `pointer_move` (x, y: global
+//! position), `pointer_button` (button: evdev code, pressed) and `key` (keycode: evdev
+//! code, pressed).
+
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use smithay::{
+    backend::input::{ButtonState, KeyState},
+    desktop::Window,
+    reexports::{
+        calloop::{generic::Generic, Interest, LoopHandle, Mode, PostAction},
+        wayland_protocols::xdg::shell::server::xdg_toplevel,
+    },
+    utils::{Logical, Rectangle},
+};
+use tracing::{debug, info, warn};
+
+use crate::{action::Action, layout::Layout, window_info::{x11_window_info, WindowInfo}, TwmLoopData, TwmState};
+
+/// Longest request line in bytes, clients sending longer ones are disconnected
+const MAX_REQUEST_LENGTH: usize = 1 << 20;
+
+/// Request sent by an IPC client
+#[derive(Debug, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum Request {
+    Spawn { arg: String },
+    Close,
+    KillClient,
+    FocusNext,
+    FocusPrev,
+    FocusLeft,
+    FocusRight,
+    FocusUp,
+    FocusDown,
+    SwapWithMaster,
+    SwapNext,
+    SwapPrev,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    IncreaseMasterRatio,
+    DecreaseMasterRatio,
+    Minimize,
+    RestoreMinimized,
+    ToggleFloating,
+    ToggleSticky,
+    SnapLeft,
+    SnapRight,
+    SnapUp,
+    SnapDown,
+    SnapTopLeft,
+    SnapTopRight,
+    SnapBottomLeft,
+    SnapBottomRight,
+    CycleLayout,
+    JoinTabs,
+    SplitTabs,
+    FocusNextTab,
+    FocusPrevTab,
+    ToggleScratchpad,
+    SwitchWorkspace { arg: usize },
+    NextWorkspace,
+    PrevWorkspace,
+    MoveWindowToWorkspace { arg: usize },
+    MoveWorkspaceToNextOutput,
+    Quit,
+    ToggleDebugOverlay,
+    ReloadConfig,
+    PointerMove { x: f64, y: f64 },
+    PointerButton { button: u32, pressed: bool },
+    Key { keycode: u32, pressed: bool },
+    FocusedWindow,
+    Windows,
+    GetTree,
+    Subscribe { events: Vec<EventKind> },
+}
+
+impl Request {
+    /// Action to execute for the request, `None` for queries
+    fn action(self) -> Option<Action> {
+        let action = match self {
+            Request::Spawn { arg } => Action::Spawn(arg),
+            Request::Close => Action::CloseWindow,
+            Request::KillClient => Action::KillClient,
+            Request::FocusNext => Action::FocusNext,
+            Request::FocusPrev => Action::FocusPrev,
+            Request::FocusLeft => Action::FocusLeft,
+            Request::FocusRight => Action::FocusRight,
+            Request::FocusUp => Action::FocusUp,
+            Request::FocusDown => Action::FocusDown,
+            Request::SwapWithMaster => Action::SwapWithMaster,
+            Request::SwapNext => Action::SwapNext,
+            Request::SwapPrev => Action::SwapPrev,
+            Request::MoveLeft => Action::MoveLeft,
+            Request::MoveRight => Action::MoveRight,
+            Request::MoveUp => Action::MoveUp,
+            Request::MoveDown => Action::MoveDown,
+            Request::IncreaseMasterRatio => Action::IncreaseMasterRatio,
+            Request::DecreaseMasterRatio => Action::DecreaseMasterRatio,
+            Request::Minimize => Action::Minimize,
+            Request::RestoreMinimized => Action::RestoreMinimized,
+            Request::ToggleFloating => Action::ToggleFloating,
+            Request::ToggleSticky => Action::ToggleSticky,
+            Request::SnapLeft => Action::SnapLeft,
+            Request::SnapRight => Action::SnapRight,
+            Request::SnapUp => Action::SnapUp,
+            Request::SnapDown => Action::SnapDown,
+            Request::SnapTopLeft => Action::SnapTopLeft,
+            Request::SnapTopRight => Action::SnapTopRight,
+            Request::SnapBottomLeft => Action::SnapBottomLeft,
+            Request::SnapBottomRight => Action::SnapBottomRight,
+            Request::CycleLayout => Action::CycleLayout,
+            Request::JoinTabs => Action::JoinTabs,
+            Request::SplitTabs => Action::SplitTabs,
+            Request::FocusNextTab => Action::FocusNextTab,
+            Request::FocusPrevTab => Action::FocusPrevTab,
+            Request::ToggleScratchpad => Action::ToggleScratchpad,
+            Request::SwitchWorkspace { arg } => Action::SwitchWorkspace(arg),
+            Request::NextWorkspace => Action::NextWorkspace,
+            Request::PrevWorkspace => Action::PrevWorkspace,
+            Request::MoveWindowToWorkspace { arg } => Action::MoveWindowToWorkspace(arg),
+            Request::MoveWorkspaceToNextOutput => Action::MoveWorkspaceToNextOutput,
+            Request::Quit => Action::Quit,
+            Request::ToggleDebugOverlay => Action::ToggleDebugOverlay,
+            Request::PointerMove { .. }
+            | Request::PointerButton { .. }
+            | Request::Key { .. }
+            | Request::FocusedWindow
+            | Request::Windows
+            | Request::GetTree
+            | Request::Subscribe { .. }
+            | Request::ReloadConfig => return None,
+        };
+        Some(action)
+    }
+}
+
+/// Response sent back for every request
+#[derive(Debug, Serialize)]
+pub struct Response {
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window: Option<WindowInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub windows: Option<Vec<MappedWindow>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tree: Option<Tree>,
+}
+
+impl Response {
+    pub fn ok() -> Self {
+        Self { success: true, error: None, window: None, windows: None, tree: None }
+    }
+
+    pub fn error(error: impl ToString) -> Self {
+        Self { success: false, error: Some(error.to_string()), window: None, windows: None, tree: None }
+    }
+
+    pub fn window(window: Option<WindowInfo>) -> Self {
+        Self { window, ..Self::ok() }
+    }
+
+    pub fn windows(windows: Vec<MappedWindow>) -> Self {
+        Self { windows: Some(windows), ..Self::ok() }
+    }
+
+    pub fn tree(tree: Tree) -> Self {
+        Self { tree: Some(tree), ..Self::ok() }
+    }
+}
+
+/// Window shown on an output, in stacking order from bottom to top
+#[derive(Debug, Serialize)]
+pub struct MappedWindow {
+    #[serde(flatten)]
+    pub info: WindowInfo,
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    pub focused: bool,
+    pub floating: bool,
+    pub sticky: bool,
+    pub urgent: bool,
+}
+
+/// Kind of events an IPC client can subscribe to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Window,
+    Workspace,
+    Focus,
+}
+
+/// Event sent to subscribed IPC clients
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    Window { change: WindowChange, window: WindowInfo },
+    /// Output `output` switched to workspace number `workspace`, counted from 1
+    Workspace { output: Option<String>, workspace: usize },
+    /// Keyboard focus moved to `window`, `None` if it isn't on a window
+    Focus { window: Option<WindowInfo> },
+}
+
+impl Event {
+    fn kind(&self) -> EventKind {
+        match self {
+            Event::Window { .. } => EventKind::Window,
+            Event::Workspace { .. } => EventKind::Workspace,
+            Event::Focus { .. } => EventKind::Focus,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowChange {
+    New,
+    /// App id or title changed
+    Title,
+    /// Window asks for attention
+    Urgent,
+    /// Window got the attention it asked for, or doesn't ask anymore
+    UrgentCleared,
+    Close,
+}
+
+/// Connection receiving events, a second handle to the stream of its event source
+#[derive(Debug)]
+pub struct Subscriber {
+    stream: UnixStream,
+    events: Vec<EventKind>,
+}
+
+/// Whole compositor state
+#[derive(Debug, Serialize)]
+pub struct Tree {
+    pub outputs: Vec<TreeOutput>,
+    /// Name of the output under the pointer
+    pub focused_output: Option<String>,
+    pub focused_window: Option<WindowInfo>,
+}
+
+/// Output with its workspaces. Workspaces left over after the last output was unplugged
+/// belong to an output without name and geometry.
+#[derive(Debug, Serialize)]
+pub struct TreeOutput {
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub geometry: Option<Geometry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scale: Option<f64>,
+    /// Number of the shown workspace, counted from 1
+    pub current_workspace: usize,
+    pub workspaces: Vec<TreeWorkspace>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TreeWorkspace {
+    /// Counted from 1
+    pub number: usize,
+    pub layout: Layout,
+    pub master_ratio: f64,
+    pub shown: bool,
+    pub windows: Vec<TreeWindow>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TreeWindow {
+    #[serde(flatten)]
+    pub info: WindowInfo,
+    /// Left out while the window is not shown
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub geometry: Option<Geometry>,
+    pub focused: bool,
+    pub floating: bool,
+    pub fullscreen: bool,
+    pub sticky: bool,
+    pub urgent: bool,
+}
+
+/// Rectangle in global logical coordinates
+#[derive(Debug, Serialize)]
+pub struct Geometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl From<Rectangle<i32, Logical>> for Geometry {
+    fn from(rect: Rectangle<i32, Logical>) -> Self {
+        Self { x: rect.loc.x, y: rect.loc.y, width: rect.size.w, height: rect.size.h }
+    }
+}
+
+impl TwmState {
+    /// Parses and executes a single request line received on `stream`
+    pub fn handle_ipc_request(&mut self, line: &str, stream: &UnixStream) -> Response {
+        match serde_json::from_str::<Request>(line) {
+            Ok(Request::FocusedWindow) => Response::window(self.focused_window_info()),
+            Ok(Request::Windows) => Response::windows(self.mapped_windows()),
+            Ok(Request::GetTree) => Response::tree(self.tree()),
+            Ok(Request::ReloadConfig) => match self.reload_config() {
+                Ok(()) => Response::ok(),
+                Err(err) => Response::error(format!("{:#}", err)),
+            },
+            Ok(Request::Subscribe { events }) => match stream.try_clone() {
+                Ok(stream) => {
+                    debug!("IPC client subscribed to {:?}", events);
+                    self.ipc_subscribers.push(Subscriber { stream, events });
+                    Response::ok()
+                },
+                Err(err) => Response::error(err),
+            },
+            Ok(request @ (Request::PointerMove { .. } | Request::PointerButton { .. } | Request::Key { .. })) => {
+                debug!("IPC input {:?}", request);
+                self.synthetic_input(request);
+                Response::ok()
+            },
+            Ok(request) => {
+                debug!("IPC request {:?}", request);
+                if let Some(action) = request.action() {
+                    self.handle_action(action);
+                }
+                Response::ok()
+            },
+            Err(err) => Response::error(err),
+        }
+    }
+
+    fn mapped_windows(&self) -> Vec<MappedWindow> {
+        let focused = self.focused_window();
+        let focused_x11 = self.focused_x11_window();
+        let windows = self.space
+            .elements()
+            .filter_map(|window| {
+                let geometry = self.space.element_geometry(window)?;
+                Some(MappedWindow {
+                    info: self.window_info(window),
+                    x: geometry.loc.x,
+                    y: geometry.loc.y,
+                    width: geometry.size.w,
+                    height: geometry.size.h,
+                    focused: Some(window) == focused.as_ref(),
+                    floating: self.is_floating(window),
+                    sticky: self.is_sticky(window),
+                    urgent: self.is_urgent(window),
+                })
+            });
+        // X11 windows are stacked above the others
+        let x11_windows = self.x11_windows()
+            .rev()
+            .filter(|window| !window.is_override_redirect())
+            .map(|window| {
+                let geometry = window.geometry();
+                MappedWindow {
+                    info: x11_window_info(window),
+                    x: geometry.loc.x,
+                    y: geometry.loc.y,
+                    width: geometry.size.w,
+                    height: geometry.size.h,
+                    focused: Some(window) == focused_x11.as_ref(),
+                    floating: true,
+                    sticky: false,
+                    urgent: false,
+                }
+            });
+        windows.chain(x11_windows).collect()
+    }
+
+    /// Sends `event` to all clients subscribed to its kind. Writes never block, clients
+    /// whose socket buffer is full or that went away are dropped.
+    pub fn emit_ipc_event(&mut self, event: Event) {
+        let kind = event.kind();
+        if !self.ipc_subscribers.iter().any(|subscriber| subscriber.events.contains(&kind)) {
+            return;
+        }
+        let mut line = serde_json::to_vec(&event).expect("Serializable IPC event");
+        line.push(b'\n');
+        self.ipc_subscribers.retain_mut(|subscriber| {
+            if !subscriber.events.contains(&kind) {
+                return true;
+            }
+            match subscriber.stream.write_all(&line) {
+                Ok(()) => true,
+                Err(err) => {
+                    debug!("Dropping IPC subscriber: {}", err);
+                    false
+                }
+            }
+        });
+    }
+
+    fn tree(&self) -> Tree {
+        let focused = self.focused_window();
+        let focused_x11 = self.focused_x11_window();
+        let outputs = self.workspaces
+            .iter()
+            .map(|workspaces| {
+                let output = workspaces.output
+                    .as_ref()
+                    .and_then(|name| self.space.outputs().find(|output| &output.name() == name));
+                TreeOutput {
+                    name: workspaces.output.clone(),
+                    geometry: output.and_then(|output| self.space.output_geometry(output)).map(Geometry::from),
+                    scale: output.map(|output| output.current_scale().fractional_scale()),
+                    current_workspace: workspaces.current + 1,
+                    workspaces: workspaces.workspaces
+                        .iter()
+                        .enumerate()
+                        .map(|(index, workspace)| TreeWorkspace {
+                            number: index + 1,
+                            layout: workspace.layout,
+                            master_ratio: workspace.master_ratio,
+                            shown: index == workspaces.current,
+                            windows: workspace.windows
+                                .iter()
+                                .map(|window| self.tree_window(window, focused.as_ref()))
+                                .chain(workspace.x11_windows.iter().map(|window| TreeWindow {
+                                    info: x11_window_info(window),
+                                    geometry: (index == workspaces.current).then(|| window.geometry().into()),
+                                    focused: Some(window) == focused_x11.as_ref(),
+                                    floating: true,
+                                    fullscreen: window.is_fullscreen(),
+                                    sticky: false,
+                                    urgent: false,
+                                }))
+                                .collect(),
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
+
+        Tree {
+            outputs,
+            focused_output: self.active_output().map(|output| output.name()),
+            focused_window: self.focused_window_info(),
+        }
+    }
+
+    fn tree_window(&self, window: &Window, focused: Option<&Window>) -> TreeWindow {
+        TreeWindow {
+            info: self.window_info(window),
+            geometry: self.space.element_geometry(window).map(Geometry::from),
+            focused: Some(window) == focused,
+            floating: self.is_floating(window),
+            fullscreen: window.toplevel().current_state().states.contains(xdg_toplevel::State::Fullscreen),
+            sticky: self.is_sticky(window),
+            urgent: self.is_urgent(window),
+        }
+    }
+
+    /// Feeds an input request into the seat like an event of a real device
+    fn synthetic_input(&mut self, request: Request) {
+        self.needs_redraw = true;
+        self.notify_activity();
+        let time = self.start_time.elapsed().as_millis() as u32;
+        match request {
+            Request::PointerMove { x, y } => self.warp_pointer((x, y).into(), time),
+            Request::PointerButton { button, pressed } => {
+                let button_state = if pressed { ButtonState::Pressed } else { ButtonState::Released };
+                self.handle_pointer_button(button, button_state, time);
+            },
+            Request::Key { keycode, pressed } => {
+                let key_state = if pressed { KeyState::Pressed } else { KeyState::Released };
+                self.handle_key(keycode, key_state, time);
+            },
+            _ => {},
+        }
+    }
+}
+
+/// Path of the socket of this compositor instance
+pub fn socket_path() -> Result<PathBuf> {
+    let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR").context("XDG_RUNTIME_DIR is not set")?;
+    Ok(PathBuf::from(runtime_dir).join(format!("twm-{}.sock", std::process::id())))
+}
+
+/// Creates the IPC socket and inserts it into the event loop, returns the socket path
+pub fn init(handle: &LoopHandle<'static, TwmLoopData>) -> Result<PathBuf> {
+    let path = socket_path()?;
+    // A stale socket of a previous instance with the same pid would make bind fail
+    let _ = std::fs::remove_file(&path);
+
+    let listener = UnixListener::bind(&path).context("Failed to bind the IPC socket")?;
+    listener.set_nonblocking(true).context("Failed to make the IPC socket non blocking")?;
+
+    let loop_handle = handle.clone();
+    handle
+        .insert_source(Generic::new(listener, Interest::READ, Mode::Level), move |_, listener, _| {
+            loop {
+                match listener.accept() {
+                    Ok((stream, _)) => insert_connection(&loop_handle, stream),
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                    Err(err) => {
+                        warn!("Failed to accept IPC connection: {}", err);
+                        break;
+                    }
+                }
+            }
+            Ok(PostAction::Continue)
+        })
+        .map_err(|err| err.error)
+        .context("Failed to insert the IPC socket into the event loop")?;
+
+    info!("IPC socket listening on {:?}", path);
+    std::env::set_var("TWM_SOCK", &path);
+    Ok(path)
+}
+
+fn insert_connection(handle: &LoopHandle<'static, TwmLoopData>, stream: UnixStream) {
+    if let Err(err) = stream.set_nonblocking(true) {
+        warn!("Failed to make IPC connection non blocking: {}", err);
+        return;
+    }
+
+    let mut buffer = Vec::new();
+    let result = handle.insert_source(Generic::new(stream, Interest::READ, Mode::Level), move |_, stream, data| {
+        let mut chunk = [0u8; 4096];
+        let mut closed = false;
+        // The rest is read once the buffered lines are handled, the source stays readable
+        while buffer.len() <= MAX_REQUEST_LENGTH {
+            match stream.read(&mut chunk) {
+                // The client closed the connection, the requests it sent before still count
+                Ok(0) => {
+                    closed = true;
+                    break;
+                },
+                Ok(read) => buffer.extend_from_slice(&chunk[..read]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    debug!("IPC connection error: {}", err);
+                    return Ok(PostAction::Remove);
+                }
+            }
+        }
+        // A last request may go without its newline
+        if closed && buffer.last().map_or(false, |&byte| byte != b'\n') {
+            buffer.push(b'\n');
+        }
+
+        while let Some(newline) = buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=newline).collect();
+            let response = match std::str::from_utf8(&line) {
+                Ok(line) if line.trim().is_empty() => continue,
+                Ok(line) => data.state.handle_ipc_request(line.trim(), stream),
+                Err(err) => Response::error(err),
+            };
+            if !send_response(stream, &response) {
+                return Ok(PostAction::Remove);
+            }
+        }
+
+        if buffer.len() > MAX_REQUEST_LENGTH {
+            warn!("IPC request longer than {} bytes, closing the connection", MAX_REQUEST_LENGTH);
+            send_response(stream, &Response::error("Request too long"));
+            return Ok(PostAction::Remove);
+        }
+        if closed {
+            return Ok(PostAction::Remove);
+        }
+        Ok(PostAction::Continue)
+    });
+
+    if let Err(err) = result {
+        warn!("Failed to insert IPC connection into the event loop: {}", err.error);
+    }
+}
+
+/// Writes `response` as one line, returns whether the client is still there
+fn send_response(stream: &mut UnixStream, response: &Response) -> bool {
+    let mut reply = serde_json::to_vec(response).expect("Serializable IPC response");
+    reply.push(b'\n');
+    match stream.write_all(&reply) {
+        Ok(()) => true,
+        Err(err) => {
+            debug!("Failed to reply to IPC client: {}", err);
+            false
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use smithay::{
+    desktop::{layer_map_for_output, LayerMap, LayerSurface, WindowSurfaceType},
+    output::Output,
+    reexports::wayland_server::protocol::{wl_output::WlOutput, wl_surface::WlSurface},
+    utils::{Logical, Point},
+    wayland::compositor::with_states,
+    wayland::shell::wlr_layer::{
+        Layer,
+        LayerSurface as WlrLayerSurface,
+        LayerSurfaceData,
+        WlrLayerShellHandler,
+        WlrLayerShellState,
+    },
+};
+use tracing::{debug, warn};
+
+use crate::TwmState;
+
+/// Location of the main surface of `layer` relative to the output of `map`. The geometry
+/// the layer map knows is the bounding box, which subsurfaces may extend to the top left.
+pub fn layer_surface_location(map: &LayerMap, layer: &LayerSurface) -> Option<Point<i32, Logical>> {
+    Some(map.layer_geometry(layer)?.loc - layer.bbox().loc)
+}
+
+impl WlrLayerShellHandler for TwmState {
+    fn shell_state(&mut self) -> &mut WlrLayerShellState {
+        &mut self.layer_shell_state
+    }
+
+    fn new_layer_surface(&mut self,
+                         surface: WlrLayerSurface,
+                         output: Option<WlOutput>,
+                         layer: Layer,
+                         namespace: String) {
+        debug!("New layer surface {:?} on layer {:?}", namespace, layer);
+        let Some(output) = output
+            .as_ref()
+            .and_then(Output::from_resource)
+            .or_else(|| self.active_output()) else {
+            warn!("No output for layer surface {:?}", namespace);
+            surface.send_close();
+            return;
+        };
+
+        let mut map = layer_map_for_output(&output);
+        if let Err(err) = map.map_layer(&LayerSurface::new(surface, namespace)) {
+            warn!("Failed to map layer surface: {}", err);
+        }
+    }
+
+    fn layer_destroyed(&mut self, surface: WlrLayerSurface) {
+        debug!("Layer surface destroyed");
+        for output in self.space.outputs() {
+            let mut map = layer_map_for_output(output);
+            if let Some(layer) = map.layer_for_surface(surface.wl_surface(), WindowSurfaceType::TOPLEVEL).cloned() {
+                map.unmap_layer(&layer);
+            }
+        }
+        // The exclusive zone of the layer is free again
+        self.retile();
+    }
+}
+
+impl TwmState {
+    /// Handles a commit of a layer surface, returns false if `surface` isn't one
+    pub fn layer_surface_commit(&mut self, surface: &WlSurface) -> bool {
+        let Some(output) = self.space
+            .outputs()
+            .find(|output| layer_map_for_output(output)
+                .layer_for_surface(surface, WindowSurfaceType::TOPLEVEL)
+                .is_some())
+            .cloned() else {
+            return false;
+        };
+
+        let initial_configure_sent = with_states(surface, |states| {
+            states
+                .data_map
+                .get::<LayerSurfaceData>()
+                .expect("Layer surface data available")
+                .lock()
+                .expect("Layer surface data lock")
+                .initial_configure_sent
+        });
+
+        {
+            let mut map = layer_map_for_output(&output);
+            // Exclusive zones or anchors may have changed with this commit
+            map.arrange();
+            if !initial_configure_sent {
+                map.layer_for_surface(surface, WindowSurfaceType::TOPLEVEL)
+                    .expect("Layer surface mapped")
+                    .layer_surface()
+                    .send_configure();
+            }
+        }
+
+        if initial_configure_sent {
+            self.retile();
+        }
+        true
+    }
+
+    /// Surface of a layer on `layers` of the output under `position`
+    pub fn layer_surface_under(&self,
+                               position: Point<f64, Logical>,
+                               layers: &[Layer]) -> Option<(WlSurface, Point<i32, Logical>)> {
+        let output = self.space.output_under(position).next()?;
+        let output_geometry = self.space.output_geometry(output)?;
+        let map = layer_map_for_output(output);
+
+        layers.iter().find_map(|&layer| {
+            let layer_surface = map.layer_under(layer, position - output_geometry.loc.to_f64())?;
+            let layer_location = layer_surface_location(&map, layer_surface)? + output_geometry.loc;
+            layer_surface
+                .surface_under(position - layer_location.to_f64(), WindowSurfaceType::ALL)
+                .map(|(surface, location)| (surface, location + layer_location))
+        })
+    }
+}
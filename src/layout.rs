@@ -0,0 +1,488 @@
+use std::cell::Cell;
+
+use serde::{Deserialize, Serialize};
+use smithay::{
+    desktop::Window,
+    utils::{Logical, Rectangle, Size},
+    wayland::compositor::{with_states, SurfaceCachedState},
+};
+
+/// Default fraction of the output width taken by the master window
+pub const DEFAULT_MASTER_RATIO: f64 = 0.5;
+/// Bounds and step of runtime changes to the master ratio
+pub const MIN_MASTER_RATIO: f64 = 0.1;
+pub const MAX_MASTER_RATIO: f64 = 0.9;
+pub const MASTER_RATIO_STEP: f64 = 0.05;
+
+/// Arrangement of the tiled windows of a workspace
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Layout {
+    /// Master window on the left, the other windows stacked on the right
+    #[default]
+    MasterStack,
+    /// Every window fills the whole area, only the focused one is visible
+    Monocle,
+    /// Every window takes half of the space left by the windows before it, winding inwards
+    Spiral,
+}
+
+impl Layout {
+    /// Layout following this one when cycling through the layouts
+    pub fn next(self) -> Self {
+        match self {
+            Layout::MasterStack => Layout::Monocle,
+            Layout::Monocle => Layout::Spiral,
+            Layout::Spiral => Layout::MasterStack,
+        }
+    }
+}
+
+/// Shrinks `area` by `amount` on every side, clamped so the result is never empty
+pub fn inset(area: Rectangle<i32, Logical>, amount: i32) -> Rectangle<i32, Logical> {
+    let amount_x = amount.clamp(0, ((area.size.w - 1) / 2).max(0));
+    let amount_y = amount.clamp(0, ((area.size.h - 1) / 2).max(0));
+    Rectangle::from_loc_and_size(
+        (area.loc.x + amount_x, area.loc.y + amount_y),
+        (area.size.w - 2 * amount_x, area.size.h - 2 * amount_y))
+}
+
+/// Minimum and maximum size the client asked for, `None` when it has no preference.
+/// A zero dimension inside a hint means the client doesn't care about that dimension.
+pub fn window_size_hints(window: &Window) -> (Option<Size<i32, Logical>>, Option<Size<i32, Logical>>) {
+    with_states(window.toplevel().wl_surface(), |states| {
+        let data = states.cached_state.current::<SurfaceCachedState>();
+        let min_size = (data.min_size.w > 0 || data.min_size.h > 0).then_some(data.min_size);
+        let max_size = (data.max_size.w > 0 || data.max_size.h > 0).then_some(data.max_size);
+        (min_size, max_size)
+    })
+}
+
+/// Size hints of a window when they were last checked for changes, kept in its user data
+#[derive(Debug, Default)]
+struct LastSizeHints(Cell<(Option<Size<i32, Logical>>, Option<Size<i32, Logical>>)>);
+
+/// Whether the size hints of `window` changed since the last call
+pub fn size_hints_changed(window: &Window) -> bool {
+    window.user_data().insert_if_missing(LastSizeHints::default);
+    let last = &window.user_data().get::<LastSizeHints>().expect("Last size hints available").0;
+    let hints = window_size_hints(window);
+    last.replace(hints) != hints
+}
+
+/// Size hints of the windows sharing a tile. Tabbed windows get the largest minimum size
+/// among them and no maximum size, as they all get the same geometry.
+pub fn tile_size_hints(windows: &[Window]) -> (Option<Size<i32, Logical>>, Option<Size<i32, Logical>>) {
+    match windows {
+        [window] => window_size_hints(window),
+        windows => {
+            let min_size = windows
+                .iter()
+                .filter_map(|window| window_size_hints(window).0)
+                .reduce(|a, b| (a.w.max(b.w), a.h.max(b.h)).into());
+            (min_size, None)
+        }
+    }
+}
+
+/// Shrinks `tile` to the maximum size of a window, keeping it centered in the tile
+pub fn fit_to_max_size(tile: Rectangle<i32, Logical>, max_size: Option<Size<i32, Logical>>) -> Rectangle<i32, Logical> {
+    let mut size = tile.size;
+    if let Some(max_size) = max_size {
+        if max_size.w > 0 {
+            size.w = size.w.min(max_size.w);
+        }
+        if max_size.h > 0 {
+            size.h = size.h.min(max_size.h);
+        }
+    }
+
+    Rectangle::from_loc_and_size(
+        (tile.loc.x + (tile.size.w - size.w) / 2, tile.loc.y + (tile.size.h - size.h) / 2),
+        size)
+}
+
+/// Whether a window with `min_size` can shrink to `size`
+pub fn fits_min_size(size: Size<i32, Logical>, min_size: Size<i32, Logical>) -> bool {
+    size.w >= min_size.w && size.h >= min_size.h
+}
+
+/// Computes a master-stack arrangement for windows with the given minimum sizes inside `area`.
+///
+/// The first rectangle is the master on the left side of the area and takes
+/// `master_ratio` of its width, the remaining ones are stacked vertically on the
+/// right side. A single window gets the whole area. Neighbouring windows are
+/// separated by `gap`, which is clamped so no window ends up empty.
+///
+/// The master grows to its minimum width and stack windows get at least their minimum
+/// height as long as the other windows keep theirs, the remaining space is shared evenly.
+pub fn master_stack(area: Rectangle<i32, Logical>,
+                    min_sizes: &[Size<i32, Logical>],
+                    master_ratio: f64,
+                    gap: i32) -> Vec<Rectangle<i32, Logical>> {
+    match min_sizes.len() {
+        0 => Vec::new(),
+        1 => vec![area],
+        count => {
+            let gap_x = gap.clamp(0, (area.size.w - 2).max(0));
+            let available_width = area.size.w - gap_x;
+            let stack_min_width = min_sizes[1..].iter().map(|size| size.w).max().unwrap_or(0);
+            let master_width = (available_width as f64 * master_ratio).round() as i32;
+            let master_width = master_width.max(min_sizes[0].w.min(available_width - stack_min_width));
+            let master_width = master_width.clamp(1, (available_width - 1).max(1));
+            let stack_width = (available_width - master_width).max(1);
+            let stack_x = area.loc.x + master_width + gap_x;
+
+            let mut geometries = Vec::with_capacity(count);
+            geometries.push(Rectangle::from_loc_and_size(area.loc, (master_width, area.size.h)));
+
+            let stack_count = (count - 1) as i32;
+            let gap_y = if stack_count > 1 {
+                gap.clamp(0, ((area.size.h - stack_count) / (stack_count - 1)).max(0))
+            } else {
+                0
+            };
+            let min_heights: Vec<i32> = min_sizes[1..].iter().map(|size| size.h).collect();
+            let heights = distribute(area.size.h - gap_y * (stack_count - 1), &min_heights);
+            let mut y = area.loc.y;
+            for height in heights {
+                geometries.push(Rectangle::from_loc_and_size((stack_x, y), (stack_width, height)));
+                y += height + gap_y;
+            }
+
+            geometries
+        }
+    }
+}
+
+/// Computes a monocle arrangement, all `count` windows get the whole `area`
+pub fn monocle(area: Rectangle<i32, Logical>, count: usize) -> Vec<Rectangle<i32, Logical>> {
+    vec![area; count]
+}
+
+/// Computes a fibonacci spiral arrangement for `count` windows inside `area`.
+///
+/// Each window but the last takes one half of the area left by the windows before it,
+/// splitting alternately side by side and on top of each other. The kept half turns from
+/// left to top to right to bottom, so the windows wind inwards. Neighbouring windows are
+/// separated by `gap`.
+pub fn spiral(area: Rectangle<i32, Logical>, count: usize, gap: i32) -> Vec<Rectangle<i32, Logical>> {
+    let mut geometries = Vec::with_capacity(count);
+    let mut remaining = area;
+    for index in 0..count {
+        if index + 1 == count {
+            geometries.push(remaining);
+            break;
+        }
+        let (tile, rest) = split_half(remaining, index % 4, gap);
+        geometries.push(tile);
+        remaining = rest;
+    }
+    geometries
+}
+
+/// Splits `area` in two halves separated by `gap`, returns the half kept at spiral `turn`
+/// and the other one
+fn split_half(area: Rectangle<i32, Logical>,
+              turn: usize,
+              gap: i32) -> (Rectangle<i32, Logical>, Rectangle<i32, Logical>) {
+    let side_by_side = turn % 2 == 0;
+    let length = if side_by_side { area.size.w } else { area.size.h };
+    let gap = gap.clamp(0, (length - 2).max(0));
+    let first = ((length - gap) / 2).max(1);
+    let second = (length - gap - first).max(1);
+
+    let (first_half, second_half) = if side_by_side {
+        (Rectangle::from_loc_and_size(area.loc, (first, area.size.h)),
+         Rectangle::from_loc_and_size((area.loc.x + first + gap, area.loc.y), (second, area.size.h)))
+    } else {
+        (Rectangle::from_loc_and_size(area.loc, (area.size.w, first)),
+         Rectangle::from_loc_and_size((area.loc.x, area.loc.y + first + gap), (area.size.w, second)))
+    };
+
+    // The first two turns keep the left and top half, the last two the right and bottom half
+    if turn < 2 {
+        (first_half, second_half)
+    } else {
+        (second_half, first_half)
+    }
+}
+
+/// Splits `total` into one share per minimum, each share gets at least its minimum if all
+/// minimums fit and the rest is split evenly between the others
+fn distribute(total: i32, minimums: &[i32]) -> Vec<i32> {
+    let mut fixed: Vec<Option<i32>> = vec![None; minimums.len()];
+    if minimums.iter().sum::<i32>() <= total {
+        // Fixing a share at its minimum lowers the even share of the others, which may
+        // push further shares below their minimum
+        loop {
+            let free_count = fixed.iter().filter(|share| share.is_none()).count() as i32;
+            if free_count == 0 {
+                break;
+            }
+            let even_share = (total - fixed.iter().flatten().sum::<i32>()) / free_count;
+            let mut changed = false;
+            for (share, &minimum) in fixed.iter_mut().zip(minimums) {
+                if share.is_none() && minimum > even_share {
+                    *share = Some(minimum);
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    let free_count = fixed.iter().filter(|share| share.is_none()).count() as i32;
+    let even_share = if free_count > 0 {
+        (total - fixed.iter().flatten().sum::<i32>()) / free_count
+    } else {
+        0
+    };
+    let mut shares: Vec<i32> = fixed.iter().map(|share| share.unwrap_or(even_share).max(1)).collect();
+
+    // the last free share takes up the rounding remainder
+    let last = fixed.iter().rposition(|share| share.is_none()).unwrap_or(shares.len() - 1);
+    shares[last] = (shares[last] + total - shares.iter().sum::<i32>()).max(1);
+    shares
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: i32, y: i32, w: i32, h: i32) -> Rectangle<i32, Logical> {
+        Rectangle::from_loc_and_size((x, y), (w, h))
+    }
+
+    fn area() -> Rectangle<i32, Logical> {
+        rect(0, 0, 1000, 600)
+    }
+
+    fn no_min_sizes(count: usize) -> Vec<Size<i32, Logical>> {
+        vec![Size::default(); count]
+    }
+
+    fn overlap(a: Rectangle<i32, Logical>, b: Rectangle<i32, Logical>) -> bool {
+        a.loc.x < b.loc.x + b.size.w && b.loc.x < a.loc.x + a.size.w &&
+            a.loc.y < b.loc.y + b.size.h && b.loc.y < a.loc.y + a.size.h
+    }
+
+    /// Tiles are inside `area` and don't overlap, without gaps they cover all of it
+    fn assert_tiles(tiles: &[Rectangle<i32, Logical>], area: Rectangle<i32, Logical>, gap: i32) {
+        for (index, tile) in tiles.iter().enumerate() {
+            assert!(tile.size.w > 0 && tile.size.h > 0, "Tile {:?} is empty", tile);
+            assert!(area.contains_rect(*tile), "Tile {:?} outside of {:?}", tile, area);
+            for other in &tiles[index + 1..] {
+                assert!(!overlap(*tile, *other), "Tiles {:?} and {:?} overlap", tile, other);
+            }
+        }
+        if gap == 0 {
+            let covered: i32 = tiles.iter().map(|tile| tile.size.w * tile.size.h).sum();
+            assert_eq!(covered, area.size.w * area.size.h);
+        }
+    }
+
+    #[test]
+    fn master_stack_without_windows() {
+        assert!(master_stack(area(), &[], DEFAULT_MASTER_RATIO, 10).is_empty());
+    }
+
+    #[test]
+    fn master_stack_single_window_fills_area() {
+        assert_eq!(master_stack(area(), &no_min_sizes(1), DEFAULT_MASTER_RATIO, 10), [area()]);
+    }
+
+    #[test]
+    fn master_stack_two_windows_split_at_ratio() {
+        assert_eq!(
+            master_stack(area(), &no_min_sizes(2), DEFAULT_MASTER_RATIO, 0),
+            [rect(0, 0, 500, 600), rect(500, 0, 500, 600)]);
+        assert_eq!(
+            master_stack(area(), &no_min_sizes(2), 0.7, 0),
+            [rect(0, 0, 700, 600), rect(700, 0, 300, 600)]);
+    }
+
+    #[test]
+    fn master_stack_stacks_further_windows() {
+        assert_eq!(
+            master_stack(area(), &no_min_sizes(4), DEFAULT_MASTER_RATIO, 0),
+            [rect(0, 0, 500, 600), rect(500, 0, 500, 200), rect(500, 200, 500, 200), rect(500, 400, 500, 200)]);
+    }
+
+    #[test]
+    fn master_stack_separates_windows_by_gap() {
+        assert_eq!(
+            master_stack(area(), &no_min_sizes(3), DEFAULT_MASTER_RATIO, 10),
+            [rect(0, 0, 495, 600), rect(505, 0, 495, 295), rect(505, 305, 495, 295)]);
+    }
+
+    #[test]
+    fn master_stack_offsets_tiles_by_area_location() {
+        let area = rect(100, 50, 1000, 600);
+        assert_eq!(
+            master_stack(area, &no_min_sizes(2), DEFAULT_MASTER_RATIO, 0),
+            [rect(100, 50, 500, 600), rect(600, 50, 500, 600)]);
+    }
+
+    #[test]
+    fn master_stack_tiles_cover_area_without_overlap() {
+        let area = rect(7, 13, 1001, 599);
+        for count in 1..=7 {
+            for gap in [0, 5, 24] {
+                let tiles = master_stack(area, &no_min_sizes(count), DEFAULT_MASTER_RATIO, gap);
+                assert_eq!(tiles.len(), count);
+                assert_tiles(&tiles, area, gap);
+            }
+        }
+    }
+
+    #[test]
+    fn master_stack_clamps_gap_of_small_area() {
+        let area = rect(0, 0, 20, 20);
+        let tiles = master_stack(area, &no_min_sizes(4), DEFAULT_MASTER_RATIO, 50);
+        assert_tiles(&tiles, area, 50);
+    }
+
+    #[test]
+    fn master_stack_grows_master_to_min_width() {
+        let min_sizes = [Size::from((700, 0)), Size::default()];
+        assert_eq!(
+            master_stack(area(), &min_sizes, DEFAULT_MASTER_RATIO, 0),
+            [rect(0, 0, 700, 600), rect(700, 0, 300, 600)]);
+    }
+
+    #[test]
+    fn master_stack_keeps_stack_min_width() {
+        let min_sizes = [Size::from((900, 0)), Size::from((300, 0))];
+        assert_eq!(
+            master_stack(area(), &min_sizes, DEFAULT_MASTER_RATIO, 0),
+            [rect(0, 0, 700, 600), rect(700, 0, 300, 600)]);
+    }
+
+    #[test]
+    fn master_stack_gives_stack_windows_min_height() {
+        let min_sizes = [Size::default(), Size::from((0, 400)), Size::default()];
+        assert_eq!(
+            master_stack(area(), &min_sizes, DEFAULT_MASTER_RATIO, 0),
+            [rect(0, 0, 500, 600), rect(500, 0, 500, 400), rect(500, 400, 500, 200)]);
+    }
+
+    #[test]
+    fn spiral_single_window_fills_area() {
+        assert_eq!(spiral(area(), 1, 10), [area()]);
+    }
+
+    #[test]
+    fn spiral_two_windows_side_by_side() {
+        assert_eq!(spiral(area(), 2, 0), [rect(0, 0, 500, 600), rect(500, 0, 500, 600)]);
+    }
+
+    #[test]
+    fn spiral_three_windows() {
+        assert_eq!(
+            spiral(area(), 3, 0),
+            [rect(0, 0, 500, 600), rect(500, 0, 500, 300), rect(500, 300, 500, 300)]);
+    }
+
+    #[test]
+    fn spiral_four_windows_wind_inwards() {
+        assert_eq!(
+            spiral(area(), 4, 0),
+            [rect(0, 0, 500, 600), rect(500, 0, 500, 300), rect(750, 300, 250, 300), rect(500, 300, 250, 300)]);
+    }
+
+    #[test]
+    fn spiral_fifth_window_takes_bottom_half() {
+        assert_eq!(
+            spiral(area(), 5, 0),
+            [rect(0, 0, 500, 600), rect(500, 0, 500, 300), rect(750, 300, 250, 300),
+             rect(500, 450, 250, 150), rect(500, 300, 250, 150)]);
+    }
+
+    #[test]
+    fn spiral_separates_windows_by_gap() {
+        assert_eq!(
+            spiral(area(), 3, 10),
+            [rect(0, 0, 495, 600), rect(505, 0, 495, 295), rect(505, 305, 495, 295)]);
+    }
+
+    #[test]
+    fn spiral_tiles_cover_area_without_overlap() {
+        let area = rect(7, 13, 1001, 599);
+        for count in 1..=8 {
+            for gap in [0, 5, 24] {
+                let tiles = spiral(area, count, gap);
+                assert_eq!(tiles.len(), count);
+                assert_tiles(&tiles, area, gap);
+            }
+        }
+    }
+
+    #[test]
+    fn fit_to_max_size_keeps_tile_without_max_size() {
+        assert_eq!(fit_to_max_size(rect(10, 20, 500, 400), None), rect(10, 20, 500, 400));
+    }
+
+    #[test]
+    fn fit_to_max_size_centers_shrunk_window() {
+        assert_eq!(fit_to_max_size(rect(10, 20, 500, 400), Some(Size::from((300, 200)))), rect(110, 120, 300, 200));
+    }
+
+    #[test]
+    fn fit_to_max_size_ignores_unconstrained_dimension() {
+        assert_eq!(fit_to_max_size(rect(0, 0, 500, 400), Some(Size::from((300, 0)))), rect(100, 0, 300, 400));
+        assert_eq!(fit_to_max_size(rect(0, 0, 500, 400), Some(Size::from((0, 100)))), rect(0, 150, 500, 100));
+    }
+
+    #[test]
+    fn fit_to_max_size_never_grows_tile() {
+        assert_eq!(fit_to_max_size(rect(0, 0, 500, 400), Some(Size::from((800, 600)))), rect(0, 0, 500, 400));
+    }
+
+    #[test]
+    fn fits_min_size_in_both_dimensions() {
+        assert!(fits_min_size(Size::from((500, 400)), Size::default()));
+        assert!(fits_min_size(Size::from((500, 400)), Size::from((500, 400))));
+        assert!(!fits_min_size(Size::from((500, 400)), Size::from((501, 0))));
+        assert!(!fits_min_size(Size::from((500, 400)), Size::from((0, 401))));
+    }
+
+    #[test]
+    fn distribute_splits_evenly() {
+        assert_eq!(distribute(600, &[0, 0, 0]), [200, 200, 200]);
+    }
+
+    #[test]
+    fn distribute_gives_remainder_to_last_share() {
+        assert_eq!(distribute(10, &[0, 0, 0]), [3, 3, 4]);
+    }
+
+    #[test]
+    fn distribute_keeps_minimums() {
+        assert_eq!(distribute(100, &[60, 0, 0]), [60, 20, 20]);
+    }
+
+    #[test]
+    fn distribute_fixes_minimums_pushed_below_even_share() {
+        // Fixing the first share at 50 drops the even share of the others from 33 to 25,
+        // below the second minimum
+        assert_eq!(distribute(100, &[50, 30, 0]), [50, 30, 20]);
+    }
+
+    #[test]
+    fn distribute_gives_remainder_to_last_free_share() {
+        assert_eq!(distribute(102, &[0, 0, 61]), [20, 21, 61]);
+    }
+
+    #[test]
+    fn distribute_splits_evenly_if_minimums_dont_fit() {
+        assert_eq!(distribute(100, &[80, 80]), [50, 50]);
+    }
+
+    #[test]
+    fn distribute_never_gives_empty_shares() {
+        assert_eq!(distribute(2, &[0, 0, 0]), [1, 1, 1]);
+    }
+}
@@ -1,33 +1,94 @@
-use std::{time::Duration, os::fd::AsRawFd};
+mod action;
+mod activation;
+mod animation;
+mod backend;
+mod cli;
+mod clipboard;
+mod config;
+mod configure;
+mod content_type;
+mod cursor;
+mod debug_overlay;
+mod direction;
+mod dmabuf;
+mod dnd;
+mod floating;
+mod focus;
+mod foreign_toplevel;
+mod gestures;
+mod grabs;
+mod idle;
+mod input;
+mod input_method;
+mod ipc;
+mod layer_shell;
+mod layout;
+mod maximize;
+mod minimize;
+mod opacity;
+mod output;
+mod pointer_constraints;
+mod popup;
+mod reload;
+mod render;
+mod scratchpad;
+mod session;
+mod screencopy;
+mod session_lock;
+mod shortcuts_inhibit;
+mod shutdown;
+mod snap;
+mod spawn;
+mod sticky;
+mod swallow;
+mod swap;
+mod tabs;
+mod touch;
+mod virtual_keyboard;
+mod window_info;
+mod window_rules;
+mod workspace;
+mod xwayland;
+
+#[cfg(test)]
+mod tests;
+
+use std::os::fd::AsRawFd;
 use std::sync::Arc;
+use std::collections::HashMap;
 
 use anyhow::{Result, Context, Ok};
-use smithay::backend::input::{AbsolutePositionEvent, PointerButtonEvent, ButtonState, PointerAxisEvent};
-use smithay::backend::renderer::damage::OutputDamageTracker;
-use smithay::backend::renderer::element::surface::WaylandSurfaceRenderElement;
-use smithay::desktop::WindowSurfaceType;
-use smithay::input::pointer::{MotionEvent, ButtonEvent};
-use smithay::wayland::data_device::{DataDeviceHandler, ServerDndGrabHandler, ClientDndGrabHandler};
+use tracing::{debug, info, trace, warn};
+use smithay::desktop::{PopupManager, WindowSurfaceType};
+use smithay::input::pointer::{Focus, CursorImageStatus};
+use smithay::wayland::data_device::{DataDeviceHandler, ServerDndGrabHandler, ClientDndGrabHandler, set_data_device_focus};
+use smithay::wayland::primary_selection::{PrimarySelectionState, PrimarySelectionHandler, set_primary_focus};
+use smithay::wayland::output::OutputManagerState;
+use smithay::wayland::fractional_scale::{FractionalScaleManagerState, FractionalScaleHandler};
+use smithay::wayland::viewporter::ViewporterState;
+use smithay::wayland::text_input::TextInputManagerState;
+use smithay::wayland::input_method::{InputMethodManagerState, PopupSurface as InputMethodPopupSurface};
+use smithay::wayland::presentation::PresentationState;
+use smithay::xwayland::{X11Wm, XWaylandClientData};
+use smithay::wayland::session_lock::SessionLockManagerState;
+use smithay::wayland::relative_pointer::RelativePointerManagerState;
+use smithay::wayland::pointer_gestures::PointerGesturesState;
+use smithay::wayland::xdg_activation::XdgActivationState;
+use smithay::wayland::dmabuf::DmabufState;
+use smithay::wayland::keyboard_shortcuts_inhibit::KeyboardShortcutsInhibitState;
 use smithay::wayland::seat::WaylandFocus;
+use smithay::wayland::shell::xdg::decoration::{XdgDecorationState, XdgDecorationHandler};
+use smithay::wayland::shell::wlr_layer::{WlrLayerShellState, Layer};
+use smithay::desktop::layer_map_for_output;
 use smithay::{
     desktop::{
         Space,
         Window
     },
-    output::{
-        Output,
-        Mode,
-        PhysicalProperties,
-        Subpixel
-    },
-    backend::{
-        input::{InputEvent, KeyboardKeyEvent, Event, Axis},
-        renderer::gles::GlesRenderer,
-        renderer::utils::on_commit_buffer_handler,
-        winit::{self, WinitEvent, WinitError},
-    },
+    output::Output,
+    backend::renderer::utils::on_commit_buffer_handler,
     reexports::{
-        calloop::{ PostAction, Interest, EventLoop, timer::{Timer, TimeoutAction}, LoopSignal, generic::Generic},
+        calloop::{ PostAction, Interest, EventLoop, LoopHandle, LoopSignal, generic::Generic},
         wayland_server::{
             protocol::{
                 wl_data_source::WlDataSource,
@@ -38,8 +99,9 @@ use smithay::{
                 wl_output::WlOutput,
             },
             Client,
-            Display, backend::ClientData
+            Display, DisplayHandle, Resource, backend::{ClientData, DisconnectReason}
         },
+        wayland_protocols::wp::primary_selection::zv1::server::zwp_primary_selection_source_v1::ZwpPrimarySelectionSourceV1,
         wayland_protocols::xdg::shell::server::xdg_toplevel::ResizeEdge,
         wayland_protocols::xdg::decoration::zv1::server::zxdg_toplevel_decoration_v1,
     }, 
@@ -62,6 +124,7 @@ use smithay::{
             ToplevelSurface,
             Configure,
             PositionerState,
+            XdgToplevelSurfaceData,
         }, 
         shm::{
             ShmState, 
@@ -70,17 +133,21 @@ use smithay::{
         buffer::BufferHandler,
     },
     utils::{
+        Clock,
+        Monotonic,
         Rectangle, 
+        Size,
         Serial,
         Logical,
         Point,
         SERIAL_COUNTER,
     },
     delegate_compositor, delegate_shm, delegate_xdg_shell, delegate_seat, delegate_output, delegate_data_device,
-    input::{
-        keyboard::{FilterResult},
-        pointer::AxisFrame,
-        SeatState, Seat, SeatHandler},
+    delegate_xdg_decoration, delegate_layer_shell, delegate_primary_selection, delegate_presentation,
+    delegate_session_lock, delegate_relative_pointer, delegate_fractional_scale, delegate_viewporter,
+    delegate_text_input_manager, delegate_input_method_manager, delegate_xdg_activation,
+    delegate_pointer_gestures, delegate_dmabuf, delegate_keyboard_shortcuts_inhibit,
+    input::{SeatState, Seat, SeatHandler},
 };
 
 
@@ -91,12 +158,12 @@ struct TwmClientState {
 
 impl ClientData for TwmClientState {
     fn initialized(&self, client_id: smithay::reexports::wayland_server::backend::ClientId) {
-        println!("Initialized client wih id: {:?}", client_id);
+        info!("Initialized client with id: {:?}", client_id);
     }
 
     fn disconnected(&self, client_id: smithay::reexports::wayland_server::backend::ClientId, 
                     reason: smithay::reexports::wayland_server::backend::DisconnectReason) {
-        println!("Client with id: {:?} disconnected with reason: {:?}", client_id, reason);
+        info!("Client with id: {:?} disconnected with reason: {:?}", client_id, reason);
     }
 
     fn debug(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -108,57 +175,508 @@ impl ClientData for TwmClientState {
 
 struct TwmState {
     start_time: std::time::Instant,
+    clock: Clock<Monotonic>,
+    display_handle: DisplayHandle,
+    config: config::Config,
     compositor_state: CompositorState,
     xdg_shell_state: XdgShellState,
+    // Only kept to keep their globals, the protocols need no further state access
+    _xdg_decoration_state: XdgDecorationState,
+    layer_shell_state: WlrLayerShellState,
     shm_state: ShmState,
     seat_state: SeatState<Self>,
     data_device_state: DataDeviceState,
+    primary_selection_state: PrimarySelectionState,
+    _presentation_state: PresentationState,
+    _output_manager_state: OutputManagerState,
+    _fractional_scale_state: FractionalScaleManagerState,
+    _viewporter_state: ViewporterState,
+    _text_input_state: TextInputManagerState,
+    _input_method_state: InputMethodManagerState,
+    xdg_activation_state: XdgActivationState,
+    keyboard_shortcuts_inhibit_state: KeyboardShortcutsInhibitState,
+    idle: idle::IdleState,
+    session_lock_state: SessionLockManagerState,
+    _relative_pointer_state: RelativePointerManagerState,
+    _pointer_gestures_state: PointerGesturesState,
+    pointer_constraints: pointer_constraints::PointerConstraintsState,
+    foreign_toplevels: foreign_toplevel::ForeignToplevelState,
+    screencopy: screencopy::ScreencopyState,
+    virtual_keyboards: virtual_keyboard::VirtualKeyboardState,
+    /// Set while the session is locked
+    session_lock: Option<session_lock::SessionLock>,
+    scratchpad: scratchpad::Scratchpad,
+    /// Saved windows new windows can still take the place of
+    session: Vec<session::SessionWindow>,
 
     space: Space<Window>,
+    popups: PopupManager,
+    /// Candidate popups of input methods, drawn below the text cursor of their parent
+    input_method_popups: Vec<InputMethodPopupSurface>,
+    /// Workspaces of each output, never empty
+    workspaces: Vec<workspace::OutputWorkspaces>,
+    /// Maximized and fullscreen windows with their geometry from before, keyed by toplevel surface
+    maximized: HashMap<WlSurface, maximize::FilledOutput>,
+    /// Windows taken out of their workspace, in the order they were minimized
+    minimized: Vec<minimize::Minimized>,
+    content_type: content_type::ContentTypeState,
+    /// Shown debug overlay with its statistics
+    debug_overlay: Option<debug_overlay::DebugOverlay>,
+    /// Xwayland server and the X11 windows it mapped
+    xwayland: xwayland::Xwayland,
 
     ev_signal: LoopSignal,
+    /// Inserts sources into the event loop at runtime
+    loop_handle: LoopHandle<'static, TwmLoopData>,
 
     seat: Seat<Self>,
+    cursor_status: CursorImageStatus,
+    /// Drawn when no client provided a cursor image
+    cursor_theme: cursor::CursorTheme,
+    /// Compositor cursor shown while no client cursor applies
+    cursor_icon: cursor::CursorIcon,
+    /// Icon of the drag-and-drop operation in progress
+    dnd_icon: Option<dnd::DndIcon>,
+    /// Font of tab titles, tabs have no titles without one
+    tab_font: Option<fontdue::Font>,
+    /// Set whenever something visible changed, backends only render a new frame if it is set
+    needs_redraw: bool,
+    /// Virtual terminal to switch to, applied by backends supporting it
+    requested_vt: Option<i32>,
+    /// Outputs absolute positions of input devices are mapped to, keyed by device id
+    device_outputs: HashMap<String, Output>,
+    /// Touchpad gesture in progress that is bound to an action instead of reaching clients
+    compositor_gesture: Option<gestures::CompositorGesture>,
+    /// Keys whose press was intercepted by a keybinding, their release is not forwarded either
+    suppressed_keys: Vec<u32>,
+    /// Serials of key and button events checked by activation tokens
+    input_serials: activation::InputSerials,
+    /// Config file read again by `reload_config`
+    config_path: Option<std::path::PathBuf>,
+    /// Name of the wayland socket clients connect to, passed to spawned clients
+    socket_name: Option<std::ffi::OsString>,
+    /// Spawned commands that didn't exit yet, reaped by the SIGCHLD source
+    children: Vec<nix::unistd::Pid>,
+    /// IPC connections that subscribed to events
+    ipc_subscribers: Vec<ipc::Subscriber>,
+    /// Outputs whose next frame is drawn without damage tracking
+    full_redraw: Vec<Output>,
+    /// Selection kept after its client exits
+    clipboard: clipboard::Clipboard,
+    dmabuf_state: DmabufState,
+    /// Only advertised by backends rendering on the gpu
+    dmabufs: dmabuf::Dmabufs,
 }
 
 
 impl TwmState {
-    fn new(event_loop: &mut EventLoop<TwmLoopData>, 
-           display: &mut Display<Self>) -> Result<Self> {
+    fn new(event_loop: &mut EventLoop<'static, TwmLoopData>, 
+           display: &mut Display<Self>,
+           config: config::Config) -> Result<Self> {
         let display_handle = display.handle();
         
         let compositor_state = CompositorState::new::<TwmState>(&display_handle);
         let shm_state = ShmState::new::<TwmState>(&display_handle, vec![]);
         let xdg_shell_state = XdgShellState::new::<TwmState>(&display_handle);
+        let xdg_decoration_state = XdgDecorationState::new::<TwmState>(&display_handle);
+        let layer_shell_state = WlrLayerShellState::new::<TwmState>(&display_handle);
        
         let mut seat_state = SeatState::new();
-        let seat = seat_state.new_wl_seat(&display_handle, "winit");
+        let seat = seat_state.new_wl_seat(&display_handle, "seat0");
         let data_device_state = DataDeviceState::new::<Self>(&display_handle);
+        let primary_selection_state = PrimarySelectionState::new::<Self>(&display_handle);
+        let clock = Clock::new().context("Failed to initialize the monotonic clock")?;
+        let presentation_state = PresentationState::new::<Self>(&display_handle, clock.id() as u32);
 
         let ev_signal = event_loop.get_signal();
+        let loop_handle = event_loop.handle();
+        // Windows of clients connecting before the first output is added go here
+        let workspaces = vec![workspace::OutputWorkspaces::new(None, config.default_layout)];
+        let tab_font = tabs::load_font(config.tab_font.as_deref());
+        let session = if config.restore_session { session::load() } else { Vec::new() };
 
         Ok(Self {
             start_time: std::time::Instant::now(),
+            clock,
+            display_handle,
+            config,
             compositor_state,
             xdg_shell_state,
+            _xdg_decoration_state: xdg_decoration_state,
+            layer_shell_state,
             shm_state,
             seat_state,
             data_device_state,
+            primary_selection_state,
+            _presentation_state: presentation_state,
+            // Bars and panels size themselves by the logical geometry from xdg-output
+            _output_manager_state: OutputManagerState::new_with_xdg_output::<Self>(&display_handle),
+            _fractional_scale_state: FractionalScaleManagerState::new::<Self>(&display_handle),
+            // The buffer handler applies viewports to the surface size the renderer draws at
+            _viewporter_state: ViewporterState::new::<Self>(&display_handle),
+            _text_input_state: TextInputManagerState::new::<Self>(&display_handle),
+            _input_method_state: InputMethodManagerState::new::<Self>(&display_handle),
+            xdg_activation_state: XdgActivationState::new::<Self>(&display_handle),
+            keyboard_shortcuts_inhibit_state: KeyboardShortcutsInhibitState::new::<Self>(&display_handle),
+            idle: idle::IdleState::new(&display_handle),
+            session_lock_state: SessionLockManagerState::new::<Self, _>(&display_handle, |_| true),
+            session_lock: None,
+            scratchpad: scratchpad::Scratchpad::default(),
+            session,
+            _relative_pointer_state: RelativePointerManagerState::new::<Self>(&display_handle),
+            _pointer_gestures_state: PointerGesturesState::new::<Self>(&display_handle),
+            pointer_constraints: pointer_constraints::PointerConstraintsState::new(&display_handle),
+            foreign_toplevels: foreign_toplevel::ForeignToplevelState::new(&display_handle),
+            screencopy: screencopy::ScreencopyState::new(&display_handle),
+            virtual_keyboards: virtual_keyboard::VirtualKeyboardState::new(&display_handle),
             space: Space::default(),
+            popups: PopupManager::default(),
+            input_method_popups: Vec::new(),
+            workspaces,
+            maximized: HashMap::new(),
+            minimized: Vec::new(),
+            content_type: content_type::ContentTypeState::new(&display_handle),
+            debug_overlay: None,
+            xwayland: xwayland::Xwayland::default(),
             ev_signal,
+            loop_handle,
             seat,
+            cursor_status: CursorImageStatus::Default,
+            cursor_theme: cursor::CursorTheme::load(),
+            cursor_icon: cursor::CursorIcon::Default,
+            dnd_icon: None,
+            tab_font,
+            needs_redraw: true,
+            requested_vt: None,
+            device_outputs: HashMap::new(),
+            compositor_gesture: None,
+            suppressed_keys: Vec::new(),
+            input_serials: activation::InputSerials::default(),
+            config_path: None,
+            socket_name: None,
+            children: Vec::new(),
+            ipc_subscribers: Vec::new(),
+            full_redraw: Vec::new(),
+            clipboard: clipboard::Clipboard::default(),
+            dmabuf_state: DmabufState::new(),
+            dmabufs: dmabuf::Dmabufs::default(),
         })
     }
 
     pub fn surface_under(&self, position: Point<f64, Logical>) -> Option<(WlSurface, Point<i32, Logical>)> {
-        self.space.element_under(position).and_then(|(window, location)| {
-            window
-                .surface_under(position - location.to_f64(), WindowSurfaceType::ALL)
-                .map(|(s,p)| (s, p + location))
-        })
+        // Input only reaches the lock surfaces of a locked session
+        if self.is_locked() {
+            return self.lock_surface_under(position);
+        }
+        // Layers above windows, then X11 windows stacked above the others, then windows, then
+        // layers below windows
+        self.layer_surface_under(position, &[Layer::Overlay, Layer::Top])
+            .or_else(|| self.x11_surface_under(position))
+            .or_else(|| self.space.element_under(position).and_then(|(window, location)| {
+                window
+                    .surface_under(position - location.to_f64(), WindowSurfaceType::ALL)
+                    .map(|(s,p)| (s, p + location))
+            }))
+            .or_else(|| self.layer_surface_under(position, &[Layer::Bottom, Layer::Background]))
+    }
+
+    /// Window owning the surface that currently has keyboard focus, popups count as part of
+    /// their window
+    pub fn focused_window(&self) -> Option<Window> {
+        let focus = self.popup_root(&self.seat.get_keyboard()?.current_focus()?);
+        self.space
+            .elements()
+            .find(|w| w.toplevel().wl_surface() == &focus)
+            .cloned()
+    }
+
+    /// Window of the toplevel on any workspace
+    pub fn window_for_toplevel(&self, toplevel: &ToplevelSurface) -> Option<Window> {
+        self.all_windows()
+            .find(|w| w.toplevel() == toplevel)
+            .cloned()
+    }
+
+    /// Raises `window` and gives it keyboard focus, `None` clears the focus
+    pub fn focus_window(&mut self, window: Option<&Window>) {
+        if self.is_locked() {
+            return;
+        }
+        if let Some(window) = window {
+            self.space.raise_element(window, true);
+            self.show_tab(window);
+        }
+        self.set_keyboard_focus(window.map(|w| w.toplevel().wl_surface().clone()));
+    }
+
+    /// Moves keyboard focus to `surface` without changing the stacking order, all focus
+    /// changes go through here. While locked only lock surfaces can get focus.
+    pub fn set_keyboard_focus(&mut self, surface: Option<WlSurface>) {
+        if self.is_locked() && surface.as_ref().map_or(false, |surface| !self.is_lock_surface(surface)) {
+            return;
+        }
+        let keyboard = self.seat.get_keyboard().expect("Keyboard available");
+        if keyboard.current_focus() == surface {
+            return;
+        }
+        // Menus close when the compositor moves the focus away from them, e.g. to another
+        // workspace, rather than keeping the seat grabbed
+        if let Some(current) = keyboard.current_focus().filter(|current| self.popups.find_popup(current).is_some()) {
+            let root = self.popup_root(&current);
+            self.dismiss_popups(&root);
+        }
+
+        // Only the window owning the focus is drawn as active by its client
+        self.space.elements().for_each(|w| {
+            w.set_activated(surface.as_ref() == Some(w.toplevel().wl_surface()));
+            w.toplevel().send_pending_configure();
+        });
+        self.activate_x11_windows(surface.as_ref());
+        // The new focus gets the held keys with its enter event, so their releases have to
+        // reach it even if the press triggered a keybinding. Modifiers follow the enter.
+        self.suppressed_keys.clear();
+        self.shortcuts_inhibit_focus_changed(keyboard.current_focus().as_ref(), surface.as_ref());
+        let serial = SERIAL_COUNTER.next_serial();
+        keyboard.set_focus(self, surface, serial);
+        self.input_serials.focus_changed(serial);
+        // Border colors follow the focus
+        self.needs_redraw = true;
+        self.damage_dimmed_windows();
+    }
+
+    /// Removes `window` from its workspace and the space, passing focus on if it had it
+    pub fn remove_window(&mut self, window: &Window) {
+        self.release_swallowed(window);
+        self.forget_minimized(window);
+        let had_focus = self.focused_window().as_ref() == Some(window);
+        let workspace_index = self.workspace_index(window);
+        let index = workspace_index.and_then(|(output_index, index)| {
+            self.workspaces[output_index].workspaces[index].windows.iter().position(|w| w == window)
+        });
+
+        self.remove_from_workspaces(window);
+        self.maximized.remove(window.toplevel().wl_surface());
+        self.space.unmap_elem(window);
+        self.retile();
+
+        if had_focus {
+            // The window that took the place of the removed one in tiling order gets focus
+            let windows = match workspace_index {
+                Some((output_index, index)) => &self.workspaces[output_index].workspaces[index].windows,
+                None => &self.current_workspace().windows,
+            };
+            let next = index
+                .and_then(|index| windows.get(index))
+                .or_else(|| windows.last())
+                .cloned();
+            self.focus_window(next.as_ref());
+        }
+    }
+
+    /// Asks the focused window to close, the window is removed once the client destroys it
+    pub fn close_focused_window(&mut self) {
+        match self.focused_window() {
+            Some(window) => self.close_window(&window),
+            None => match self.focused_x11_window() {
+                Some(window) => {
+                    if let Err(err) = window.close() {
+                        warn!("Failed to close X11 window: {}", err);
+                    }
+                },
+                None => debug!("No focused window to close"),
+            },
+        }
+    }
+
+    /// Disconnects the client owning the focused window, for clients ignoring close requests
+    pub fn kill_focused_client(&mut self) {
+        let Some(window) = self.focused_window() else {
+            debug!("No focused window to kill");
+            return;
+        };
+        let Some(client) = self.display_handle.get_client(window.toplevel().wl_surface().id()).ok() else {
+            return;
+        };
+        info!("Killing client {:?}", client.id());
+
+        // Removed right away, the destruction of the client's objects comes with the next dispatch
+        let windows: Vec<Window> = self.all_windows()
+            .filter(|w| w.toplevel().wl_surface().id().same_client_as(&window.toplevel().wl_surface().id()))
+            .cloned()
+            .collect();
+        self.display_handle.backend_handle().kill_client(client.id(), DisconnectReason::ConnectionClosed);
+        for window in windows {
+            self.remove_window(&window);
+        }
+    }
+
+    /// Removes windows whose client is gone without their toplevel being destroyed properly
+    pub fn remove_dead_windows(&mut self) {
+        let dead: Vec<Window> = self.all_windows()
+            .filter(|w| !w.toplevel().alive())
+            .cloned()
+            .collect();
+        for window in dead {
+            debug!("Removing window of a disconnected client");
+            self.remove_window(&window);
+        }
+    }
+
+    /// Output under the pointer, or the primary output if the pointer is not on any
+    pub fn active_output(&self) -> Option<Output> {
+        let pointer_location = self.seat.get_pointer().map(|pointer| pointer.current_location());
+        pointer_location
+            .and_then(|location| self.space.output_under(location).next().cloned())
+            .or_else(|| self.primary_output())
+    }
+
+    /// Output at the origin of the global space, where the pointer starts out, or the first
+    /// mapped output if none is there
+    pub fn primary_output(&self) -> Option<Output> {
+        self.space
+            .output_under((0.0, 0.0))
+            .next()
+            .or_else(|| self.space.outputs().next())
+            .cloned()
+    }
+
+    /// Arranges the windows of the shown workspace of each output in its layout
+    pub fn retile(&mut self) {
+        self.needs_redraw = true;
+        let outputs: Vec<Output> = self.space.outputs().cloned().collect();
+        for output in outputs {
+            self.retile_output(&output);
+        }
+        self.raise_scratchpad();
+    }
+
+    /// Arranges the windows of the workspace shown on `output`
+    fn retile_output(&mut self, output: &Output) {
+        let Some(output_geometry) = self.space.output_geometry(output) else {
+            return;
+        };
+        let Some((workspace_layout, master_ratio)) = self.output_workspace(output)
+            .map(|workspace| (workspace.layout, workspace.master_ratio)) else {
+            return;
+        };
+
+        // Tiled windows don't cover the exclusive zones of layer surfaces like bars
+        let mut tiling_area = layer_map_for_output(output).non_exclusive_zone();
+        tiling_area.loc += output_geometry.loc;
+
+        // Floating windows keep their geometry above the tiled ones
+        let (floating, windows): (Vec<Window>, Vec<Window>) = self
+            .windows_on_output(output)
+            .into_iter()
+            .partition(|window| self.is_floating(window) && !self.is_maximized(window));
+        // Maximized windows cover the whole output instead of taking a tile
+        let (maximized, windows): (Vec<Window>, Vec<Window>) = windows
+            .into_iter()
+            .partition(|window| self.is_maximized(window));
+        // Monocle windows share one tile, the focused one has to stay on top of the others
+        let shown = self.focused_window()
+            .filter(|window| workspace_layout == layout::Layout::Monocle && windows.contains(window));
+        // Windows of a tabbed container share a tile below its tab bar
+        let tiles = self.output_workspace(output)
+            .map(|workspace| tabs::group_tiles(&workspace.containers, windows))
+            .unwrap_or_default();
+        self.clear_tab_bars(output);
+
+        // Borders are drawn inside the tile around the window content
+        let border_width = self.config.total_border_width();
+        let hints: Vec<_> = tiles.iter().map(|tile| layout::tile_size_hints(tile)).collect();
+        let min_tile_sizes: Vec<Size<i32, Logical>> = tiles
+            .iter()
+            .zip(hints.iter())
+            .map(|(tile, (min_size, _))| {
+                let min_size = min_size.unwrap_or_default();
+                let tab_bar_height = if tile.len() > 1 { self.config.tab_bar_height } else { 0 };
+                (min_size.w + 2 * border_width, min_size.h + 2 * border_width + tab_bar_height).into()
+            })
+            .collect();
+
+        let area = layout::inset(tiling_area, self.config.outer_gap);
+        let geometries = match workspace_layout {
+            layout::Layout::MasterStack => {
+                layout::master_stack(area, &min_tile_sizes, master_ratio, self.config.inner_gap)
+            },
+            layout::Layout::Monocle => layout::monocle(area, tiles.len()),
+            layout::Layout::Spiral => layout::spiral(area, tiles.len(), self.config.inner_gap),
+        };
+
+        // Windows that can't shrink to their tile float instead of covering their neighbours,
+        // the last one in tiling order first until the others fit
+        let oversized = tiles.iter().zip(geometries.iter()).rev().find_map(|(tile, geometry)| {
+            let tab_bar_height = if tile.len() > 1 { self.config.tab_bar_height } else { 0 };
+            tile.iter()
+                .rev()
+                .find(|window| {
+                    let min_size = layout::window_size_hints(window).0.unwrap_or_default();
+                    let min_size = (min_size.w + 2 * border_width, min_size.h + 2 * border_width + tab_bar_height);
+                    !layout::fits_min_size(geometry.size, min_size.into())
+                })
+                .cloned()
+        });
+        if let Some(window) = oversized {
+            self.float_oversized_window(&window);
+            return self.retile_output(output);
+        }
+
+        for ((tile, geometry), (_, max_size)) in tiles.into_iter().zip(geometries).zip(hints) {
+            let geometry = if tile.len() > 1 { self.place_tab_bar(&tile[0], geometry) } else { geometry };
+            let geometry = layout::fit_to_max_size(layout::inset(geometry, border_width), max_size);
+            for window in tile {
+                self.configure_window(window, geometry);
+            }
+        }
+        self.raise_shown_tabs(output);
+        if let Some(window) = shown {
+            self.space.raise_element(&window, false);
+        }
+        for window in floating {
+            self.space.raise_element(&window, false);
+        }
+        for window in maximized {
+            self.configure_window(window.clone(), output_geometry);
+            self.space.raise_element(&window, false);
+        }
+    }
+
+    /// Sends the window its new size and maps it at the new location, once the client
+    /// committed the new size if the window is shown and gets resized
+    pub fn configure_window(&mut self, window: Window, geometry: Rectangle<i32, Logical>) {
+        self.animate_move(&window, geometry);
+        window.toplevel().with_pending_state(|state| {
+            state.size = Some(geometry.size);
+        });
+        // Before the initial commit the configure is sent from the commit handler
+        if initial_configure_sent(window.toplevel()) {
+            if let Some(serial) = window.toplevel().send_pending_configure() {
+                if self.space.element_location(&window).is_some() && window.geometry().size != geometry.size {
+                    self.move_on_commit(&window, serial, geometry.loc);
+                    return;
+                }
+            }
+        }
+        self.cancel_pending_move(&window);
+        self.space.map_element(window, geometry.loc, false);
     }
 }
 
+/// Whether the initial configure of the toplevel was already sent, which xdg-shell only
+/// allows after the client's initial commit
+pub fn initial_configure_sent(toplevel: &ToplevelSurface) -> bool {
+    with_states(toplevel.wl_surface(), |states| {
+        states
+            .data_map
+            .get::<XdgToplevelSurfaceData>()
+            .expect("Toplevel surface data available")
+            .lock()
+            .expect("Toplevel surface data lock")
+            .initial_configure_sent
+    })
+}
+
 impl SeatHandler for TwmState {
     type PointerFocus = WlSurface;
     type KeyboardFocus = WlSurface;
@@ -167,20 +685,52 @@ impl SeatHandler for TwmState {
         &mut self.seat_state
     }
 
-    fn cursor_image(&mut self, _seat: &Seat<Self>, _image: smithay::input::pointer::CursorImageStatus) {
-        //println!("Cursor image");
+    fn cursor_image(&mut self, _seat: &Seat<Self>, image: CursorImageStatus) {
+        trace!("Cursor image {:?}", image);
+        self.cursor_status = image;
+        self.needs_redraw = true;
     }
 
-    fn focus_changed(&mut self, _seat: &Seat<Self>, _focused: Option<&Self::KeyboardFocus>) {
-       println!("Focus changed"); 
+    fn focus_changed(&mut self, seat: &Seat<Self>, focused: Option<&Self::KeyboardFocus>) {
+       debug!("Focus changed");
+        // Selections are only offered to the client with keyboard focus
+        let client = focused.and_then(|surface| self.display_handle.get_client(surface.id()).ok());
+        set_data_device_focus(&self.display_handle, seat, client.clone());
+        set_primary_focus(&self.display_handle, seat, client);
+
+        // Focused windows got the attention they asked for
+        let root = focused.map(|surface| self.popup_root(surface));
+        let window = root.as_ref().and_then(|surface| self.all_windows()
+            .chain(self.scratchpad_window())
+            .find(|w| w.toplevel().wl_surface() == surface)
+            .cloned());
+        if let Some(window) = &window {
+            self.set_urgent(window, false);
+        }
+        let window = match window {
+            Some(window) => Some(self.window_info(&window)),
+            None => focused
+                .and_then(|surface| self.x11_windows().find(|w| w.wl_surface().as_ref() == Some(surface)))
+                .map(window_info::x11_window_info),
+        };
+        self.emit_ipc_event(ipc::Event::Focus { window });
     }
 }
 
 impl CompositorHandler for TwmState {
     fn commit(&mut self, surface: &WlSurface) {
-        println!("Commit");
+        trace!("Commit");
+        // Before the buffer handler, which drops the buffer offset the icon is moved by
+        self.dnd_icon_commit(surface);
+        // Associates surfaces of Xwayland with their X11 windows
+        X11Wm::commit_hook::<TwmLoopData>(surface);
         on_commit_buffer_handler::<Self>(surface);
+        self.content_type_commit(surface);
+        self.needs_redraw = true;
+        self.x11_commit(surface);
+        // Sync subsurfaces only apply their state once their parent commits
         if !is_sync_subsurface(surface) {
+            self.pointer_constraints_commit(surface);
             let mut root = surface.clone();
             while let Some(parent) = get_parent(&root) {
                 root = parent;
@@ -188,14 +738,47 @@ impl CompositorHandler for TwmState {
 
             if let Some(window) = self.space
                 .elements()
-                .find(|w| w.toplevel().wl_surface() == &root) {
+                .chain(self.all_windows())
+                .chain(self.scratchpad_window())
+                .find(|w| w.toplevel().wl_surface() == &root)
+                .cloned() {
+                // Hidden windows too, their buffer size and transform may change meanwhile and
+                // the damage of the next frame they are shown in is based on them
                 window.on_commit();
+                self.refresh_window_info(&window);
+                // Moves, rules, configures and placement only concern shown windows
+                if self.space.element_location(&window).is_some() {
+                    self.apply_pending_move(&window);
+                    self.adopt_scratchpad(&window);
+                    // Before the initial configure, so it already carries the size the rules lead to
+                    if !self.is_scratchpad(&window) {
+                        self.apply_window_rules(&window);
+                        self.restore_session_window(&window);
+                    }
+                    if !initial_configure_sent(window.toplevel()) {
+                        self.swallow_terminal(&window);
+                        let info = self.window_info(&window);
+                        self.emit_ipc_event(ipc::Event::Window { change: ipc::WindowChange::New, window: info });
+                        window.toplevel().send_configure();
+                        if !self.is_scratchpad(&window) {
+                            self.focus_new_window(&window);
+                        }
+                    } else if self.is_floating(&window) {
+                        self.place_floating_window(&window);
+                    } else if layout::size_hints_changed(&window) {
+                        // Tiles follow new minimum and maximum sizes
+                        self.retile();
+                    }
+                }
+            } else {
+                self.layer_surface_commit(&root);
             }
+            self.popup_commit(surface);
         }
     }
 
     fn new_surface(&mut self, surface: &WlSurface) {  
-        println!("new surface");
+        trace!("new surface");
     }
 
     fn compositor_state(&mut self) -> &mut CompositorState {
@@ -203,11 +786,15 @@ impl CompositorHandler for TwmState {
     }
 
     fn client_compositor_state<'a>(&self, client: &'a Client) -> &'a CompositorClientState {
+        // Xwayland is started with its own client data
+        if let Some(xwayland) = client.get_data::<XWaylandClientData>() {
+            return &xwayland.compositor_state;
+        }
         &client.get_data::<TwmClientState>().unwrap().compositor_state
     }
 
     fn destroyed(&mut self, _surface: &WlSurface) {
-        println!("Destroyed surfact");
+        trace!("Destroyed surface");
     }
 }
 
@@ -219,47 +806,98 @@ impl ShmHandler for TwmState {
 
 impl XdgShellHandler for TwmState {
     fn new_client(&mut self, client: ShellClient) {
-        println!("new client: {:?}", client);
+        info!("new client: {:?}", client);
     }
 
     fn new_popup(&mut self, 
                  surface: PopupSurface,
                  positioner: PositionerState) {
-        println!("New popup");
+        debug!("New popup");
+        self.track_popup(surface, positioner);
     }
 
     fn new_toplevel(&mut self, surface: ToplevelSurface) {
-       println!("New top level"); 
+       debug!("New top level");
+        // Transient windows like dialogs float at the size the client picks
+        let transient = surface.parent().is_some();
+        // New windows open on the shown workspace of the output under the pointer
+        let output = self.active_output();
+        let output_geometry = output.as_ref().and_then(|output| self.space.output_geometry(output));
+        // The configure itself is sent on the initial commit, see `commit`
+        if let Some(output_size) = output_geometry.map(|geometry| geometry.size).filter(|_| !transient) {
+            surface.with_pending_state(|state| {
+                state.size = Some(output_size);
+            });
+        }
         let window = Window::new(surface);
-        self.space.map_element(window, (0, 0), false);
-        //let pointer = self.seat.get_pointer().unwrap();  
-        //let output = self.space
-        //    .output_under(pointer.current_location());
-
-        //let geometry = output.
+        if transient {
+            self.float_new_window(&window);
+        }
+        match output.as_ref().and_then(|output| self.output_workspace_mut(output)) {
+            Some(workspace) => workspace.windows.push(window.clone()),
+            None => self.current_workspace_mut().windows.push(window.clone()),
+        }
+        // Until the layout placed it the window stays on its own output
+        let location = output_geometry.map(|geometry| geometry.loc).unwrap_or_default();
+        self.space.map_element(window, location, false);
+        self.retile();
     }
 
     fn client_pong(&mut self, client: ShellClient) {
-        println!("clieng pont");
+        trace!("Client pong");
     }
 
     fn grab(&mut self, 
             surface: PopupSurface, 
             seat: WlSeat, 
             serial: Serial) {
-        println!("grap");
+        debug!("Popup grab");
+        if let Some(seat) = Seat::<Self>::from_resource(&seat) {
+            self.grab_popup(surface, &seat, serial);
+        }
     }
 
     fn ack_configure(&mut self, surface: WlSurface, configure: Configure) {
-       println!("Ack configure"); 
+        trace!("Ack configure");
+        if let Configure::Toplevel(configure) = configure {
+            self.configure_acked(&surface, configure.serial);
+        }
     }
 
     fn move_request(&mut self, 
                     surface: ToplevelSurface, 
                     seat: WlSeat, 
                     serial: Serial) {
-       println!("move request"); 
-
+       debug!("move request");
+        let Some(seat) = Seat::<Self>::from_resource(&seat) else {
+            return;
+        };
+        let wl_surface = surface.wl_surface();
+
+        let Some(start_data) = grabs::check_grab(&seat, wl_surface, serial) else {
+            debug!("Move request without a matching pointer grab");
+            return;
+        };
+
+        // Only windows that are still mapped can be moved
+        let Some(window) = self.space
+            .elements()
+            .find(|w| w.toplevel().wl_surface() == wl_surface)
+            .cloned() else {
+            return;
+        };
+        let Some(initial_window_location) = self.space.element_location(&window) else {
+            return;
+        };
+
+        let grab = grabs::MoveSurfaceGrab {
+            start_data,
+            window,
+            initial_window_location,
+        };
+        self.show_cursor_icon(cursor::CursorIcon::Move);
+        let pointer = seat.get_pointer().expect("Pointer available");
+        pointer.set_grab(self, grab, serial, Focus::Clear);
     }
 
     fn resize_request(
@@ -269,7 +907,34 @@ impl XdgShellHandler for TwmState {
             serial: Serial,
             edges: ResizeEdge,
         ) {
-        println!("Resize request");
+        debug!("Resize request");
+        let Some(seat) = Seat::<Self>::from_resource(&seat) else {
+            return;
+        };
+        let wl_surface = surface.wl_surface();
+
+        let Some(start_data) = grabs::check_grab(&seat, wl_surface, serial) else {
+            debug!("Resize request without a matching pointer grab");
+            return;
+        };
+
+        let Some(window) = self.space
+            .elements()
+            .find(|w| w.toplevel().wl_surface() == wl_surface)
+            .cloned() else {
+            return;
+        };
+        let Some(initial_window_location) = self.space.element_location(&window) else {
+            return;
+        };
+        let initial_window_geometry = Rectangle::from_loc_and_size(
+            initial_window_location,
+            window.geometry().size);
+
+        let grab = grabs::ResizeSurfaceGrab::new(start_data, window, edges, initial_window_geometry);
+        self.show_cursor_icon(cursor::CursorIcon::Resize(edges));
+        let pointer = seat.get_pointer().expect("Pointer available");
+        pointer.set_grab(self, grab, serial, Focus::Clear);
     }
 
     fn xdg_shell_state(&mut self) -> &mut XdgShellState {
@@ -277,16 +942,21 @@ impl XdgShellHandler for TwmState {
     }
 
     fn popup_destroyed(&mut self, surface: PopupSurface) {
-        println!("Popup destroyed");
+        debug!("Popup destroyed");
     }
 
     fn maximize_request(&mut self, surface: ToplevelSurface) {
-       println!("maximize request"); 
-
+       debug!("Maximize request");
+        if let Some(window) = self.window_for_toplevel(&surface) {
+            self.maximize_window(&window);
+        }
     }
 
     fn minimize_request(&mut self, surface: ToplevelSurface) {
-       println!("Minimize request"); 
+       debug!("Minimize request");
+        if let Some(window) = self.window_for_toplevel(&surface) {
+            self.minimize_window(&window);
+        }
     }
 
     fn show_window_menu(
@@ -296,52 +966,99 @@ impl XdgShellHandler for TwmState {
             serial: Serial,
             location: Point<i32, Logical>,
         ) {
-       println!("Shod window menu"); 
+       debug!("Show window menu");
 
     }
 
     fn unmaximize_request(&mut self, surface: ToplevelSurface) {
-       println!("Unmaximize request"); 
+       debug!("Unmaximize request");
+        if let Some(window) = self.window_for_toplevel(&surface) {
+            self.unmaximize_window(&window);
+        }
     }
 
     fn fullscreen_request(&mut self, 
                           surface: ToplevelSurface, 
                           output: Option<WlOutput>) {
-        println!("Fullscreen request");
+        debug!("Fullscreen request");
+        // Windows stay on the workspace of their output, so `output` is only a hint to ignore
+        if let Some(window) = self.window_for_toplevel(&surface) {
+            self.fullscreen_window(&window);
+        }
     }
 
     fn reposition_request(&mut self, 
                           surface: PopupSurface, 
                           positioner: PositionerState, 
                           token: u32) {
-       println!("Reposition request"); 
-
+       debug!("Reposition request");
+        self.reposition_popup(surface, positioner, token);
     }
 
     fn toplevel_destroyed(&mut self, surface: ToplevelSurface) {
-       println!("Toplevel destroyed"); 
+       debug!("Toplevel destroyed");
+        if let Some(window) = self.window_for_toplevel(&surface) {
+            let info = self.window_info(&window);
+            self.emit_ipc_event(ipc::Event::Window { change: ipc::WindowChange::Close, window: info });
+            self.remove_window(&window);
+        }
+        self.scratchpad_destroyed(&surface);
+        // Taskbars learn about the closed window right away
+        self.refresh_foreign_toplevels();
     }
 
     fn unfullscreen_request(&mut self, surface: ToplevelSurface) {
-       println!("Unfullscreen request"); 
+       debug!("Unfullscreen request");
+        if let Some(window) = self.window_for_toplevel(&surface) {
+            self.unfullscreen_window(&window);
+        }
+    }
+}
+
+/// Tiled windows are decorated by the compositor, so clients are always asked for
+/// server side decorations
+fn set_server_side_decorations(toplevel: &ToplevelSurface) {
+    toplevel.with_pending_state(|state| {
+        state.decoration_mode = Some(zxdg_toplevel_decoration_v1::Mode::ServerSide);
+    });
+    if initial_configure_sent(toplevel) {
+        toplevel.send_pending_configure();
+    }
+}
+
+impl XdgDecorationHandler for TwmState {
+    fn new_decoration(&mut self, toplevel: ToplevelSurface) {
+        debug!("New decoration");
+        set_server_side_decorations(&toplevel);
+    }
+
+    fn request_mode(&mut self, toplevel: ToplevelSurface, mode: zxdg_toplevel_decoration_v1::Mode) {
+        debug!("Decoration mode {:?} requested", mode);
+        set_server_side_decorations(&toplevel);
+    }
+
+    fn unset_mode(&mut self, toplevel: ToplevelSurface) {
+        debug!("Decoration mode unset");
+        set_server_side_decorations(&toplevel);
     }
 }
 
 impl BufferHandler for TwmState {
     fn buffer_destroyed(&mut self, buffer: &WlBuffer) {
-       println!("Buffer destroyed"); 
+       trace!("Buffer destroyed");
     }
 }
 
 impl DataDeviceHandler for TwmState {
     type SelectionUserData = ();
     fn action_choice(&mut self, available: DndAction, preferred: DndAction) -> DndAction {
-        println!("Action choice");
+        debug!("Action choice");
         preferred
     }
 
     fn new_selection(&mut self, source: Option<WlDataSource>, seat: Seat<Self>) {
-        println!("new selectio");
+        debug!("New selection");
+        self.persist_selection(source);
     }
 
     fn send_selection(
@@ -351,7 +1068,12 @@ impl DataDeviceHandler for TwmState {
             seat: Seat<Self>,
             user_data: &Self::SelectionUserData,
         ) {
-        println!("Send selection");
+        // Only called for selections the compositor owns itself, which are the persisted
+        // ones. Selections of clients are offered to the focused client with all their mime
+        // types, and each transfer goes from the source client straight to the fd of the
+        // receiving client within smithay.
+        debug!("Send selection");
+        self.send_persisted_selection(&mime_type, fd);
     }
 
     fn data_device_state(&self) -> &DataDeviceState {
@@ -359,62 +1081,125 @@ impl DataDeviceHandler for TwmState {
     }
 }
 
+impl FractionalScaleHandler for TwmState {
+    fn new_fractional_scale(&mut self, surface: WlSurface) {
+        let mut root = surface.clone();
+        while let Some(parent) = get_parent(&root) {
+            root = parent;
+        }
+        if let Some(output) = self.output_for_surface(&root) {
+            output::set_preferred_scale(&surface, output.current_scale().fractional_scale());
+        }
+    }
+}
+
+impl PrimarySelectionHandler for TwmState {
+    type SelectionUserData = ();
+
+    fn new_selection(&mut self, source: Option<ZwpPrimarySelectionSourceV1>, seat: Seat<Self>) {
+        debug!("New primary selection");
+    }
+
+    fn send_selection(
+            &mut self,
+            mime_type: String,
+            fd: std::os::fd::OwnedFd,
+            seat: Seat<Self>,
+            user_data: &Self::SelectionUserData,
+        ) {
+        // The compositor never owns the primary selection itself, transfers between
+        // clients are handled by smithay
+        debug!("Send primary selection");
+    }
+
+    fn primary_selection_state(&self) -> &PrimarySelectionState {
+        &self.primary_selection_state
+    }
+}
+
 impl ClientDndGrabHandler for TwmState {
     fn started(&mut self, source: Option<WlDataSource>, icon: Option<WlSurface>, seat: Seat<Self>) {
-        println!("Client dnd grab started");
+        debug!("Client dnd grab started");
+        self.dnd_icon = icon.map(dnd::DndIcon::new);
+        self.needs_redraw = true;
     }
 
+    // Called for cancelled drags as well
     fn dropped(&mut self, seat: Seat<Self>) {
-        println!("Client dhd grab dropped");
+        debug!("Client dnd grab dropped");
+        self.dnd_icon = None;
+        self.needs_redraw = true;
     }
 }
 
 impl ServerDndGrabHandler for TwmState {
     fn dropped(&mut self, seat: Seat<Self>) {
-       println!("Server dnd grab deopped"); 
+       debug!("Server dnd grab dropped");
     }
 
     fn cancelled(&mut self, seat: Seat<Self>) {
-        println!("Server dnd grab cancelled");
+        debug!("Server dnd grab cancelled");
     }
 
     fn finished(&mut self, seat: Seat<Self>) {
-        println!("Server dnd grab finished");
+        debug!("Server dnd grab finished");
     }
 
     fn action(&mut self, action: DndAction, seat: Seat<Self>) {
-        println!("Served dnd grab action");
+        debug!("Server dnd grab action");
     }
 
     fn accept(&mut self, mime_type: Option<String>, seat: Seat<Self>) {
-        println!("Server dnd grab accept");
+        debug!("Server dnd grab accept");
     }
 
     fn send(&mut self, mime_type: String, fd: std::os::fd::OwnedFd, seat: Seat<Self>) {
-        println!("Server dnd grab send");
+        debug!("Server dnd grab send");
     }
 }
 
 struct TwmLoopData {
     display: Display<TwmState>,
     state: TwmState,
+    backend: backend::Backend,
 }
 
 fn main() -> Result<()>  {
+    let env_filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info"));
+    tracing_subscriber::fmt().with_env_filter(env_filter).init();
+
+    let Some(args) = cli::Args::parse()? else {
+        return Ok(());
+    };
 
     let current_display = std::env::var("WAYLAND_DISPLAY");
-    println!("TWM Starting");
+    let backend_kind = args.backend.unwrap_or_else(backend::BackendKind::from_env);
+    info!("TWM Starting with {:?} backend", backend_kind);
 
     let mut display: Display<TwmState> = Display::new().context("Failed to get wayland display")?;
     let mut event_loop: EventLoop<TwmLoopData> = EventLoop::try_new()
         .context("Couldn't create event loop")?;
 
-    let mut state = TwmState::new(&mut event_loop, &mut display)
+    let config_path = args.config.or_else(config::Config::path);
+    let mut state = TwmState::new(&mut event_loop, &mut display, config::Config::load(config_path.clone()))
         .context("Failed to initialize compositor state")?;
+    state.config_path = config_path;
+
+    let backend = match backend_kind {
+        backend::BackendKind::Winit => backend::winit::init(&mut event_loop, &mut display, &mut state),
+        backend::BackendKind::X11 => backend::x11::init(&mut event_loop, &mut display, &mut state),
+        backend::BackendKind::Udev => backend::udev::init(&mut event_loop, &mut display, &mut state, false),
+        backend::BackendKind::Pixman => backend::udev::init(&mut event_loop, &mut display, &mut state, true),
+        backend::BackendKind::Headless => {
+            let size = args.size.unwrap_or(backend::headless::DEFAULT_SIZE);
+            backend::headless::init(&mut event_loop, &mut display, &mut state, size)
+        },
+    }.context("Failed to initialize the backend")?;
 
-    let (mut gfx_backend, mut winit_el) = winit::init::< GlesRenderer>().expect("Failed to Initialize a graphics and input backend");
-    let keyboard = state.seat.add_keyboard(Default::default(), 200, 200).context("Failed to init keyboard")?;
+    state.add_keyboard().context("Failed to init keyboard")?;
     state.seat.add_pointer();
+    state.seat.add_touch();
 
 
     event_loop
@@ -425,201 +1210,24 @@ fn main() -> Result<()>  {
                 Interest::READ,
                 smithay::reexports::calloop::Mode::Level), 
             |_, _, data| {
-                data.display.dispatch_clients(&mut data.state).expect("Dispatch state to clients");
+                // A misbehaving or dying client must not take the compositor down with it
+                if let Err(err) = data.display.dispatch_clients(&mut data.state) {
+                    warn!("Failed to dispatch clients: {}", err);
+                    data.state.remove_dead_windows();
+                }
                 std::io::Result::Ok(PostAction::Continue)
             })
     .context("Failed to insert display fd source into event loop")?;
 
     
 
-    println!("State initialized!");
+    info!("State initialized!");
 
     let socket = ListeningSocketSource::new_auto().context("Failed to open socket")?;
     let socket_name = socket.socket_name().to_os_string();
     std::env::set_var("WAYLAND_DISPLAY", socket_name.clone());
-    println!("Updated wayland display to: {:?}", socket_name);
-
-    let output = Output::new(
-        "winit".to_string(),
-        PhysicalProperties { 
-            size: (0, 0).into(), // initial size
-            subpixel: Subpixel::Unknown, // sub bixel setting
-            make: "Twm".into(), // monitor manufacturer 
-            model: "Winit".into() // monitor model
-        }
-    );
-    
-    let mode = Mode {
-        size: gfx_backend.window_size().physical_size,
-        refresh: 60_000,
-    };
-
-    println!("window size {:?}", gfx_backend.window_size());
-
-    let _global = output.create_global::<TwmState>(&display.handle());
-    output.change_current_state(Some(mode), Some(smithay::utils::Transform::Flipped180), None, Some((0, 0).into()));
-    output.set_preferred(mode);
-
-    state.space.map_output(&output, (0, 0));
-
-    let mut damage_tracker = OutputDamageTracker::from_output(&output);
-
-    
-    let timert = Timer::immediate();
-    event_loop.handle().insert_source(timert, move |_, _, data| {
-
-       let res = winit_el.dispatch_new_events(|event| match event {
-           WinitEvent::Input(input_event) => match input_event {
-               InputEvent::Keyboard { event } => {
-                   let serial = SERIAL_COUNTER.next_serial();
-                   let time = Event::time_msec(&event);
-
-                   keyboard.input::<(), _>(
-                       &mut data.state, // composer state
-                       event.key_code(), // keyboard key code
-                       event.state(), // keoboard event state
-                       serial,
-                       time,
-                       |_, _, _| { // Event filter block
-                           println!("pressed: {}", event.key_code());
-                           FilterResult::Forward // Filter result we forward everything
-                       }
-                    );
-               },
-               InputEvent::PointerMotionAbsolute { event } => {
-                    let output = data.state.space.outputs().next().expect("Output avaiable");
-                    let geometry_output = data.state.space.output_geometry(output).expect("Geometry output available");
-                    let position = event.position_transformed(geometry_output.size) + geometry_output.loc.to_f64();
-                    let serial = SERIAL_COUNTER.next_serial();
-                    let pointer = data.state.seat.get_pointer().expect("Pointer available");
-                    let surface_under_pointer = data.state.surface_under(position);
-
-                    pointer.motion(&mut data.state, surface_under_pointer, &MotionEvent {
-                        location: position,
-                        serial,
-                        time: event.time_msec()
-                    });
-                },
-                InputEvent::PointerButton { event } => {
-                    let pointer = data.state.seat.get_pointer().expect("Pointer available");
-                    let keyboard = data.state.seat.get_keyboard().expect("Keyboard available");
-                    let serial = SERIAL_COUNTER.next_serial();
-                    let button = event.button_code();
-                    let buton_state = event.state();
-
-                    if ButtonState::Pressed == buton_state && !pointer.is_grabbed() {
-                        if let Some((window, _location)) = data.state
-                                .space
-                                .element_under(pointer.current_location())
-                                .map(|(w, l)| (w.clone(), l)) {
-                            print!("clicked on window");
-                            data.state.space.raise_element(&window, true);
-                            keyboard.set_focus(&mut data.state, Some(window.toplevel().wl_surface().clone()), serial);
-                            data.state.space.elements().for_each(|window| {
-                                window.toplevel().send_pending_configure();
-                            });
-                            println!("Update focus");
-                        } else {
-                            data.state.space.elements().for_each(|window| {
-                                window.set_activated(false);
-                                window.toplevel().send_pending_configure();
-                            });
-                            keyboard.set_focus(&mut data.state, Option::<WlSurface>::None, serial);
-                            println!("Reset focus");
-                        }
-
-                        pointer.button(
-                            &mut data.state,
-                            &ButtonEvent {
-                                button,
-                                state: buton_state,
-                                serial,
-                                time: event.time_msec()
-                            }
-                        );
-                    }
-                },
-                InputEvent::PointerAxis { event } => {
-                    let source = event.source();
-
-                    let horizontal_amount = event.amount(Axis::Horizontal)
-                        .unwrap_or_else(|| event.amount_discrete(Axis::Horizontal).unwrap_or(0.0) * 3.0);
-                    let vertical_amount = event.amount(Axis::Vertical)
-                        .unwrap_or_else(|| event.amount_discrete(Axis::Vertical).unwrap_or(0.0) * 3.0);
-                    let horizontal_amount_dis = event.amount_discrete(Axis::Horizontal);
-                    let vertical_amount_dis = event.amount_discrete(Axis::Vertical);
-
-                    let mut frame = AxisFrame::new(event.time_msec()).source(source);
-
-                    if horizontal_amount != 0.0 {
-                        frame = frame.value(Axis::Horizontal, horizontal_amount);
-                        if let Some(value) = horizontal_amount_dis {
-                            frame = frame.discrete(Axis::Horizontal, value as i32);
-                        }
-                    }
-                    if vertical_amount != 0.0 {
-                        frame = frame.value(Axis::Vertical, vertical_amount);
-                        if let Some(value) = vertical_amount_dis {
-                            frame = frame.discrete(Axis::Vertical, value as i32);
-                        }
-                    }
-
-
-                    data.state.seat.get_pointer().expect("Pointer available").axis(&mut data.state, frame);
-                },
-               _ => {}
-           },
-           _ => {}
-       });
-
-       if let Err(WinitError::WindowClosed) = res { // if this happens our composer got closed
-           data.state.ev_signal.stop(); // Since the composer stopped we stop the whole event loop
-           return TimeoutAction::Drop;
-       } else {
-           res.expect("Failed to dispatch new events on input event loop"); // Somethng else went
-                                                                            // wrong 
-       }
-
-       gfx_backend.bind().expect("Failed to bind gfx context"); // Bind the graphics backend
-
-       let size = gfx_backend.window_size().physical_size; // Physical size of the main display
-                                                           // window
-       let damage = Rectangle::from_loc_and_size((0,0), size); // Damage rectangle covering the
-                                                               // whole available screen
-      
-
-       smithay::desktop::space::render_output::<_, WaylandSurfaceRenderElement<GlesRenderer>, _, _> (
-           &output, 
-           gfx_backend.renderer(), 
-           1.0, 
-           0, 
-           [&data.state.space],
-           &[], 
-           &mut damage_tracker, 
-           [0.1, 0.1, 0.1, 1.0])
-           .expect("Failed to render output");
-
-       gfx_backend.submit(Some(&[damage])).expect("Failed to submit damage on gfx backend");
-
-
-       data.state.space.elements().for_each(|window| {
-           window
-               .send_frame(
-                   &output, 
-                   data.state.start_time.elapsed(), 
-                   Some(Duration::ZERO),
-                   |_, _| {
-                       Some(output.clone())
-                   });
-       });
-
-       data.state.space.refresh();
-       data.display.flush_clients().expect("Flush clients correctly");
-
-
-
-       TimeoutAction::ToDuration(Duration::from_millis(16))
-    }).expect("Failed to insert new sourc to event loop");
+    info!("Updated wayland display to: {:?}", socket_name);
+    state.socket_name = Some(socket_name);
 
     event_loop.handle().insert_source(socket, move |client_stream, _, data| {
         data.display
@@ -629,21 +1237,55 @@ fn main() -> Result<()>  {
     }).context("Failed to insert wayland socket source")?;
 
 
-    std::process::Command::new("alacritty").spawn().context("Failed to spawn process")?;
-    std::process::Command::new("alacritty").spawn().context("Failed to spawn process")?;
-    
+    idle::init(&event_loop.handle())?;
+    animation::init(&event_loop.handle())?;
+    spawn::init(&event_loop.handle())?;
+    // X11 clients are only unavailable without it
+    if let Err(err) = xwayland::init(&event_loop.handle(), &mut state) {
+        warn!("Failed to start Xwayland: {:#}", err);
+    }
+    shutdown::init(&event_loop.handle())?;
+
+    // After the SIGCHLD source is in place, so the commands get reaped
+    spawn::autostart(&event_loop.handle(), &mut state);
+    for command in args.commands.iter() {
+        state.spawn(command);
+    }
+
+    // Scripting is optional, twm keeps working without the socket
+    let ipc_socket = ipc::init(&event_loop.handle())
+        .map_err(|err| warn!("Failed to set up the IPC socket: {:#}", err))
+        .ok();
+
     let mut loop_data = TwmLoopData {
         display,
         state,
+        backend,
     };
 
-    let _ = event_loop.run(None, &mut loop_data, move |_| {}).context("Failed to start event loop")?;    
-
-    println!("TWM finishing working ");
+    let _ = event_loop.run(None, &mut loop_data, move |data| {
+        data.state.space.refresh();
+        data.state.popups.cleanup();
+        data.state.refresh_foreign_toplevels();
+        data.backend.render_if_needed(&mut data.state);
+        data.display.flush_clients().expect("Flush clients correctly");
+    }).context("Failed to start event loop")?;    
+
+    info!("TWM finishing");
+    // Before the clients close their windows
+    loop_data.state.save_session();
+    shutdown::close_clients(&mut event_loop, &mut loop_data);
+
+    if let Some(path) = ipc_socket {
+        let _ = std::fs::remove_file(path);
+    }
 
-    if let std::result::Result::Ok(socket_name) = current_display {
-        std::env::set_var("WAYLAND_DISPLAY", socket_name.clone());
-        println!("Reverted wayland display to: {:?}", socket_name.clone());
+    match current_display {
+        std::result::Result::Ok(socket_name) => {
+            std::env::set_var("WAYLAND_DISPLAY", socket_name.clone());
+            info!("Reverted wayland display to: {:?}", socket_name);
+        },
+        Err(_) => std::env::remove_var("WAYLAND_DISPLAY"),
     }
     Ok(())
 }
@@ -654,3 +1296,17 @@ delegate_xdg_shell!(TwmState);
 delegate_seat!(TwmState);
 delegate_output!(TwmState);
 delegate_data_device!(TwmState);
+delegate_xdg_decoration!(TwmState);
+delegate_layer_shell!(TwmState);
+delegate_primary_selection!(TwmState);
+delegate_presentation!(TwmState);
+delegate_session_lock!(TwmState);
+delegate_relative_pointer!(TwmState);
+delegate_fractional_scale!(TwmState);
+delegate_viewporter!(TwmState);
+delegate_text_input_manager!(TwmState);
+delegate_input_method_manager!(TwmState);
+delegate_xdg_activation!(TwmState);
+delegate_pointer_gestures!(TwmState);
+delegate_dmabuf!(TwmState);
+delegate_keyboard_shortcuts_inhibit!(TwmState);
@@ -0,0 +1,112 @@
+use smithay::{
+    desktop::Window,
+    reexports::wayland_protocols::xdg::shell::server::xdg_toplevel,
+    utils::{Logical, Rectangle},
+};
+use tracing::debug;
+
+use crate::{initial_configure_sent, TwmState};
+
+/// Window filling its output, maximized, fullscreen or both
+#[derive(Debug)]
+pub struct FilledOutput {
+    /// Geometry from before the window filled its output
+    geometry: Rectangle<i32, Logical>,
+    /// Maximized and fullscreen state the window is in, never empty
+    states: Vec<xdg_toplevel::State>,
+}
+
+impl TwmState {
+    /// Whether the window currently fills its output, maximized or fullscreen
+    pub fn is_maximized(&self, window: &Window) -> bool {
+        self.maximized.contains_key(window.toplevel().wl_surface())
+    }
+
+    /// Maximizes the window to its output, remembering its current geometry
+    pub fn maximize_window(&mut self, window: &Window) {
+        self.fill_output(window, xdg_toplevel::State::Maximized);
+    }
+
+    /// Fullscreens the window on its output, which other than maximizing tells the client
+    /// to leave out its own decorations
+    pub fn fullscreen_window(&mut self, window: &Window) {
+        self.fill_output(window, xdg_toplevel::State::Fullscreen);
+    }
+
+    /// Resizes the window to its whole output in the maximized or fullscreen `state`, on
+    /// top of the other one if the window is in it already
+    fn fill_output(&mut self, window: &Window, toplevel_state: xdg_toplevel::State) {
+        let Some(output_geometry) = self.output_for_window(window)
+            .or_else(|| self.active_output())
+            .and_then(|output| self.space.output_geometry(&output)) else {
+            return;
+        };
+
+        let surface = window.toplevel().wl_surface();
+        if let Some(filled) = self.maximized.get_mut(surface) {
+            if filled.states.contains(&toplevel_state) {
+                debug!("Window already is {:?}", toplevel_state);
+                // xdg-shell still expects a configure in response to the request
+                if initial_configure_sent(window.toplevel()) {
+                    window.toplevel().send_configure();
+                }
+                return;
+            }
+            filled.states.push(toplevel_state);
+        } else {
+            let location = self.space.element_location(window).unwrap_or_default();
+            let geometry = Rectangle::from_loc_and_size(location, window.geometry().size);
+            self.maximized.insert(surface.clone(), FilledOutput { geometry, states: vec![toplevel_state] });
+        }
+
+        window.toplevel().with_pending_state(|state| {
+            state.states.set(toplevel_state);
+        });
+        self.configure_window(window.clone(), output_geometry);
+        self.space.raise_element(window, true);
+    }
+
+    /// Unsets the maximized state, restoring the geometry the window had before it filled
+    /// its output unless it stays fullscreen
+    pub fn unmaximize_window(&mut self, window: &Window) {
+        self.leave_filled_state(window, xdg_toplevel::State::Maximized);
+    }
+
+    /// Unsets the fullscreen state, the window stays maximized if it was before
+    pub fn unfullscreen_window(&mut self, window: &Window) {
+        self.leave_filled_state(window, xdg_toplevel::State::Fullscreen);
+    }
+
+    /// Unsets the maximized or fullscreen `state` and restores the previous geometry once
+    /// the window is in neither
+    fn leave_filled_state(&mut self, window: &Window, toplevel_state: xdg_toplevel::State) {
+        let surface = window.toplevel().wl_surface();
+        let Some(filled) = self.maximized
+            .get_mut(surface)
+            .filter(|filled| filled.states.contains(&toplevel_state)) else {
+            debug!("Window is not {:?}", toplevel_state);
+            if initial_configure_sent(window.toplevel()) {
+                window.toplevel().send_configure();
+            }
+            return;
+        };
+        filled.states.retain(|state| *state != toplevel_state);
+        let restored = filled.states.is_empty().then_some(filled.geometry);
+
+        window.toplevel().with_pending_state(|state| {
+            state.states.unset(toplevel_state);
+        });
+        match restored {
+            Some(geometry) => {
+                self.maximized.remove(surface);
+                self.configure_window(window.clone(), geometry);
+                // Tiled windows go back to their tile
+                self.retile();
+            },
+            // Still fills its output in the other state
+            None => if initial_configure_sent(window.toplevel()) {
+                window.toplevel().send_pending_configure();
+            },
+        }
+    }
+}
@@ -0,0 +1,98 @@
+//! Minimized windows, taken out of their workspace until they are restored.
+//!
+//! Clients minimize their windows on their own, the user through the `minimize` action and
+//! taskbars through foreign-toplevel. Restored windows go back to the workspace they came
+//! from, at the end of its tiling order. Activating a minimized window restores it.
+
+use smithay::{
+    desktop::Window,
+    utils::{Logical, Point},
+};
+use tracing::debug;
+
+use crate::TwmState;
+
+/// Minimized window and where it was
+#[derive(Debug)]
+pub struct Minimized {
+    pub window: Window,
+    /// Name of the output of its workspace
+    output: Option<String>,
+    workspace: usize,
+    /// Location of floating windows
+    location: Option<Point<i32, Logical>>,
+}
+
+impl TwmState {
+    pub fn is_minimized(&self, window: &Window) -> bool {
+        self.minimized.iter().any(|minimized| &minimized.window == window)
+    }
+
+    /// Minimizes the focused window
+    pub fn minimize_focused_window(&mut self) {
+        match self.focused_window() {
+            Some(window) => self.minimize_window(&window),
+            None => debug!("No focused window to minimize"),
+        }
+    }
+
+    /// Takes `window` out of its workspace and hides it, passing focus on if it had it
+    pub fn minimize_window(&mut self, window: &Window) {
+        let Some((output_index, index)) = self.workspace_index(window) else {
+            debug!("Only windows of workspaces can be minimized");
+            return;
+        };
+        debug!("Minimizing {:?}", self.window_info(window));
+        let had_focus = self.focused_window().as_ref() == Some(window);
+        self.minimized.push(Minimized {
+            window: window.clone(),
+            output: self.workspaces[output_index].output.clone(),
+            workspace: index,
+            location: self.space.element_location(window),
+        });
+        self.remove_from_workspaces(window);
+        self.space.unmap_elem(window);
+        self.retile();
+
+        if had_focus {
+            let next = self.current_workspace().windows.last().cloned();
+            self.focus_window(next.as_ref());
+        }
+    }
+
+    /// Activates the window minimized last
+    pub fn restore_last_minimized(&mut self) {
+        match self.minimized.last().map(|minimized| minimized.window.clone()) {
+            Some(window) => self.activate_window(&window),
+            None => debug!("No minimized window to restore"),
+        }
+    }
+
+    /// Puts the minimized `window` back into its workspace, falling back to the active output
+    /// if its output is gone. Does nothing for windows that aren't minimized.
+    pub fn restore_window(&mut self, window: &Window) {
+        let Some(position) = self.minimized.iter().position(|minimized| &minimized.window == window) else {
+            return;
+        };
+        let minimized = self.minimized.remove(position);
+        debug!("Restoring {:?}", self.window_info(window));
+
+        let output_index = self.workspaces
+            .iter()
+            .position(|workspaces| workspaces.output == minimized.output)
+            .unwrap_or_else(|| self.active_workspaces());
+        let workspaces = &mut self.workspaces[output_index];
+        let index = minimized.workspace.min(workspaces.workspaces.len() - 1);
+        workspaces.workspaces[index].windows.push(window.clone());
+        // Windows of hidden workspaces are mapped once their workspace is shown
+        if workspaces.current == index {
+            self.space.map_element(window.clone(), minimized.location.unwrap_or_default(), false);
+        }
+        self.retile();
+    }
+
+    /// Forgets `window` if it was minimized, for windows going away
+    pub fn forget_minimized(&mut self, window: &Window) {
+        self.minimized.retain(|minimized| &minimized.window != window);
+    }
+}
@@ -0,0 +1,49 @@
+//! Opacity of windows, set by window rules, and dimming of the windows without focus.
+
+use std::cell::Cell;
+
+use smithay::{desktop::Window, output::Output};
+use tracing::debug;
+
+use crate::{content_type, TwmState};
+
+/// Opacity a window rule gave the window, kept in its user data
+#[derive(Debug)]
+struct Opacity(Cell<f32>);
+
+impl TwmState {
+    /// Draws `window` with `opacity` from now on, between 0 (invisible) and 1 (opaque)
+    pub fn set_opacity(&mut self, window: &Window, opacity: f32) {
+        let opacity = opacity.clamp(0.0, 1.0);
+        debug!("Window {:?} opacity: {}", self.window_info(window), opacity);
+        window.user_data().insert_if_missing(|| Opacity(Cell::new(1.0)));
+        window.user_data().get::<Opacity>().expect("Opacity available").0.set(opacity);
+        self.needs_redraw = true;
+    }
+
+    /// Alpha `window` is drawn with, its own opacity dimmed by the inactive opacity unless
+    /// it is `focused` or shows video or a game
+    pub fn window_alpha(&self, window: &Window, focused: bool) -> f32 {
+        let opacity = window.user_data().get::<Opacity>().map_or(1.0, |opacity| opacity.0.get());
+        if focused || content_type::shows_media(window) {
+            opacity
+        } else {
+            opacity * self.config.inactive_opacity.clamp(0.0, 1.0)
+        }
+    }
+
+    /// Redraws all outputs in full after a focus change if windows without focus are
+    /// dimmed, as the damage of their surfaces doesn't cover the changed alpha
+    pub fn damage_dimmed_windows(&mut self) {
+        if self.config.inactive_opacity < 1.0 {
+            self.full_redraw = self.space.outputs().cloned().collect();
+        }
+    }
+
+    /// Whether the next frame of `output` has to be drawn in full, resets the request
+    pub fn take_full_redraw(&mut self, output: &Output) -> bool {
+        let len = self.full_redraw.len();
+        self.full_redraw.retain(|other| other != output);
+        self.full_redraw.len() != len
+    }
+}
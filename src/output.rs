@@ -0,0 +1,217 @@
+use smithay::{
+    desktop::{layer_map_for_output, Window, WindowSurfaceType},
+    output::{Mode, Output, Scale},
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::{Logical, Point, Transform},
+    wayland::{
+        compositor::{with_surface_tree_downward, TraversalAction},
+        fractional_scale::with_fractional_scale,
+    },
+};
+use tracing::{debug, info, warn};
+
+use crate::{config::OutputConfig, TwmState};
+
+impl TwmState {
+    /// Maps a new output at its configured position or to the right of the existing ones
+    pub fn add_output(&mut self, output: &Output) {
+        info!("Adding output {}", output.name());
+        self.apply_output_scale(output);
+        self.space.map_output(output, (0, 0));
+        self.arrange_outputs();
+        self.add_output_workspaces(output);
+        self.retile();
+    }
+
+    /// Unmaps an unplugged output, its windows move to the remaining outputs
+    pub fn remove_output(&mut self, output: &Output) {
+        info!("Removing output {}", output.name());
+        let location = self.space.output_geometry(output).map_or_else(Point::default, |geometry| geometry.loc);
+        self.space.unmap_output(output);
+
+        let mut map = layer_map_for_output(output);
+        for layer in map.layers().cloned().collect::<Vec<_>>() {
+            layer.layer_surface().send_close();
+            map.unmap_layer(&layer);
+        }
+        drop(map);
+
+        self.arrange_outputs();
+        self.remove_output_workspaces(output, location);
+        self.retile();
+    }
+
+    /// Fits layers, the lock surface and windows to a new mode of `output`
+    pub fn output_resized(&mut self, output: &Output) {
+        debug!("Output {} resized", output.name());
+        // Layers are reconfigured to the new size and their exclusive zones recomputed
+        layer_map_for_output(output).arrange();
+        self.arrange_outputs();
+        self.configure_lock_surface(output);
+        // Also gives maximized windows the new output geometry
+        self.retile();
+    }
+
+    /// Places the outputs at their configured positions, the others left to right in the
+    /// order they were added after the rightmost configured one, without gaps. Has to run
+    /// whenever an output is added, removed or changes its mode.
+    pub fn arrange_outputs(&mut self) {
+        let outputs: Vec<_> = self.space
+            .outputs()
+            .filter_map(|output| {
+                let size = self.space.output_geometry(output)?.size;
+                let position = self.output_config(output).and_then(|config| config.position);
+                Some((output.clone(), size, position.map(Point::from)))
+            })
+            .collect();
+        let mut x = outputs
+            .iter()
+            .filter_map(|(_, size, position)| Some(position.as_ref()?.x + size.w))
+            .max()
+            .unwrap_or(0);
+
+        for (output, size, position) in outputs {
+            let location = match position {
+                Some(position) => position,
+                None => {
+                    let location = Point::from((x, 0));
+                    x += size.w;
+                    location
+                },
+            };
+            if output.current_location() != location {
+                debug!("Moving output {} to {:?}", output.name(), location);
+                // Also updates the position clients see through xdg-output
+                reconfigure_output(&output, None, None, None, Some(location));
+            }
+            self.space.map_output(&output, location);
+        }
+    }
+
+    /// Settings of `output` in the config
+    pub fn output_config(&self, output: &Output) -> Option<&OutputConfig> {
+        let properties = output.physical_properties();
+        self.config.output_config(&output.name(), &properties.make, &properties.model)
+    }
+
+    /// Configured transform of `output`, applied by the backends when they create the output
+    pub fn output_transform(&self, output: &Output) -> Transform {
+        self.output_config(output).map_or(Transform::Normal, |config| config.transform.into())
+    }
+
+    /// Sets the configured scale of `output`, whole numbers are also advertised as is to
+    /// clients not supporting fractional scaling
+    fn apply_output_scale(&self, output: &Output) {
+        let scale = self.output_config(output).map_or(1.0, |config| config.scale);
+        let scale = if scale.is_finite() && scale > 0.0 {
+            scale
+        } else {
+            warn!("Invalid scale {} for output {}, using 1", scale, output.name());
+            1.0
+        };
+        let scale = if scale.fract() == 0.0 {
+            Scale::Integer(scale as i32)
+        } else {
+            Scale::Fractional(scale)
+        };
+        debug!("Scale of output {}: {:?}", output.name(), scale);
+        reconfigure_output(output, None, None, Some(scale), None);
+    }
+
+    /// Tells fractional scale aware surfaces on `output` the scale to render at, only sends
+    /// anything if the scale changed
+    pub fn send_preferred_scale(&self, output: &Output) {
+        let windows = self.space
+            .elements()
+            .filter(|window| self.output_for_window(window).as_ref() == Some(output))
+            .map(|window| window.toplevel().wl_surface().clone());
+        let layers = layer_map_for_output(output)
+            .layers()
+            .map(|layer| layer.wl_surface().clone())
+            .collect::<Vec<_>>();
+        let lock_surface = self.lock_surface(output);
+
+        let scale = output.current_scale().fractional_scale();
+        windows
+            .chain(layers)
+            .chain(lock_surface)
+            .for_each(|surface| set_preferred_scale(&surface, scale));
+    }
+
+    /// Output of the window, layer or lock surface `root`, the active output if it has none
+    pub fn output_for_surface(&self, root: &WlSurface) -> Option<Output> {
+        let window_output = self.space
+            .elements()
+            .find(|window| window.toplevel().wl_surface() == root)
+            .and_then(|window| self.output_for_window(window));
+        window_output
+            .or_else(|| self.space.outputs().find(|output| {
+                let map = layer_map_for_output(output);
+                map.layer_for_surface(root, WindowSurfaceType::TOPLEVEL).is_some()
+                    || self.lock_surface(output).as_ref() == Some(root)
+            }).cloned())
+            .or_else(|| self.active_output())
+    }
+
+    /// Output whose workspaces the window is on, `None` for windows outside of workspaces
+    pub fn output_for_window(&self, window: &Window) -> Option<Output> {
+        let (output_index, _) = self.workspace_index(window)?;
+        let name = self.workspaces[output_index].output.as_ref()?;
+        self.space.outputs().find(|output| &output.name() == name).cloned()
+    }
+
+    /// Windows of the workspace shown on `output` in tiling order
+    pub fn windows_on_output(&self, output: &Output) -> Vec<Window> {
+        self.output_workspace(output)
+            .map(|workspace| workspace.windows.clone())
+            .unwrap_or_default()
+    }
+
+    /// Moves the keyboard focus to `output` when the pointer entered it from `previous`
+    pub fn focus_output_under(&mut self, previous: Point<f64, Logical>, position: Point<f64, Logical>) {
+        let previous_output = self.space.output_under(previous).next().cloned();
+        let Some(output) = self.space.output_under(position).next().cloned() else {
+            return;
+        };
+        if previous_output.as_ref() == Some(&output) {
+            return;
+        }
+        if self.focused_window().and_then(|window| self.output_for_window(&window)).as_ref() == Some(&output) {
+            return;
+        }
+
+        debug!("Pointer entered output {}", output.name());
+        let windows = self.windows_on_output(&output);
+        let window = self.space
+            .element_under(position)
+            .map(|(window, _)| window.clone())
+            .filter(|window| windows.contains(window))
+            .or_else(|| windows.first().cloned());
+        if let Some(window) = window {
+            self.focus_window(Some(&window));
+        }
+    }
+}
+
+/// Changes the given parts of the state of `output` in one go, so clients get a single batch
+/// of geometry, mode and scale events ending in `done`. All output changes go through here.
+/// A new mode also becomes the preferred one, before it is sent so it carries the flag.
+pub fn reconfigure_output(output: &Output,
+                          mode: Option<Mode>,
+                          transform: Option<Transform>,
+                          scale: Option<Scale>,
+                          location: Option<Point<i32, Logical>>) {
+    if let Some(mode) = mode {
+        output.set_preferred(mode);
+    }
+    output.change_current_state(mode, transform, scale, location);
+}
+
+/// Sets the preferred fractional scale of `surface` and its subsurfaces
+pub fn set_preferred_scale(surface: &WlSurface, scale: f64) {
+    with_surface_tree_downward(surface, (), |_, _, _| TraversalAction::DoChildren(()), |_, states, _| {
+        with_fractional_scale(states, |fractional_scale| {
+            fractional_scale.set_preferred_scale(scale);
+        });
+    }, |_, _, _| true);
+}
@@ -0,0 +1,281 @@
+//! The pointer-constraints-v1 protocol.
+//!
+//! A constraint only takes effect while its surface has pointer focus and the pointer is
+//! inside the constraint region. Locked pointers don't move at all, confined pointers can't
+//! leave the region. Relative motion keeps reaching the client in both cases.
+
+use smithay::{
+    reexports::{
+        wayland_protocols::wp::pointer_constraints::zv1::server::{
+            zwp_confined_pointer_v1::{self, ZwpConfinedPointerV1},
+            zwp_locked_pointer_v1::{self, ZwpLockedPointerV1},
+            zwp_pointer_constraints_v1::{self, Lifetime, ZwpPointerConstraintsV1},
+        },
+        wayland_server::{
+            backend::{ClientId, GlobalId, ObjectId},
+            protocol::{wl_region::WlRegion, wl_surface::WlSurface},
+            Client,
+            DataInit,
+            Dispatch,
+            DisplayHandle,
+            GlobalDispatch,
+            New,
+            Resource,
+            WEnum,
+        },
+    },
+    utils::{Logical, Point},
+    wayland::compositor::{get_region_attributes, with_surface_tree_downward, RegionAttributes, TraversalAction},
+};
+use tracing::debug;
+
+use crate::TwmState;
+
+#[derive(Debug)]
+enum ConstraintKind {
+    Locked(ZwpLockedPointerV1),
+    Confined(ZwpConfinedPointerV1),
+}
+
+/// Lock or confinement requested by a client for one of its surfaces
+#[derive(Debug)]
+struct PointerConstraint {
+    kind: ConstraintKind,
+    surface: WlSurface,
+    /// Region the pointer has to be in, the whole surface when unset
+    region: Option<RegionAttributes>,
+    /// Region applied on the next commit of the surface
+    pending_region: Option<Option<RegionAttributes>>,
+    persistent: bool,
+    active: bool,
+    /// Oneshot constraint that was already deactivated, it never activates again
+    defunct: bool,
+}
+
+impl PointerConstraint {
+    fn resource_id(&self) -> ObjectId {
+        match &self.kind {
+            ConstraintKind::Locked(locked) => locked.id(),
+            ConstraintKind::Confined(confined) => confined.id(),
+        }
+    }
+
+    fn contains(&self, surface_position: Point<f64, Logical>) -> bool {
+        self.region
+            .as_ref()
+            .map_or(true, |region| region.contains(surface_position.to_i32_floor()))
+    }
+
+    fn activate(&mut self) {
+        debug!("Activating pointer constraint {:?}", self.kind);
+        self.active = true;
+        match &self.kind {
+            ConstraintKind::Locked(locked) => locked.locked(),
+            ConstraintKind::Confined(confined) => confined.confined(),
+        }
+    }
+
+    fn deactivate(&mut self) {
+        debug!("Deactivating pointer constraint {:?}", self.kind);
+        self.active = false;
+        self.defunct = !self.persistent;
+        match &self.kind {
+            ConstraintKind::Locked(locked) => locked.unlocked(),
+            ConstraintKind::Confined(confined) => confined.unconfined(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PointerConstraintsState {
+    _global: GlobalId,
+    constraints: Vec<PointerConstraint>,
+}
+
+impl PointerConstraintsState {
+    pub fn new(display_handle: &DisplayHandle) -> Self {
+        Self {
+            _global: display_handle.create_global::<TwmState, ZwpPointerConstraintsV1, _>(1, ()),
+            constraints: Vec::new(),
+        }
+    }
+}
+
+impl TwmState {
+    /// Restricts pointer motion from `current` to `target` by the active constraint
+    pub fn constrain_pointer_motion(&self,
+                                    current: Point<f64, Logical>,
+                                    target: Point<f64, Logical>) -> Point<f64, Logical> {
+        let Some(constraint) = self.pointer_constraints.constraints.iter().find(|c| c.active) else {
+            return target;
+        };
+
+        match constraint.kind {
+            ConstraintKind::Locked(_) => current,
+            ConstraintKind::Confined(_) => {
+                let allowed = |position: Point<f64, Logical>| {
+                    self.surface_under(position).map_or(false, |(surface, location)| {
+                        surface == constraint.surface && constraint.contains(position - location.to_f64())
+                    })
+                };
+                // Sliding along the edge of the region when only one axis leaves it
+                [target, (target.x, current.y).into(), (current.x, target.y).into()]
+                    .into_iter()
+                    .find(|&position| allowed(position))
+                    .unwrap_or(current)
+            },
+        }
+    }
+
+    /// Activates the constraint of the surface under the pointer at `position` and
+    /// deactivates the constraints of all other surfaces
+    pub fn update_pointer_constraint(&mut self, position: Point<f64, Logical>) {
+        let pointer = self.seat.get_pointer().expect("Pointer available");
+        let focus = if pointer.is_grabbed() { None } else { self.surface_under(position) };
+
+        for constraint in self.pointer_constraints.constraints.iter_mut() {
+            let focused = focus.as_ref().map_or(false, |(surface, location)| {
+                *surface == constraint.surface && constraint.contains(position - location.to_f64())
+            });
+            if constraint.active && focus.as_ref().map(|(surface, _)| surface) != Some(&constraint.surface) {
+                constraint.deactivate();
+            } else if !constraint.active && !constraint.defunct && focused {
+                constraint.activate();
+            }
+        }
+    }
+
+    /// Applies the pending constraint regions of `surface` and of its subsurfaces, whose
+    /// state is applied along with it
+    pub fn pointer_constraints_commit(&mut self, surface: &WlSurface) {
+        let mut surfaces = Vec::new();
+        with_surface_tree_downward(
+            surface,
+            (),
+            |_, _, _| TraversalAction::DoChildren(()),
+            |surface, _, _| surfaces.push(surface.clone()),
+            |_, _, _| true);
+        self.pointer_constraints.constraints
+            .iter_mut()
+            .filter(|c| surfaces.contains(&c.surface))
+            .for_each(|constraint| {
+                if let Some(region) = constraint.pending_region.take() {
+                    constraint.region = region;
+                }
+            });
+    }
+
+    fn constraint_mut(&mut self, resource: ObjectId) -> Option<&mut PointerConstraint> {
+        self.pointer_constraints.constraints.iter_mut().find(|c| c.resource_id() == resource)
+    }
+
+    fn add_pointer_constraint(&mut self,
+                              constraints: &ZwpPointerConstraintsV1,
+                              kind: impl FnOnce() -> ConstraintKind,
+                              surface: WlSurface,
+                              region: Option<WlRegion>,
+                              lifetime: WEnum<Lifetime>) {
+        self.pointer_constraints.constraints.retain(|c| c.surface.alive());
+        if self.pointer_constraints.constraints.iter().any(|c| c.surface == surface) {
+            constraints.post_error(zwp_pointer_constraints_v1::Error::AlreadyConstrained,
+                                   "Surface already has a pointer constraint");
+            return;
+        }
+
+        let kind = kind();
+        debug!("New pointer constraint {:?}", kind);
+        self.pointer_constraints.constraints.push(PointerConstraint {
+            kind,
+            surface,
+            region: region.as_ref().map(get_region_attributes),
+            pending_region: None,
+            persistent: lifetime == WEnum::Value(Lifetime::Persistent),
+            active: false,
+            defunct: false,
+        });
+    }
+}
+
+impl GlobalDispatch<ZwpPointerConstraintsV1, ()> for TwmState {
+    fn bind(_state: &mut Self,
+            _handle: &DisplayHandle,
+            _client: &Client,
+            resource: New<ZwpPointerConstraintsV1>,
+            _global_data: &(),
+            data_init: &mut DataInit<'_, Self>) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<ZwpPointerConstraintsV1, ()> for TwmState {
+    fn request(state: &mut Self,
+               _client: &Client,
+               resource: &ZwpPointerConstraintsV1,
+               request: zwp_pointer_constraints_v1::Request,
+               _data: &(),
+               _handle: &DisplayHandle,
+               data_init: &mut DataInit<'_, Self>) {
+        match request {
+            zwp_pointer_constraints_v1::Request::LockPointer { id, surface, pointer: _, region, lifetime } => {
+                let kind = || ConstraintKind::Locked(data_init.init(id, ()));
+                state.add_pointer_constraint(resource, kind, surface, region, lifetime);
+            },
+            zwp_pointer_constraints_v1::Request::ConfinePointer { id, surface, pointer: _, region, lifetime } => {
+                let kind = || ConstraintKind::Confined(data_init.init(id, ()));
+                state.add_pointer_constraint(resource, kind, surface, region, lifetime);
+            },
+            zwp_pointer_constraints_v1::Request::Destroy => {},
+            _ => {},
+        }
+    }
+}
+
+impl Dispatch<ZwpLockedPointerV1, ()> for TwmState {
+    fn request(state: &mut Self,
+               _client: &Client,
+               resource: &ZwpLockedPointerV1,
+               request: zwp_locked_pointer_v1::Request,
+               _data: &(),
+               _handle: &DisplayHandle,
+               _data_init: &mut DataInit<'_, Self>) {
+        match request {
+            zwp_locked_pointer_v1::Request::SetRegion { region } => {
+                if let Some(constraint) = state.constraint_mut(resource.id()) {
+                    constraint.pending_region = Some(region.as_ref().map(get_region_attributes));
+                }
+            },
+            // The pointer stays where it was locked, so the hint isn't needed
+            zwp_locked_pointer_v1::Request::SetCursorPositionHint { .. } => {},
+            zwp_locked_pointer_v1::Request::Destroy => {},
+            _ => {},
+        }
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, resource: ObjectId, _data: &()) {
+        state.pointer_constraints.constraints.retain(|c| c.resource_id() != resource);
+    }
+}
+
+impl Dispatch<ZwpConfinedPointerV1, ()> for TwmState {
+    fn request(state: &mut Self,
+               _client: &Client,
+               resource: &ZwpConfinedPointerV1,
+               request: zwp_confined_pointer_v1::Request,
+               _data: &(),
+               _handle: &DisplayHandle,
+               _data_init: &mut DataInit<'_, Self>) {
+        match request {
+            zwp_confined_pointer_v1::Request::SetRegion { region } => {
+                if let Some(constraint) = state.constraint_mut(resource.id()) {
+                    constraint.pending_region = Some(region.as_ref().map(get_region_attributes));
+                }
+            },
+            zwp_confined_pointer_v1::Request::Destroy => {},
+            _ => {},
+        }
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, resource: ObjectId, _data: &()) {
+        state.pointer_constraints.constraints.retain(|c| c.resource_id() != resource);
+    }
+}
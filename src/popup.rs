@@ -0,0 +1,163 @@
+use smithay::{
+    desktop::{
+        find_popup_root_surface,
+        get_popup_toplevel_coords,
+        layer_map_for_output,
+        PopupKeyboardGrab,
+        PopupKind,
+        PopupManager,
+        PopupPointerGrab,
+        PopupUngrabStrategy,
+        WindowSurfaceType,
+    },
+    input::{pointer::Focus, Seat},
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::{Logical, Point, Rectangle, Serial},
+    wayland::{
+        compositor::with_states,
+        shell::xdg::{PopupSurface, PositionerState, XdgPopupSurfaceData},
+    },
+};
+use tracing::{debug, warn};
+
+use crate::{layer_shell::layer_surface_location, TwmState};
+
+impl TwmState {
+    /// Starts tracking a new popup, placed according to its positioner
+    pub fn track_popup(&mut self, surface: PopupSurface, positioner: PositionerState) {
+        surface.with_pending_state(|state| {
+            state.geometry = positioner.get_geometry();
+        });
+        self.unconstrain_popup(&surface);
+        if let Err(err) = self.popups.track_popup(PopupKind::Xdg(surface)) {
+            warn!("Failed to track popup: {}", err);
+        }
+    }
+
+    /// Places the popup according to a new positioner, as requested by the client
+    pub fn reposition_popup(&mut self, surface: PopupSurface, positioner: PositionerState, token: u32) {
+        surface.with_pending_state(|state| {
+            state.geometry = positioner.get_geometry();
+            state.positioner = positioner;
+        });
+        self.unconstrain_popup(&surface);
+        surface.send_repositioned(token);
+    }
+
+    /// Handles a commit of a popup surface, returns false if `surface` isn't one
+    pub fn popup_commit(&mut self, surface: &WlSurface) -> bool {
+        self.popups.commit(surface);
+        let Some(popup) = self.popups.find_popup(surface) else {
+            return false;
+        };
+
+        let PopupKind::Xdg(ref popup) = popup;
+        let initial_configure_sent = with_states(surface, |states| {
+            states
+                .data_map
+                .get::<XdgPopupSurfaceData>()
+                .expect("Popup surface data available")
+                .lock()
+                .expect("Popup surface data lock")
+                .initial_configure_sent
+        });
+        if !initial_configure_sent {
+            if let Err(err) = popup.send_configure() {
+                warn!("Failed to send the initial popup configure: {}", err);
+            }
+        }
+        true
+    }
+
+    /// Sets up a popup grab, clicking outside of the popup chain dismisses it
+    pub fn grab_popup(&mut self, surface: PopupSurface, seat: &Seat<Self>, serial: Serial) {
+        let popup = PopupKind::Xdg(surface);
+        let Some(root) = find_popup_root_surface(&popup).ok() else {
+            return;
+        };
+        let mut grab = match self.popups.grab_popup(root, popup, seat, serial) {
+            Ok(grab) => grab,
+            Err(err) => {
+                debug!("Popup grab denied: {:?}", err);
+                return;
+            }
+        };
+
+        // Only popups opened in response to the input currently grabbing the seat may grab it
+        if let Some(keyboard) = seat.get_keyboard() {
+            if keyboard.is_grabbed()
+                && !(keyboard.has_grab(serial) || keyboard.has_grab(grab.previous_serial().unwrap_or(serial))) {
+                grab.ungrab(PopupUngrabStrategy::All);
+                return;
+            }
+            keyboard.set_focus(self, grab.current_grab(), serial);
+            keyboard.set_grab(PopupKeyboardGrab::new(&grab), serial);
+        }
+        if let Some(pointer) = seat.get_pointer() {
+            if pointer.is_grabbed()
+                && !(pointer.has_grab(serial) || pointer.has_grab(grab.previous_serial().unwrap_or_else(|| grab.serial()))) {
+                grab.ungrab(PopupUngrabStrategy::All);
+                return;
+            }
+            pointer.set_grab(self, PopupPointerGrab::new(&grab), serial, Focus::Keep);
+        }
+    }
+
+    /// Toplevel or layer surface at the root of the popup chain of `surface`, `surface`
+    /// itself if it isn't a popup
+    pub fn popup_root(&self, surface: &WlSurface) -> WlSurface {
+        self.popups
+            .find_popup(surface)
+            .and_then(|popup| find_popup_root_surface(&popup).ok())
+            .unwrap_or_else(|| surface.clone())
+    }
+
+    /// Dismisses all popups of `root` including nested ones, which ends their grab. The
+    /// clients get `popup_done` for every popup, topmost first.
+    pub fn dismiss_popups(&mut self, root: &WlSurface) {
+        let popups: Vec<_> = PopupManager::popups_for_surface(root).map(|(popup, _)| popup).collect();
+        for popup in popups {
+            if let Err(err) = PopupManager::dismiss_popup(root, &popup) {
+                debug!("Failed to dismiss popup: {:?}", err);
+            }
+        }
+    }
+
+    /// Flips and slides the popup so it stays on the output of its root surface
+    fn unconstrain_popup(&self, popup: &PopupSurface) {
+        let kind = PopupKind::Xdg(popup.clone());
+        let Ok(root) = find_popup_root_surface(&kind) else {
+            return;
+        };
+        let Some((root_location, output_geometry)) = self.popup_root_placement(&root) else {
+            return;
+        };
+
+        // The target is relative to the parent of the popup
+        let mut target = output_geometry;
+        target.loc -= get_popup_toplevel_coords(&kind);
+        target.loc -= root_location;
+
+        popup.with_pending_state(|state| {
+            state.geometry = state.positioner.get_unconstrained_geometry(target);
+        });
+    }
+
+    /// Location of the window or layer surface `root` and geometry of the output it is on
+    fn popup_root_placement(&self, root: &WlSurface)
+        -> Option<(Point<i32, Logical>, Rectangle<i32, Logical>)> {
+        if let Some(window) = self.space.elements().find(|w| w.toplevel().wl_surface() == root) {
+            let location = self.space.element_location(window)?;
+            let output = self.output_for_window(window).or_else(|| self.active_output())?;
+            return Some((location, self.space.output_geometry(&output)?));
+        }
+
+        self.space.outputs().find_map(|output| {
+            let map = layer_map_for_output(output);
+            let layer = map.layer_for_surface(root, WindowSurfaceType::TOPLEVEL)?;
+            let output_geometry = self.space.output_geometry(output)?;
+            let location = layer_surface_location(&map, layer)? + output_geometry.loc;
+            Some((location, output_geometry))
+        })
+    }
+}
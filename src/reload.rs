@@ -0,0 +1,41 @@
+//! Reloading the config file while twm runs.
+//!
+//! Keybindings, gaps, borders, colors and most other settings are read from the config
+//! whenever they are needed, so swapping the config applies them. The keymap, key repeat
+//! and the tab font are applied here, the layout is retiled with the new gaps and borders.
+//! Outputs move to their new positions, their other settings apply once an output is added
+//! again.
+
+use anyhow::{anyhow, Context, Result};
+use tracing::info;
+
+use crate::{config::Config, tabs, TwmState};
+
+impl TwmState {
+    /// Reads the config file again and applies it. An invalid file or keymap keeps the
+    /// current config.
+    pub fn reload_config(&mut self) -> Result<()> {
+        let path = self.config_path.clone().context("Couldn't determine config path")?;
+        let config = Config::read(&path)?;
+        info!("Reloading config {:?}", path);
+
+        if config.keyboard != self.config.keyboard {
+            let keyboard = self.seat.get_keyboard().context("Seat has no keyboard")?;
+            keyboard
+                .set_xkb_config(self, config.keyboard.xkb_config())
+                .map_err(|err| anyhow!("Invalid keymap {:?}: {:?}", config.keyboard, err))?;
+            keyboard.change_repeat_info(config.keyboard.repeat_rate, config.keyboard.repeat_delay);
+        }
+        if config.tab_font != self.config.tab_font {
+            self.tab_font = tabs::load_font(config.tab_font.as_deref());
+        }
+        self.config = config;
+
+        self.arrange_outputs();
+        self.retile();
+        // Borders and dimming change without any surface damage
+        self.full_redraw = self.space.outputs().cloned().collect();
+        self.needs_redraw = true;
+        Ok(())
+    }
+}
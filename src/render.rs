@@ -0,0 +1,392 @@
+use std::cell::RefCell;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use smithay::{
+    backend::renderer::{
+        damage::{Error as OutputDamageTrackerError, OutputDamageTracker},
+        element::{
+            memory::MemoryRenderBufferRenderElement,
+            render_elements,
+            utils::RescaleRenderElement,
+            solid::{SolidColorBuffer, SolidColorRenderElement},
+            surface::{render_elements_from_surface_tree, WaylandSurfaceRenderElement},
+            AsRenderElements,
+            RenderElementStates,
+        },
+        ImportAll, ImportMem, Renderer,
+    },
+    desktop::{
+        layer_map_for_output,
+        utils::{send_frames_surface_tree, surface_presentation_feedback_flags_from_states, OutputPresentationFeedback},
+    },
+    input::pointer::{CursorImageAttributes, CursorImageStatus},
+    output::Output,
+    reexports::wayland_server::Resource,
+    utils::{Logical, Physical, Point, Rectangle, Scale},
+    wayland::{compositor::with_states, shell::wlr_layer::Layer},
+};
+
+use tracing::warn;
+
+use crate::{layer_shell::layer_surface_location, TwmState};
+
+render_elements! {
+    pub CustomRenderElements<R> where R: ImportAll + ImportMem;
+    Surface=WaylandSurfaceRenderElement<R>,
+    SolidColor=SolidColorRenderElement,
+    Memory=MemoryRenderBufferRenderElement<R>,
+    Rescaled=RescaleRenderElement<WaylandSurfaceRenderElement<R>>,
+}
+
+/// Color the output is cleared with before drawing
+pub const CLEAR_COLOR: [f32; 4] = [0.1, 0.1, 0.1, 1.0];
+/// Color of locked outputs without a lock surface
+pub const LOCKED_CLEAR_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+/// Buffers of the four sides of a border
+#[derive(Debug, Default)]
+struct BorderBuffers {
+    top: SolidColorBuffer,
+    bottom: SolidColorBuffer,
+    left: SolidColorBuffer,
+    right: SolidColorBuffer,
+}
+
+/// Border buffers of a window kept in its user data, so they only cause damage when the
+/// window geometry or focus changes
+#[derive(Debug, Default)]
+struct WindowBorders {
+    outer: BorderBuffers,
+    inner: BorderBuffers,
+}
+
+/// Rectangles of a `width` wide border around `geometry` in top, bottom, left, right order
+fn border_rectangles(geometry: Rectangle<i32, Logical>, width: i32) -> [Rectangle<i32, Logical>; 4] {
+    let (x, y, w, h) = (geometry.loc.x, geometry.loc.y, geometry.size.w, geometry.size.h);
+    [
+        Rectangle::from_loc_and_size((x - width, y - width), (w + 2 * width, width)),
+        Rectangle::from_loc_and_size((x - width, y + h), (w + 2 * width, width)),
+        Rectangle::from_loc_and_size((x - width, y), (width, h)),
+        Rectangle::from_loc_and_size((x + w, y), (width, h)),
+    ]
+}
+
+impl TwmState {
+    /// Renders windows, layers, borders and the cursor of `output` into the currently bound
+    /// target, `age` is the age of the target buffer used for damage tracking
+    pub fn render_output<R>(&self,
+                            renderer: &mut R,
+                            output: &Output,
+                            age: usize,
+                            damage_tracker: &mut OutputDamageTracker)
+        -> Result<(Option<Vec<Rectangle<i32, Physical>>>, RenderElementStates), OutputDamageTrackerError<R>>
+    where
+        R: Renderer + ImportAll + ImportMem,
+        <R as Renderer>::TextureId: Clone + 'static,
+    {
+        let output_location = self.space
+            .output_geometry(output)
+            .map(|geometry| geometry.loc)
+            .unwrap_or_default();
+        let scale = Scale::from(output.current_scale().fractional_scale());
+
+        let mut custom_elements = self.cursor_elements(renderer, output);
+
+        // Locked outputs only show the lock surface, they stay blank if the lock client died
+        if self.is_locked() {
+            if let Some(surface) = self.lock_surface(output) {
+                custom_elements.extend(
+                    render_elements_from_surface_tree(renderer, &surface, (0, 0), scale, 1.0)
+                        .into_iter()
+                        .map(CustomRenderElements::Surface));
+            }
+            return damage_tracker.render_output(renderer, age, &custom_elements, LOCKED_CLEAR_COLOR);
+        }
+
+        custom_elements.extend(self.debug_overlay_elements(renderer, output));
+        custom_elements.extend(self.dnd_icon_elements(renderer, output));
+        custom_elements.extend(self.input_method_popup_elements(renderer, output));
+        custom_elements.extend(self.border_elements(output_location, scale));
+        custom_elements.extend(self.tab_bar_elements(renderer, output));
+        custom_elements.extend(self.layer_elements(renderer, output, &[Layer::Overlay, Layer::Top], scale));
+        custom_elements.extend(self.x11_window_elements(renderer, output_location, scale));
+        custom_elements.extend(self.window_elements(renderer, output, output_location, scale));
+        custom_elements.extend(self.layer_elements(renderer, output, &[Layer::Bottom, Layer::Background], scale));
+
+        let result = damage_tracker.render_output(renderer, age, &custom_elements, CLEAR_COLOR);
+        if let (Some(overlay), Ok((Some(damage), _))) = (self.debug_overlay.as_ref(), &result) {
+            overlay.frame_rendered(output, damage);
+        }
+        result
+    }
+
+    /// Render elements of the windows on `output` located at `output_location` with their
+    /// popups, each with its own alpha
+    fn window_elements<R>(&self,
+                          renderer: &mut R,
+                          output: &Output,
+                          output_location: Point<i32, Logical>,
+                          scale: Scale<f64>) -> Vec<CustomRenderElements<R>>
+    where
+        R: Renderer + ImportAll + ImportMem,
+        <R as Renderer>::TextureId: Clone + 'static,
+    {
+        let focused = self.focused_window();
+        // Topmost window first
+        self.space
+            .elements()
+            .rev()
+            .filter(|window| self.space.outputs_for_element(window).contains(output))
+            .flat_map(|window| {
+                let alpha = self.window_alpha(window, Some(window) == focused.as_ref()) * self.animation_alpha(window);
+                let Some(animated) = self.animated_geometry(window) else {
+                    let location = self.space.element_location(window).unwrap_or_default() - window.geometry().loc - output_location;
+                    return window.render_elements::<CustomRenderElements<R>>(renderer, location.to_physical_precise_round(scale), scale, alpha);
+                };
+                // Drawn at the animated location, scaled from the current size of the window
+                let size = window.geometry().size;
+                let factor = Scale {
+                    x: animated.size.w as f64 / size.w.max(1) as f64,
+                    y: animated.size.h as f64 / size.h.max(1) as f64,
+                };
+                let origin = (animated.loc - output_location).to_physical_precise_round(scale);
+                let location = animated.loc - window.geometry().loc - output_location;
+                window
+                    .render_elements::<WaylandSurfaceRenderElement<R>>(renderer, location.to_physical_precise_round(scale), scale, alpha)
+                    .into_iter()
+                    .map(|element| CustomRenderElements::Rescaled(RescaleRenderElement::from_element(element, origin, factor)))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Render elements of the X11 windows relative to the output located at
+    /// `output_location`, windows off the output are left out by the damage tracker
+    fn x11_window_elements<R>(&self,
+                              renderer: &mut R,
+                              output_location: Point<i32, Logical>,
+                              scale: Scale<f64>) -> Vec<CustomRenderElements<R>>
+    where
+        R: Renderer + ImportAll + ImportMem,
+        <R as Renderer>::TextureId: Clone + 'static,
+    {
+        self.x11_windows()
+            .flat_map(|window| {
+                let location = (window.geometry().loc - output_location).to_physical_precise_round(scale);
+                window.render_elements::<CustomRenderElements<R>>(renderer, location, scale, 1.0)
+            })
+            .collect()
+    }
+
+    /// Render elements of the layer surfaces of `output` on `layers`, given from top to
+    /// bottom
+    fn layer_elements<R>(&self,
+                         renderer: &mut R,
+                         output: &Output,
+                         layers: &[Layer],
+                         scale: Scale<f64>) -> Vec<CustomRenderElements<R>>
+    where
+        R: Renderer + ImportAll + ImportMem,
+        <R as Renderer>::TextureId: Clone + 'static,
+    {
+        let map = layer_map_for_output(output);
+        // Surfaces mapped later are drawn over earlier ones of the same layer
+        layers
+            .iter()
+            .flat_map(|&layer| map.layers_on(layer).rev())
+            .filter_map(|surface| {
+                let location = layer_surface_location(&map, surface)?.to_physical_precise_round(scale);
+                Some(surface.render_elements::<CustomRenderElements<R>>(renderer, location, scale, 1.0))
+            })
+            .flatten()
+            .collect()
+    }
+
+    /// Collects the presentation feedback of all surfaces drawn in the last frame of `output`,
+    /// to be marked presented once the frame reached the screen
+    pub fn take_presentation_feedback(&self,
+                                      output: &Output,
+                                      states: &RenderElementStates) -> OutputPresentationFeedback {
+        let mut feedback = OutputPresentationFeedback::new(output);
+        self.space.elements().for_each(|window| {
+            window.take_presentation_feedback(
+                &mut feedback,
+                |_, _| Some(output.clone()),
+                |surface, _| surface_presentation_feedback_flags_from_states(surface, states));
+        });
+        layer_map_for_output(output).layers().for_each(|layer| {
+            layer.take_presentation_feedback(
+                &mut feedback,
+                |_, _| Some(output.clone()),
+                |surface, _| surface_presentation_feedback_flags_from_states(surface, states));
+        });
+        feedback
+    }
+
+    /// Sends frame callbacks to all windows and layer surfaces on `output`
+    pub fn send_frames(&self, output: &Output) {
+        let time = self.start_time.elapsed();
+        self.send_preferred_scale(output);
+        if self.is_locked() {
+            if let Some(surface) = self.lock_surface(output) {
+                send_frames_surface_tree(&surface, output, time, Some(Duration::ZERO), |_, _| Some(output.clone()));
+            }
+            return;
+        }
+        self.space.elements().for_each(|window| {
+            window.send_frame(output, time, Some(Duration::ZERO), |_, _| Some(output.clone()));
+        });
+        self.x11_windows().filter_map(|window| window.wl_surface()).for_each(|surface| {
+            send_frames_surface_tree(&surface, output, time, Some(Duration::ZERO), |_, _| Some(output.clone()));
+        });
+        layer_map_for_output(output).layers().for_each(|layer| {
+            layer.send_frame(output, time, Some(Duration::ZERO), |_, _| Some(output.clone()));
+        });
+        self.input_method_popup_surfaces().for_each(|surface| {
+            send_frames_surface_tree(surface, output, time, Some(Duration::ZERO), |_, _| Some(output.clone()));
+        });
+        if let Some(icon) = self.dnd_icon.as_ref() {
+            send_frames_surface_tree(&icon.surface, output, time, Some(Duration::ZERO), |_, _| Some(output.clone()));
+        }
+    }
+
+    /// Render elements of the borders around all mapped windows relative to the output
+    /// located at `output_location`
+    pub fn border_elements<R>(&self,
+                              output_location: Point<i32, Logical>,
+                              scale: Scale<f64>) -> Vec<CustomRenderElements<R>>
+    where
+        R: Renderer + ImportAll + ImportMem,
+        <R as Renderer>::TextureId: Clone + 'static,
+    {
+        let outer_width = self.config.border_width.max(0);
+        let inner_width = self.config.inner_border_width.max(0);
+        if outer_width + inner_width == 0 {
+            return Vec::new();
+        }
+
+        let focused = self.focused_window();
+        // Elements are drawn front to back, borders of upper windows cover the ones below
+        self.space
+            .elements()
+            .rev()
+            .filter(|window| !self.is_maximized(window))
+            .flat_map(|window| {
+                let Some(geometry) = self.animated_geometry(window).or_else(|| self.space.element_geometry(window)) else {
+                    return Vec::new();
+                };
+                let alpha = self.animation_alpha(window);
+                let config = &self.config;
+                let (outer_color, inner_color) = if Some(window) == focused.as_ref() {
+                    (config.focused_border_color, config.focused_inner_border_color)
+                } else if self.is_urgent(window) {
+                    (config.urgent_border_color, config.urgent_inner_border_color)
+                } else {
+                    (config.unfocused_border_color, config.unfocused_inner_border_color)
+                };
+
+                window.user_data().insert_if_missing(|| RefCell::new(WindowBorders::default()));
+                let mut borders = window
+                    .user_data()
+                    .get::<RefCell<WindowBorders>>()
+                    .expect("Window borders available")
+                    .borrow_mut();
+                let WindowBorders { outer, inner } = &mut *borders;
+                // The outer border goes around the inner one
+                let inner_geometry = Rectangle::from_loc_and_size(
+                    geometry.loc - Point::from((inner_width, inner_width)),
+                    (geometry.size.w + 2 * inner_width, geometry.size.h + 2 * inner_width));
+                let mut elements = border_elements(inner, geometry, inner_width, inner_color, alpha, output_location, scale);
+                elements.extend(border_elements(outer, inner_geometry, outer_width, outer_color, alpha, output_location, scale));
+                elements
+            })
+            .collect()
+    }
+
+    /// Render elements of the pointer cursor relative to `output`
+    pub fn cursor_elements<R>(&self, renderer: &mut R, output: &Output) -> Vec<CustomRenderElements<R>>
+    where
+        R: Renderer + ImportAll + ImportMem,
+        <R as Renderer>::TextureId: Clone + 'static,
+    {
+        let Some(pointer) = self.seat.get_pointer() else {
+            return Vec::new();
+        };
+        let output_location = self.space
+            .output_geometry(output)
+            .map(|geometry| geometry.loc)
+            .unwrap_or_default();
+        let scale = Scale::from(output.current_scale().fractional_scale());
+        // Cursor themes only come in whole sizes, fractional outputs get the next larger one
+        let output_scale = output.current_scale().integer_scale();
+        let location = pointer.current_location() - output_location.to_f64();
+
+        match &self.cursor_status {
+            CursorImageStatus::Hidden => Vec::new(),
+            CursorImageStatus::Surface(surface) if surface.alive() => {
+                let hotspot = with_states(surface, |states| {
+                    states
+                        .data_map
+                        .get::<Mutex<CursorImageAttributes>>()
+                        .map(|attributes| attributes.lock().expect("Cursor attributes lock").hotspot)
+                        .unwrap_or_default()
+                });
+                let position = (location - hotspot.to_f64()).to_physical_precise_round(scale);
+                render_elements_from_surface_tree(renderer, surface, position, scale, 1.0)
+                    .into_iter()
+                    .map(CustomRenderElements::Surface)
+                    .collect()
+            },
+            // Compositor cursor, also used when the client destroyed its cursor surface
+            _ => {
+                let cursor = self.cursor_theme.buffer(self.cursor_icon, output_scale);
+                let position = (location - cursor.hotspot.to_f64()).to_physical(scale);
+                match MemoryRenderBufferRenderElement::from_buffer(renderer, position, &cursor.buffer, None, None, None) {
+                    Ok(element) => vec![CustomRenderElements::Memory(element)],
+                    Err(err) => {
+                        warn!("Failed to draw the cursor: {:?}", err);
+                        Vec::new()
+                    }
+                }
+            },
+        }
+    }
+}
+
+/// Render elements of a `width` wide border around `geometry`, none if `width` is 0
+fn border_elements<R>(buffers: &mut BorderBuffers,
+                      geometry: Rectangle<i32, Logical>,
+                      width: i32,
+                      color: [f32; 4],
+                      alpha: f32,
+                      output_location: Point<i32, Logical>,
+                      scale: Scale<f64>) -> Vec<CustomRenderElements<R>>
+where
+    R: Renderer + ImportAll + ImportMem,
+    <R as Renderer>::TextureId: Clone + 'static,
+{
+    if width <= 0 {
+        return Vec::new();
+    }
+    let BorderBuffers { top, bottom, left, right } = buffers;
+    [top, bottom, left, right]
+        .into_iter()
+        .zip(border_rectangles(geometry, width))
+        .map(|(buffer, rectangle)| {
+            buffer.update(rectangle.size, color);
+            let position = (rectangle.loc - output_location).to_physical_precise_round(scale);
+            CustomRenderElements::SolidColor(
+                SolidColorRenderElement::from_buffer(buffer, position, scale, alpha))
+        })
+        .collect()
+}
+
+/// Time between two frames of `output` in nanoseconds, 0 when unknown
+pub fn refresh_interval(output: &Output) -> u32 {
+    output
+        .current_mode()
+        .filter(|mode| mode.refresh > 0)
+        .map(|mode| (1_000_000_000_000u64 / mode.refresh as u64) as u32)
+        .unwrap_or(0)
+}
@@ -0,0 +1,129 @@
+use smithay::{
+    desktop::{layer_map_for_output, Window},
+    utils::{Logical, Rectangle},
+    wayland::shell::xdg::ToplevelSurface,
+};
+use tracing::{debug, info};
+
+use crate::TwmState;
+
+/// Share of the output width and height the shown scratchpad takes
+const SCRATCHPAD_WIDTH: f64 = 0.8;
+const SCRATCHPAD_HEIGHT: f64 = 0.6;
+
+/// Window toggled over the current workspace, kept out of all workspaces
+#[derive(Debug, Default)]
+pub struct Scratchpad {
+    window: Option<Window>,
+    visible: bool,
+    /// Window to give focus back to when the scratchpad is hidden
+    focus_before: Option<Window>,
+}
+
+impl TwmState {
+    /// Shows or hides the scratchpad, starting the scratchpad command if there is none
+    pub fn toggle_scratchpad(&mut self) {
+        match self.scratchpad.window.clone() {
+            Some(window) if self.scratchpad.visible => self.hide_scratchpad(&window),
+            Some(window) => self.show_scratchpad(&window),
+            // The window is adopted once it commits with the scratchpad app id
+            None => match self.config.scratchpad.command.clone() {
+                Some(command) => {
+                    info!("Starting scratchpad {:?}", command);
+                    self.spawn(&command);
+                },
+                None => debug!("No scratchpad command configured"),
+            },
+        }
+    }
+
+    /// Turns `window` into the scratchpad if its app id is the configured one and there is
+    /// no scratchpad yet, it is shown right away
+    pub fn adopt_scratchpad(&mut self, window: &Window) {
+        if self.scratchpad.window.is_some() {
+            return;
+        }
+        let app_id = self.window_info(window).app_id;
+        if app_id.is_none() || app_id != self.config.scratchpad.app_id {
+            return;
+        }
+        debug!("Adopting {:?} as scratchpad", app_id);
+        self.remove_from_workspaces(window);
+        self.scratchpad.window = Some(window.clone());
+        self.retile();
+        self.show_scratchpad(window);
+    }
+
+    pub fn is_scratchpad(&self, window: &Window) -> bool {
+        self.scratchpad.window.as_ref() == Some(window)
+    }
+
+    /// The scratchpad window, shown or not
+    pub fn scratchpad_window(&self) -> Option<&Window> {
+        self.scratchpad.window.as_ref()
+    }
+
+    /// Forgets the scratchpad when the user closed it
+    pub fn scratchpad_destroyed(&mut self, toplevel: &ToplevelSurface) {
+        let Some(window) = self.scratchpad.window.clone().filter(|window| window.toplevel() == toplevel) else {
+            return;
+        };
+        debug!("Scratchpad closed");
+        let had_focus = self.focused_window().as_ref() == Some(&window);
+        self.space.unmap_elem(&window);
+        if had_focus {
+            self.focus_workspace_window();
+        }
+        self.scratchpad = Scratchpad::default();
+    }
+
+    /// Keeps the shown scratchpad above the windows of the workspace
+    pub fn raise_scratchpad(&mut self) {
+        if let Some(window) = self.scratchpad.window.as_ref().filter(|_| self.scratchpad.visible) {
+            self.space.raise_element(window, false);
+        }
+    }
+
+    fn show_scratchpad(&mut self, window: &Window) {
+        let Some(geometry) = self.scratchpad_geometry() else {
+            return;
+        };
+        debug!("Showing scratchpad");
+        self.scratchpad.visible = true;
+        self.scratchpad.focus_before = self.focused_window().filter(|focused| focused != window);
+        self.configure_window(window.clone(), geometry);
+        self.focus_window(Some(window));
+    }
+
+    fn hide_scratchpad(&mut self, window: &Window) {
+        debug!("Hiding scratchpad");
+        self.scratchpad.visible = false;
+        self.space.unmap_elem(window);
+        // The client stays around, only its window is gone until shown again
+        self.focus_workspace_window();
+    }
+
+    /// Gives focus back to the current workspace after the scratchpad went away
+    fn focus_workspace_window(&mut self) {
+        let focus_before = self.scratchpad.focus_before.take();
+        let windows = &self.current_workspace().windows;
+        let focus = focus_before
+            .filter(|window| windows.contains(window))
+            .or_else(|| windows.last().cloned());
+        self.focus_window(focus.as_ref());
+    }
+
+    /// Centered part of the usable area of the active output
+    fn scratchpad_geometry(&self) -> Option<Rectangle<i32, Logical>> {
+        let output = self.active_output()?;
+        let output_geometry = self.space.output_geometry(&output)?;
+        let mut area = layer_map_for_output(&output).non_exclusive_zone();
+        area.loc += output_geometry.loc;
+
+        let width = (area.size.w as f64 * SCRATCHPAD_WIDTH).round() as i32;
+        let height = (area.size.h as f64 * SCRATCHPAD_HEIGHT).round() as i32;
+        Some(Rectangle::from_loc_and_size(
+            (area.loc.x + (area.size.w - width) / 2, area.loc.y + (area.size.h - height) / 2),
+            (width, height)))
+    }
+}
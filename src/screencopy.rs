@@ -0,0 +1,283 @@
+//! The wlr-screencopy protocol used by screenshot tools like grim.
+//!
+//! Copies are done with the next frame of the captured output, which is then rendered
+//! without damage tracking so the whole frame is read back from the renderer.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use smithay::{
+    backend::{
+        allocator::Fourcc,
+        renderer::{ExportMem, Texture},
+    },
+    output::Output,
+    reexports::{
+        wayland_protocols_wlr::screencopy::v1::server::{
+            zwlr_screencopy_frame_v1::{self, ZwlrScreencopyFrameV1},
+            zwlr_screencopy_manager_v1::{self, ZwlrScreencopyManagerV1},
+        },
+        wayland_server::{
+            backend::{ClientId, GlobalId, ObjectId},
+            protocol::{wl_buffer::WlBuffer, wl_shm},
+            Client,
+            DataInit,
+            Dispatch,
+            DisplayHandle,
+            GlobalDispatch,
+            New,
+            Resource,
+        },
+    },
+    utils::{Buffer, Logical, Physical, Rectangle, Size},
+    wayland::shm::{with_buffer_contents, with_buffer_contents_mut},
+};
+use tracing::{debug, warn};
+
+use crate::TwmState;
+
+/// Layout of the pixels read back from the renderer
+const FORMAT: wl_shm::Format = wl_shm::Format::Abgr8888;
+const BYTES_PER_PIXEL: i32 = 4;
+
+/// Capture requested by a client, waiting for its buffer or for the next frame
+#[derive(Debug)]
+struct ScreencopyFrame {
+    frame: ZwlrScreencopyFrameV1,
+    output: Output,
+    /// Captured part of the output in output-local physical coordinates
+    region: Rectangle<i32, Physical>,
+    /// Buffer to copy into, set once the client asked for the copy
+    buffer: Option<WlBuffer>,
+    with_damage: bool,
+}
+
+#[derive(Debug)]
+pub struct ScreencopyState {
+    _global: GlobalId,
+    frames: Vec<ScreencopyFrame>,
+}
+
+impl ScreencopyState {
+    pub fn new(display_handle: &DisplayHandle) -> Self {
+        Self {
+            _global: display_handle.create_global::<TwmState, ZwlrScreencopyManagerV1, _>(3, ()),
+            frames: Vec::new(),
+        }
+    }
+}
+
+impl TwmState {
+    /// Whether a client waits for the next frame of `output`, which then has to be rendered
+    /// in full
+    pub fn has_pending_screencopy(&self, output: &Output) -> bool {
+        self.screencopy.frames.iter().any(|frame| frame.buffer.is_some() && &frame.output == output)
+    }
+
+    /// Copies the frame of `output` just rendered into the framebuffer bound to `renderer`
+    /// to the buffers of all pending captures of the output. `bottom_up` framebuffers like
+    /// those of OpenGL ES have their origin at the bottom.
+    pub fn finish_screencopy<R: ExportMem>(&mut self, renderer: &mut R, output: &Output, bottom_up: bool) {
+        let (ready, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.screencopy.frames)
+            .into_iter()
+            .partition(|frame| frame.buffer.is_some() && &frame.output == output);
+        self.screencopy.frames = pending;
+        if ready.is_empty() {
+            return;
+        }
+
+        let Some(output_size) = output_physical_size(output) else {
+            ready.iter().for_each(|frame| frame.frame.failed());
+            return;
+        };
+        let time = Duration::from(self.clock.now());
+
+        for frame in ready {
+            let buffer = frame.buffer.as_ref().expect("Ready frames have a buffer");
+            if let Err(err) = copy_region(renderer, frame.region, output_size.h, bottom_up, buffer) {
+                warn!("Screencopy failed: {:#}", err);
+                frame.frame.failed();
+                continue;
+            }
+
+            if bottom_up {
+                frame.frame.flags(zwlr_screencopy_frame_v1::Flags::YInvert);
+            }
+            if frame.with_damage {
+                frame.frame.damage(0, 0, frame.region.size.w as u32, frame.region.size.h as u32);
+            }
+            let seconds = time.as_secs();
+            frame.frame.ready((seconds >> 32) as u32, seconds as u32, time.subsec_nanos());
+        }
+    }
+
+    fn new_screencopy_frame(&mut self,
+                            frame: ZwlrScreencopyFrameV1,
+                            output: Option<Output>,
+                            region: Option<Rectangle<i32, Logical>>) {
+        let Some(output) = output else {
+            frame.failed();
+            return;
+        };
+        let Some(output_size) = output_physical_size(&output) else {
+            frame.failed();
+            return;
+        };
+
+        let output_rect = Rectangle::from_loc_and_size((0, 0), output_size);
+        let region = match region {
+            Some(region) => {
+                let scale = output.current_scale().fractional_scale();
+                let region = region.to_f64().to_physical(scale).to_i32_round();
+                match region.intersection(output_rect) {
+                    Some(region) if region.size.w > 0 && region.size.h > 0 => region,
+                    _ => {
+                        frame.failed();
+                        return;
+                    }
+                }
+            },
+            None => output_rect,
+        };
+
+        debug!("New screencopy of {} at {:?}", output.name(), region);
+        frame.buffer(FORMAT, region.size.w as u32, region.size.h as u32, (region.size.w * BYTES_PER_PIXEL) as u32);
+        if frame.version() >= 3 {
+            frame.buffer_done();
+        }
+        self.screencopy.frames.push(ScreencopyFrame {
+            frame,
+            output,
+            region,
+            buffer: None,
+            with_damage: false,
+        });
+    }
+
+    fn screencopy_copy(&mut self, frame: &ZwlrScreencopyFrameV1, buffer: WlBuffer, with_damage: bool) {
+        let Some(pending) = self.screencopy.frames.iter_mut().find(|pending| &pending.frame == frame) else {
+            return;
+        };
+        if pending.buffer.is_some() {
+            frame.post_error(zwlr_screencopy_frame_v1::Error::AlreadyUsed, "Frame was already copied");
+            return;
+        }
+
+        let size = pending.region.size;
+        let valid = with_buffer_contents(&buffer, |_, data| {
+            data.format == FORMAT
+                && data.width == size.w
+                && data.height == size.h
+                && data.stride >= size.w * BYTES_PER_PIXEL
+        });
+        if !matches!(valid, Ok(true)) {
+            frame.post_error(zwlr_screencopy_frame_v1::Error::InvalidBuffer, "Buffer doesn't match the frame");
+            return;
+        }
+
+        pending.buffer = Some(buffer);
+        pending.with_damage = with_damage;
+        // The copy is made with the next frame
+        self.needs_redraw = true;
+    }
+}
+
+/// Size of the framebuffer of `output`
+fn output_physical_size(output: &Output) -> Option<Size<i32, Physical>> {
+    let mode = output.current_mode()?;
+    Some(output.current_transform().transform_size(mode.size))
+}
+
+/// Reads `region` of the bound framebuffer of height `framebuffer_height` into `buffer`
+fn copy_region<R: ExportMem>(renderer: &mut R,
+                             region: Rectangle<i32, Physical>,
+                             framebuffer_height: i32,
+                             bottom_up: bool,
+                             buffer: &WlBuffer) -> Result<()> {
+    let y = if bottom_up { framebuffer_height - region.loc.y - region.size.h } else { region.loc.y };
+    let buffer_region = Rectangle::<i32, Buffer>::from_loc_and_size((region.loc.x, y), (region.size.w, region.size.h));
+    let mapping = renderer
+        .copy_framebuffer(buffer_region)
+        .map_err(|err| anyhow!("Failed to read the framebuffer: {:?}", err))?;
+    // Software framebuffers are read back in the byte order of the scanout buffer
+    let swap_red_blue = matches!(mapping.format(), Some(Fourcc::Argb8888 | Fourcc::Xrgb8888));
+    let pixels = renderer
+        .map_texture(&mapping)
+        .map_err(|err| anyhow!("Failed to map the framebuffer copy: {:?}", err))?;
+
+    let row_length = (region.size.w * BYTES_PER_PIXEL) as usize;
+    with_buffer_contents_mut(buffer, |ptr, len, data| {
+        let stride = data.stride as usize;
+        let offset = data.offset as usize;
+        if offset + stride * (region.size.h as usize - 1) + row_length > len {
+            return Err(anyhow!("Buffer too small"));
+        }
+        for (row, source) in pixels.chunks_exact(row_length).take(region.size.h as usize).enumerate() {
+            // Bounds checked above, the buffer pool is mapped for the duration of the closure
+            let target = unsafe { std::slice::from_raw_parts_mut(ptr.add(offset + row * stride), row_length) };
+            target.copy_from_slice(source);
+            if swap_red_blue {
+                target.chunks_exact_mut(BYTES_PER_PIXEL as usize).for_each(|pixel| pixel.swap(0, 2));
+            }
+        }
+        Ok(())
+    })
+    .context("Failed to access the client buffer")?
+}
+
+impl GlobalDispatch<ZwlrScreencopyManagerV1, ()> for TwmState {
+    fn bind(_state: &mut Self,
+            _handle: &DisplayHandle,
+            _client: &Client,
+            resource: New<ZwlrScreencopyManagerV1>,
+            _global_data: &(),
+            data_init: &mut DataInit<'_, Self>) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<ZwlrScreencopyManagerV1, ()> for TwmState {
+    fn request(state: &mut Self,
+               _client: &Client,
+               _resource: &ZwlrScreencopyManagerV1,
+               request: zwlr_screencopy_manager_v1::Request,
+               _data: &(),
+               _handle: &DisplayHandle,
+               data_init: &mut DataInit<'_, Self>) {
+        // The cursor is part of every captured frame, overlay_cursor is ignored
+        match request {
+            zwlr_screencopy_manager_v1::Request::CaptureOutput { frame, overlay_cursor: _, output } => {
+                let frame = data_init.init(frame, ());
+                state.new_screencopy_frame(frame, Output::from_resource(&output), None);
+            },
+            zwlr_screencopy_manager_v1::Request::CaptureOutputRegion { frame, overlay_cursor: _, output, x, y, width, height } => {
+                let frame = data_init.init(frame, ());
+                let region = Rectangle::from_loc_and_size((x, y), (width, height));
+                state.new_screencopy_frame(frame, Output::from_resource(&output), Some(region));
+            },
+            zwlr_screencopy_manager_v1::Request::Destroy => {},
+            _ => {},
+        }
+    }
+}
+
+impl Dispatch<ZwlrScreencopyFrameV1, ()> for TwmState {
+    fn request(state: &mut Self,
+               _client: &Client,
+               resource: &ZwlrScreencopyFrameV1,
+               request: zwlr_screencopy_frame_v1::Request,
+               _data: &(),
+               _handle: &DisplayHandle,
+               _data_init: &mut DataInit<'_, Self>) {
+        match request {
+            zwlr_screencopy_frame_v1::Request::Copy { buffer } => state.screencopy_copy(resource, buffer, false),
+            zwlr_screencopy_frame_v1::Request::CopyWithDamage { buffer } => state.screencopy_copy(resource, buffer, true),
+            zwlr_screencopy_frame_v1::Request::Destroy => {},
+            _ => {},
+        }
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, resource: ObjectId, _data: &()) {
+        state.screencopy.frames.retain(|frame| frame.frame.id() != resource);
+    }
+}
@@ -0,0 +1,158 @@
+//! Window arrangement kept across restarts, enabled with `restore_session`.
+//!
+//! On shutdown the output, workspace, floating state and geometry of every window with an
+//! app id are written to `$XDG_STATE_HOME/twm/session.json`. After the next start each new
+//! window takes the place of the first saved window with the same app id, which is only a
+//! guess as clients carry no identity across restarts.
+
+use std::cell::Cell;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use smithay::{desktop::Window, utils::Rectangle};
+use tracing::{debug, info, warn};
+
+use crate::{workspace::WORKSPACE_COUNT, TwmState};
+
+/// Saved place of a window
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionWindow {
+    app_id: String,
+    output: Option<String>,
+    /// Index of the workspace on the output
+    workspace: usize,
+    floating: bool,
+    /// Location and size of floating windows that were shown
+    geometry: Option<(i32, i32, i32, i32)>,
+}
+
+/// Marks windows that already had the chance to take a saved place
+#[derive(Debug, Default)]
+struct SessionChecked(Cell<bool>);
+
+/// Location of the session file
+fn session_path() -> Option<PathBuf> {
+    std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state")))
+        .map(|state_dir| state_dir.join("twm").join("session.json"))
+}
+
+/// Reads the windows saved by the last shutdown, none if there is no valid session file
+pub fn load() -> Vec<SessionWindow> {
+    let Some(path) = session_path() else {
+        return Vec::new();
+    };
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(err) => {
+            debug!("No session to restore from {:?}: {}", path, err);
+            return Vec::new();
+        }
+    };
+    match serde_json::from_str::<Vec<SessionWindow>>(&content) {
+        Ok(windows) => {
+            info!("Restoring {} windows from {:?}", windows.len(), path);
+            windows
+        },
+        Err(err) => {
+            warn!("Invalid session file {:?}: {}", path, err);
+            Vec::new()
+        }
+    }
+}
+
+impl TwmState {
+    /// Writes the place of every window to the session file, if sessions are restored
+    pub fn save_session(&self) {
+        if !self.config.restore_session {
+            return;
+        }
+        if let Err(err) = self.write_session() {
+            warn!("Failed to save the session: {:#}", err);
+        }
+    }
+
+    fn write_session(&self) -> Result<()> {
+        let path = session_path().context("Couldn't determine the session path")?;
+        let mut windows = Vec::new();
+        for output_workspaces in self.workspaces.iter() {
+            for (workspace_index, workspace) in output_workspaces.workspaces.iter().enumerate() {
+                for window in workspace.windows.iter() {
+                    let Some(app_id) = self.window_info(window).app_id else {
+                        continue;
+                    };
+                    let floating = self.is_floating(window);
+                    let geometry = self.space
+                        .element_geometry(window)
+                        .filter(|_| floating)
+                        .map(|geometry| (geometry.loc.x, geometry.loc.y, geometry.size.w, geometry.size.h));
+                    windows.push(SessionWindow {
+                        app_id,
+                        output: output_workspaces.output.clone(),
+                        workspace: workspace_index,
+                        floating,
+                        geometry,
+                    });
+                }
+            }
+        }
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+        }
+        let content = serde_json::to_string_pretty(&windows).context("Failed to serialize the session")?;
+        std::fs::write(&path, content).with_context(|| format!("Failed to write {:?}", path))?;
+        info!("Saved {} windows to {:?}", windows.len(), path);
+        Ok(())
+    }
+
+    /// Moves a new window to the place of the first saved window with its app id, once the
+    /// client set one. Called after the window rules, which the saved place overrides.
+    pub fn restore_session_window(&mut self, window: &Window) {
+        if self.session.is_empty() {
+            return;
+        }
+        window.user_data().insert_if_missing(SessionChecked::default);
+        let checked = &window.user_data().get::<SessionChecked>().expect("Session check available").0;
+        if checked.get() {
+            return;
+        }
+        let Some(app_id) = self.window_info(window).app_id else {
+            return;
+        };
+        checked.set(true);
+        let Some(index) = self.session.iter().position(|saved| saved.app_id == app_id) else {
+            return;
+        };
+        let saved = self.session.remove(index);
+        debug!("Restoring {:?} to workspace {} on {:?}", app_id, saved.workspace, saved.output);
+
+        if saved.floating != self.is_floating(window) {
+            self.set_floating(window, saved.floating);
+        }
+        if let Some((x, y, w, h)) = saved.geometry.filter(|_| saved.floating) {
+            self.configure_window(window.clone(), Rectangle::from_loc_and_size((x, y), (w, h)));
+        }
+
+        if saved.workspace >= WORKSPACE_COUNT {
+            return;
+        }
+        let current_output = self.workspace_index(window).map(|(output_index, _)| output_index);
+        let saved_output = self.workspaces
+            .iter()
+            .position(|workspaces| workspaces.output.is_some() && workspaces.output == saved.output);
+        match saved_output {
+            Some(output_index) if Some(output_index) != current_output => {
+                self.remove_from_workspaces(window);
+                if saved.workspace != self.workspaces[output_index].current {
+                    self.space.unmap_elem(window);
+                }
+                self.workspaces[output_index].workspaces[saved.workspace].windows.push(window.clone());
+                self.retile();
+            },
+            _ => self.send_window_to_workspace(window, saved.workspace),
+        }
+    }
+}
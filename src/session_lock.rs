@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+
+use smithay::{
+    desktop::{utils::under_from_surface_tree, Window, WindowSurfaceType},
+    output::Output,
+    reexports::wayland_server::{protocol::{wl_output::WlOutput, wl_surface::WlSurface}, Resource},
+    utils::{Logical, Point},
+    wayland::session_lock::{LockSurface, SessionLockHandler, SessionLockManagerState, SessionLocker},
+};
+use tracing::{debug, info};
+
+use crate::TwmState;
+
+/// State of a locked session
+#[derive(Debug, Default)]
+pub struct SessionLock {
+    /// Lock surface shown on each output, keyed by output name
+    surfaces: HashMap<String, LockSurface>,
+    /// Window to give focus back to when the session is unlocked
+    focus_before_lock: Option<Window>,
+}
+
+impl SessionLockHandler for TwmState {
+    fn lock_state(&mut self) -> &mut SessionLockManagerState {
+        &mut self.session_lock_state
+    }
+
+    fn lock(&mut self, confirmation: SessionLocker) {
+        info!("Locking session");
+        let focus_before_lock = self.focused_window();
+        // Nothing but lock surfaces may have focus while locked
+        self.set_keyboard_focus(None);
+        self.session_lock = Some(SessionLock {
+            surfaces: HashMap::new(),
+            focus_before_lock,
+        });
+        self.needs_redraw = true;
+        // Window content is hidden from the next frame on
+        confirmation.lock();
+    }
+
+    fn unlock(&mut self) {
+        info!("Unlocking session");
+        let Some(lock) = self.session_lock.take() else {
+            return;
+        };
+        self.needs_redraw = true;
+
+        let focus = lock.focus_before_lock
+            .filter(|window| self.current_workspace().windows.contains(window))
+            .or_else(|| self.current_workspace().windows.last().cloned());
+        self.focus_window(focus.as_ref());
+    }
+
+    fn new_surface(&mut self, surface: LockSurface, output: WlOutput) {
+        let Some(output) = Output::from_resource(&output) else {
+            return;
+        };
+        let Some(lock) = self.session_lock.as_mut() else {
+            return;
+        };
+        debug!("New lock surface on {}", output.name());
+        lock.surfaces.insert(output.name(), surface.clone());
+        self.configure_lock_surface(&output);
+
+        // The lock surface of the active output gets keyboard focus
+        if self.active_output().as_ref() == Some(&output) {
+            self.set_keyboard_focus(Some(surface.wl_surface().clone()));
+        }
+    }
+}
+
+impl TwmState {
+    /// Whether a lock client locked the session, this stays true if the client dies
+    pub fn is_locked(&self) -> bool {
+        self.session_lock.is_some()
+    }
+
+    /// Lock surface shown on `output` while locked
+    pub fn lock_surface(&self, output: &Output) -> Option<WlSurface> {
+        self.session_lock
+            .as_ref()?
+            .surfaces
+            .get(&output.name())
+            .map(|surface| surface.wl_surface().clone())
+            .filter(|surface| surface.alive())
+    }
+
+    /// Sends the lock surface of `output` the size of the output
+    pub fn configure_lock_surface(&self, output: &Output) {
+        let Some(surface) = self.session_lock.as_ref().and_then(|lock| lock.surfaces.get(&output.name())) else {
+            return;
+        };
+        if let Some(size) = self.space.output_geometry(output).map(|geometry| geometry.size) {
+            surface.with_pending_state(|state| {
+                state.size = Some((size.w as u32, size.h as u32).into());
+            });
+        }
+        surface.send_configure();
+    }
+
+    /// Whether `surface` is the lock surface of any output
+    pub fn is_lock_surface(&self, surface: &WlSurface) -> bool {
+        self.session_lock.as_ref().map_or(false, |lock| {
+            lock.surfaces.values().any(|lock_surface| lock_surface.wl_surface() == surface)
+        })
+    }
+
+    /// Lock surface or one of its subsurfaces under `position` and its location
+    pub fn lock_surface_under(&self, position: Point<f64, Logical>) -> Option<(WlSurface, Point<i32, Logical>)> {
+        let output = self.space.output_under(position).next()?;
+        let location = self.space.output_geometry(output)?.loc;
+        let surface = self.lock_surface(output)?;
+        under_from_surface_tree(&surface, position - location.to_f64(), (0, 0), WindowSurfaceType::ALL)
+            .map(|(surface, surface_location)| (surface, surface_location + location))
+    }
+}
@@ -0,0 +1,67 @@
+//! The keyboard-shortcuts-inhibit protocol used by remote desktop clients and virtual
+//! machines to get all keys, including those bound to compositor actions.
+//!
+//! An inhibitor is active while its surface has keyboard focus. Only the
+//! `release_shortcuts_inhibitor` binding and virtual terminal switching still work then,
+//! the former turns the inhibitor off until its surface gets focus again.
+
+use smithay::{
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    wayland::keyboard_shortcuts_inhibit::{
+        KeyboardShortcutsInhibitHandler,
+        KeyboardShortcutsInhibitState,
+        KeyboardShortcutsInhibitor,
+        KeyboardShortcutsInhibitorSeat,
+    },
+};
+use tracing::debug;
+
+use crate::TwmState;
+
+impl KeyboardShortcutsInhibitHandler for TwmState {
+    fn keyboard_shortcuts_inhibit_state(&mut self) -> &mut KeyboardShortcutsInhibitState {
+        &mut self.keyboard_shortcuts_inhibit_state
+    }
+
+    fn new_inhibitor(&mut self, inhibitor: KeyboardShortcutsInhibitor) {
+        let focus = self.seat.get_keyboard().and_then(|keyboard| keyboard.current_focus());
+        if focus.as_ref() == Some(inhibitor.wl_surface()) {
+            debug!("Inhibiting keyboard shortcuts");
+            inhibitor.activate();
+        }
+    }
+
+    fn inhibitor_destroyed(&mut self, _inhibitor: KeyboardShortcutsInhibitor) {}
+}
+
+impl TwmState {
+    /// The surface with keyboard focus gets all keys
+    pub fn shortcuts_inhibited(&self) -> bool {
+        self.seat
+            .get_keyboard()
+            .and_then(|keyboard| keyboard.current_focus())
+            .and_then(|focus| self.seat.keyboard_shortcuts_inhibitor_for_surface(&focus))
+            .map_or(false, |inhibitor| inhibitor.is_active())
+    }
+
+    /// Moves the active inhibitor from the surface losing keyboard focus to the one getting it
+    pub fn shortcuts_inhibit_focus_changed(&self, previous: Option<&WlSurface>, focus: Option<&WlSurface>) {
+        if let Some(inhibitor) = previous.and_then(|surface| self.seat.keyboard_shortcuts_inhibitor_for_surface(surface)) {
+            inhibitor.inactivate();
+        }
+        if let Some(inhibitor) = focus.and_then(|surface| self.seat.keyboard_shortcuts_inhibitor_for_surface(surface)) {
+            inhibitor.activate();
+        }
+    }
+
+    /// Gives the compositor its keybindings back from a client that inhibited them
+    pub fn release_shortcuts_inhibitor(&mut self) {
+        let Some(focus) = self.seat.get_keyboard().and_then(|keyboard| keyboard.current_focus()) else {
+            return;
+        };
+        if let Some(inhibitor) = self.seat.keyboard_shortcuts_inhibitor_for_surface(&focus) {
+            debug!("Releasing keyboard shortcuts inhibitor");
+            inhibitor.inactivate();
+        }
+    }
+}
@@ -0,0 +1,54 @@
+//! Graceful shutdown.
+//!
+//! SIGINT and SIGTERM stop the event loop just like the quit action. Afterwards all clients
+//! are asked to close their windows and get a short grace period to do so, which lets them
+//! save their state instead of just losing the connection.
+
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use calloop::signals::{Signal, Signals};
+use smithay::reexports::calloop::{EventLoop, LoopHandle};
+use tracing::{debug, info, warn};
+
+use crate::TwmLoopData;
+
+/// Longest time clients get to close their windows
+const GRACE_PERIOD: Duration = Duration::from_millis(500);
+
+/// Inserts the source stopping the event loop on SIGINT and SIGTERM into the event loop
+pub fn init(handle: &LoopHandle<'static, TwmLoopData>) -> Result<()> {
+    let signals = Signals::new(&[Signal::SIGINT, Signal::SIGTERM])
+        .context("Failed to block SIGINT and SIGTERM")?;
+    handle
+        .insert_source(signals, |event, _, data| {
+            info!("Received {:?}, shutting down", event.signal());
+            data.state.ev_signal.stop();
+        })
+        .map_err(|err| err.error)
+        .context("Failed to insert the exit signal source into the event loop")?;
+    Ok(())
+}
+
+/// Asks all clients to close their windows and keeps dispatching them until they did so
+/// or the grace period is over
+pub fn close_clients(event_loop: &mut EventLoop<TwmLoopData>, data: &mut TwmLoopData) {
+    data.state.all_windows().for_each(|window| window.toplevel().send_close());
+
+    let deadline = Instant::now() + GRACE_PERIOD;
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        data.state.remove_dead_windows();
+        if data.state.all_windows().next().is_none() {
+            debug!("All windows closed");
+            return;
+        }
+        if let Err(err) = data.display.flush_clients() {
+            warn!("Failed to flush clients: {}", err);
+        }
+        if let Err(err) = event_loop.dispatch(Some(remaining), data) {
+            warn!("Failed to dispatch the event loop: {}", err);
+            return;
+        }
+    }
+    debug!("Grace period over, leaving the remaining windows behind");
+}
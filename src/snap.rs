@@ -0,0 +1,106 @@
+//! Snapping floating windows to halves, thirds and corners of their output with the
+//! keyboard.
+//!
+//! Snapping a window the same way again cycles its share of the output from a half to a
+//! third to two thirds. The window's output area left by panels is divided, borders stay
+//! inside it.
+
+use std::cell::Cell;
+
+use smithay::{
+    desktop::{layer_map_for_output, Window},
+    utils::{Logical, Rectangle},
+};
+use tracing::debug;
+
+use crate::TwmState;
+
+/// Shares of the output a window snapped the same way again cycles through
+const FRACTIONS: [(i32, i32); 3] = [(1, 2), (1, 3), (2, 3)];
+
+/// Part of the output a window snaps to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Snap {
+    Left,
+    Right,
+    Up,
+    Down,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// Last snap of a window and the geometry it got, kept in its user data
+#[derive(Debug, Default)]
+struct Snapped(Cell<Option<(Snap, usize, Rectangle<i32, Logical>)>>);
+
+/// Geometry of `snap` in `area`, taking `numerator / denominator` of the snapped axes
+fn snap_geometry(snap: Snap, (numerator, denominator): (i32, i32), area: Rectangle<i32, Logical>) -> Rectangle<i32, Logical> {
+    let width = area.size.w * numerator / denominator;
+    let height = area.size.h * numerator / denominator;
+    let right = area.loc.x + area.size.w - width;
+    let bottom = area.loc.y + area.size.h - height;
+    let (x, y, w, h) = match snap {
+        Snap::Left => (area.loc.x, area.loc.y, width, area.size.h),
+        Snap::Right => (right, area.loc.y, width, area.size.h),
+        Snap::Up => (area.loc.x, area.loc.y, area.size.w, height),
+        Snap::Down => (area.loc.x, bottom, area.size.w, height),
+        Snap::TopLeft => (area.loc.x, area.loc.y, width, height),
+        Snap::TopRight => (right, area.loc.y, width, height),
+        Snap::BottomLeft => (area.loc.x, bottom, width, height),
+        Snap::BottomRight => (right, bottom, width, height),
+    };
+    Rectangle::from_loc_and_size((x, y), (w, h))
+}
+
+impl TwmState {
+    /// Moves and resizes the focused floating window to the `snap` part of its output,
+    /// the next share of it if the window is still where the last equal snap put it
+    pub fn snap_focused_window(&mut self, snap: Snap) {
+        let Some(window) = self.focused_window() else {
+            debug!("No focused window to snap");
+            return;
+        };
+        if !self.is_floating(&window) || self.is_maximized(&window) {
+            debug!("Only floating windows snap");
+            return;
+        }
+        let Some(output) = self.output_for_window(&window).or_else(|| self.active_output()) else {
+            return;
+        };
+        let Some(output_geometry) = self.space.output_geometry(&output) else {
+            return;
+        };
+        let mut area = layer_map_for_output(&output).non_exclusive_zone();
+        area.loc += output_geometry.loc;
+
+        let fraction = self.next_snap_fraction(&window, snap);
+        let snapped = snap_geometry(snap, FRACTIONS[fraction], area);
+        // The border is drawn around the geometry
+        let border = self.config.total_border_width();
+        let geometry = Rectangle::from_loc_and_size(
+            (snapped.loc.x + border, snapped.loc.y + border),
+            ((snapped.size.w - 2 * border).max(1), (snapped.size.h - 2 * border).max(1)));
+        debug!("Snapping {:?} to {:?}: {:?}", self.window_info(&window), snap, geometry);
+
+        window.user_data().insert_if_missing(Snapped::default);
+        window.user_data().get::<Snapped>().expect("Snap state available").0.set(Some((snap, fraction, geometry)));
+        self.configure_window(window.clone(), geometry);
+        self.space.raise_element(&window, true);
+    }
+
+    /// Index into `FRACTIONS` for snapping `window` with `snap`, the next one if the window
+    /// is still where the same snap put it last
+    fn next_snap_fraction(&self, window: &Window, snap: Snap) -> usize {
+        let Some((last, fraction, geometry)) = window.user_data().get::<Snapped>().and_then(|snapped| snapped.0.get()) else {
+            return 0;
+        };
+        let location = self.space.element_location(window);
+        if last == snap && location == Some(geometry.loc) {
+            (fraction + 1) % FRACTIONS.len()
+        } else {
+            0
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result};
+use calloop::signals::{Signal, Signals};
+use nix::{
+    errno::Errno,
+    sys::wait::{waitpid, WaitPidFlag, WaitStatus},
+    unistd::Pid,
+};
+use smithay::reexports::calloop::{
+    timer::{TimeoutAction, Timer},
+    LoopHandle,
+};
+use tracing::{debug, warn};
+
+use crate::{TwmLoopData, TwmState};
+
+/// Inserts the SIGCHLD source reaping exited children into the event loop, spawned clients
+/// would stay around as zombies otherwise
+pub fn init(handle: &LoopHandle<'static, TwmLoopData>) -> Result<()> {
+    let signals = Signals::new(&[Signal::SIGCHLD]).context("Failed to block SIGCHLD")?;
+    handle
+        .insert_source(signals, |_, _, data| data.state.reap_children())
+        .map_err(|err| err.error)
+        .context("Failed to insert the SIGCHLD source into the event loop")?;
+    Ok(())
+}
+
+/// Starts the autostart commands of the config, delayed ones from timers. Spawn failures
+/// are only logged.
+pub fn autostart(handle: &LoopHandle<'static, TwmLoopData>, state: &mut TwmState) {
+    for entry in state.config.autostart.clone() {
+        let command = entry.command_line();
+        let delay = entry.delay();
+        if delay.is_zero() {
+            state.spawn(&command);
+            continue;
+        }
+        debug!("Starting {:?} in {:?}", command, delay);
+        let timer = Timer::from_duration(delay);
+        if let Err(err) = handle.insert_source(timer, move |_, _, data| {
+            data.state.spawn(&command);
+            TimeoutAction::Drop
+        }) {
+            warn!("Failed to schedule autostart command: {}", err.error);
+        }
+    }
+}
+
+/// Waits for `pid` if it exited, returns whether it is gone
+fn reap_child(pid: Pid) -> bool {
+    loop {
+        match waitpid(pid, Some(WaitPidFlag::WNOHANG)) {
+            Ok(WaitStatus::StillAlive) => return false,
+            Ok(WaitStatus::Exited(pid, code)) => {
+                debug!("Child {} exited with {}", pid, code);
+                return true;
+            },
+            Ok(WaitStatus::Signaled(pid, signal, _)) => {
+                debug!("Child {} killed by {}", pid, signal);
+                return true;
+            },
+            // Stopped or continued, still running
+            Ok(_) => return false,
+            Err(Errno::EINTR) => {},
+            // Already reaped elsewhere
+            Err(Errno::ECHILD) => return true,
+            Err(err) => {
+                warn!("Failed to reap child {}: {}", pid, err);
+                return false;
+            }
+        }
+    }
+}
+
+impl TwmState {
+    /// Waits for the spawned commands that exited, one signal may stand for several of them.
+    /// Other children like Xwayland are left to whoever started them.
+    fn reap_children(&mut self) {
+        self.children.retain(|&pid| !reap_child(pid));
+    }
+
+    /// Spawns the shell-style command line `command` as a client of this compositor,
+    /// failures are only logged
+    pub fn spawn(&mut self, command: &str) {
+        let args = match shell_words::split(command) {
+            Ok(args) => args,
+            Err(err) => {
+                warn!("Invalid command {:?}: {}", command, err);
+                return;
+            }
+        };
+        let Some((program, args)) = args.split_first() else {
+            warn!("Empty command");
+            return;
+        };
+
+        let mut process = Command::new(program);
+        process
+            .args(args)
+            .stdin(Stdio::null())
+            // Keyboard interrupts in the terminal twm was started from don't reach the client
+            .process_group(0);
+        if let Some(socket_name) = &self.socket_name {
+            process.env("WAYLAND_DISPLAY", socket_name);
+        }
+        if let Some(display) = self.xwayland.display {
+            process.env("DISPLAY", format!(":{}", display));
+        }
+
+        match process.spawn() {
+            // The SIGCHLD source reaps the child once it exits
+            Ok(child) => {
+                debug!("Spawned {:?} with pid {}", command, child.id());
+                self.children.push(Pid::from_raw(child.id() as i32));
+            },
+            Err(err) => warn!("Failed to spawn {:?}: {}", command, err),
+        }
+    }
+}
@@ -0,0 +1,35 @@
+//! Sticky windows, shown on every workspace of their output.
+//!
+//! A sticky window belongs to the shown workspace of its output and moves along to the
+//! next one on every switch, so it never gets hidden. Tiled sticky windows join the end
+//! of the tiling order of the workspace they move to.
+
+use std::cell::Cell;
+
+use smithay::desktop::Window;
+use tracing::debug;
+
+use crate::TwmState;
+
+/// Sticky flag of a window, kept in its user data
+#[derive(Debug, Default)]
+struct Sticky(Cell<bool>);
+
+impl TwmState {
+    /// Whether the window follows workspace switches of its output
+    pub fn is_sticky(&self, window: &Window) -> bool {
+        window.user_data().get::<Sticky>().map_or(false, |sticky| sticky.0.get())
+    }
+
+    /// Makes the focused window sticky or puts it back on a single workspace
+    pub fn toggle_sticky(&mut self) {
+        let Some(window) = self.focused_window() else {
+            debug!("No focused window to toggle sticky");
+            return;
+        };
+        window.user_data().insert_if_missing(Sticky::default);
+        let sticky = &window.user_data().get::<Sticky>().expect("Sticky flag available").0;
+        sticky.set(!sticky.get());
+        debug!("Window {:?} sticky: {}", self.window_info(&window), sticky.get());
+    }
+}
@@ -0,0 +1,115 @@
+//! Window swallowing: a terminal hides while a window started from it is open, that
+//! window takes the tile of the terminal until it closes.
+//!
+//! Which terminal started a window is guessed from the processes of their clients, the
+//! terminal's process has to be an ancestor of the window's. Only terminals whose app id
+//! is configured swallow, and only tiled windows on the same workspace.
+
+use std::cell::RefCell;
+
+use smithay::{
+    desktop::Window,
+    reexports::wayland_server::Resource,
+    utils::IsAlive,
+};
+use tracing::debug;
+
+use crate::TwmState;
+
+/// Terminal hidden by a window, kept in the window's user data
+#[derive(Debug, Default)]
+struct Swallowed(RefCell<Option<Window>>);
+
+/// Parent of process `pid`, from the process table
+fn parent_pid(pid: i32) -> Option<i32> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The command name in parentheses may contain spaces, the state and parent follow it
+    let fields = &stat[stat.rfind(')')? + 1..];
+    fields.split_whitespace().nth(1)?.parse().ok()
+}
+
+impl TwmState {
+    /// Process of the client owning `window`
+    fn window_pid(&self, window: &Window) -> Option<i32> {
+        let client = self.display_handle.get_client(window.toplevel().wl_surface().id()).ok()?;
+        client.get_credentials(&self.display_handle).ok().map(|credentials| credentials.pid)
+    }
+
+    /// Hides the terminal that started the new `window` and puts `window` in its place,
+    /// called once before the initial configure
+    pub fn swallow_terminal(&mut self, window: &Window) {
+        if !self.config.swallow.enabled || self.is_floating(window) {
+            return;
+        }
+        let Some((output_index, index)) = self.workspace_index(window) else {
+            return;
+        };
+        let Some(pid) = self.window_pid(window) else {
+            return;
+        };
+
+        let terminals: Vec<(i32, Window)> = self.workspaces[output_index].workspaces[index].windows
+            .iter()
+            .filter(|other| *other != window && !self.is_floating(other))
+            .filter(|other| {
+                let app_id = self.window_info(other).app_id;
+                app_id.map_or(false, |app_id| self.config.swallow.terminals.contains(&app_id))
+            })
+            .filter_map(|other| Some((self.window_pid(other)?, other.clone())))
+            .collect();
+        if terminals.is_empty() {
+            return;
+        }
+        // Closest ancestor first, the chain ends at init
+        let mut ancestor = parent_pid(pid);
+        let terminal = loop {
+            match ancestor {
+                Some(ancestor_pid) if ancestor_pid > 1 => {
+                    if let Some((_, terminal)) = terminals.iter().find(|(pid, _)| *pid == ancestor_pid) {
+                        break terminal.clone();
+                    }
+                    ancestor = parent_pid(ancestor_pid);
+                },
+                _ => return,
+            }
+        };
+        debug!("Window {:?} swallows {:?}", self.window_info(window), self.window_info(&terminal));
+
+        let workspace = &mut self.workspaces[output_index].workspaces[index];
+        workspace.windows.retain(|w| w != window);
+        let position = workspace.windows.iter().position(|w| w == &terminal).unwrap_or(workspace.windows.len());
+        workspace.windows.insert(position, window.clone());
+        workspace.remove_window(&terminal);
+        self.space.unmap_elem(&terminal);
+
+        window.user_data().insert_if_missing(Swallowed::default);
+        *window.user_data().get::<Swallowed>().expect("Swallowed terminal available").0.borrow_mut() = Some(terminal);
+        self.retile();
+    }
+
+    /// Puts the terminal swallowed by `window` back into its place, before `window` is
+    /// removed
+    pub fn release_swallowed(&mut self, window: &Window) {
+        let Some(terminal) = window
+            .user_data()
+            .get::<Swallowed>()
+            .and_then(|swallowed| swallowed.0.borrow_mut().take())
+            .filter(|terminal| terminal.toplevel().alive()) else {
+            return;
+        };
+        let Some((output_index, index)) = self.workspace_index(window) else {
+            return;
+        };
+        debug!("Restoring swallowed {:?}", self.window_info(&terminal));
+
+        let workspaces = &mut self.workspaces[output_index];
+        let shown = workspaces.current == index;
+        let workspace = &mut workspaces.workspaces[index];
+        // Right after `window`, so the terminal takes its index once it is gone
+        let position = workspace.windows.iter().position(|w| w == window).map_or(workspace.windows.len(), |p| p + 1);
+        workspace.windows.insert(position, terminal.clone());
+        if shown {
+            self.space.map_element(terminal, (0, 0), false);
+        }
+    }
+}
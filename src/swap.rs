@@ -0,0 +1,123 @@
+use smithay::desktop::Window;
+use tracing::debug;
+
+use crate::{direction::{self, Direction}, TwmState};
+
+impl TwmState {
+    /// Swaps the focused window with the master of its output, the master itself is
+    /// swapped with the first stack window instead
+    pub fn swap_with_master(&mut self) {
+        let Some((focused, windows)) = self.focused_tiling_order() else {
+            return;
+        };
+        let other = if windows.first() == Some(&focused) {
+            windows.get(1)
+        } else {
+            windows.first()
+        };
+        if let Some(other) = other.cloned() {
+            self.swap_windows(&focused, &other);
+        }
+    }
+
+    /// Swaps the focused window with the one after it in tiling order, wrapping around
+    pub fn swap_next(&mut self) {
+        self.swap_with_neighbour(1);
+    }
+
+    /// Swaps the focused window with the one before it in tiling order, wrapping around
+    pub fn swap_prev(&mut self) {
+        self.swap_with_neighbour(-1);
+    }
+
+    fn swap_with_neighbour(&mut self, step: isize) {
+        let Some((focused, windows)) = self.focused_tiling_order() else {
+            return;
+        };
+        let Some(index) = windows.iter().position(|w| w == &focused) else {
+            return;
+        };
+        let other = (index as isize + step).rem_euclid(windows.len() as isize) as usize;
+        let other = windows[other].clone();
+        self.swap_windows(&focused, &other);
+    }
+
+    /// Swaps the focused tiled window with the tiled window next to it in `direction` on its
+    /// output. Floating windows move by the configured step instead.
+    pub fn move_in_direction(&mut self, direction: Direction) {
+        let Some(focused) = self.focused_window() else {
+            debug!("No focused window to move");
+            return;
+        };
+        if self.is_floating(&focused) {
+            self.move_floating_window(&focused, direction);
+            return;
+        }
+        let Some((focused, windows)) = self.focused_tiling_order() else {
+            return;
+        };
+        let Some(geometry) = self.space.element_geometry(&focused) else {
+            return;
+        };
+        let (windows, geometries): (Vec<Window>, Vec<_>) = windows
+            .into_iter()
+            .filter(|window| window != &focused)
+            .filter_map(|window| {
+                let geometry = self.space.element_geometry(&window)?;
+                Some((window, geometry))
+            })
+            .unzip();
+        match direction::neighbour(geometry, &geometries, direction) {
+            Some(index) => self.swap_windows(&focused, &windows[index]),
+            None => debug!("No window {:?} of the focused one", direction),
+        }
+    }
+
+    /// Moves a floating window by the configured step in `direction`
+    fn move_floating_window(&mut self, window: &Window, direction: Direction) {
+        if self.is_maximized(window) {
+            return;
+        }
+        let Some(mut location) = self.space.element_location(window) else {
+            return;
+        };
+        let step = self.config.floating_move_step;
+        match direction {
+            Direction::Left => location.x -= step,
+            Direction::Right => location.x += step,
+            Direction::Up => location.y -= step,
+            Direction::Down => location.y += step,
+        }
+        self.space.map_element(window.clone(), location, false);
+        self.needs_redraw = true;
+    }
+
+    /// Focused window and the tiling order of the tiled windows on its output
+    fn focused_tiling_order(&self) -> Option<(Window, Vec<Window>)> {
+        let focused = self.focused_window().filter(|window| !self.is_floating(window))?;
+        let output = self.output_for_window(&focused)?;
+        let windows = self.windows_on_output(&output)
+            .into_iter()
+            .filter(|window| !self.is_floating(window))
+            .collect();
+        Some((focused, windows))
+    }
+
+    /// Exchanges the positions of two windows of the same workspace in tiling order,
+    /// keyboard focus stays on the same window
+    fn swap_windows(&mut self, a: &Window, b: &Window) {
+        if a == b {
+            return;
+        }
+        let Some(workspace) = self.workspace_of_mut(a) else {
+            return;
+        };
+        let windows = &mut workspace.windows;
+        let (Some(a), Some(b)) = (windows.iter().position(|w| w == a), windows.iter().position(|w| w == b)) else {
+            return;
+        };
+        debug!("Swapping windows {} and {}", a, b);
+        windows.swap(a, b);
+        self.retile();
+    }
+}
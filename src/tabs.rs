@@ -0,0 +1,400 @@
+//! Tabbed containers, several tiled windows sharing one tile.
+//!
+//! A container takes the tile of its first window in tiling order, the layout skips its
+//! other windows. All windows of a container get the same geometry below a tab bar listing
+//! their titles, and only the shown one is raised above the others. The shown window
+//! follows keyboard focus.
+
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+
+use fontdue::{Font, FontSettings};
+use smithay::{
+    backend::{
+        allocator::Fourcc,
+        renderer::{
+            element::{
+                memory::{MemoryRenderBuffer, MemoryRenderBufferRenderElement},
+                solid::{SolidColorBuffer, SolidColorRenderElement},
+            },
+            ImportAll, ImportMem, Renderer,
+        },
+    },
+    desktop::Window,
+    output::Output,
+    utils::{Logical, Physical, Point, Rectangle, Scale, Size, Transform},
+};
+use tracing::{debug, info, warn};
+
+use crate::{render::CustomRenderElements, TwmState};
+
+/// Fonts tried for tab titles when the config names none
+const FALLBACK_FONTS: &[&str] = &[
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/noto/NotoSans-Regular.ttf",
+    "/usr/share/fonts/truetype/noto/NotoSans-Regular.ttf",
+    "/usr/share/fonts/liberation/LiberationSans-Regular.ttf",
+    "/usr/share/fonts/truetype/liberation/LiberationSans-Regular.ttf",
+];
+
+/// Space in logical pixels between the edges of a tab and its title
+const TITLE_PADDING: i32 = 6;
+/// Share of the tab bar height taken by the title font
+const TITLE_FONT_SIZE: f32 = 0.65;
+
+/// Windows of one workspace sharing a tile
+#[derive(Debug)]
+pub struct TabContainer {
+    /// Windows in tab order, always at least two
+    pub windows: Vec<Window>,
+    /// Window shown while none of the container has focus
+    pub shown: Window,
+    /// Global geometry of the tab bar, set by the layout while the container is tiled
+    pub bar: Option<Rectangle<i32, Logical>>,
+}
+
+impl TabContainer {
+    /// Window on top of the container, the focused one if it is part of it
+    fn visible(&self, focused: Option<&Window>) -> &Window {
+        focused.filter(|focused| self.windows.contains(focused)).unwrap_or(&self.shown)
+    }
+}
+
+/// Takes `window` out of its container, containers left with a single window are dissolved
+pub fn remove_from_containers(containers: &mut Vec<TabContainer>, window: &Window) {
+    for container in containers.iter_mut() {
+        container.windows.retain(|w| w != window);
+        if &container.shown == window {
+            if let Some(first) = container.windows.first() {
+                container.shown = first.clone();
+            }
+        }
+    }
+    containers.retain(|container| container.windows.len() > 1);
+}
+
+/// Groups `windows` in tiling order into tiles, the windows of a container share the tile
+/// of the first of them
+pub fn group_tiles(containers: &[TabContainer], windows: Vec<Window>) -> Vec<Vec<Window>> {
+    let mut tiles: Vec<Vec<Window>> = Vec::with_capacity(windows.len());
+    for window in windows {
+        let container = containers.iter().find(|container| container.windows.contains(&window));
+        let tile = container.and_then(|container| {
+            tiles.iter_mut().find(|tile| container.windows.contains(&tile[0]))
+        });
+        match tile {
+            Some(tile) => tile.push(window),
+            None => tiles.push(vec![window]),
+        }
+    }
+    tiles
+}
+
+/// Loads the font of tab titles from `path` or the first fallback font found
+pub fn load_font(path: Option<&Path>) -> Option<Font> {
+    let candidates = path
+        .map(Path::to_path_buf)
+        .into_iter()
+        .chain(FALLBACK_FONTS.iter().map(PathBuf::from));
+    for candidate in candidates {
+        let Ok(data) = std::fs::read(&candidate) else {
+            continue;
+        };
+        match Font::from_bytes(data, FontSettings::default()) {
+            Ok(font) => {
+                info!("Tab title font {:?}", candidate);
+                return Some(font);
+            },
+            Err(err) => warn!("Invalid font {:?}: {}", candidate, err),
+        }
+    }
+    warn!("No font found, tabs are drawn without titles");
+    None
+}
+
+/// Buffers of the tab of a window kept in its user data, so they only cause damage when the
+/// tab changes
+#[derive(Debug, Default)]
+struct TabElements {
+    background: SolidColorBuffer,
+    /// Title text, buffer size and scale the title buffer was drawn for
+    title: Option<(String, Size<i32, Physical>, i32, MemoryRenderBuffer)>,
+}
+
+impl TwmState {
+    /// Container `window` is part of
+    pub fn container_of(&self, window: &Window) -> Option<&TabContainer> {
+        self.workspaces
+            .iter()
+            .flat_map(|workspaces| workspaces.workspaces.iter())
+            .flat_map(|workspace| workspace.containers.iter())
+            .find(|container| container.windows.contains(window))
+    }
+
+    fn container_of_mut(&mut self, window: &Window) -> Option<&mut TabContainer> {
+        self.workspace_of_mut(window)?
+            .containers
+            .iter_mut()
+            .find(|container| container.windows.contains(window))
+    }
+
+    /// Puts the focused window and the next tiled window into one container, joining their
+    /// containers if they have one
+    pub fn join_tabs(&mut self) {
+        let Some(focused) = self.focused_window().filter(|window| !self.is_floating(window)) else {
+            return;
+        };
+        let Some((output_index, workspace_index)) = self.workspace_index(&focused) else {
+            return;
+        };
+        let workspace = &self.workspaces[output_index].workspaces[workspace_index];
+        let Some(index) = workspace.windows.iter().position(|w| w == &focused) else {
+            return;
+        };
+        let focused_container = self.container_of(&focused).map(|container| container.windows.clone()).unwrap_or_default();
+        // The next tiled window in tiling order outside of the focused container
+        let Some(next) = workspace.windows[index + 1..]
+            .iter()
+            .find(|window| !self.is_floating(window) && !self.is_maximized(window) && !focused_container.contains(window))
+            .cloned() else {
+            debug!("No window to join the focused window with");
+            return;
+        };
+        debug!("Joining windows into a tabbed container");
+
+        let containers = &mut self.workspaces[output_index].workspaces[workspace_index].containers;
+        let mut take = |window: &Window| {
+            match containers.iter().position(|container| container.windows.contains(window)) {
+                Some(index) => containers.remove(index).windows,
+                None => vec![window.clone()],
+            }
+        };
+        let mut windows = take(&focused);
+        windows.extend(take(&next));
+        containers.push(TabContainer {
+            windows,
+            shown: focused,
+            bar: None,
+        });
+        self.retile();
+    }
+
+    /// Takes the focused window out of its container, it gets a tile of its own again
+    pub fn split_tabs(&mut self) {
+        let Some(focused) = self.focused_window() else {
+            return;
+        };
+        let Some(workspace) = self.workspace_of_mut(&focused) else {
+            return;
+        };
+        debug!("Taking window out of its tabbed container");
+        remove_from_containers(&mut workspace.containers, &focused);
+        self.retile();
+    }
+
+    /// Focuses the tab after the focused one in its container, wrapping around
+    pub fn focus_next_tab(&mut self) {
+        self.cycle_tab(1);
+    }
+
+    /// Focuses the tab before the focused one in its container, wrapping around
+    pub fn focus_prev_tab(&mut self) {
+        self.cycle_tab(-1);
+    }
+
+    fn cycle_tab(&mut self, step: isize) {
+        let Some(focused) = self.focused_window() else {
+            return;
+        };
+        let Some(windows) = self.container_of(&focused).map(|container| container.windows.clone()) else {
+            return;
+        };
+        let Some(index) = windows.iter().position(|w| w == &focused) else {
+            return;
+        };
+        let next = (index as isize + step).rem_euclid(windows.len() as isize) as usize;
+        self.focus_window(Some(&windows[next]));
+    }
+
+    /// Makes `window` the shown window of its container once it got focus
+    pub fn show_tab(&mut self, window: &Window) {
+        if let Some(container) = self.container_of_mut(window) {
+            container.shown = window.clone();
+        }
+    }
+
+    /// Reserves the tab bar at the top of `tile` for the container of `window`, returns the
+    /// space left for the windows
+    pub fn place_tab_bar(&mut self, window: &Window, tile: Rectangle<i32, Logical>) -> Rectangle<i32, Logical> {
+        let height = self.config.tab_bar_height.clamp(0, (tile.size.h - 1).max(0));
+        let Some(container) = self.container_of_mut(window) else {
+            return tile;
+        };
+        container.bar = Some(Rectangle::from_loc_and_size(tile.loc, (tile.size.w, height)));
+        Rectangle::from_loc_and_size((tile.loc.x, tile.loc.y + height), (tile.size.w, tile.size.h - height))
+    }
+
+    /// Forgets the tab bars of the containers on `output` before the layout places them anew
+    pub fn clear_tab_bars(&mut self, output: &Output) {
+        if let Some(workspace) = self.output_workspace_mut(output) {
+            workspace.containers.iter_mut().for_each(|container| container.bar = None);
+        }
+    }
+
+    /// Raises the shown window of every tiled container on `output`
+    pub fn raise_shown_tabs(&mut self, output: &Output) {
+        let focused = self.focused_window();
+        let shown: Vec<Window> = self.output_workspace(output)
+            .map(|workspace| workspace.containers
+                .iter()
+                .filter(|container| container.bar.is_some())
+                .map(|container| container.visible(focused.as_ref()).clone())
+                .collect())
+            .unwrap_or_default();
+        for window in shown {
+            self.space.raise_element(&window, false);
+        }
+    }
+
+    /// Render elements of the tab bars on `output`
+    pub fn tab_bar_elements<R>(&self, renderer: &mut R, output: &Output) -> Vec<CustomRenderElements<R>>
+    where
+        R: Renderer + ImportAll + ImportMem,
+        <R as Renderer>::TextureId: Clone + 'static,
+    {
+        let (Some(workspace), Some(output_geometry)) = (self.output_workspace(output), self.space.output_geometry(output)) else {
+            return Vec::new();
+        };
+        let scale = Scale::from(output.current_scale().fractional_scale());
+        // Titles are drawn at whole scales like the cursor
+        let buffer_scale = output.current_scale().integer_scale().max(1);
+        let focused = self.focused_window();
+
+        let mut elements = Vec::new();
+        for container in workspace.containers.iter() {
+            let Some(bar) = container.bar else {
+                continue;
+            };
+            let visible = container.visible(focused.as_ref());
+            let count = container.windows.len() as i32;
+            let tab_width = bar.size.w / count;
+
+            for (index, window) in container.windows.iter().enumerate() {
+                let index = index as i32;
+                // The last tab takes the rounding remainder
+                let width = if index + 1 == count { bar.size.w - tab_width * index } else { tab_width };
+                let tab = Rectangle::from_loc_and_size((bar.loc.x + tab_width * index, bar.loc.y), (width, bar.size.h));
+                let color = if window == visible {
+                    self.config.focused_border_color
+                } else if self.is_urgent(window) {
+                    self.config.urgent_border_color
+                } else {
+                    self.config.unfocused_border_color
+                };
+                let title = self.window_info(window).title.unwrap_or_default();
+                elements.extend(self.tab_elements(renderer, window, &title, tab, color, output_geometry.loc, scale, buffer_scale));
+            }
+        }
+        elements
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn tab_elements<R>(&self,
+                       renderer: &mut R,
+                       window: &Window,
+                       title: &str,
+                       tab: Rectangle<i32, Logical>,
+                       color: [f32; 4],
+                       output_location: Point<i32, Logical>,
+                       scale: Scale<f64>,
+                       buffer_scale: i32) -> Vec<CustomRenderElements<R>>
+    where
+        R: Renderer + ImportAll + ImportMem,
+        <R as Renderer>::TextureId: Clone + 'static,
+    {
+        window.user_data().insert_if_missing(|| RefCell::new(TabElements::default()));
+        let mut tab_elements = window
+            .user_data()
+            .get::<RefCell<TabElements>>()
+            .expect("Tab elements available")
+            .borrow_mut();
+
+        let position = (tab.loc - output_location).to_physical_precise_round(scale);
+        let mut elements = Vec::with_capacity(2);
+
+        let text_size = Size::<i32, Physical>::from((
+            (tab.size.w - 2 * TITLE_PADDING).max(0) * buffer_scale,
+            tab.size.h * buffer_scale,
+        ));
+        if let Some(font) = self.tab_font.as_ref().filter(|_| text_size.w > 0 && text_size.h > 0 && !title.is_empty()) {
+            let outdated = tab_elements.title
+                .as_ref()
+                .map_or(true, |(text, size, cached_scale, _)| text != title || *size != text_size || *cached_scale != buffer_scale);
+            if outdated {
+                let pixels = rasterize_title(font, title, text_size);
+                let buffer = MemoryRenderBuffer::from_memory(
+                    &pixels,
+                    Fourcc::Abgr8888,
+                    (text_size.w, text_size.h),
+                    buffer_scale,
+                    Transform::Normal,
+                    None);
+                tab_elements.title = Some((title.to_string(), text_size, buffer_scale, buffer));
+            }
+            let (_, _, _, buffer) = tab_elements.title.as_ref().expect("Title buffer drawn");
+            let text_position = (tab.loc - output_location + Point::from((TITLE_PADDING, 0)))
+                .to_f64()
+                .to_physical(scale);
+            match MemoryRenderBufferRenderElement::from_buffer(renderer, text_position, buffer, None, None, None) {
+                Ok(element) => elements.push(CustomRenderElements::Memory(element)),
+                Err(err) => warn!("Failed to draw a tab title: {:?}", err),
+            }
+        }
+
+        // Elements are drawn front to back, the title goes above its background
+        tab_elements.background.update(tab.size, color);
+        elements.push(CustomRenderElements::SolidColor(
+            SolidColorRenderElement::from_buffer(&tab_elements.background, position, scale, 1.0)));
+        elements
+    }
+}
+
+/// White `text` on a transparent background, vertically centered in a buffer of `size` and
+/// cut off at its right edge
+pub fn rasterize_title(font: &Font, text: &str, size: Size<i32, Physical>) -> Vec<u8> {
+    let (width, height) = (size.w, size.h);
+    let mut pixels = vec![0u8; (width * height * 4) as usize];
+    let font_size = height as f32 * TITLE_FONT_SIZE;
+    let (ascent, descent) = font
+        .horizontal_line_metrics(font_size)
+        .map_or((font_size * 0.8, -font_size * 0.2), |metrics| (metrics.ascent, metrics.descent));
+    let baseline = ((height as f32 + ascent + descent) / 2.0).round() as i32;
+
+    let mut pen_x = 0;
+    for character in text.chars() {
+        if pen_x >= width {
+            break;
+        }
+        let (metrics, coverage) = font.rasterize(character, font_size);
+        let left = pen_x + metrics.xmin;
+        let top = baseline - metrics.height as i32 - metrics.ymin;
+        for row in 0..metrics.height {
+            for column in 0..metrics.width {
+                let (x, y) = (left + column as i32, top + row as i32);
+                if x < 0 || x >= width || y < 0 || y >= height {
+                    continue;
+                }
+                let alpha = coverage[row * metrics.width + column];
+                let offset = ((y * width + x) * 4) as usize;
+                // Premultiplied white, overlapping glyphs keep the stronger coverage
+                let pixel = &mut pixels[offset..offset + 4];
+                let alpha = alpha.max(pixel[3]);
+                pixel.copy_from_slice(&[alpha; 4]);
+            }
+        }
+        pen_x += metrics.advance_width.round() as i32;
+    }
+    pixels
+}
@@ -0,0 +1,106 @@
+use super::fixture::Fixture;
+
+/// Evdev code of the A key, bound to nothing
+const KEY_A: u32 = 30;
+
+/// A chat client with the focus and a browser client with an unfocused window, returns
+/// the chat client, the browser client and the index of the browser window
+fn chat_and_browser(fixture: &mut Fixture) -> (usize, usize, usize) {
+    let browser = fixture.add_client();
+    let browser_window = fixture.map_window(browser, "browser");
+    let chat = fixture.add_client();
+    fixture.map_window(chat, "chat");
+    let window = fixture.window("chat");
+    fixture.state().focus_window(Some(&window));
+    fixture.dispatch();
+    assert_eq!(fixture.focused_app_id().as_deref(), Some("chat"));
+    (chat, browser, browser_window)
+}
+
+#[test]
+fn new_window_of_another_client_does_not_steal_focus() {
+    let mut fixture = Fixture::new();
+    let chat = fixture.add_client();
+    fixture.map_window(chat, "chat");
+    assert_eq!(fixture.focused_app_id().as_deref(), Some("chat"));
+
+    let browser = fixture.add_client();
+    fixture.map_window(browser, "browser");
+    assert_eq!(fixture.focused_app_id().as_deref(), Some("chat"));
+    let window = fixture.window("browser");
+    assert!(fixture.state().is_urgent(&window));
+}
+
+#[test]
+fn new_window_of_the_focused_client_takes_focus() {
+    let mut fixture = Fixture::new();
+    let client = fixture.add_client();
+    fixture.map_window(client, "editor");
+    fixture.map_window(client, "dialog");
+    assert_eq!(fixture.focused_app_id().as_deref(), Some("dialog"));
+    let window = fixture.window("dialog");
+    assert!(!fixture.state().is_urgent(&window));
+}
+
+#[test]
+fn token_without_serial_only_marks_urgent() {
+    let mut fixture = Fixture::new();
+    let (_, browser, browser_window) = chat_and_browser(&mut fixture);
+
+    fixture.client(browser).request_token(None);
+    fixture.dispatch();
+    let token = fixture.client(browser).state.tokens.pop().expect("Token received");
+    fixture.client(browser).activate(browser_window, &token);
+    fixture.dispatch();
+
+    assert_eq!(fixture.focused_app_id().as_deref(), Some("chat"));
+    let window = fixture.window("browser");
+    assert!(fixture.state().is_urgent(&window));
+}
+
+#[test]
+fn token_from_input_to_the_focused_client_focuses() {
+    let mut fixture = Fixture::new();
+    let (chat, browser, browser_window) = chat_and_browser(&mut fixture);
+    fixture.client(chat).get_keyboard();
+    fixture.dispatch();
+    fixture.tap_key(KEY_A);
+    let serial = fixture.client(chat).state.key_serial.expect("Key event received");
+
+    // The chat client hands the token to the browser opening a link
+    fixture.client(chat).request_token(Some(serial));
+    fixture.dispatch();
+    let token = fixture.client(chat).state.tokens.pop().expect("Token received");
+    fixture.client(browser).activate(browser_window, &token);
+    fixture.dispatch();
+
+    assert_eq!(fixture.focused_app_id().as_deref(), Some("browser"));
+    let window = fixture.window("browser");
+    assert!(!fixture.state().is_urgent(&window));
+}
+
+#[test]
+fn token_from_input_before_the_focus_changed_only_marks_urgent() {
+    let mut fixture = Fixture::new();
+    let (chat, browser, browser_window) = chat_and_browser(&mut fixture);
+    fixture.client(chat).get_keyboard();
+    fixture.dispatch();
+    fixture.tap_key(KEY_A);
+    let serial = fixture.client(chat).state.key_serial.expect("Key event received");
+
+    let editor = fixture.add_client();
+    fixture.map_window(editor, "editor");
+    let window = fixture.window("editor");
+    fixture.state().focus_window(Some(&window));
+    fixture.dispatch();
+
+    fixture.client(chat).request_token(Some(serial));
+    fixture.dispatch();
+    let token = fixture.client(chat).state.tokens.pop().expect("Token received");
+    fixture.client(browser).activate(browser_window, &token);
+    fixture.dispatch();
+
+    assert_eq!(fixture.focused_app_id().as_deref(), Some("editor"));
+    let window = fixture.window("browser");
+    assert!(fixture.state().is_urgent(&window));
+}
@@ -0,0 +1,494 @@
+//! Wayland client of the tests, connected to the compositor through a socket pair and
+//! dispatched from the same thread, so it never blocks on the compositor.
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use wayland_client::{
+    delegate_noop,
+    event_created_child,
+    protocol::{
+        wl_buffer::WlBuffer,
+        wl_compositor::WlCompositor,
+        wl_data_device::{self, WlDataDevice},
+        wl_data_device_manager::WlDataDeviceManager,
+        wl_data_offer::{self, WlDataOffer},
+        wl_data_source::{self, WlDataSource},
+        wl_keyboard::{self, WlKeyboard},
+        wl_registry::{self, WlRegistry},
+        wl_seat::WlSeat,
+        wl_shm::{self, WlShm},
+        wl_shm_pool::WlShmPool,
+        wl_subcompositor::WlSubcompositor,
+        wl_subsurface::WlSubsurface,
+        wl_surface::WlSurface,
+    },
+    backend::WaylandError,
+    Connection,
+    Dispatch,
+    EventQueue,
+    Proxy,
+    QueueHandle,
+};
+use wayland_protocols::xdg::{
+    activation::v1::client::{
+        xdg_activation_token_v1::{self, XdgActivationTokenV1},
+        xdg_activation_v1::XdgActivationV1,
+    },
+    shell::client::{
+        xdg_surface::{self, XdgSurface},
+        xdg_toplevel::{self, XdgToplevel},
+        xdg_wm_base::{self, XdgWmBase},
+    },
+};
+
+/// Size of toplevels the compositor leaves the size of to the client
+const DEFAULT_SIZE: (i32, i32) = (100, 100);
+
+/// Toplevel of a test client. It commits a buffer of the configured size right after
+/// acking every configure.
+pub struct Window {
+    pub surface: WlSurface,
+    _xdg_surface: XdgSurface,
+    pub toplevel: XdgToplevel,
+    /// Sizes of the toplevel configures in the order they came, `(0, 0)` leaves the size
+    /// to the client
+    pub configured_sizes: Vec<(i32, i32)>,
+}
+
+/// Objects and events of a test client
+#[derive(Default)]
+pub struct ClientState {
+    /// Name, interface and version of the globals the compositor announced
+    globals: Vec<(u32, String, u32)>,
+    compositor: Option<WlCompositor>,
+    subcompositor: Option<WlSubcompositor>,
+    shm: Option<WlShm>,
+    wm_base: Option<XdgWmBase>,
+    seat: Option<WlSeat>,
+    keyboard: Option<WlKeyboard>,
+    data_device_manager: Option<WlDataDeviceManager>,
+    data_device: Option<WlDataDevice>,
+    pub windows: Vec<Window>,
+    /// Mime types announced for each data offer
+    offers: Vec<(WlDataOffer, Vec<String>)>,
+    /// Offer of the current selection
+    selection: Option<WlDataOffer>,
+    /// Data served by each data source, by mime type
+    sources: Vec<(WlDataSource, HashMap<String, Vec<u8>>)>,
+    activation: Option<XdgActivationV1>,
+    /// Serial of the latest key event
+    pub key_serial: Option<u32>,
+    /// Activation tokens the compositor handed out
+    pub tokens: Vec<String>,
+}
+
+pub struct Client {
+    connection: Connection,
+    registry: WlRegistry,
+    event_queue: EventQueue<ClientState>,
+    qh: QueueHandle<ClientState>,
+    pub state: ClientState,
+}
+
+impl Client {
+    /// Client talking over `stream`, it knows the globals after the next roundtrip
+    pub fn new(stream: UnixStream) -> Self {
+        let connection = Connection::from_socket(stream).expect("Connection to the compositor");
+        let event_queue = connection.new_event_queue();
+        let qh = event_queue.handle();
+        let registry = connection.display().get_registry(&qh, ());
+        Self { connection, registry, event_queue, qh, state: ClientState::default() }
+    }
+
+    /// Sends the pending requests and handles the events that arrived meanwhile
+    pub fn dispatch(&mut self) {
+        self.connection.flush().expect("Flush requests");
+        let guard = self.connection.prepare_read().expect("Prepare reading events");
+        match guard.read() {
+            Ok(_) => {},
+            Err(WaylandError::Io(err)) if err.kind() == ErrorKind::WouldBlock => {},
+            Err(err) => panic!("Failed to read events: {}", err),
+        }
+        self.event_queue.dispatch_pending(&mut self.state).expect("Dispatch events");
+    }
+
+    /// Binds the global of `interface` in the version of `I`, or the announced one if older
+    fn bind<I: Proxy + 'static>(&self, interface: &str, max_version: u32) -> I
+    where
+        ClientState: Dispatch<I, ()>,
+    {
+        let &(name, _, version) = self.state.globals
+            .iter()
+            .find(|(_, announced, _)| announced == interface)
+            .unwrap_or_else(|| panic!("Global {} announced", interface));
+        self.registry.bind(name, version.min(max_version), &self.qh, ())
+    }
+
+    fn compositor(&mut self) -> WlCompositor {
+        if self.state.compositor.is_none() {
+            self.state.compositor = Some(self.bind("wl_compositor", 5));
+        }
+        self.state.compositor.clone().expect("Compositor bound")
+    }
+
+    fn seat(&mut self) -> WlSeat {
+        if self.state.seat.is_none() {
+            self.state.seat = Some(self.bind("wl_seat", 7));
+        }
+        self.state.seat.clone().expect("Seat bound")
+    }
+
+    fn ensure_shell(&mut self) {
+        if self.state.wm_base.is_none() {
+            self.state.wm_base = Some(self.bind("xdg_wm_base", 3));
+        }
+        if self.state.shm.is_none() {
+            self.state.shm = Some(self.bind("wl_shm", 1));
+        }
+    }
+
+    /// Creates a toplevel with `app_id` and commits it without a buffer, the buffer
+    /// follows with the ack of the initial configure. Returns the index of the window.
+    pub fn create_window(&mut self, app_id: &str) -> usize {
+        let compositor = self.compositor();
+        self.ensure_shell();
+        let index = self.state.windows.len();
+        let surface = compositor.create_surface(&self.qh, ());
+        let wm_base = self.state.wm_base.as_ref().expect("Shell bound");
+        let xdg_surface = wm_base.get_xdg_surface(&surface, &self.qh, index);
+        let toplevel = xdg_surface.get_toplevel(&self.qh, index);
+        toplevel.set_app_id(app_id.to_string());
+        surface.commit();
+        self.state.windows.push(Window {
+            surface,
+            _xdg_surface: xdg_surface,
+            toplevel,
+            configured_sizes: Vec::new(),
+        });
+        index
+    }
+
+    /// Adds a subsurface of `size` at `offset` to the window `index` and commits a buffer
+    /// to it. A synchronized subsurface and the position only apply with the next commit of
+    /// the window.
+    pub fn add_subsurface(&mut self, index: usize, offset: (i32, i32), size: (i32, i32), sync: bool) -> WlSurface {
+        let compositor = self.compositor();
+        if self.state.subcompositor.is_none() {
+            self.state.subcompositor = Some(self.bind("wl_subcompositor", 1));
+        }
+        let parent = &self.state.windows[index].surface;
+        let surface = compositor.create_surface(&self.qh, ());
+        let subcompositor = self.state.subcompositor.as_ref().expect("Subcompositor bound");
+        let subsurface: WlSubsurface = subcompositor.get_subsurface(&surface, parent, &self.qh, ());
+        subsurface.set_position(offset.0, offset.1);
+        if !sync {
+            subsurface.set_desync();
+        }
+        let buffer = self.state.buffer(size, &self.qh);
+        surface.attach(Some(&buffer), 0, 0);
+        surface.damage_buffer(0, 0, size.0, size.1);
+        surface.commit();
+        surface
+    }
+
+    /// Sets the minimum size of the window `index`, 0 leaves a dimension unconstrained
+    pub fn set_min_size(&mut self, index: usize, size: (i32, i32)) {
+        let window = &self.state.windows[index];
+        window.toplevel.set_min_size(size.0, size.1);
+        window.surface.commit();
+    }
+
+    /// Sets the maximum size of the window `index`, 0 leaves a dimension unconstrained
+    pub fn set_max_size(&mut self, index: usize, size: (i32, i32)) {
+        let window = &self.state.windows[index];
+        window.toplevel.set_max_size(size.0, size.1);
+        window.surface.commit();
+    }
+
+    /// Commits the surface of the window `index` without any new state
+    pub fn commit(&mut self, index: usize) {
+        self.state.windows[index].surface.commit();
+    }
+
+    /// Gets the data device of the seat, selections are only offered through it
+    pub fn get_data_device(&mut self) {
+        if self.state.data_device.is_some() {
+            return;
+        }
+        let seat = self.seat();
+        let manager: WlDataDeviceManager = self.bind("wl_data_device_manager", 3);
+        self.state.data_device = Some(manager.get_data_device(&seat, &self.qh, ()));
+        self.state.data_device_manager = Some(manager);
+    }
+
+    /// Sets a selection offering `contents` by mime type. The compositor only lets the
+    /// client with keyboard focus set it.
+    pub fn set_selection(&mut self, contents: &[(&str, &[u8])]) {
+        self.get_data_device();
+        let manager = self.state.data_device_manager.as_ref().expect("Data device manager bound");
+        let source = manager.create_data_source(&self.qh, ());
+        for (mime_type, _) in contents {
+            source.offer(mime_type.to_string());
+        }
+        let contents = contents
+            .iter()
+            .map(|(mime_type, content)| (mime_type.to_string(), content.to_vec()))
+            .collect();
+        let data_device = self.state.data_device.as_ref().expect("Data device available");
+        // The serial isn't checked against input, only the focus
+        data_device.set_selection(Some(&source), 0);
+        self.state.sources.push((source, contents));
+    }
+
+    /// Mime types of the current selection, `None` without selection
+    pub fn selection_mime_types(&self) -> Option<Vec<String>> {
+        let selection = self.state.selection.as_ref()?;
+        self.state.offers
+            .iter()
+            .find(|(offer, _)| offer == selection)
+            .map(|(_, mime_types)| mime_types.clone())
+    }
+
+    /// Asks for the selection as `mime_type`, its contents arrive through the returned
+    /// stream once the source client was dispatched
+    pub fn receive_selection(&mut self, mime_type: &str) -> UnixStream {
+        let selection = self.state.selection.as_ref().expect("Selection offered");
+        let (stream, source_stream) = UnixStream::pair().expect("Selection socket pair");
+        selection.receive(mime_type.to_string(), source_stream.as_raw_fd());
+        // The compositor got its own copy with the request
+        self.connection.flush().expect("Flush the receive request");
+        stream
+    }
+
+    /// Gets the keyboard of the seat, which records the serials of key events
+    pub fn get_keyboard(&mut self) {
+        if self.state.keyboard.is_none() {
+            let seat = self.seat();
+            self.state.keyboard = Some(seat.get_keyboard(&self.qh, ()));
+        }
+    }
+
+    /// Requests an activation token, created from the input event with `serial` if any.
+    /// The token is added to `tokens` once the compositor sent it.
+    pub fn request_token(&mut self, serial: Option<u32>) {
+        let seat = self.seat();
+        if self.state.activation.is_none() {
+            self.state.activation = Some(self.bind("xdg_activation_v1", 1));
+        }
+        let activation = self.state.activation.as_ref().expect("Activation bound");
+        let token = activation.get_activation_token(&self.qh, ());
+        if let Some(serial) = serial {
+            token.set_serial(serial, &seat);
+        }
+        token.commit();
+    }
+
+    /// Asks for the window `index` to be activated with `token`
+    pub fn activate(&mut self, index: usize, token: &str) {
+        if self.state.activation.is_none() {
+            self.state.activation = Some(self.bind("xdg_activation_v1", 1));
+        }
+        let activation = self.state.activation.as_ref().expect("Activation bound");
+        activation.activate(token.to_string(), &self.state.windows[index].surface);
+    }
+}
+
+/// Reads everything written to `stream` until the writer closed it
+pub fn read_to_end(mut stream: UnixStream) -> Vec<u8> {
+    // Fails the test instead of hanging it if a copy of the writing end stays open
+    stream.set_read_timeout(Some(Duration::from_secs(5))).expect("Set the read timeout");
+    let mut content = Vec::new();
+    stream.read_to_end(&mut content).expect("Read the transferred data");
+    content
+}
+
+impl ClientState {
+    /// Buffer of `size` in a new shared memory pool, filled with opaque white
+    fn buffer(&self, size: (i32, i32), qh: &QueueHandle<Self>) -> WlBuffer {
+        static FILES: AtomicUsize = AtomicUsize::new(0);
+        let stride = size.0 * 4;
+        let length = stride * size.1;
+        let path = std::env::temp_dir().join(format!(
+            "twm-test-{}-{}",
+            std::process::id(),
+            FILES.fetch_add(1, Ordering::Relaxed)));
+        let mut file: File = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .expect("Shared memory file");
+        std::fs::remove_file(&path).expect("Unlink the shared memory file");
+        file.write_all(&vec![0xff; length as usize]).expect("Fill the shared memory file");
+
+        let shm = self.shm.as_ref().expect("Shm bound");
+        let pool: WlShmPool = shm.create_pool(file.as_raw_fd(), length, qh, ());
+        let buffer = pool.create_buffer(0, size.0, size.1, stride, wl_shm::Format::Argb8888, qh, ());
+        pool.destroy();
+        buffer
+    }
+}
+
+impl Dispatch<WlRegistry, ()> for ClientState {
+    fn event(state: &mut Self,
+             _registry: &WlRegistry,
+             event: wl_registry::Event,
+             _data: &(),
+             _conn: &Connection,
+             _qh: &QueueHandle<Self>) {
+        match event {
+            wl_registry::Event::Global { name, interface, version } => {
+                state.globals.push((name, interface, version));
+            },
+            wl_registry::Event::GlobalRemove { name } => {
+                state.globals.retain(|(global, _, _)| *global != name);
+            },
+            _ => {},
+        }
+    }
+}
+
+impl Dispatch<XdgWmBase, ()> for ClientState {
+    fn event(_state: &mut Self,
+             wm_base: &XdgWmBase,
+             event: xdg_wm_base::Event,
+             _data: &(),
+             _conn: &Connection,
+             _qh: &QueueHandle<Self>) {
+        if let xdg_wm_base::Event::Ping { serial } = event {
+            wm_base.pong(serial);
+        }
+    }
+}
+
+impl Dispatch<XdgSurface, usize> for ClientState {
+    fn event(state: &mut Self,
+             xdg_surface: &XdgSurface,
+             event: xdg_surface::Event,
+             index: &usize,
+             _conn: &Connection,
+             qh: &QueueHandle<Self>) {
+        if let xdg_surface::Event::Configure { serial } = event {
+            xdg_surface.ack_configure(serial);
+            let size = match state.windows[*index].configured_sizes.last() {
+                Some(&(w, h)) if w > 0 && h > 0 => (w, h),
+                _ => DEFAULT_SIZE,
+            };
+            let buffer = state.buffer(size, qh);
+            let surface = &state.windows[*index].surface;
+            surface.attach(Some(&buffer), 0, 0);
+            surface.damage_buffer(0, 0, size.0, size.1);
+            surface.commit();
+        }
+    }
+}
+
+impl Dispatch<XdgToplevel, usize> for ClientState {
+    fn event(state: &mut Self,
+             _toplevel: &XdgToplevel,
+             event: xdg_toplevel::Event,
+             index: &usize,
+             _conn: &Connection,
+             _qh: &QueueHandle<Self>) {
+        if let xdg_toplevel::Event::Configure { width, height, .. } = event {
+            state.windows[*index].configured_sizes.push((width, height));
+        }
+    }
+}
+
+impl Dispatch<WlDataDevice, ()> for ClientState {
+    fn event(state: &mut Self,
+             _data_device: &WlDataDevice,
+             event: wl_data_device::Event,
+             _data: &(),
+             _conn: &Connection,
+             _qh: &QueueHandle<Self>) {
+        match event {
+            wl_data_device::Event::DataOffer { id } => state.offers.push((id, Vec::new())),
+            wl_data_device::Event::Selection { id } => state.selection = id,
+            _ => {},
+        }
+    }
+
+    event_created_child!(ClientState, WlDataDevice, [
+        wl_data_device::EVT_DATA_OFFER_OPCODE => (WlDataOffer, ()),
+    ]);
+}
+
+impl Dispatch<WlDataOffer, ()> for ClientState {
+    fn event(state: &mut Self,
+             offer: &WlDataOffer,
+             event: wl_data_offer::Event,
+             _data: &(),
+             _conn: &Connection,
+             _qh: &QueueHandle<Self>) {
+        if let wl_data_offer::Event::Offer { mime_type } = event {
+            if let Some((_, mime_types)) = state.offers.iter_mut().find(|(known, _)| known == offer) {
+                mime_types.push(mime_type);
+            }
+        }
+    }
+}
+
+impl Dispatch<WlDataSource, ()> for ClientState {
+    fn event(state: &mut Self,
+             source: &WlDataSource,
+             event: wl_data_source::Event,
+             _data: &(),
+             _conn: &Connection,
+             _qh: &QueueHandle<Self>) {
+        if let wl_data_source::Event::Send { mime_type, fd } = event {
+            let content = state.sources
+                .iter()
+                .find(|(known, _)| known == source)
+                .and_then(|(_, contents)| contents.get(&mime_type))
+                .cloned()
+                .unwrap_or_default();
+            let mut file = File::from(OwnedFd::from(fd));
+            file.write_all(&content).expect("Write the selection");
+        }
+    }
+}
+
+impl Dispatch<WlKeyboard, ()> for ClientState {
+    fn event(state: &mut Self,
+             _keyboard: &WlKeyboard,
+             event: wl_keyboard::Event,
+             _data: &(),
+             _conn: &Connection,
+             _qh: &QueueHandle<Self>) {
+        if let wl_keyboard::Event::Key { serial, .. } = event {
+            state.key_serial = Some(serial);
+        }
+    }
+}
+
+impl Dispatch<XdgActivationTokenV1, ()> for ClientState {
+    fn event(state: &mut Self,
+             _token: &XdgActivationTokenV1,
+             event: xdg_activation_token_v1::Event,
+             _data: &(),
+             _conn: &Connection,
+             _qh: &QueueHandle<Self>) {
+        if let xdg_activation_token_v1::Event::Done { token } = event {
+            state.tokens.push(token);
+        }
+    }
+}
+
+delegate_noop!(ClientState: WlCompositor);
+delegate_noop!(ClientState: WlSubcompositor);
+delegate_noop!(ClientState: WlSubsurface);
+delegate_noop!(ClientState: WlShmPool);
+delegate_noop!(ClientState: WlDataDeviceManager);
+delegate_noop!(ClientState: XdgActivationV1);
+delegate_noop!(ClientState: ignore WlSurface);
+delegate_noop!(ClientState: ignore WlShm);
+delegate_noop!(ClientState: ignore WlBuffer);
+delegate_noop!(ClientState: ignore WlSeat);
@@ -0,0 +1,41 @@
+use super::{client::read_to_end, fixture::Fixture};
+
+const PLAIN: &[u8] = b"copied text";
+const HTML: &[u8] = b"<b>copied</b> text";
+
+#[test]
+fn selection_transfers_in_every_mime_type() {
+    let mut fixture = Fixture::new();
+    let source = fixture.add_client();
+    fixture.map_window(source, "source");
+    fixture.client(source).set_selection(&[("text/plain", PLAIN), ("text/html", HTML)]);
+    fixture.dispatch();
+
+    // The selection is offered to the client getting the focus
+    let target = fixture.add_client();
+    fixture.client(target).get_data_device();
+    fixture.map_window(target, "target");
+    let window = fixture.window("target");
+    fixture.state().focus_window(Some(&window));
+    fixture.dispatch();
+    let mime_types = fixture.client(target).selection_mime_types().expect("Selection offered");
+    assert_eq!(mime_types, ["text/plain", "text/html"]);
+
+    for (mime_type, content) in [("text/plain", PLAIN), ("text/html", HTML)] {
+        let stream = fixture.client(target).receive_selection(mime_type);
+        fixture.dispatch();
+        assert_eq!(read_to_end(stream), content, "Selection as {}", mime_type);
+    }
+}
+
+#[test]
+fn selection_of_unfocused_client_is_ignored() {
+    let mut fixture = Fixture::new();
+    let focused = fixture.add_client();
+    fixture.map_window(focused, "focused");
+    let other = fixture.add_client();
+    fixture.client(other).set_selection(&[("text/plain", PLAIN)]);
+    fixture.client(focused).get_data_device();
+    fixture.dispatch();
+    assert_eq!(fixture.client(focused).selection_mime_types(), None);
+}
@@ -0,0 +1,49 @@
+use smithay::reexports::wayland_protocols::xdg::shell::server::xdg_toplevel::State;
+
+use super::fixture::{Fixture, OUTPUT_SIZE};
+
+#[test]
+fn first_configure_has_tile_size() {
+    let mut fixture = Fixture::new();
+    let client = fixture.add_client();
+    let window = fixture.map_window(client, "window");
+
+    // The whole output inset by the default border width of 2
+    let tile = (OUTPUT_SIZE.0 - 4, OUTPUT_SIZE.1 - 4);
+    let sizes = &fixture.client(client).state.windows[window].configured_sizes;
+    assert_eq!(sizes.first(), Some(&tile));
+}
+
+#[test]
+fn tiled_windows_are_resized_by_new_windows() {
+    let mut fixture = Fixture::new();
+    let client = fixture.add_client();
+    let first = fixture.map_window(client, "first");
+    let second = fixture.map_window(client, "second");
+
+    let half = (OUTPUT_SIZE.0 / 2 - 4, OUTPUT_SIZE.1 - 4);
+    assert_eq!(fixture.client(client).state.windows[first].configured_sizes.last(), Some(&half));
+    assert_eq!(fixture.client(client).state.windows[second].configured_sizes.first(), Some(&half));
+}
+
+#[test]
+fn leaving_fullscreen_keeps_maximize() {
+    let mut fixture = Fixture::new();
+    let client = fixture.add_client();
+    let window = fixture.map_window(client, "window");
+
+    fixture.client(client).state.windows[window].toplevel.set_maximized();
+    fixture.dispatch();
+    fixture.client(client).state.windows[window].toplevel.set_fullscreen(None);
+    fixture.dispatch();
+    let states = fixture.window("window").toplevel().current_state().states;
+    assert!(states.contains(State::Maximized));
+    assert!(states.contains(State::Fullscreen));
+
+    fixture.client(client).state.windows[window].toplevel.unset_fullscreen();
+    fixture.dispatch();
+    let states = fixture.window("window").toplevel().current_state().states;
+    assert!(states.contains(State::Maximized));
+    assert!(!states.contains(State::Fullscreen));
+    assert_eq!(fixture.client(client).state.windows[window].configured_sizes.last(), Some(&OUTPUT_SIZE));
+}
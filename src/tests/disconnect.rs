@@ -0,0 +1,36 @@
+use super::fixture::Fixture;
+
+#[test]
+fn windows_of_a_disconnected_client_are_removed() {
+    let mut fixture = Fixture::new();
+    let survivor = fixture.add_client();
+    let crashing = fixture.add_client();
+    fixture.map_window(survivor, "survivor");
+    fixture.map_window(crashing, "first");
+    fixture.map_window(crashing, "second");
+    // New windows of another client don't steal the focus
+    let second = fixture.window("second");
+    fixture.state().focus_window(Some(&second));
+
+    fixture.disconnect_client(crashing);
+    fixture.dispatch();
+    assert!(fixture.find_window("first").is_none());
+    assert!(fixture.find_window("second").is_none());
+    assert_eq!(fixture.state().space.elements().count(), 1);
+    // Focus passes on to the remaining window
+    assert_eq!(fixture.focused_app_id().as_deref(), Some("survivor"));
+}
+
+#[test]
+fn compositor_keeps_running_after_a_disconnect() {
+    let mut fixture = Fixture::new();
+    let crashing = fixture.add_client();
+    fixture.map_window(crashing, "crashing");
+    fixture.disconnect_client(crashing);
+    fixture.dispatch();
+
+    let client = fixture.add_client();
+    fixture.map_window(client, "next");
+    assert!(fixture.geometry("next").is_some());
+    assert_eq!(fixture.focused_app_id().as_deref(), Some("next"));
+}
@@ -0,0 +1,139 @@
+//! Compositor on the headless backend with test clients connected to it, everything
+//! dispatched from the test's thread.
+
+use std::os::unix::net::UnixStream;
+use std::sync::Arc;
+use std::time::Duration;
+
+use smithay::{
+    backend::input::KeyState,
+    desktop::Window,
+    reexports::{calloop::EventLoop, wayland_server::Display},
+    utils::{Logical, Rectangle},
+};
+
+use crate::{backend::headless, config::Config, TwmClientState, TwmLoopData, TwmState};
+
+use super::client::Client;
+
+/// Size of the virtual output of the tests
+pub const OUTPUT_SIZE: (i32, i32) = (1920, 1080);
+/// Dispatch rounds of `Fixture::dispatch`, enough for requests, the events they lead to and
+/// the requests answering those to get through
+const ROUNDS: usize = 8;
+
+pub struct Fixture {
+    event_loop: EventLoop<'static, TwmLoopData>,
+    data: TwmLoopData,
+    /// Connected clients, `None` once disconnected so the indices stay the same
+    clients: Vec<Option<Client>>,
+}
+
+impl Fixture {
+    pub fn new() -> Self {
+        Self::with_config(Config::default())
+    }
+
+    /// Compositor with `config` and a single output of `OUTPUT_SIZE`
+    pub fn with_config(config: Config) -> Self {
+        let mut display: Display<TwmState> = Display::new().expect("Wayland display");
+        let mut event_loop: EventLoop<TwmLoopData> = EventLoop::try_new().expect("Event loop");
+        let mut state = TwmState::new(&mut event_loop, &mut display, config).expect("Compositor state");
+        let backend = headless::init(&mut event_loop, &mut display, &mut state, OUTPUT_SIZE)
+            .expect("Headless backend");
+        state.add_keyboard().expect("Keyboard");
+        state.seat.add_pointer();
+        Self {
+            event_loop,
+            data: TwmLoopData { display, state, backend },
+            clients: Vec::new(),
+        }
+    }
+
+    pub fn state(&mut self) -> &mut TwmState {
+        &mut self.data.state
+    }
+
+    /// Connects a new client and returns its index, its globals are known right away
+    pub fn add_client(&mut self) -> usize {
+        let (client_stream, server_stream) = UnixStream::pair().expect("Client socket pair");
+        self.data.display
+            .handle()
+            .insert_client(server_stream, Arc::new(TwmClientState::default()))
+            .expect("Insert client");
+        self.clients.push(Some(Client::new(client_stream)));
+        self.dispatch();
+        self.clients.len() - 1
+    }
+
+    pub fn client(&mut self, index: usize) -> &mut Client {
+        self.clients[index].as_mut().expect("Client connected")
+    }
+
+    /// Disconnects the client `index` like a crashing client, its index stays taken
+    pub fn disconnect_client(&mut self, index: usize) {
+        self.clients[index] = None;
+    }
+
+    /// Lets the clients and the compositor handle everything pending, as the main loop
+    /// would
+    pub fn dispatch(&mut self) {
+        for _ in 0..ROUNDS {
+            for client in self.clients.iter_mut().flatten() {
+                client.dispatch();
+            }
+            if let Err(err) = self.data.display.dispatch_clients(&mut self.data.state) {
+                tracing::warn!("Failed to dispatch clients: {}", err);
+                self.data.state.remove_dead_windows();
+            }
+            self.event_loop
+                .dispatch(Some(Duration::ZERO), &mut self.data)
+                .expect("Dispatch the event loop");
+            self.data.state.space.refresh();
+            self.data.state.popups.cleanup();
+            self.data.state.refresh_foreign_toplevels();
+            self.data.backend.render_if_needed(&mut self.data.state);
+            self.data.display.flush_clients().expect("Flush clients");
+        }
+    }
+
+    /// Presses and releases the key with the evdev `keycode`, like a physical keyboard
+    pub fn tap_key(&mut self, keycode: u32) {
+        self.data.state.handle_key(keycode, KeyState::Pressed, 0);
+        self.data.state.handle_key(keycode, KeyState::Released, 0);
+        self.dispatch();
+    }
+
+    /// Compositor side of the window with `app_id`, on any workspace
+    pub fn window(&self, app_id: &str) -> Window {
+        self.find_window(app_id).unwrap_or_else(|| panic!("Window {} known to the compositor", app_id))
+    }
+
+    /// Like `window`, `None` if the compositor doesn't know the window (anymore)
+    pub fn find_window(&self, app_id: &str) -> Option<Window> {
+        self.data.state
+            .all_windows()
+            .find(|window| self.data.state.window_info(window).app_id.as_deref() == Some(app_id))
+            .cloned()
+    }
+
+    /// App id of the window with keyboard focus
+    pub fn focused_app_id(&self) -> Option<String> {
+        let window = self.data.state.focused_window()?;
+        self.data.state.window_info(&window).app_id
+    }
+
+    /// Geometry of the shown window with `app_id` in the space, `None` while it is hidden
+    pub fn geometry(&self, app_id: &str) -> Option<Rectangle<i32, Logical>> {
+        let window = self.window(app_id);
+        self.data.state.space.element_geometry(&window)
+    }
+
+    /// Maps a window with `app_id` for the client `index` and waits until it is shown with
+    /// the size it was configured with
+    pub fn map_window(&mut self, index: usize, app_id: &str) -> usize {
+        let window = self.client(index).create_window(app_id);
+        self.dispatch();
+        window
+    }
+}
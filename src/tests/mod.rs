@@ -0,0 +1,12 @@
+//! End-to-end tests running the compositor on the headless backend with test clients.
+
+mod activation;
+mod client;
+mod clipboard;
+mod configure;
+mod disconnect;
+mod fixture;
+mod size_hints;
+mod subsurface;
+mod swap;
+mod workspace;
@@ -0,0 +1,48 @@
+use smithay::utils::Rectangle;
+
+use super::fixture::Fixture;
+
+#[test]
+fn stack_window_gets_its_min_height() {
+    let mut fixture = Fixture::new();
+    let client = fixture.add_client();
+    fixture.map_window(client, "first");
+    let second = fixture.map_window(client, "second");
+    let third = fixture.map_window(client, "third");
+
+    fixture.client(client).set_min_size(third, (0, 700));
+    fixture.dispatch();
+    // The other stack window takes the rest of the height
+    assert_eq!(fixture.geometry("second"), Some(Rectangle::from_loc_and_size((962, 2), (956, 372))));
+    assert_eq!(fixture.geometry("third"), Some(Rectangle::from_loc_and_size((962, 378), (956, 700))));
+    assert_eq!(fixture.client(client).state.windows[second].configured_sizes.last(), Some(&(956, 372)));
+    assert_eq!(fixture.client(client).state.windows[third].configured_sizes.last(), Some(&(956, 700)));
+}
+
+#[test]
+fn window_larger_than_its_tile_floats() {
+    let mut fixture = Fixture::new();
+    let client = fixture.add_client();
+    fixture.map_window(client, "first");
+    let second = fixture.map_window(client, "second");
+
+    fixture.client(client).set_min_size(second, (1900, 0));
+    fixture.dispatch();
+    let window = fixture.window("second");
+    assert!(fixture.state().is_floating(&window));
+    // Centered at its minimum width, above the remaining tiled window
+    assert_eq!(fixture.geometry("second"), Some(Rectangle::from_loc_and_size((10, 2), (1900, 1076))));
+    assert_eq!(fixture.geometry("first"), Some(Rectangle::from_loc_and_size((2, 2), (1916, 1076))));
+}
+
+#[test]
+fn window_is_shrunk_to_its_max_size() {
+    let mut fixture = Fixture::new();
+    let client = fixture.add_client();
+    let window = fixture.map_window(client, "window");
+
+    fixture.client(client).set_max_size(window, (400, 300));
+    fixture.dispatch();
+    // Centered in its tile
+    assert_eq!(fixture.geometry("window"), Some(Rectangle::from_loc_and_size((760, 390), (400, 300))));
+}
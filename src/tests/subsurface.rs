@@ -0,0 +1,54 @@
+use smithay::{
+    reexports::wayland_server::Resource,
+    utils::{Logical, Point},
+};
+use wayland_client::Proxy;
+
+use super::fixture::Fixture;
+
+/// Protocol id of the surface under `position` and its location
+fn surface_under(fixture: &mut Fixture, position: Point<i32, Logical>) -> Option<(u32, Point<i32, Logical>)> {
+    fixture.state()
+        .surface_under(position.to_f64())
+        .map(|(surface, location)| (surface.id().protocol_id(), location))
+}
+
+#[test]
+fn surface_under_finds_subsurface() {
+    let mut fixture = Fixture::new();
+    let client = fixture.add_client();
+    let window = fixture.map_window(client, "parent");
+    let origin = fixture.geometry("parent").expect("Window shown").loc;
+
+    let subsurface = fixture.client(client).add_subsurface(window, (10, 20), (50, 40), false);
+    fixture.client(client).commit(window);
+    fixture.dispatch();
+
+    let parent = fixture.client(client).state.windows[window].surface.id().protocol_id();
+    let subsurface = subsurface.id().protocol_id();
+    let subsurface_origin = origin + Point::from((10, 20));
+    assert_eq!(surface_under(&mut fixture, subsurface_origin + Point::from((25, 20))), Some((subsurface, subsurface_origin)));
+    assert_eq!(surface_under(&mut fixture, subsurface_origin + Point::from((49, 39))), Some((subsurface, subsurface_origin)));
+    // Next to the subsurface the window itself is hit
+    assert_eq!(surface_under(&mut fixture, subsurface_origin + Point::from((50, 39))), Some((parent, origin)));
+    assert_eq!(surface_under(&mut fixture, origin + Point::from((5, 5))), Some((parent, origin)));
+}
+
+#[test]
+fn sync_subsurface_applies_with_its_parent() {
+    let mut fixture = Fixture::new();
+    let client = fixture.add_client();
+    let window = fixture.map_window(client, "parent");
+    let origin = fixture.geometry("parent").expect("Window shown").loc;
+    let parent = fixture.client(client).state.windows[window].surface.id().protocol_id();
+
+    let subsurface = fixture.client(client).add_subsurface(window, (10, 20), (50, 40), true);
+    fixture.dispatch();
+    let position = origin + Point::from((30, 30));
+    assert_eq!(surface_under(&mut fixture, position), Some((parent, origin)));
+
+    fixture.client(client).commit(window);
+    fixture.dispatch();
+    let subsurface = subsurface.id().protocol_id();
+    assert_eq!(surface_under(&mut fixture, position), Some((subsurface, origin + Point::from((10, 20)))));
+}
@@ -0,0 +1,63 @@
+use smithay::utils::Rectangle;
+
+use super::fixture::Fixture;
+use crate::action::Action;
+
+// Tiles of three windows on the 1920x1080 output without gaps, inset by the default
+// border width of 2
+const MASTER: (i32, i32, i32, i32) = (2, 2, 956, 1076);
+const STACK_TOP: (i32, i32, i32, i32) = (962, 2, 956, 536);
+const STACK_BOTTOM: (i32, i32, i32, i32) = (962, 542, 956, 536);
+
+fn assert_tile(fixture: &Fixture, app_id: &str, (x, y, w, h): (i32, i32, i32, i32)) {
+    assert_eq!(fixture.geometry(app_id), Some(Rectangle::from_loc_and_size((x, y), (w, h))), "Tile of {}", app_id);
+}
+
+fn three_windows() -> Fixture {
+    let mut fixture = Fixture::new();
+    let client = fixture.add_client();
+    for app_id in ["first", "second", "third"] {
+        fixture.map_window(client, app_id);
+    }
+    assert_tile(&fixture, "first", MASTER);
+    assert_tile(&fixture, "second", STACK_TOP);
+    assert_tile(&fixture, "third", STACK_BOTTOM);
+    fixture
+}
+
+#[test]
+fn swap_with_master() {
+    let mut fixture = three_windows();
+    assert_eq!(fixture.focused_app_id().as_deref(), Some("third"));
+
+    fixture.state().handle_action(Action::SwapWithMaster);
+    fixture.dispatch();
+    assert_tile(&fixture, "third", MASTER);
+    assert_tile(&fixture, "second", STACK_TOP);
+    assert_tile(&fixture, "first", STACK_BOTTOM);
+    assert_eq!(fixture.focused_app_id().as_deref(), Some("third"));
+}
+
+#[test]
+fn swap_next_wraps_around() {
+    let mut fixture = three_windows();
+
+    fixture.state().handle_action(Action::SwapNext);
+    fixture.dispatch();
+    assert_tile(&fixture, "third", MASTER);
+    assert_tile(&fixture, "second", STACK_TOP);
+    assert_tile(&fixture, "first", STACK_BOTTOM);
+    assert_eq!(fixture.focused_app_id().as_deref(), Some("third"));
+}
+
+#[test]
+fn swap_prev() {
+    let mut fixture = three_windows();
+
+    fixture.state().handle_action(Action::SwapPrev);
+    fixture.dispatch();
+    assert_tile(&fixture, "first", MASTER);
+    assert_tile(&fixture, "third", STACK_TOP);
+    assert_tile(&fixture, "second", STACK_BOTTOM);
+    assert_eq!(fixture.focused_app_id().as_deref(), Some("third"));
+}
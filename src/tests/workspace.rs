@@ -0,0 +1,86 @@
+use smithay::utils::Point;
+
+use super::fixture::Fixture;
+use crate::action::Action;
+
+#[test]
+fn switching_hides_and_shows_windows() {
+    let mut fixture = Fixture::new();
+    let client = fixture.add_client();
+    fixture.map_window(client, "first");
+    fixture.map_window(client, "second");
+    assert!(fixture.geometry("first").is_some());
+    assert!(fixture.geometry("second").is_some());
+
+    fixture.state().handle_action(Action::SwitchWorkspace(2));
+    fixture.dispatch();
+    assert_eq!(fixture.geometry("first"), None);
+    assert_eq!(fixture.geometry("second"), None);
+    assert_eq!(fixture.focused_app_id(), None);
+
+    fixture.map_window(client, "third");
+    assert_eq!(fixture.focused_app_id().as_deref(), Some("third"));
+
+    fixture.state().handle_action(Action::SwitchWorkspace(1));
+    fixture.dispatch();
+    assert!(fixture.geometry("first").is_some());
+    assert!(fixture.geometry("second").is_some());
+    assert_eq!(fixture.geometry("third"), None);
+}
+
+#[test]
+fn switching_back_restores_focus() {
+    let mut fixture = Fixture::new();
+    let client = fixture.add_client();
+    fixture.map_window(client, "first");
+    fixture.map_window(client, "second");
+    let first = fixture.window("first");
+    fixture.state().focus_window(Some(&first));
+
+    fixture.state().handle_action(Action::SwitchWorkspace(2));
+    fixture.dispatch();
+    fixture.state().handle_action(Action::SwitchWorkspace(1));
+    fixture.dispatch();
+    assert_eq!(fixture.focused_app_id().as_deref(), Some("first"));
+}
+
+#[test]
+fn moved_window_shows_on_its_new_workspace() {
+    let mut fixture = Fixture::new();
+    let client = fixture.add_client();
+    fixture.map_window(client, "first");
+    fixture.map_window(client, "second");
+
+    fixture.state().handle_action(Action::MoveWindowToWorkspace(3));
+    fixture.dispatch();
+    assert_eq!(fixture.geometry("second"), None);
+    // The remaining window takes the whole output
+    let first = fixture.geometry("first").expect("First window shown");
+    let second = fixture.window("second");
+    assert_eq!(fixture.state().workspace_index(&second), Some((0, 2)));
+
+    fixture.state().handle_action(Action::SwitchWorkspace(3));
+    fixture.dispatch();
+    assert_eq!(fixture.geometry("first"), None);
+    assert_eq!(fixture.geometry("second"), Some(first));
+    assert_eq!(fixture.focused_app_id().as_deref(), Some("second"));
+}
+
+#[test]
+fn floating_window_keeps_its_location() {
+    let mut fixture = Fixture::new();
+    let client = fixture.add_client();
+    fixture.map_window(client, "tiled");
+    fixture.map_window(client, "floating");
+    let window = fixture.window("floating");
+    fixture.state().set_floating(&window, true);
+    fixture.state().space.map_element(window.clone(), (300, 200), true);
+    fixture.dispatch();
+
+    fixture.state().handle_action(Action::SwitchWorkspace(2));
+    fixture.dispatch();
+    assert_eq!(fixture.state().space.element_location(&window), None);
+    fixture.state().handle_action(Action::SwitchWorkspace(1));
+    fixture.dispatch();
+    assert_eq!(fixture.state().space.element_location(&window), Some(Point::from((300, 200))));
+}
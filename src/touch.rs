@@ -0,0 +1,71 @@
+//! Touchscreen input forwarded to the surfaces under the touch points.
+//!
+//! Every touch point keeps sending to the surface it went down on until it is lifted, the
+//! seat tracks them by slot. Touching a window focuses and raises it like a click.
+
+use smithay::{
+    backend::input::{Event, InputBackend, TouchEvent},
+    utils::SERIAL_COUNTER,
+};
+use tracing::trace;
+
+use crate::TwmState;
+
+impl TwmState {
+    pub fn on_touch_down<I: InputBackend>(&mut self, event: I::TouchDownEvent) {
+        let Some(touch) = self.seat.get_touch() else {
+            return;
+        };
+        let Some(position) = self.absolute_position(&event) else {
+            return;
+        };
+
+        if !self.is_locked() {
+            if let Some(window) = self.space.element_under(position).map(|(window, _)| window.clone()) {
+                self.focus_window(Some(&window));
+            }
+        }
+
+        let Some((surface, surface_location)) = self.surface_under(position) else {
+            trace!("Touch down without surface");
+            return;
+        };
+        touch.down(
+            SERIAL_COUNTER.next_serial(),
+            event.time_msec(),
+            &surface,
+            surface_location,
+            event.slot(),
+            position);
+    }
+
+    pub fn on_touch_motion<I: InputBackend>(&mut self, event: I::TouchMotionEvent) {
+        let Some(touch) = self.seat.get_touch() else {
+            return;
+        };
+        let Some(position) = self.absolute_position(&event) else {
+            return;
+        };
+        touch.motion(event.time_msec(), event.slot(), position);
+    }
+
+    pub fn on_touch_up<I: InputBackend>(&mut self, event: I::TouchUpEvent) {
+        if let Some(touch) = self.seat.get_touch() {
+            touch.up(SERIAL_COUNTER.next_serial(), event.time_msec(), event.slot());
+        }
+    }
+
+    /// Ends a set of touch events that belong together
+    pub fn on_touch_frame(&mut self) {
+        if let Some(touch) = self.seat.get_touch() {
+            touch.frame();
+        }
+    }
+
+    /// The touchscreen took all touch points away, e.g. for a gesture of its own
+    pub fn on_touch_cancel(&mut self) {
+        if let Some(touch) = self.seat.get_touch() {
+            touch.cancel();
+        }
+    }
+}
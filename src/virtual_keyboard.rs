@@ -0,0 +1,172 @@
+//! The virtual-keyboard-v1 protocol used by on-screen keyboards like wvkbd.
+//!
+//! Keys of virtual keyboards take the same path as those of physical keyboards, so they
+//! trigger keybindings too. Keycodes are interpreted with the keymap of the seat, the
+//! keymap a virtual keyboard has to send is only checked for. Modifier changes are turned
+//! into presses and releases of the left modifier keys.
+
+use smithay::{
+    backend::input::KeyState,
+    reexports::{
+        wayland_protocols_misc::zwp_virtual_keyboard_v1::server::{
+            zwp_virtual_keyboard_manager_v1::{self, ZwpVirtualKeyboardManagerV1},
+            zwp_virtual_keyboard_v1::{self, ZwpVirtualKeyboardV1},
+        },
+        wayland_server::{
+            backend::{ClientId, GlobalId, ObjectId},
+            Client,
+            DataInit,
+            Dispatch,
+            DisplayHandle,
+            GlobalDispatch,
+            New,
+            Resource,
+        },
+    },
+};
+use tracing::debug;
+
+use crate::TwmState;
+
+/// Modifier masks of the core keymap and the evdev keycodes of the keys setting them
+const MODIFIER_KEYS: [(u32, u32); 4] = [
+    (1 << 0, 42), // Shift, KEY_LEFTSHIFT
+    (1 << 2, 29), // Control, KEY_LEFTCTRL
+    (1 << 3, 56), // Mod1, KEY_LEFTALT
+    (1 << 6, 125), // Mod4, KEY_LEFTMETA
+];
+
+/// Keyboard created by a client
+#[derive(Debug)]
+struct VirtualKeyboard {
+    keyboard: ZwpVirtualKeyboardV1,
+    has_keymap: bool,
+    /// Keys pressed through the keyboard, released when it goes away
+    pressed: Vec<u32>,
+    /// Depressed and latched modifiers last sent
+    modifiers: u32,
+}
+
+#[derive(Debug)]
+pub struct VirtualKeyboardState {
+    _global: GlobalId,
+    keyboards: Vec<VirtualKeyboard>,
+}
+
+impl VirtualKeyboardState {
+    pub fn new(display_handle: &DisplayHandle) -> Self {
+        Self {
+            _global: display_handle.create_global::<TwmState, ZwpVirtualKeyboardManagerV1, _>(1, ()),
+            keyboards: Vec::new(),
+        }
+    }
+}
+
+impl TwmState {
+    fn virtual_keyboard_key(&mut self, keyboard: &ZwpVirtualKeyboardV1, keycode: u32, key_state: KeyState, time: u32) {
+        let Some(virtual_keyboard) = self.virtual_keyboards.keyboards.iter_mut().find(|k| &k.keyboard == keyboard) else {
+            return;
+        };
+        if !virtual_keyboard.has_keymap {
+            keyboard.post_error(zwp_virtual_keyboard_v1::Error::NoKeymap, "No keymap was set");
+            return;
+        }
+
+        match key_state {
+            KeyState::Pressed if !virtual_keyboard.pressed.contains(&keycode) => virtual_keyboard.pressed.push(keycode),
+            KeyState::Released if virtual_keyboard.pressed.contains(&keycode) => virtual_keyboard.pressed.retain(|&k| k != keycode),
+            // Repeated presses and releases of keys that aren't pressed would confuse the seat
+            _ => return,
+        }
+        self.notify_activity();
+        self.handle_key(keycode, key_state, time);
+    }
+
+    fn virtual_keyboard_modifiers(&mut self, keyboard: &ZwpVirtualKeyboardV1, modifiers: u32) {
+        let Some(virtual_keyboard) = self.virtual_keyboards.keyboards.iter_mut().find(|k| &k.keyboard == keyboard) else {
+            return;
+        };
+        if !virtual_keyboard.has_keymap {
+            keyboard.post_error(zwp_virtual_keyboard_v1::Error::NoKeymap, "No keymap was set");
+            return;
+        }
+
+        let changed = virtual_keyboard.modifiers ^ modifiers;
+        virtual_keyboard.modifiers = modifiers;
+        let time = self.start_time.elapsed().as_millis() as u32;
+        for (mask, keycode) in MODIFIER_KEYS.into_iter().filter(|(mask, _)| changed & mask != 0) {
+            let key_state = if modifiers & mask != 0 { KeyState::Pressed } else { KeyState::Released };
+            self.virtual_keyboard_key(keyboard, keycode, key_state, time);
+        }
+    }
+}
+
+impl GlobalDispatch<ZwpVirtualKeyboardManagerV1, ()> for TwmState {
+    fn bind(_state: &mut Self,
+            _handle: &DisplayHandle,
+            _client: &Client,
+            resource: New<ZwpVirtualKeyboardManagerV1>,
+            _global_data: &(),
+            data_init: &mut DataInit<'_, Self>) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<ZwpVirtualKeyboardManagerV1, ()> for TwmState {
+    fn request(state: &mut Self,
+               _client: &Client,
+               _resource: &ZwpVirtualKeyboardManagerV1,
+               request: zwp_virtual_keyboard_manager_v1::Request,
+               _data: &(),
+               _handle: &DisplayHandle,
+               data_init: &mut DataInit<'_, Self>) {
+        if let zwp_virtual_keyboard_manager_v1::Request::CreateVirtualKeyboard { seat: _, id } = request {
+            debug!("New virtual keyboard");
+            let keyboard = data_init.init(id, ());
+            state.virtual_keyboards.keyboards.push(VirtualKeyboard {
+                keyboard,
+                has_keymap: false,
+                pressed: Vec::new(),
+                modifiers: 0,
+            });
+        }
+    }
+}
+
+impl Dispatch<ZwpVirtualKeyboardV1, ()> for TwmState {
+    fn request(state: &mut Self,
+               _client: &Client,
+               resource: &ZwpVirtualKeyboardV1,
+               request: zwp_virtual_keyboard_v1::Request,
+               _data: &(),
+               _handle: &DisplayHandle,
+               _data_init: &mut DataInit<'_, Self>) {
+        match request {
+            zwp_virtual_keyboard_v1::Request::Keymap { .. } => {
+                if let Some(keyboard) = state.virtual_keyboards.keyboards.iter_mut().find(|k| &k.keyboard == resource) {
+                    keyboard.has_keymap = true;
+                }
+            },
+            zwp_virtual_keyboard_v1::Request::Key { time, key, state: key_state } => {
+                let key_state = if key_state == 1 { KeyState::Pressed } else { KeyState::Released };
+                state.virtual_keyboard_key(resource, key, key_state, time);
+            },
+            zwp_virtual_keyboard_v1::Request::Modifiers { mods_depressed, mods_latched, .. } => {
+                state.virtual_keyboard_modifiers(resource, mods_depressed | mods_latched);
+            },
+            _ => {},
+        }
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, resource: ObjectId, _data: &()) {
+        let Some(index) = state.virtual_keyboards.keyboards.iter().position(|k| k.keyboard.id() == resource) else {
+            return;
+        };
+        // Keys held down would otherwise stay pressed on the seat
+        let keyboard = state.virtual_keyboards.keyboards.remove(index);
+        let time = state.start_time.elapsed().as_millis() as u32;
+        for keycode in keyboard.pressed {
+            state.handle_key(keycode, KeyState::Released, time);
+        }
+    }
+}
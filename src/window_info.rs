@@ -0,0 +1,73 @@
+use std::cell::RefCell;
+
+use serde::Serialize;
+use smithay::{
+    desktop::Window,
+    wayland::{compositor::with_states, shell::xdg::XdgToplevelSurfaceData},
+    xwayland::X11Surface,
+};
+use tracing::debug;
+
+use crate::{initial_configure_sent, ipc, TwmState};
+
+/// App id and title the client set on its toplevel
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct WindowInfo {
+    pub app_id: Option<String>,
+    pub title: Option<String>,
+}
+
+/// App id and title of the X11 `window`, its WM_CLASS class standing in for the app id
+pub fn x11_window_info(window: &X11Surface) -> WindowInfo {
+    let non_empty = |value: String| Some(value).filter(|value| !value.is_empty());
+    WindowInfo {
+        app_id: non_empty(window.class()),
+        title: non_empty(window.title()),
+    }
+}
+
+/// Window info as of the last commit, kept in the window's user data
+#[derive(Debug, Default)]
+struct CommittedInfo(RefCell<WindowInfo>);
+
+impl TwmState {
+    /// Current app id and title of `window`
+    pub fn window_info(&self, window: &Window) -> WindowInfo {
+        with_states(window.toplevel().wl_surface(), |states| {
+            let data = states
+                .data_map
+                .get::<XdgToplevelSurfaceData>()
+                .expect("Toplevel surface data available")
+                .lock()
+                .expect("Toplevel surface data lock");
+            WindowInfo {
+                app_id: data.app_id.clone(),
+                title: data.title.clone(),
+            }
+        })
+    }
+
+    /// App id and title of the Wayland or X11 window with keyboard focus
+    pub fn focused_window_info(&self) -> Option<WindowInfo> {
+        match self.focused_window() {
+            Some(window) => Some(self.window_info(&window)),
+            None => self.focused_x11_window().map(|window| x11_window_info(&window)),
+        }
+    }
+
+    /// Picks up app id and title changes of `window`, called on every commit of the window
+    pub fn refresh_window_info(&mut self, window: &Window) {
+        let info = self.window_info(window);
+        window.user_data().insert_if_missing(CommittedInfo::default);
+        let committed = window.user_data().get::<CommittedInfo>().expect("Committed info available");
+        if *committed.0.borrow() == info {
+            return;
+        }
+        debug!("Window info changed to {:?}", info);
+        *committed.0.borrow_mut() = info.clone();
+        // Subscribers learn about the initial info with the new window
+        if initial_configure_sent(window.toplevel()) {
+            self.emit_ipc_event(ipc::Event::Window { change: ipc::WindowChange::Title, window: info });
+        }
+    }
+}
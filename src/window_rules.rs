@@ -0,0 +1,57 @@
+use std::cell::RefCell;
+
+use smithay::desktop::Window;
+use tracing::debug;
+
+use crate::{initial_configure_sent, window_info::WindowInfo, TwmState};
+
+/// App id the window rules were last applied for, kept in the window's user data
+#[derive(Debug, Default)]
+struct AppliedRules(RefCell<Option<Option<String>>>);
+
+impl TwmState {
+    /// Applies the matching window rules on the first commit of `window` and whenever its
+    /// app id changed, as clients may set it only after their window was mapped
+    pub fn apply_window_rules(&mut self, window: &Window) {
+        let WindowInfo { app_id, title } = self.window_info(window);
+        window.user_data().insert_if_missing(AppliedRules::default);
+        let applied = window.user_data().get::<AppliedRules>().expect("Applied rules available");
+        if applied.0.borrow().as_ref() == Some(&app_id) {
+            return;
+        }
+        *applied.0.borrow_mut() = Some(app_id.clone());
+
+        let rules: Vec<_> = self.config.window_rules
+            .iter()
+            .filter(|rule| rule.matches(app_id.as_deref(), title.as_deref()))
+            .cloned()
+            .collect();
+        for rule in rules {
+            debug!("Applying window rule {:?} to {:?}", rule, app_id);
+            if let Some(placement) = rule.placement {
+                self.set_placement(window, placement);
+            }
+            if let Some(floating) = rule.floating.filter(|&floating| floating != self.is_floating(window)) {
+                if floating && !initial_configure_sent(window.toplevel()) {
+                    // Centered at the size the client picks, like dialogs
+                    window.toplevel().with_pending_state(|state| {
+                        state.size = None;
+                    });
+                    self.float_new_window(window);
+                    self.retile();
+                } else {
+                    self.set_floating(window, floating);
+                }
+            }
+            if let Some(opacity) = rule.opacity {
+                self.set_opacity(window, opacity);
+            }
+            if rule.fullscreen {
+                self.fullscreen_window(window);
+            }
+            if let Some(number) = rule.workspace {
+                self.send_window_to_workspace(window, number.saturating_sub(1));
+            }
+        }
+    }
+}
@@ -0,0 +1,392 @@
+use smithay::{
+    desktop::Window,
+    output::Output,
+    utils::{Logical, Point},
+    xwayland::X11Surface,
+};
+use tracing::debug;
+
+use crate::{ipc, layout, tabs, TwmState};
+
+/// Number of virtual workspaces of each output
+pub const WORKSPACE_COUNT: usize = 9;
+
+/// Virtual workspace holding its windows in tiling order
+#[derive(Debug)]
+pub struct Workspace {
+    pub windows: Vec<Window>,
+    /// Window that had keyboard focus when the workspace was last shown
+    pub last_focused: Option<Window>,
+    /// Fraction of the output width taken by the master window
+    pub master_ratio: f64,
+    pub layout: layout::Layout,
+    /// Tabbed containers grouping some of the windows
+    pub containers: Vec<tabs::TabContainer>,
+    /// X11 windows, floating above the others. Override-redirect windows belong to no
+    /// workspace.
+    pub x11_windows: Vec<X11Surface>,
+    /// X11 window that had keyboard focus when the workspace was last shown, it takes
+    /// precedence over `last_focused`
+    pub last_focused_x11: Option<X11Surface>,
+}
+
+impl Default for Workspace {
+    fn default() -> Self {
+        Self {
+            windows: Vec::new(),
+            last_focused: None,
+            master_ratio: layout::DEFAULT_MASTER_RATIO,
+            layout: layout::Layout::default(),
+            containers: Vec::new(),
+            x11_windows: Vec::new(),
+            last_focused_x11: None,
+        }
+    }
+}
+
+impl Workspace {
+    pub fn remove_window(&mut self, window: &Window) {
+        self.windows.retain(|w| w != window);
+        if self.last_focused.as_ref() == Some(window) {
+            self.last_focused = None;
+        }
+        tabs::remove_from_containers(&mut self.containers, window);
+    }
+}
+
+/// Workspaces of one output and the one it shows
+#[derive(Debug)]
+pub struct OutputWorkspaces {
+    /// Name of the output, `None` after the last output was unplugged. The next output
+    /// plugged in takes the workspaces over.
+    pub output: Option<String>,
+    pub workspaces: Vec<Workspace>,
+    /// Index of the shown workspace
+    pub current: usize,
+}
+
+impl OutputWorkspaces {
+    pub fn new(output: Option<String>, layout: layout::Layout) -> Self {
+        Self {
+            output,
+            workspaces: (0..WORKSPACE_COUNT)
+                .map(|_| Workspace { layout, ..Default::default() })
+                .collect(),
+            current: 0,
+        }
+    }
+
+    pub fn current(&self) -> &Workspace {
+        &self.workspaces[self.current]
+    }
+
+    pub fn current_mut(&mut self) -> &mut Workspace {
+        &mut self.workspaces[self.current]
+    }
+}
+
+impl TwmState {
+    /// Shown workspace of the output under the pointer
+    pub fn current_workspace(&self) -> &Workspace {
+        self.workspaces[self.active_workspaces()].current()
+    }
+
+    pub fn current_workspace_mut(&mut self) -> &mut Workspace {
+        let index = self.active_workspaces();
+        self.workspaces[index].current_mut()
+    }
+
+    /// Shown workspace of `output`
+    pub fn output_workspace(&self, output: &Output) -> Option<&Workspace> {
+        self.workspaces
+            .iter()
+            .find(|workspaces| workspaces.output.as_ref() == Some(&output.name()))
+            .map(OutputWorkspaces::current)
+    }
+
+    pub fn output_workspace_mut(&mut self, output: &Output) -> Option<&mut Workspace> {
+        self.workspaces
+            .iter_mut()
+            .find(|workspaces| workspaces.output.as_ref() == Some(&output.name()))
+            .map(OutputWorkspaces::current_mut)
+    }
+
+    /// Windows of all workspaces of all outputs and the minimized windows
+    pub fn all_windows(&self) -> impl Iterator<Item = &Window> {
+        self.workspaces
+            .iter()
+            .flat_map(|workspaces| workspaces.workspaces.iter())
+            .flat_map(|workspace| workspace.windows.iter())
+            .chain(self.minimized.iter().map(|minimized| &minimized.window))
+    }
+
+    /// Indices of the output workspaces and the workspace `window` is on
+    pub fn workspace_index(&self, window: &Window) -> Option<(usize, usize)> {
+        self.workspaces.iter().enumerate().find_map(|(output_index, workspaces)| {
+            workspaces.workspaces
+                .iter()
+                .position(|workspace| workspace.windows.contains(window))
+                .map(|index| (output_index, index))
+        })
+    }
+
+    /// Workspace `window` is on
+    pub fn workspace_of_mut(&mut self, window: &Window) -> Option<&mut Workspace> {
+        let (output_index, index) = self.workspace_index(window)?;
+        Some(&mut self.workspaces[output_index].workspaces[index])
+    }
+
+    /// Takes `window` out of every workspace
+    pub fn remove_from_workspaces(&mut self, window: &Window) {
+        self.workspaces
+            .iter_mut()
+            .flat_map(|workspaces| workspaces.workspaces.iter_mut())
+            .for_each(|workspace| workspace.remove_window(window));
+    }
+
+    /// Gives a new output its workspaces, those of an output with the same name or of the
+    /// last unplugged output are reused
+    pub fn add_output_workspaces(&mut self, output: &Output) {
+        let name = output.name();
+        if self.workspaces.iter().any(|workspaces| workspaces.output.as_ref() == Some(&name)) {
+            return;
+        }
+        match self.workspaces.iter_mut().find(|workspaces| workspaces.output.is_none()) {
+            Some(workspaces) => {
+                debug!("Output {} takes over the workspaces of an unplugged output", name);
+                workspaces.output = Some(name);
+            },
+            None => self.workspaces.push(OutputWorkspaces::new(Some(name), self.config.default_layout)),
+        }
+    }
+
+    /// Moves the windows of an unplugged output to the same workspaces of the active output.
+    /// `location` is where the output was, floating windows keep their place relative to it.
+    pub fn remove_output_workspaces(&mut self, output: &Output, location: Point<i32, Logical>) {
+        let Some(index) = self.workspaces
+            .iter()
+            .position(|workspaces| workspaces.output.as_ref() == Some(&output.name())) else {
+            return;
+        };
+        let Some(target_output) = self.active_output() else {
+            // Kept until the next output is plugged in
+            self.workspaces[index].output = None;
+            return;
+        };
+
+        let removed = self.workspaces.remove(index);
+        let target = self.active_workspaces();
+        let target_location = self.space
+            .output_geometry(&target_output)
+            .map_or_else(Point::default, |geometry| geometry.loc);
+        let offset = target_location - location;
+        for (index, workspace) in removed.workspaces.into_iter().enumerate() {
+            self.shift_floating_windows(&workspace.windows, offset);
+            self.shift_x11_windows(&workspace.x11_windows, offset);
+            if index != self.workspaces[target].current {
+                workspace.windows.iter().for_each(|window| self.hide_window(window));
+            }
+            let target = &mut self.workspaces[target].workspaces[index];
+            target.windows.extend(workspace.windows);
+            target.containers.extend(workspace.containers);
+            target.x11_windows.extend(workspace.x11_windows);
+        }
+    }
+
+    /// Shows workspace `index` on the output under the pointer, hiding the windows of the
+    /// workspace it showed before
+    pub fn switch_workspace(&mut self, index: usize) {
+        self.show_workspace(self.active_workspaces(), index);
+    }
+
+    /// Shows the workspace `step` workspaces away from the current one on the output under
+    /// the pointer, wrapping around
+    pub fn cycle_workspace(&mut self, step: isize) {
+        let output_index = self.active_workspaces();
+        let current = self.workspaces[output_index].current as isize;
+        self.show_workspace(output_index, (current + step).rem_euclid(WORKSPACE_COUNT as isize) as usize);
+    }
+
+    /// Shows workspace `index` of the output workspaces at `output_index` and focuses it
+    pub fn show_workspace(&mut self, output_index: usize, index: usize) {
+        let workspaces = &self.workspaces[output_index];
+        if index == workspaces.current || index >= workspaces.workspaces.len() {
+            return;
+        }
+        debug!("Switching to workspace {} on {:?}", index, workspaces.output);
+
+        // The scratchpad isn't part of any workspace and stays shown over the next one
+        let focused = self.focused_window().filter(|window| !self.is_scratchpad(window));
+        // Sticky windows move along and keep the focus if they had it
+        let sticky: Vec<Window> = self.workspaces[output_index].current().windows
+            .iter()
+            .filter(|window| self.is_sticky(window))
+            .cloned()
+            .collect();
+        let focused_sticky = focused.clone().filter(|focused| sticky.contains(focused));
+        let focused_x11 = self.focused_x11_window();
+        let workspace = self.workspaces[output_index].current_mut();
+        if focused.as_ref().map_or(false, |focused| workspace.windows.contains(focused)) {
+            workspace.last_focused = focused;
+        }
+        // X11 windows are hidden by being left out of the shown workspaces
+        workspace.last_focused_x11 = focused_x11.filter(|focused| workspace.x11_windows.contains(focused));
+        for window in sticky.iter() {
+            workspace.remove_window(window);
+        }
+        let windows = workspace.windows.clone();
+        for window in windows.iter() {
+            self.hide_window(window);
+        }
+
+        let workspaces = &mut self.workspaces[output_index];
+        workspaces.current = index;
+        let workspace = workspaces.current_mut();
+        let windows = workspace.windows.clone();
+        // A sticky window keeps the focus, otherwise an X11 window focused last gets it back
+        let focus_x11 = match focused_sticky {
+            Some(_) => None,
+            None => workspace.last_focused_x11.take(),
+        };
+        let focus = focused_sticky
+            .or_else(|| workspace.last_focused.clone())
+            .or_else(|| windows.last().cloned());
+        // Without other windows the X11 window mapped last gets the focus
+        let focus_x11 = focus_x11.or_else(|| workspace.x11_windows.last().cloned().filter(|_| focus.is_none()));
+        // Still mapped where they are
+        workspace.windows.extend(sticky);
+        for window in windows.iter() {
+            self.unhide_window(window);
+        }
+        self.retile();
+        let output = self.workspaces[output_index].output.clone();
+        self.emit_ipc_event(ipc::Event::Workspace { output, workspace: index + 1 });
+        match focus_x11 {
+            Some(window) => self.focus_x11_window(&window),
+            None => self.focus_window(focus.as_ref()),
+        }
+    }
+
+    /// Changes the master ratio of the current workspace by `delta`, within the allowed bounds
+    pub fn adjust_master_ratio(&mut self, delta: f64) {
+        let workspace = self.current_workspace_mut();
+        let ratio = (workspace.master_ratio + delta).clamp(layout::MIN_MASTER_RATIO, layout::MAX_MASTER_RATIO);
+        // Avoid drifting away from the step grid through float rounding
+        workspace.master_ratio = (ratio * 100.0).round() / 100.0;
+        debug!("Master ratio {}", workspace.master_ratio);
+        self.retile();
+    }
+
+    /// Switches the current workspace to the next layout
+    pub fn cycle_layout(&mut self) {
+        let workspace = self.current_workspace_mut();
+        workspace.layout = workspace.layout.next();
+        debug!("Layout {:?}", workspace.layout);
+        self.retile();
+    }
+
+    /// Moves the focused window to workspace `index` of its output
+    pub fn move_window_to_workspace(&mut self, index: usize) {
+        let Some(window) = self.focused_window() else {
+            if let Some(window) = self.focused_x11_window() {
+                self.move_x11_window_to_workspace(&window, index);
+            }
+            return;
+        };
+        let Some((output_index, current)) = self.workspace_index(&window) else {
+            return;
+        };
+        if index == current || index >= self.workspaces[output_index].workspaces.len() {
+            return;
+        }
+        self.send_window_to_workspace(&window, index);
+
+        let focus = self.workspaces[output_index].workspaces[current]
+            .windows
+            .last()
+            .map(|w| w.toplevel().wl_surface().clone());
+        self.set_keyboard_focus(focus);
+    }
+
+    /// Moves `window` from its workspace to workspace `index` of the same output without
+    /// touching focus
+    pub fn send_window_to_workspace(&mut self, window: &Window, index: usize) {
+        let Some((output_index, current)) = self.workspace_index(window) else {
+            return;
+        };
+        let workspaces = &self.workspaces[output_index];
+        if index == current || index >= workspaces.workspaces.len() {
+            return;
+        }
+        debug!("Moving window to workspace {}", index);
+
+        if index != workspaces.current {
+            self.hide_window(window);
+        }
+        self.remove_from_workspaces(window);
+        self.workspaces[output_index].workspaces[index].windows.push(window.clone());
+        self.retile();
+    }
+
+    /// Exchanges the shown workspace of the output under the pointer with the one shown on
+    /// the next output, focus stays on the same window
+    pub fn move_workspace_to_next_output(&mut self) {
+        let outputs: Vec<Output> = self.space.outputs().cloned().collect();
+        let Some(output) = self.active_output() else {
+            return;
+        };
+        let Some(position) = outputs.iter().position(|o| o == &output) else {
+            return;
+        };
+        let next_output = &outputs[(position + 1) % outputs.len()];
+        if next_output == &output {
+            debug!("No other output to move the workspace to");
+            return;
+        }
+        let source = self.active_workspaces();
+        let Some(target) = self.workspaces
+            .iter()
+            .position(|workspaces| workspaces.output.as_ref() == Some(&next_output.name())) else {
+            return;
+        };
+        debug!("Moving workspace from {} to {}", output.name(), next_output.name());
+
+        let (Some(from), Some(to)) = (self.space.output_geometry(&output), self.space.output_geometry(next_output)) else {
+            return;
+        };
+        let moved = self.workspaces[source].current().windows.clone();
+        let replaced = self.workspaces[target].current().windows.clone();
+        self.shift_floating_windows(&moved, to.loc - from.loc);
+        self.shift_floating_windows(&replaced, from.loc - to.loc);
+        let moved = self.workspaces[source].current().x11_windows.clone();
+        let replaced = self.workspaces[target].current().x11_windows.clone();
+        self.shift_x11_windows(&moved, to.loc - from.loc);
+        self.shift_x11_windows(&replaced, from.loc - to.loc);
+
+        // Layout and master ratio go along with the windows
+        let moved = std::mem::take(self.workspaces[source].current_mut());
+        let replaced = std::mem::replace(self.workspaces[target].current_mut(), moved);
+        *self.workspaces[source].current_mut() = replaced;
+        self.retile();
+    }
+
+    /// Index of the output workspaces of the output under the pointer
+    fn active_workspaces(&self) -> usize {
+        let name = self.active_output().map(|output| output.name());
+        self.workspaces
+            .iter()
+            .position(|workspaces| workspaces.output.is_some() && workspaces.output == name)
+            .unwrap_or(0)
+    }
+
+    /// Moves the floating windows among `windows` by `offset`, hidden ones where they are
+    /// shown again
+    fn shift_floating_windows(&mut self, windows: &[Window], offset: Point<i32, Logical>) {
+        for window in windows.iter().filter(|window| self.is_floating(window)) {
+            match self.space.element_location(window) {
+                Some(location) => self.space.map_element(window.clone(), location + offset, false),
+                None => self.shift_hidden_location(window, offset),
+            }
+        }
+    }
+}
@@ -0,0 +1,429 @@
+//! X11 clients through Xwayland.
+//!
+//! Xwayland is started with the compositor and its windows are managed by smithay's X11
+//! window manager. The space only holds Wayland toplevels, so X11 windows are kept apart
+//! and stacked above the tiled windows like floating ones, at their X11 geometry, which
+//! shares the coordinates of the space. New windows outside of all outputs are centered on
+//! the active one. They belong to the workspace of the output they were mapped on and are
+//! only drawn and reachable by the pointer while it is shown. They are moved and resized
+//! like floating windows, on request of their client or with the mouse modifier.
+//! Override-redirect windows like menus and tooltips place themselves and are always
+//! shown.
+
+use anyhow::{Context, Result};
+use smithay::{
+    desktop::{utils::under_from_surface_tree, WindowSurfaceType},
+    input::pointer::{Focus, GrabStartData as PointerGrabStartData},
+    output::Output,
+    reexports::{
+        calloop::LoopHandle,
+        wayland_protocols::xdg::shell::server::xdg_toplevel,
+        wayland_server::{protocol::wl_surface::WlSurface, Resource},
+    },
+    utils::{Logical, Point, Rectangle, Serial, SERIAL_COUNTER},
+    xwayland::{
+        xwm::{Reorder, ResizeEdge, XwmId},
+        X11Surface, X11Wm, XWayland, XWaylandEvent, XwmHandler,
+    },
+};
+use tracing::{debug, info, warn};
+
+use crate::{cursor::CursorIcon, grabs, ipc, window_info::x11_window_info, workspace::Workspace, TwmLoopData, TwmState};
+
+/// Running Xwayland server and its mapped windows
+#[derive(Default)]
+pub struct Xwayland {
+    /// Kept alive for the server to keep running
+    server: Option<XWayland>,
+    wm: Option<X11Wm>,
+    /// Number of the X11 display, `DISPLAY` of spawned clients
+    pub display: Option<u32>,
+    /// Mapped windows, the topmost last
+    windows: Vec<X11Surface>,
+    /// Window mapped last, focused once its surface is known
+    pending_focus: Option<X11Surface>,
+}
+
+/// Starts Xwayland unless disabled in the config. X11 clients can connect once the window
+/// manager started, they are only logged as failures until then.
+pub fn init(handle: &LoopHandle<'static, TwmLoopData>, state: &mut TwmState) -> Result<()> {
+    if !state.config.xwayland {
+        return Ok(());
+    }
+
+    let (server, channel) = XWayland::new(&state.display_handle);
+    let display_handle = state.display_handle.clone();
+    let wm_handle = handle.clone();
+    handle
+        .insert_source(channel, move |event, _, data| match event {
+            XWaylandEvent::Ready { connection, client, display, .. } => {
+                match X11Wm::start_wm(wm_handle.clone(), display_handle.clone(), connection, client) {
+                    Ok(wm) => {
+                        info!("Xwayland ready on display :{}", display);
+                        data.state.xwayland.wm = Some(wm);
+                    },
+                    Err(err) => warn!("Failed to start the X11 window manager: {}", err),
+                }
+            },
+            XWaylandEvent::Exited => {
+                warn!("Xwayland exited");
+                data.state.xwayland.wm = None;
+                data.state.xwayland.display = None;
+                data.state.xwayland.windows.clear();
+                data.state.workspaces
+                    .iter_mut()
+                    .flat_map(|workspaces| workspaces.workspaces.iter_mut())
+                    .for_each(|workspace| {
+                        workspace.x11_windows.clear();
+                        workspace.last_focused_x11 = None;
+                    });
+                data.state.needs_redraw = true;
+            },
+        })
+        .map_err(|err| err.error)
+        .context("Failed to insert the Xwayland source into the event loop")?;
+
+    let display = server
+        .start(handle.clone(), None, std::iter::empty::<(String, String)>(), true, |_| {})
+        .context("Failed to start Xwayland")?;
+    state.xwayland.display = Some(display);
+    state.xwayland.server = Some(server);
+    Ok(())
+}
+
+impl TwmState {
+    /// Shown X11 windows, the topmost first
+    pub fn x11_windows(&self) -> impl DoubleEndedIterator<Item = &X11Surface> {
+        self.xwayland.windows
+            .iter()
+            .rev()
+            .filter(|window| window.is_override_redirect() || self.is_x11_window_shown(window))
+    }
+
+    /// Whether the workspace of the X11 `window` is shown on its output
+    fn is_x11_window_shown(&self, window: &X11Surface) -> bool {
+        self.workspaces
+            .iter()
+            .any(|workspaces| workspaces.current().x11_windows.contains(window))
+    }
+
+    /// Indices of the output workspaces and the workspace the X11 `window` is on
+    pub fn x11_workspace_index(&self, window: &X11Surface) -> Option<(usize, usize)> {
+        self.workspaces.iter().enumerate().find_map(|(output_index, workspaces)| {
+            workspaces.workspaces
+                .iter()
+                .position(|workspace| workspace.x11_windows.contains(window))
+                .map(|index| (output_index, index))
+        })
+    }
+
+    /// Output of the workspace the X11 `window` is on
+    pub fn x11_output(&self, window: &X11Surface) -> Option<Output> {
+        let (output_index, _) = self.x11_workspace_index(window)?;
+        let name = self.workspaces[output_index].output.as_ref()?;
+        self.space.outputs().find(|output| &output.name() == name).cloned()
+    }
+
+    /// Moves the X11 `windows` by `offset`
+    pub fn shift_x11_windows(&mut self, windows: &[X11Surface], offset: Point<i32, Logical>) {
+        for window in windows {
+            let mut geometry = window.geometry();
+            geometry.loc += offset;
+            if let Err(err) = window.configure(geometry) {
+                warn!("Failed to move X11 window: {}", err);
+            }
+        }
+        self.needs_redraw = true;
+    }
+
+    /// Moves the X11 `window` to workspace `index` of its output, the focus stays on the
+    /// workspace it leaves
+    pub fn move_x11_window_to_workspace(&mut self, window: &X11Surface, index: usize) {
+        let Some((output_index, current)) = self.x11_workspace_index(window) else {
+            return;
+        };
+        if index == current || index >= self.workspaces[output_index].workspaces.len() {
+            return;
+        }
+        debug!("Moving X11 window to workspace {}", index);
+        let workspaces = &mut self.workspaces[output_index];
+        workspaces.workspaces[current].x11_windows.retain(|w| w != window);
+        workspaces.workspaces[index].x11_windows.push(window.clone());
+        self.needs_redraw = true;
+
+        self.focus_last_window_of(output_index, current);
+    }
+
+    /// Focuses the X11 window mapped last on workspace `index` of the output workspaces at
+    /// `output_index`, or the last of its other windows
+    fn focus_last_window_of(&mut self, output_index: usize, index: usize) {
+        let workspace = &self.workspaces[output_index].workspaces[index];
+        match workspace.x11_windows.last().cloned() {
+            Some(window) => self.focus_x11_window(&window),
+            None => {
+                let focus = workspace.windows.last().cloned();
+                self.focus_window(focus.as_ref());
+            },
+        }
+    }
+
+    /// Topmost X11 window under `position`
+    pub fn x11_window_under(&self, position: Point<f64, Logical>) -> Option<X11Surface> {
+        self.x11_windows()
+            .find(|window| window.geometry().to_f64().contains(position))
+            .cloned()
+    }
+
+    /// Surface of an X11 window under `position` with its location
+    pub fn x11_surface_under(&self, position: Point<f64, Logical>) -> Option<(WlSurface, Point<i32, Logical>)> {
+        let window = self.x11_window_under(position)?;
+        under_from_surface_tree(&window.wl_surface()?, position, window.geometry().loc, WindowSurfaceType::ALL)
+    }
+
+    /// X11 window owning the surface with keyboard focus
+    pub fn focused_x11_window(&self) -> Option<X11Surface> {
+        let focus = self.seat.get_keyboard()?.current_focus()?;
+        self.x11_windows()
+            .find(|window| window.wl_surface().as_ref() == Some(&focus))
+            .cloned()
+    }
+
+    /// Focuses the window mapped last with the first commit of its surface, which Xwayland
+    /// only associates with the window after mapping it
+    pub fn x11_commit(&mut self, surface: &WlSurface) {
+        let Some(window) = self.xwayland.pending_focus.take() else {
+            return;
+        };
+        if window.wl_surface().as_ref() == Some(surface) {
+            self.focus_x11_window(&window);
+        } else if window.alive() {
+            self.xwayland.pending_focus = Some(window);
+        }
+    }
+
+    /// Raises the X11 `window` and gives it keyboard focus
+    pub fn focus_x11_window(&mut self, window: &X11Surface) {
+        let Some(surface) = window.wl_surface() else {
+            debug!("X11 window has no surface yet");
+            return;
+        };
+        if let Some(index) = self.xwayland.windows.iter().position(|w| w == window) {
+            let window = self.xwayland.windows.remove(index);
+            self.xwayland.windows.push(window);
+        }
+        if let Some(wm) = self.xwayland.wm.as_mut() {
+            if let Err(err) = wm.raise_window(window) {
+                warn!("Failed to raise X11 window: {}", err);
+            }
+        }
+        self.set_keyboard_focus(Some(surface));
+    }
+
+    /// Marks the X11 window owning `focus` active and all others inactive
+    pub fn activate_x11_windows(&self, focus: Option<&WlSurface>) {
+        for window in self.xwayland.windows.iter().filter(|window| !window.is_override_redirect()) {
+            if let Err(err) = window.set_activated(focus.is_some() && window.wl_surface().as_ref() == focus) {
+                warn!("Failed to update the activation of an X11 window: {}", err);
+            }
+        }
+    }
+
+    /// Geometry the new X11 `window` is mapped at, centered on the active output unless it
+    /// asked for a location on one of the outputs
+    fn place_x11_window(&self, window: &X11Surface) -> Rectangle<i32, Logical> {
+        let mut geometry = window.geometry();
+        if self.space.output_under(geometry.loc.to_f64()).next().is_some() && geometry.loc != Point::default() {
+            return geometry;
+        }
+        if let Some(output_geometry) = self.active_output().and_then(|output| self.space.output_geometry(&output)) {
+            geometry.loc = output_geometry.loc + Point::from((
+                (output_geometry.size.w - geometry.size.w).max(0) / 2,
+                (output_geometry.size.h - geometry.size.h).max(0) / 2,
+            ));
+        }
+        geometry
+    }
+
+    /// Moves the X11 `window` along with the pointer until all buttons are released
+    pub fn start_x11_move(&mut self, window: X11Surface, start_data: PointerGrabStartData<TwmState>, serial: Serial) {
+        let pointer = self.seat.get_pointer().expect("Pointer available");
+        let grab = grabs::X11MoveGrab {
+            start_data,
+            initial_window_location: window.geometry().loc,
+            window,
+        };
+        self.show_cursor_icon(CursorIcon::Move);
+        pointer.set_grab(self, grab, serial, Focus::Clear);
+    }
+
+    /// Resizes the X11 `window` from `edges` along with the pointer until all buttons are
+    /// released
+    pub fn start_x11_resize(&mut self,
+                            window: X11Surface,
+                            start_data: PointerGrabStartData<TwmState>,
+                            edges: xdg_toplevel::ResizeEdge,
+                            serial: Serial) {
+        let pointer = self.seat.get_pointer().expect("Pointer available");
+        let grab = grabs::X11ResizeGrab {
+            start_data,
+            initial_window_geometry: window.geometry(),
+            window,
+            edges,
+        };
+        self.show_cursor_icon(CursorIcon::Resize(edges));
+        pointer.set_grab(self, grab, serial, Focus::Clear);
+    }
+
+    /// Start data of the pointer grab of a button held on the X11 `window`, which X11
+    /// clients start moves and resizes with
+    fn x11_grab_start_data(&self, window: &X11Surface) -> Option<PointerGrabStartData<TwmState>> {
+        let start_data = self.seat.get_pointer()?.grab_start_data()?;
+        let surface = window.wl_surface()?;
+        let (focus, _) = start_data.focus.as_ref()?;
+        focus.id().same_client_as(&surface.id()).then_some(start_data)
+    }
+
+    /// Shown workspace of the output holding the center of `geometry`, the current one if
+    /// no output does
+    fn x11_workspace_for(&mut self, geometry: Rectangle<i32, Logical>) -> &mut Workspace {
+        let center = geometry.loc + Point::from((geometry.size.w / 2, geometry.size.h / 2));
+        let output = self.space
+            .output_under(center.to_f64())
+            .next()
+            .cloned()
+            .filter(|output| self.output_workspace(output).is_some());
+        match output {
+            Some(output) => self.output_workspace_mut(&output).expect("Workspace of the output"),
+            None => self.current_workspace_mut(),
+        }
+    }
+
+    /// Forgets the X11 `window`, passing focus on to its workspace if it had it
+    fn unmap_x11_window(&mut self, window: &X11Surface) {
+        let Some(index) = self.xwayland.windows.iter().position(|w| w == window) else {
+            return;
+        };
+        let had_focus = self.focused_x11_window().as_ref() == Some(window);
+        let workspace_index = self.x11_workspace_index(window);
+        self.xwayland.windows.remove(index);
+        if workspace_index.is_some() {
+            let info = x11_window_info(window);
+            self.emit_ipc_event(ipc::Event::Window { change: ipc::WindowChange::Close, window: info });
+        }
+        for workspace in self.workspaces.iter_mut().flat_map(|workspaces| workspaces.workspaces.iter_mut()) {
+            workspace.x11_windows.retain(|w| w != window);
+            if workspace.last_focused_x11.as_ref() == Some(window) {
+                workspace.last_focused_x11 = None;
+            }
+        }
+        self.needs_redraw = true;
+        if had_focus {
+            match workspace_index {
+                Some((output_index, index)) => self.focus_last_window_of(output_index, index),
+                None => {
+                    let next = self.current_workspace().windows.last().cloned();
+                    self.focus_window(next.as_ref());
+                },
+            }
+        }
+    }
+}
+
+impl XwmHandler for TwmLoopData {
+    fn xwm_state(&mut self, _xwm: XwmId) -> &mut X11Wm {
+        self.state.xwayland.wm.as_mut().expect("X11 window manager running")
+    }
+
+    fn new_window(&mut self, _xwm: XwmId, _window: X11Surface) {}
+
+    fn new_override_redirect_window(&mut self, _xwm: XwmId, _window: X11Surface) {}
+
+    fn map_window_request(&mut self, _xwm: XwmId, window: X11Surface) {
+        debug!("Mapping X11 window {:?}", window.title());
+        if let Err(err) = window.set_mapped(true) {
+            warn!("Failed to map X11 window: {}", err);
+            return;
+        }
+        let geometry = self.state.place_x11_window(&window);
+        if let Err(err) = window.configure(geometry) {
+            warn!("Failed to configure X11 window: {}", err);
+        }
+        self.state.x11_workspace_for(geometry).x11_windows.push(window.clone());
+        self.state.xwayland.windows.push(window.clone());
+        let info = x11_window_info(&window);
+        self.state.emit_ipc_event(ipc::Event::Window { change: ipc::WindowChange::New, window: info });
+        self.state.xwayland.pending_focus = Some(window);
+        self.state.needs_redraw = true;
+    }
+
+    fn mapped_override_redirect_window(&mut self, _xwm: XwmId, window: X11Surface) {
+        self.state.xwayland.windows.push(window);
+        self.state.needs_redraw = true;
+    }
+
+    fn unmapped_window(&mut self, _xwm: XwmId, window: X11Surface) {
+        self.state.unmap_x11_window(&window);
+        if !window.is_override_redirect() {
+            if let Err(err) = window.set_mapped(false) {
+                warn!("Failed to unmap X11 window: {}", err);
+            }
+        }
+    }
+
+    fn destroyed_window(&mut self, _xwm: XwmId, window: X11Surface) {
+        self.state.unmap_x11_window(&window);
+    }
+
+    fn configure_request(&mut self,
+                         _xwm: XwmId,
+                         window: X11Surface,
+                         x: Option<i32>,
+                         y: Option<i32>,
+                         w: Option<u32>,
+                         h: Option<u32>,
+                         _reorder: Option<Reorder>) {
+        // X11 windows aren't tiled, so they get the geometry they ask for
+        let mut geometry = window.geometry();
+        geometry.loc.x = x.unwrap_or(geometry.loc.x);
+        geometry.loc.y = y.unwrap_or(geometry.loc.y);
+        geometry.size.w = w.map_or(geometry.size.w, |w| w as i32);
+        geometry.size.h = h.map_or(geometry.size.h, |h| h as i32);
+        if let Err(err) = window.configure(geometry) {
+            warn!("Failed to configure X11 window: {}", err);
+        }
+    }
+
+    fn configure_notify(&mut self,
+                        _xwm: XwmId,
+                        _window: X11Surface,
+                        _geometry: Rectangle<i32, Logical>,
+                        _above: Option<u32>) {
+        self.state.needs_redraw = true;
+    }
+
+    fn resize_request(&mut self, _xwm: XwmId, window: X11Surface, _button: u32, resize_edge: ResizeEdge) {
+        let Some(start_data) = self.state.x11_grab_start_data(&window) else {
+            debug!("X11 resize request without a held button");
+            return;
+        };
+        let edges = match resize_edge {
+            ResizeEdge::Top => xdg_toplevel::ResizeEdge::Top,
+            ResizeEdge::Bottom => xdg_toplevel::ResizeEdge::Bottom,
+            ResizeEdge::Left => xdg_toplevel::ResizeEdge::Left,
+            ResizeEdge::Right => xdg_toplevel::ResizeEdge::Right,
+            ResizeEdge::TopLeft => xdg_toplevel::ResizeEdge::TopLeft,
+            ResizeEdge::TopRight => xdg_toplevel::ResizeEdge::TopRight,
+            ResizeEdge::BottomLeft => xdg_toplevel::ResizeEdge::BottomLeft,
+            ResizeEdge::BottomRight => xdg_toplevel::ResizeEdge::BottomRight,
+        };
+        self.state.start_x11_resize(window, start_data, edges, SERIAL_COUNTER.next_serial());
+    }
+
+    fn move_request(&mut self, _xwm: XwmId, window: X11Surface, _button: u32) {
+        let Some(start_data) = self.state.x11_grab_start_data(&window) else {
+            debug!("X11 move request without a held button");
+            return;
+        };
+        self.state.start_x11_move(window, start_data, SERIAL_COUNTER.next_serial());
+    }
+}